@@ -1,6 +1,7 @@
 import { app, BrowserWindow, ipcMain, Menu } from "electron";
 import { MenuItemConstructorOptions } from "electron/main";
 import log, { LoggingMethod } from "loglevel";
+import * as jsonrpc from "./jsonrpc";
 import { State } from "./state";
 
 const originalFactory = log.methodFactory;
@@ -136,6 +137,19 @@ async function main() {
               window.webContents.send("challenge.peer-accepted", { peerId });
             }
             break;
+
+          case "challenge_timed_out":
+          case "challenge_failed":
+          case "peer_unreachable":
+            {
+              const { peer_id: peerId, direction, reason } = eventData;
+              window.webContents.send("challenge.failed", {
+                peerId,
+                direction,
+                reason,
+              });
+            }
+            break;
         }
       }
     );
@@ -150,9 +164,22 @@ async function main() {
       await new Promise((resolve) => setTimeout(resolve, 100));
     }
 
-    await appState.jsonrpcClient.call("subscribe_events");
+    // replay the events emitted while waiting for the daemon to connect
+    await appState.jsonrpcClient.call("subscribe_events", [0]);
     const nodeId = await appState.jsonrpcClient.call("node_id");
     window.webContents.send("app.initialized", { nodeId });
+
+    // rebuild the challenges view from the daemon's state
+    const challenges = await appState.jsonrpcClient.call("list_challenges");
+    challenges.forEach((challenge: { peer_id: string; direction: string }) => {
+      const { peer_id: peerId, direction } = challenge;
+
+      if (direction === "outbound") {
+        window.webContents.send("challenge.send", { peerId });
+      } else {
+        window.webContents.send("challenge.received", { peerId });
+      }
+    });
   }
 
   ipcMain.on("challenge.send", async (event, peerId) => {
@@ -160,9 +187,14 @@ async function main() {
       return;
     }
 
-    appState.jsonrpcClient.call("challenge_peer", [peerId]).then(() => {
-      event.reply("challenge.send", { peerId });
-    });
+    appState.jsonrpcClient
+      .call("challenge_peer", [peerId])
+      .then(() => {
+        event.reply("challenge.send", { peerId });
+      })
+      .catch((err: jsonrpc.Error) => {
+        log.warn(`failed challenging peer ${peerId} ERROR=${err.message}`);
+      });
   });
 
   ipcMain.on("challenge.cancel", async (event, peerId) => {
@@ -171,9 +203,16 @@ async function main() {
     }
 
     log.debug(`cancelling peer challenge ${peerId}`);
-    appState.jsonrpcClient.call("cancel_challenge", [peerId]).then(() => {
-      event.reply("challenge.cancel", { peerId });
-    });
+    appState.jsonrpcClient
+      .call("cancel_challenge", [peerId])
+      .then(() => {
+        event.reply("challenge.cancel", { peerId });
+      })
+      .catch((err: jsonrpc.Error) => {
+        log.warn(
+          `failed cancelling peer challenge ${peerId} ERROR=${err.message}`
+        );
+      });
   });
 
   ipcMain.on("challenge.decline", async (event, peerId) => {
@@ -182,9 +221,16 @@ async function main() {
     }
 
     log.debug(`declining peer challenge ${peerId}`);
-    appState.jsonrpcClient.call("decline_peer_challenge", [peerId]).then(() => {
-      event.reply("challenge.decline", { peerId });
-    });
+    appState.jsonrpcClient
+      .call("decline_peer_challenge", [peerId])
+      .then(() => {
+        event.reply("challenge.decline", { peerId });
+      })
+      .catch((err: jsonrpc.Error) => {
+        log.warn(
+          `failed declining peer challenge ${peerId} ERROR=${err.message}`
+        );
+      });
   });
 
   ipcMain.on("challenge.accept", async (event, peerId) => {
@@ -193,9 +239,16 @@ async function main() {
     }
 
     log.debug(`accepting peer challenge ${peerId}`);
-    appState.jsonrpcClient.call("accept_peer_challenge", [peerId]).then(() => {
-      event.reply("challenge.accept", { peerId });
-    });
+    appState.jsonrpcClient
+      .call("accept_peer_challenge", [peerId])
+      .then(() => {
+        event.reply("challenge.accept", { peerId });
+      })
+      .catch((err: jsonrpc.Error) => {
+        log.warn(
+          `failed accepting peer challenge ${peerId} ERROR=${err.message}`
+        );
+      });
   });
 }
 
@@ -1,5 +1,6 @@
 import childProcess, { ChildProcessWithoutNullStreams } from "child_process";
 import { app } from "electron";
+import { promises as fs } from "fs";
 import getPort from "get-port";
 import log from "loglevel";
 import path from "path";
@@ -61,7 +62,12 @@ export class State {
     log.info(`starting local daemon process API_PORT=${this.daemonApiPort}`);
     const daemonProcess = childProcess.spawn(
       path.join(app.getAppPath(), "ipchessd"),
-      ["--api-port", this.daemonApiPort.toString()],
+      [
+        "--api-port",
+        this.daemonApiPort.toString(),
+        "--data-dir",
+        this.daemonDataDir(),
+      ],
       { detached: false, killSignal: "SIGTERM" }
     );
     log.debug(`daemon processes started PID=${daemonProcess.pid}`);
@@ -85,9 +91,19 @@ export class State {
   private async startDaemonWebSocketConnection() {
     log.debug(`connecting to daemon API API_PORT=${this.daemonApiPort}`);
 
-    const wsTryConnect = () =>
-      new Promise<boolean>((resolve) => {
-        const ws = new WebSocket(`ws://127.0.0.1:${this.daemonApiPort}`);
+    const wsTryConnect = async () => {
+      // the daemon writes a new API token on every launch
+      let token: string;
+      try {
+        token = await fs.readFile(this.daemonCookiePath(), "utf8");
+      } catch {
+        return false;
+      }
+
+      return new Promise<boolean>((resolve) => {
+        const ws = new WebSocket(`ws://127.0.0.1:${this.daemonApiPort}`, {
+          headers: { Authorization: `Bearer ${token}` },
+        });
 
         ws.on("close", () => {
           resolve(false);
@@ -115,6 +131,7 @@ export class State {
           resolve(true);
         });
       });
+    };
 
     // keep trying until the daemon's process responds
     for (;;) {
@@ -125,4 +142,12 @@ export class State {
       await new Promise<void>((resolve) => setTimeout(resolve, 10));
     }
   }
+
+  private daemonDataDir(): string {
+    return path.join(app.getPath("userData"), "daemon");
+  }
+
+  private daemonCookiePath(): string {
+    return path.join(this.daemonDataDir(), "api.cookie");
+  }
 }
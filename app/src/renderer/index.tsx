@@ -44,6 +44,13 @@ const ipcListenersFunc: IpcListenersFunc = (dispatch) => ({
   "challenge.peer-accepted": (_event, { peerId }) => {
     dispatch({ type: "match-ready", payload: { peerId } });
   },
+
+  "challenge.failed": (_event, { peerId, direction, reason }) => {
+    dispatch({
+      type: "challenge-failed",
+      payload: { peerId, direction, reason },
+    });
+  },
 });
 
 const App = () => {
@@ -85,6 +85,14 @@ export type AppMessage =
       type: "peer-declined-challenge";
       payload: { peerId: string };
     }
+  | {
+      type: "challenge-failed";
+      payload: {
+        peerId: string;
+        direction: "inbound" | "outbound";
+        reason: string;
+      };
+    }
   | {
       type: "match-ready";
       payload: {
@@ -147,6 +155,18 @@ export const update = (state: AppState, message: AppMessage): AppState => {
         return nextState;
       }
 
+      case "challenge-failed": {
+        const nextState = { ...state };
+
+        if (message.payload.direction === "outbound") {
+          delete nextState.sentChallenges[message.payload.peerId];
+        } else {
+          delete nextState.receivedChallenges[message.payload.peerId];
+        }
+
+        return nextState;
+      }
+
       case "received-challenge":
         return {
           ...state,
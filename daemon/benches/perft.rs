@@ -0,0 +1,38 @@
+//! Move generation speed, reported in perft nodes per second.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ipchess::chess::{perft, Position, STARTING_FEN};
+
+const POSITIONS: [(&str, &str, u32); 4] = [
+    ("start", STARTING_FEN, 4),
+    (
+        "kiwipete",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        3,
+    ),
+    ("position_3", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 4),
+    (
+        "position_5",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        3,
+    ),
+];
+
+fn bench_perft(c: &mut Criterion) {
+    let mut group = c.benchmark_group("perft");
+    group.sample_size(10);
+
+    for &(name, fen, depth) in POSITIONS.iter() {
+        let position = Position::from_fen(fen).unwrap();
+
+        group.throughput(Throughput::Elements(perft(&position, depth)));
+        group.bench_with_input(BenchmarkId::new(name, depth), &position, |b, position| {
+            b.iter(|| perft(position, depth))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_perft);
+criterion_main!(benches);
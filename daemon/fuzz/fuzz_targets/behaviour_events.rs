@@ -0,0 +1,364 @@
+//! Sequences of events from a handful of peers and local requests fed to the behaviour, checking
+//! that peers cannot make it panic whatever order they send challenge and match messages in.
+
+#![no_main]
+
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use arbitrary::Arbitrary;
+use ipchess::{
+    chess::{Move, Square},
+    game::{MatchId, Rematch, TimeControl, Variant},
+    protocol::{sign_move_record, Ipchess, IpchessConfig, IpchessHandlerEventOut, ManualClock},
+};
+use libfuzzer_sys::fuzz_target;
+use libp2p::{
+    core::connection::ConnectionId,
+    identity::{ed25519, Keypair},
+    swarm::{AddressRecord, NetworkBehaviour, PollParameters},
+    Multiaddr, PeerId,
+};
+
+const PEERS: u8 = 4;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    /// Message received from a peer.
+    Received(u8, Message),
+    Connected(u8),
+    Disconnected(u8),
+    DialFailure(u8),
+    ChallengePeer {
+        peer: u8,
+        variant: u8,
+        time_control: TimeControlChoice,
+    },
+    Accept(u8),
+    Cancel(u8),
+    Decline(u8),
+    /// Plays the legal move at the second index in the match at the first one, indices being
+    /// taken modulo the number of matches and legal moves.
+    MakeMove(u8, u8),
+    ClaimDraw(u8),
+    SendChat(u8, String),
+    OfferRematch(u8, bool),
+    AcceptRematch(u8),
+    AdvanceClock(u16),
+    Poll,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Message {
+    Challenge {
+        commitment: Vec<u8>,
+        variant: String,
+        time_control: TimeControlChoice,
+        /// Index of the match offered a rematch of, and whether colors are swapped.
+        rematch: Option<(u8, bool)>,
+    },
+    Reveal(Vec<u8>),
+    Accept(Vec<u8>),
+    Cancel,
+    Decline(Vec<String>),
+    /// Move in the match at the given index, at its current ply unless one is given, either the
+    /// legal move at an index or one between two arbitrary squares.
+    Move {
+        game: u8,
+        ply: Option<u32>,
+        mv: MoveChoice,
+        clock_ms: Option<u32>,
+    },
+    DrawClaim {
+        game: u8,
+        ply: Option<u32>,
+    },
+    /// Move record sent to this node as a store peer.
+    StoreRecord(RecordChoice),
+    /// Fetch of the record of a move in the match at the given index, made by a peer.
+    FetchRecord {
+        game: u8,
+        ply: Option<u32>,
+        signer: u8,
+    },
+    /// Move record sent back by a store peer.
+    FoundRecord(RecordChoice),
+    Chat {
+        game: u8,
+        text: String,
+    },
+    SubstreamFailed,
+}
+
+#[derive(Arbitrary, Debug)]
+enum RecordChoice {
+    Raw(Vec<u8>),
+    /// Record of a move in the match at the given index signed by a peer, at the match's
+    /// current ply unless one is given.
+    Signed {
+        signer: u8,
+        game: u8,
+        ply: Option<u32>,
+        mv: MoveChoice,
+        played_at_secs: u32,
+    },
+}
+
+#[derive(Arbitrary, Debug)]
+enum MoveChoice {
+    Legal(u8),
+    Raw(u8, u8),
+}
+
+#[derive(Arbitrary, Debug)]
+enum TimeControlChoice {
+    /// Initial time and increment in seconds.
+    Live(u16, u8),
+    /// Days per move and the store peer, if any.
+    Correspondence(u32, Option<u8>),
+}
+
+impl From<TimeControlChoice> for TimeControl {
+    fn from(choice: TimeControlChoice) -> Self {
+        match choice {
+            TimeControlChoice::Live(initial, increment) => TimeControl::Live {
+                initial: Duration::from_secs(initial.into()),
+                increment: Duration::from_secs(increment.into()),
+            },
+            TimeControlChoice::Correspondence(days_per_move, store_peer) => {
+                TimeControl::Correspondence {
+                    days_per_move,
+                    store_peer: store_peer.map(peer_id),
+                }
+            }
+        }
+    }
+}
+
+struct Parameters(PeerId);
+
+impl PollParameters for Parameters {
+    type SupportedProtocolsIter = std::iter::Empty<Vec<u8>>;
+    type ListenedAddressesIter = std::iter::Empty<Multiaddr>;
+    type ExternalAddressesIter = std::iter::Empty<AddressRecord>;
+
+    fn supported_protocols(&self) -> Self::SupportedProtocolsIter {
+        std::iter::empty()
+    }
+
+    fn listened_addresses(&self) -> Self::ListenedAddressesIter {
+        std::iter::empty()
+    }
+
+    fn external_addresses(&self) -> Self::ExternalAddressesIter {
+        std::iter::empty()
+    }
+
+    fn local_peer_id(&self) -> &PeerId {
+        &self.0
+    }
+}
+
+fn keypair(index: u8) -> Keypair {
+    let secret = ed25519::SecretKey::from_bytes([index % PEERS + 1; 32]).unwrap();
+    Keypair::Ed25519(secret.into())
+}
+
+fn peer_id(index: u8) -> PeerId {
+    PeerId::from(keypair(index).public())
+}
+
+/// Id and ply of the match at `index` modulo the number of matches, an unknown match if there
+/// are none.
+fn match_at(ipchess: &Ipchess, index: u8) -> (MatchId, u32) {
+    let mut games: Vec<_> = ipchess.matches().collect();
+    // match ids are random, order them for the same input to pick the same match
+    games.sort_by_key(|game| game.id().to_string());
+
+    match games.get(index as usize % games.len().max(1)) {
+        Some(game) => (game.id(), game.ply()),
+        None => (MatchId::from_bytes(&[0; 32]).unwrap(), 0),
+    }
+}
+
+fn legal_move(ipchess: &Ipchess, match_id: MatchId, index: u8) -> Option<Move> {
+    let moves = ipchess
+        .matches()
+        .find(|game| game.id() == match_id)?
+        .legal_moves();
+
+    moves.get(index as usize % moves.len().max(1)).copied()
+}
+
+fn chosen_move(ipchess: &Ipchess, match_id: MatchId, choice: MoveChoice) -> Option<Move> {
+    match choice {
+        MoveChoice::Legal(index) => legal_move(ipchess, match_id, index),
+        MoveChoice::Raw(from, to) => Some(Move {
+            from: Square::from_index(from % 64).unwrap(),
+            to: Square::from_index(to % 64).unwrap(),
+            promotion: None,
+        }),
+    }
+}
+
+fn record(ipchess: &Ipchess, choice: RecordChoice) -> Option<Vec<u8>> {
+    match choice {
+        RecordChoice::Raw(value) => Some(value),
+        RecordChoice::Signed {
+            signer,
+            game,
+            ply,
+            mv,
+            played_at_secs,
+        } => {
+            let (match_id, current_ply) = match_at(ipchess, game);
+            let mv = chosen_move(ipchess, match_id, mv)?;
+            let played_at = UNIX_EPOCH + Duration::from_secs(played_at_secs.into());
+
+            sign_move_record(
+                &keypair(signer),
+                match_id,
+                ply.unwrap_or(current_ply),
+                mv,
+                played_at,
+            )
+            .ok()
+        }
+    }
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let clock = ManualClock::new();
+    let mut ipchess = Ipchess::with_clock(IpchessConfig::default(), Arc::new(clock.clone()));
+    let mut params = Parameters(peer_id(PEERS));
+    let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+
+    for op in ops {
+        match op {
+            Op::Received(peer, message) => {
+                let event = match message {
+                    Message::Challenge {
+                        commitment,
+                        variant,
+                        time_control,
+                        rematch,
+                    } => IpchessHandlerEventOut::ChallengeReceived {
+                        commitment,
+                        variant,
+                        time_control: time_control.into(),
+                        rematch: rematch.map(|(game, swap_colors)| Rematch {
+                            previous_match: match_at(&ipchess, game).0,
+                            swap_colors,
+                        }),
+                    },
+                    Message::Reveal(preimage) => {
+                        IpchessHandlerEventOut::ChallengeRevealReceived { preimage }
+                    }
+                    Message::Accept(random) => IpchessHandlerEventOut::ChallengeAccepted { random },
+                    Message::Cancel => IpchessHandlerEventOut::ChallengeCanceled,
+                    Message::Decline(supported_variants) => {
+                        IpchessHandlerEventOut::ChallengeDeclined { supported_variants }
+                    }
+                    Message::Move {
+                        game,
+                        ply,
+                        mv,
+                        clock_ms,
+                    } => {
+                        let (match_id, current_ply) = match_at(&ipchess, game);
+
+                        match chosen_move(&ipchess, match_id, mv) {
+                            Some(mv) => IpchessHandlerEventOut::MatchMoveReceived {
+                                match_id,
+                                ply: ply.unwrap_or(current_ply),
+                                mv,
+                                clock: clock_ms.map(|ms| Duration::from_millis(ms.into())),
+                            },
+                            None => continue,
+                        }
+                    }
+                    Message::DrawClaim { game, ply } => {
+                        let (match_id, current_ply) = match_at(&ipchess, game);
+                        IpchessHandlerEventOut::MatchDrawClaimReceived {
+                            match_id,
+                            ply: ply.unwrap_or(current_ply),
+                        }
+                    }
+                    Message::StoreRecord(choice) => match record(&ipchess, choice) {
+                        Some(record) => IpchessHandlerEventOut::MoveRecordStoreReceived { record },
+                        None => continue,
+                    },
+                    Message::FetchRecord { game, ply, signer } => {
+                        let (match_id, current_ply) = match_at(&ipchess, game);
+                        IpchessHandlerEventOut::MoveRecordFetchReceived {
+                            match_id,
+                            ply: ply.unwrap_or(current_ply),
+                            signer: peer_id(signer),
+                        }
+                    }
+                    Message::FoundRecord(choice) => match record(&ipchess, choice) {
+                        Some(record) => IpchessHandlerEventOut::MoveRecordFoundReceived { record },
+                        None => continue,
+                    },
+                    Message::Chat { game, text } => IpchessHandlerEventOut::MatchChatReceived {
+                        match_id: match_at(&ipchess, game).0,
+                        text,
+                    },
+                    Message::SubstreamFailed => IpchessHandlerEventOut::OutboundSubstreamFailed,
+                };
+
+                ipchess.inject_event(peer_id(peer), ConnectionId::new(0), event);
+            }
+
+            Op::Connected(peer) => ipchess.inject_connected(&peer_id(peer)),
+            Op::Disconnected(peer) => ipchess.inject_disconnected(&peer_id(peer)),
+            Op::DialFailure(peer) => ipchess.inject_dial_failure(&peer_id(peer)),
+
+            Op::ChallengePeer {
+                peer,
+                variant,
+                time_control,
+            } => {
+                let variant = Variant::ALL[variant as usize % Variant::ALL.len()];
+                let _ = ipchess.challenge_peer(peer_id(peer), variant, time_control.into());
+            }
+            Op::Accept(peer) => {
+                let _ = ipchess.accept_peer_challenge(peer_id(peer));
+            }
+            Op::Cancel(peer) => {
+                let _ = ipchess.cancel_challenge(peer_id(peer));
+            }
+            Op::Decline(peer) => {
+                let _ = ipchess.decline_peer_challenge(peer_id(peer));
+            }
+
+            Op::MakeMove(game, index) => {
+                let (match_id, _) = match_at(&ipchess, game);
+                if let Some(mv) = legal_move(&ipchess, match_id, index) {
+                    let _ = ipchess.make_move(match_id, mv);
+                }
+            }
+            Op::ClaimDraw(game) => {
+                let _ = ipchess.claim_draw(match_at(&ipchess, game).0);
+            }
+            Op::SendChat(game, text) => {
+                let _ = ipchess.send_chat_message(match_at(&ipchess, game).0, text);
+            }
+            Op::OfferRematch(game, swap_colors) => {
+                let _ = ipchess.offer_rematch(match_at(&ipchess, game).0, swap_colors);
+            }
+            Op::AcceptRematch(game) => {
+                let _ = ipchess.accept_rematch(match_at(&ipchess, game).0);
+            }
+
+            Op::AdvanceClock(secs) => clock.advance(Duration::from_secs(secs.into())),
+
+            Op::Poll => while let Poll::Ready(_) = ipchess.poll(&mut cx, &mut params) {},
+        }
+
+        let _ = ipchess.challenges();
+    }
+});
@@ -0,0 +1,16 @@
+//! FEN parsing, checking that parsed positions survive a round trip and generate moves.
+
+#![no_main]
+
+use ipchess::chess::Position;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|fen: &str| {
+    if let Ok(position) = Position::from_fen(fen) {
+        assert_eq!(Position::from_fen(&position.fen()), Ok(position.clone()));
+
+        for mv in position.legal_moves() {
+            position.clone().play(mv).unwrap();
+        }
+    }
+});
@@ -0,0 +1,10 @@
+//! Frames read from peers' substreams, which may be arbitrary bytes.
+
+#![no_main]
+
+use ipchess::protocol::{decode_frame, MAX_FRAME_SIZE_LIMIT};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_frame(data, MAX_FRAME_SIZE_LIMIT);
+});
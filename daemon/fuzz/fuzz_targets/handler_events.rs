@@ -0,0 +1,167 @@
+//! Sequences of behaviour events and outbound substream failures fed to a connection handler.
+
+#![no_main]
+
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use arbitrary::Arbitrary;
+use ipchess::{
+    chess::{Move, Square},
+    game::{MatchId, TimeControl, Variant},
+    protocol::{IpchessHandler, IpchessHandlerEventIn, ManualClock, MAX_FRAME_SIZE_LIMIT},
+};
+use libfuzzer_sys::fuzz_target;
+use libp2p::{
+    identity::{ed25519, Keypair},
+    swarm::{ProtocolsHandler, ProtocolsHandlerEvent, ProtocolsHandlerUpgrErr},
+    PeerId,
+};
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Challenge {
+        commitment: Vec<u8>,
+        variant: u8,
+        /// Days per move of a correspondence match, a live match of that many seconds without
+        /// them.
+        days_per_move: Option<u32>,
+        with_store_peer: bool,
+        initial_secs: u32,
+    },
+    Accept(Vec<u8>),
+    Reveal(Vec<u8>),
+    Cancel,
+    Decline,
+    Move {
+        ply: u32,
+        from: u8,
+        to: u8,
+        clock_ms: Option<u64>,
+    },
+    DrawClaim(u32),
+    StoreRecord(Vec<u8>),
+    FetchRecord(u32),
+    FoundRecord(Vec<u8>),
+    Chat(String),
+    Poison,
+    Poll,
+    /// Fails the outbound substream request at this index, modulo the number of pending ones.
+    UpgradeTimeout(u8),
+    AdvanceClock(u16),
+}
+
+fn match_id() -> MatchId {
+    MatchId::from_bytes(&[1; 32]).unwrap()
+}
+
+fn peer_id() -> PeerId {
+    let secret = ed25519::SecretKey::from_bytes([1; 32]).unwrap();
+    PeerId::from(Keypair::Ed25519(secret.into()).public())
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let clock = ManualClock::new();
+    let mut handler = IpchessHandler::new(
+        Duration::from_secs(30),
+        MAX_FRAME_SIZE_LIMIT,
+        Arc::new(clock.clone()),
+    );
+    let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+    let mut requested_substreams = vec![];
+
+    for op in ops {
+        match op {
+            Op::Challenge {
+                commitment,
+                variant,
+                days_per_move,
+                with_store_peer,
+                initial_secs,
+            } => handler.inject_event(IpchessHandlerEventIn::Challenge {
+                commitment,
+                variant: Variant::ALL[variant as usize % Variant::ALL.len()],
+                time_control: match days_per_move {
+                    Some(days_per_move) => TimeControl::Correspondence {
+                        days_per_move,
+                        store_peer: Some(peer_id()).filter(|_| with_store_peer),
+                    },
+                    None => TimeControl::Live {
+                        initial: Duration::from_secs(initial_secs.into()),
+                        increment: Duration::from_secs(0),
+                    },
+                },
+                rematch: None,
+            }),
+            Op::Accept(random) => {
+                handler.inject_event(IpchessHandlerEventIn::ChallengeAccept { random })
+            }
+            Op::Reveal(preimage) => {
+                handler.inject_event(IpchessHandlerEventIn::ChallengeReveal { preimage })
+            }
+            Op::Cancel => handler.inject_event(IpchessHandlerEventIn::ChallengeCanceled),
+            Op::Decline => handler.inject_event(IpchessHandlerEventIn::ChallengeDeclined {
+                supported_variants: vec![],
+            }),
+            Op::Move {
+                ply,
+                from,
+                to,
+                clock_ms,
+            } => handler.inject_event(IpchessHandlerEventIn::MatchMove {
+                match_id: match_id(),
+                ply,
+                mv: Move {
+                    from: Square::from_index(from % 64).unwrap(),
+                    to: Square::from_index(to % 64).unwrap(),
+                    promotion: None,
+                },
+                clock: clock_ms.map(Duration::from_millis),
+            }),
+            Op::DrawClaim(ply) => handler.inject_event(IpchessHandlerEventIn::MatchDrawClaim {
+                match_id: match_id(),
+                ply,
+            }),
+            Op::StoreRecord(record) => {
+                handler.inject_event(IpchessHandlerEventIn::MoveRecordStore { record })
+            }
+            Op::FetchRecord(ply) => handler.inject_event(IpchessHandlerEventIn::MoveRecordFetch {
+                match_id: match_id(),
+                ply,
+                signer: peer_id(),
+            }),
+            Op::FoundRecord(record) => {
+                handler.inject_event(IpchessHandlerEventIn::MoveRecordFound { record })
+            }
+            Op::Chat(text) => handler.inject_event(IpchessHandlerEventIn::MatchChat {
+                match_id: match_id(),
+                text,
+            }),
+            Op::Poison => handler.inject_event(IpchessHandlerEventIn::ChallengePoisoned),
+
+            Op::Poll => match handler.poll(&mut cx) {
+                Poll::Ready(ProtocolsHandlerEvent::OutboundSubstreamRequest { protocol }) => {
+                    requested_substreams.push(protocol.into_upgrade().1);
+                }
+                // the connection would be closed
+                Poll::Ready(ProtocolsHandlerEvent::Close(_)) => return,
+                _ => {}
+            },
+
+            Op::UpgradeTimeout(index) => {
+                if !requested_substreams.is_empty() {
+                    let info =
+                        requested_substreams.remove(index as usize % requested_substreams.len());
+                    handler.inject_dial_upgrade_error(info, ProtocolsHandlerUpgrErr::Timeout);
+                }
+            }
+
+            Op::AdvanceClock(secs) => clock.advance(Duration::from_secs(secs.into())),
+        }
+
+        let _ = handler.connection_keep_alive();
+    }
+});
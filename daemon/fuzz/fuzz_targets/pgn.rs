@@ -0,0 +1,10 @@
+//! PGN parsing of arbitrary text.
+
+#![no_main]
+
+use ipchess::chess::Pgn;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|pgn: &str| {
+    let _ = Pgn::parse(pgn);
+});
@@ -0,0 +1,78 @@
+//! Move application from a FEN position, mixing legal moves with arbitrary ones which must be
+//! rejected without altering the position, and checking that unmaking a move restores it.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use ipchess::chess::{Move, Position, Role, Square};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+enum Choice {
+    /// Plays the legal move at this index, modulo the number of legal moves.
+    Legal(u8),
+    /// Tries a move between two squares, promoting to the given role index if any.
+    Raw {
+        from: u8,
+        to: u8,
+        promotion: Option<u8>,
+    },
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    fen: String,
+    choices: Vec<Choice>,
+}
+
+fuzz_target!(|input: Input| {
+    let mut position = Position::from_fen(&input.fen).unwrap_or_else(|_| Position::startpos());
+
+    for choice in input.choices {
+        let legal_moves = position.legal_moves();
+
+        match choice {
+            Choice::Legal(index) => {
+                if legal_moves.is_empty() {
+                    assert!(position.outcome().is_some());
+                    return;
+                }
+
+                let mv = legal_moves[index as usize % legal_moves.len()];
+
+                let mut unmade = position.clone();
+                let undo = unmade.make_move(mv);
+                unmade.unmake_move(mv, undo);
+                assert_eq!(unmade, position);
+
+                position.play(mv).unwrap();
+            }
+
+            Choice::Raw {
+                from,
+                to,
+                promotion,
+            } => {
+                let mv = match (Square::from_index(from % 64), Square::from_index(to % 64)) {
+                    (Some(from), Some(to)) => Move {
+                        from,
+                        to,
+                        promotion: promotion.map(|role| Role::PROMOTIONS[role as usize % 4]),
+                    },
+                    _ => unreachable!("squares are taken modulo 64"),
+                };
+
+                let before = position.clone();
+                match position.play(mv) {
+                    Ok(()) => assert!(legal_moves.contains(&mv)),
+                    Err(_) => {
+                        assert!(!legal_moves.contains(&mv));
+                        assert_eq!(position, before);
+                    }
+                }
+            }
+        }
+
+        assert_eq!(Position::from_fen(&position.fen()), Ok(position.clone()));
+    }
+});
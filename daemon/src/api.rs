@@ -1,208 +1,544 @@
+#[cfg(feature = "websocket")]
+use std::net::SocketAddr;
 use std::{
-    net::SocketAddr,
-    str::FromStr,
+    any::Any,
+    collections::VecDeque,
+    fmt,
+    path::PathBuf,
     sync::{Arc, RwLock},
     task::Poll,
+    time::Duration,
 };
 
-use futures::FutureExt;
+#[cfg(feature = "websocket")]
+use jsonrpsee::server::ServerBuilder;
 use jsonrpsee::{
-    http_client::v2::params::OwnedRpcParams,
-    ws_server::{RpcModule, WsServerBuilder},
+    core::Error,
+    server::{RpcModule, SubscriptionSink},
+    types::{error::CallError, ErrorObject},
 };
-use serde::Serialize;
 use tokio::sync::{mpsc, oneshot};
 
-use crate::utils::SerializablePeerId;
+#[cfg(feature = "websocket")]
+use self::auth::AuthLayer;
+pub use self::{
+    auth::{ServerAuth, COOKIE_FILE_NAME},
+    client::{Client, ClientError, EventStream},
+    types::*,
+};
+use crate::{
+    game::{MatchError, MatchId, TimeControl, Variant},
+    protocol::ChallengeError,
+    utils::{SerializableMatchId, SerializablePeerId},
+};
 
-#[derive(Serialize)]
-pub struct NodeIdResponse(pub SerializablePeerId);
+mod auth;
+mod client;
+mod openrpc;
+mod types;
+#[cfg(unix)]
+mod unix;
+
+/// Application specific JSON-RPC error codes returned by the API methods.
+///
+/// Besides these, methods taking a peer id or match id return the standard `-32602`
+/// (invalid params) code when the given parameters cannot be parsed.
+pub mod error_code {
+    /// The daemon stopped before answering the request.
+    pub const UNAVAILABLE: i32 = -32000;
+    /// There is no challenge with the given peer (`accept_peer_challenge`, `cancel_challenge`,
+    /// `decline_peer_challenge`), or no rematch offered by the match's opponent
+    /// (`accept_rematch`).
+    pub const NO_SUCH_CHALLENGE: i32 = -32001;
+    /// A challenge to the given peer is already in progress (`challenge_peer`, `offer_rematch`).
+    pub const DUPLICATE_CHALLENGE: i32 = -32002;
+    /// The challenge with the given peer is in a state that does not allow the requested
+    /// operation, e.g. accepting an already accepted challenge (`accept_peer_challenge`,
+    /// `decline_peer_challenge`, `accept_rematch`).
+    pub const INVALID_CHALLENGE_STATE: i32 = -32003;
+    /// There is no match with the given id (`legal_moves`, `make_move`, `claim_draw`,
+    /// `send_chat_message`, `offer_rematch`, `accept_rematch`).
+    pub const NO_SUCH_MATCH: i32 = -32004;
+    /// The match is already over (`make_move`, `claim_draw`).
+    pub const MATCH_FINISHED: i32 = -32005;
+    /// It is the opponent's turn to move (`make_move`, `claim_draw`).
+    pub const NOT_YOUR_TURN: i32 = -32006;
+    /// The move is not legal in the match's current position, or its notation is invalid or
+    /// ambiguous (`make_move`).
+    pub const ILLEGAL_MOVE: i32 = -32007;
+    /// Neither a threefold repetition nor the fifty move rule allows claiming a draw in the
+    /// match's current position (`claim_draw`).
+    pub const NO_CLAIMABLE_DRAW: i32 = -32008;
+    /// The days per move of a correspondence match are zero or above the allowed maximum
+    /// (`challenge_peer`).
+    pub const INVALID_DAYS_PER_MOVE: i32 = -32009;
+    /// The match is not over yet, so no rematch can be offered or accepted (`offer_rematch`,
+    /// `accept_rematch`).
+    pub const MATCH_IN_PROGRESS: i32 = -32010;
+    /// The clock of a live match starts without time or above the allowed maximum, or its
+    /// increment is above the allowed maximum, or it is given along with days per move
+    /// (`challenge_peer`).
+    pub const INVALID_TIME_CONTROL: i32 = -32011;
+    /// The store peer is one of the match's players, or it is given for a live match
+    /// (`challenge_peer`).
+    pub const INVALID_STORE_PEER: i32 = -32012;
+    /// The chat message is blank or longer than the allowed maximum (`send_chat_message`).
+    pub const INVALID_CHAT_MESSAGE: i32 = -32013;
+}
+
+fn call_error(code: i32, message: String) -> Error {
+    CallError::Custom(ErrorObject::owned(code, message, None::<()>)).into()
+}
 
-#[derive(Serialize)]
-pub struct IsConnectedResponse(pub bool);
+fn challenge_call_error(err: ChallengeError) -> Error {
+    let code = match err {
+        ChallengeError::NoSuchChallenge { .. } => error_code::NO_SUCH_CHALLENGE,
+        ChallengeError::DuplicateChallenge { .. } => error_code::DUPLICATE_CHALLENGE,
+        ChallengeError::InvalidState { .. } => error_code::INVALID_CHALLENGE_STATE,
+        ChallengeError::InvalidDaysPerMove { .. } => error_code::INVALID_DAYS_PER_MOVE,
+        ChallengeError::InvalidTimeControl => error_code::INVALID_TIME_CONTROL,
+        ChallengeError::InvalidStorePeer => error_code::INVALID_STORE_PEER,
+        ChallengeError::NoSuchMatch { .. } => error_code::NO_SUCH_MATCH,
+        ChallengeError::MatchInProgress { .. } => error_code::MATCH_IN_PROGRESS,
+    };
+
+    call_error(code, err.to_string())
+}
 
-pub struct ChallengePeerResponse;
+fn match_call_error(err: MatchError) -> Error {
+    let code = match err {
+        MatchError::NoSuchMatch { .. } => error_code::NO_SUCH_MATCH,
+        MatchError::Finished => error_code::MATCH_FINISHED,
+        MatchError::NotYourTurn => error_code::NOT_YOUR_TURN,
+        MatchError::IllegalMove => error_code::ILLEGAL_MOVE,
+        MatchError::NoClaimableDraw => error_code::NO_CLAIMABLE_DRAW,
+        MatchError::InvalidChatMessage => error_code::INVALID_CHAT_MESSAGE,
+    };
+
+    call_error(code, err.to_string())
+}
 
-impl Serialize for ChallengePeerResponse {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        serializer.serialize_str("ok")
-    }
+/// Waits for the response to a request sent to the daemon's main loop.
+async fn recv_response<T>(res_rx: oneshot::Receiver<T>) -> Result<T, Error> {
+    res_rx.await.map_err(|_| {
+        call_error(
+            error_code::UNAVAILABLE,
+            "daemon stopped before answering the request".into(),
+        )
+    })
 }
 
-#[derive(Serialize)]
-pub struct AcceptPeerChallengeResponse;
+/// Waits for the response to a challenge operation sent to the daemon's main loop.
+async fn recv_challenge_response<T>(
+    res_rx: oneshot::Receiver<Result<T, ChallengeError>>,
+) -> Result<T, Error> {
+    recv_response(res_rx).await?.map_err(challenge_call_error)
+}
 
-#[derive(Serialize)]
-pub struct CancelPeerChallengeResponse;
+/// Waits for the response to a match operation sent to the daemon's main loop.
+async fn recv_match_response<T>(
+    res_rx: oneshot::Receiver<Result<T, MatchError>>,
+) -> Result<T, Error> {
+    recv_response(res_rx).await?.map_err(match_call_error)
+}
 
-#[derive(Serialize)]
-pub struct DeclinePeerChallengeResponse;
+pub type ChallengeResponseSender<T> = oneshot::Sender<Result<T, ChallengeError>>;
+pub type MatchResponseSender<T> = oneshot::Sender<Result<T, MatchError>>;
 
 pub enum ServerEvent {
     NodeIdRequest(oneshot::Sender<NodeIdResponse>),
     IsConnectedRequest(oneshot::Sender<IsConnectedResponse>),
-    ChallengePeerRequest(libp2p::PeerId, oneshot::Sender<ChallengePeerResponse>),
-    AcceptPeerChallengeRequest(libp2p::PeerId, oneshot::Sender<AcceptPeerChallengeResponse>),
-    CancelPeerChallengeRequest(libp2p::PeerId, oneshot::Sender<CancelPeerChallengeResponse>),
+    ListChallengesRequest(oneshot::Sender<ListChallengesResponse>),
+    ChallengePeerRequest(
+        libp2p::PeerId,
+        Variant,
+        TimeControl,
+        ChallengeResponseSender<ChallengePeerResponse>,
+    ),
+    AcceptPeerChallengeRequest(
+        libp2p::PeerId,
+        ChallengeResponseSender<AcceptPeerChallengeResponse>,
+    ),
+    CancelPeerChallengeRequest(
+        libp2p::PeerId,
+        ChallengeResponseSender<CancelPeerChallengeResponse>,
+    ),
     DeclinePeerChallengeRequest(
         libp2p::PeerId,
-        oneshot::Sender<DeclinePeerChallengeResponse>,
+        ChallengeResponseSender<DeclinePeerChallengeResponse>,
+    ),
+    ListMatchesRequest(oneshot::Sender<ListMatchesResponse>),
+    LegalMovesRequest(MatchId, MatchResponseSender<LegalMovesResponse>),
+    MakeMoveRequest(MatchId, String, MatchResponseSender<MakeMoveResponse>),
+    ClaimDrawRequest(MatchId, MatchResponseSender<ClaimDrawResponse>),
+    SendChatMessageRequest(
+        MatchId,
+        String,
+        MatchResponseSender<SendChatMessageResponse>,
     ),
+    OfferRematchRequest(MatchId, bool, ChallengeResponseSender<OfferRematchResponse>),
+    AcceptRematchRequest(MatchId, ChallengeResponseSender<AcceptRematchResponse>),
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "snake_case", tag = "event_type", content = "data")]
-pub enum ServerEventNotification {
-    PeerChallenge { peer_id: SerializablePeerId },
-    ChallengeCanceled { peer_id: SerializablePeerId },
-    ChallengeDeclined { peer_id: SerializablePeerId },
-    ChallengeAccepted { peer_id: SerializablePeerId },
-}
+/// Maximum number of past event notifications kept for replaying to late subscribers.
+const EVENTS_HISTORY_CAPACITY: usize = 256;
 
-pub struct Server {
-    event_rx: mpsc::UnboundedReceiver<ServerEvent>,
-    local_addr: SocketAddr,
-
-    events_subscribers: Arc<RwLock<Vec<jsonrpsee::ws_server::SubscriptionSink>>>,
+/// Subscribers of the events stream along with the most recent notifications sent to them.
+struct EventsState {
+    subscribers: Vec<SubscriptionSink>,
+    history: VecDeque<SequencedEventNotification>,
+    last_sequence: u64,
 }
 
-impl Server {
-    pub async fn new(port: u16) -> Result<Self, Box<dyn std::error::Error>> {
-        let (event_tx, event_rx) = mpsc::unbounded_channel();
-
-        let mut server = WsServerBuilder::default()
-            .build(format!("127.0.0.1:{}", port))
-            .await?;
-
-        let mut module = RpcModule::new(event_tx);
-
-        module.register_async_method("node_id", move |_, event_tx| {
-            let (res_tx, res_rx) = oneshot::channel();
-            let _ = event_tx.send(ServerEvent::NodeIdRequest(res_tx));
-
-            async move { Ok(res_rx.await.unwrap()) }.boxed()
-        })?;
+impl EventsState {
+    fn new() -> Self {
+        Self {
+            subscribers: vec![],
+            history: VecDeque::with_capacity(EVENTS_HISTORY_CAPACITY),
+            last_sequence: 0,
+        }
+    }
 
-        module.register_async_method("is_connected", move |_, event_tx| {
-            let (res_tx, res_rx) = oneshot::channel();
-            let _ = event_tx.send(ServerEvent::IsConnectedRequest(res_tx));
+    /// Adds a subscriber, first replaying to it all buffered notifications with a sequence number
+    /// greater than `since`. Notifications older than the buffer's capacity cannot be replayed,
+    /// subscribers can detect this by checking for gaps in the sequence numbers.
+    fn subscribe(&mut self, mut sink: SubscriptionSink, since: Option<u64>) {
+        if let Some(since) = since {
+            for event in self.history.iter().filter(|event| event.sequence > since) {
+                if !matches!(sink.send(event), Ok(true)) {
+                    return;
+                }
+            }
+        }
 
-            async move { Ok(res_rx.await.unwrap()) }.boxed()
-        })?;
+        self.subscribers.push(sink);
+    }
 
-        module.register_async_method("challenge_peer", move |params, event_tx| {
-            let params = OwnedRpcParams::from(params);
+    fn publish(&mut self, notification: ServerEventNotification) {
+        self.last_sequence += 1;
 
-            async move {
-                let (res_tx, res_rx) = oneshot::channel();
+        let event = SequencedEventNotification {
+            sequence: self.last_sequence,
+            notification,
+        };
 
-                let params_str: String = params.borrowed().one()?;
-                let peer_id = libp2p::PeerId::from_str(params_str.as_str())
-                    .map_err(|_| jsonrpsee_types::error::CallError::InvalidParams)?;
+        for i in (0..self.subscribers.len()).rev() {
+            let mut sub = self.subscribers.swap_remove(i);
 
-                let _ = event_tx.send(ServerEvent::ChallengePeerRequest(peer_id, res_tx));
-                Ok(res_rx.await.unwrap())
+            if let Ok(true) = sub.send(&event) {
+                self.subscribers.push(sub);
             }
-            .boxed()
-        })?;
-
-        module.register_async_method("accept_peer_challenge", move |params, event_tx| {
-            let params = OwnedRpcParams::from(params);
+        }
 
-            async move {
-                let (res_tx, res_rx) = oneshot::channel();
+        if self.history.len() == EVENTS_HISTORY_CAPACITY {
+            self.history.pop_front();
+        }
+        self.history.push_back(event);
+    }
+}
 
-                let params_str: String = params.borrowed().one()?;
-                let peer_id = libp2p::PeerId::from_str(params_str.as_str())
-                    .map_err(|_| jsonrpsee_types::error::CallError::InvalidParams)?;
+fn rpc_module(
+    event_tx: mpsc::UnboundedSender<ServerEvent>,
+    events: Arc<RwLock<EventsState>>,
+) -> Result<RpcModule<mpsc::UnboundedSender<ServerEvent>>, Error> {
+    let mut module = RpcModule::new(event_tx);
+
+    module.register_async_method("node_id", |_, event_tx| async move {
+        let (res_tx, res_rx) = oneshot::channel();
+        let _ = event_tx.send(ServerEvent::NodeIdRequest(res_tx));
+
+        recv_response(res_rx).await
+    })?;
+
+    module.register_async_method("is_connected", |_, event_tx| async move {
+        let (res_tx, res_rx) = oneshot::channel();
+        let _ = event_tx.send(ServerEvent::IsConnectedRequest(res_tx));
+
+        recv_response(res_rx).await
+    })?;
+
+    module.register_async_method("list_challenges", |_, event_tx| async move {
+        let (res_tx, res_rx) = oneshot::channel();
+        let _ = event_tx.send(ServerEvent::ListChallengesRequest(res_tx));
+
+        recv_response(res_rx).await
+    })?;
+
+    module.register_async_method("challenge_peer", |params, event_tx| async move {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        // the variant is optional, standard chess is played without it, and so is the time
+        // control, a ten minute live match is played without days per move or clock
+        let ChallengePeerParams {
+            peer_id: SerializablePeerId(peer_id),
+            variant,
+            days_per_move,
+            time_control,
+            store_peer,
+        } = params.parse()?;
+        let variant = variant.map_or_else(Variant::default, |variant| variant.0);
+        let store_peer = store_peer.map(|store_peer| store_peer.0);
+        if store_peer.is_some() && days_per_move.is_none() {
+            return Err(call_error(
+                error_code::INVALID_STORE_PEER,
+                "Only the moves of correspondence matches are relayed by a store peer".into(),
+            ));
+        }
+        let time_control = match (days_per_move, time_control) {
+            (Some(days_per_move), None) => TimeControl::Correspondence {
+                days_per_move,
+                store_peer,
+            },
+            (None, Some(clock)) => TimeControl::Live {
+                initial: Duration::from_secs(clock.initial_secs.into()),
+                increment: Duration::from_secs(clock.increment_secs.into()),
+            },
+            (None, None) => TimeControl::default(),
+            (Some(_), Some(_)) => {
+                return Err(call_error(
+                    error_code::INVALID_TIME_CONTROL,
+                    "A match is either live or by correspondence, not both".into(),
+                ))
+            }
+        };
+
+        let _ = event_tx.send(ServerEvent::ChallengePeerRequest(
+            peer_id,
+            variant,
+            time_control,
+            res_tx,
+        ));
+        recv_challenge_response(res_rx).await
+    })?;
+
+    module.register_async_method("accept_peer_challenge", |params, event_tx| async move {
+        let (res_tx, res_rx) = oneshot::channel();
+        let PeerIdParams {
+            peer_id: SerializablePeerId(peer_id),
+        } = params.parse()?;
+
+        let _ = event_tx.send(ServerEvent::AcceptPeerChallengeRequest(peer_id, res_tx));
+        recv_challenge_response(res_rx).await
+    })?;
+
+    module.register_async_method("cancel_challenge", |params, event_tx| async move {
+        let (res_tx, res_rx) = oneshot::channel();
+        let PeerIdParams {
+            peer_id: SerializablePeerId(peer_id),
+        } = params.parse()?;
+
+        let _ = event_tx.send(ServerEvent::CancelPeerChallengeRequest(peer_id, res_tx));
+        recv_challenge_response(res_rx).await
+    })?;
+
+    module.register_async_method("decline_peer_challenge", |params, event_tx| async move {
+        let (res_tx, res_rx) = oneshot::channel();
+        let PeerIdParams {
+            peer_id: SerializablePeerId(peer_id),
+        } = params.parse()?;
+
+        let _ = event_tx.send(ServerEvent::DeclinePeerChallengeRequest(peer_id, res_tx));
+        recv_challenge_response(res_rx).await
+    })?;
+
+    module.register_async_method("list_matches", |_, event_tx| async move {
+        let (res_tx, res_rx) = oneshot::channel();
+        let _ = event_tx.send(ServerEvent::ListMatchesRequest(res_tx));
+
+        recv_response(res_rx).await
+    })?;
+
+    module.register_async_method("legal_moves", |params, event_tx| async move {
+        let (res_tx, res_rx) = oneshot::channel();
+        let MatchIdParams {
+            match_id: SerializableMatchId(match_id),
+        } = params.parse()?;
+
+        let _ = event_tx.send(ServerEvent::LegalMovesRequest(match_id, res_tx));
+        recv_match_response(res_rx).await
+    })?;
+
+    module.register_async_method("make_move", |params, event_tx| async move {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        let MakeMoveParams {
+            match_id: SerializableMatchId(match_id),
+            mv,
+        } = params.parse()?;
+
+        let _ = event_tx.send(ServerEvent::MakeMoveRequest(match_id, mv, res_tx));
+        recv_match_response(res_rx).await
+    })?;
+
+    module.register_async_method("claim_draw", |params, event_tx| async move {
+        let (res_tx, res_rx) = oneshot::channel();
+        let MatchIdParams {
+            match_id: SerializableMatchId(match_id),
+        } = params.parse()?;
+
+        let _ = event_tx.send(ServerEvent::ClaimDrawRequest(match_id, res_tx));
+        recv_match_response(res_rx).await
+    })?;
+
+    module.register_async_method("send_chat_message", |params, event_tx| async move {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        let SendChatMessageParams {
+            match_id: SerializableMatchId(match_id),
+            text,
+        } = params.parse()?;
+
+        let _ = event_tx.send(ServerEvent::SendChatMessageRequest(match_id, text, res_tx));
+        recv_match_response(res_rx).await
+    })?;
+
+    module.register_async_method("offer_rematch", |params, event_tx| async move {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        // colors are drawn anew unless swapping them is asked for
+        let OfferRematchParams {
+            match_id: SerializableMatchId(match_id),
+            swap_colors,
+        } = params.parse()?;
+
+        let _ = event_tx.send(ServerEvent::OfferRematchRequest(
+            match_id,
+            swap_colors,
+            res_tx,
+        ));
+        recv_challenge_response(res_rx).await
+    })?;
+
+    module.register_async_method("accept_rematch", |params, event_tx| async move {
+        let (res_tx, res_rx) = oneshot::channel();
+        let MatchIdParams {
+            match_id: SerializableMatchId(match_id),
+        } = params.parse()?;
+
+        let _ = event_tx.send(ServerEvent::AcceptRematchRequest(match_id, res_tx));
+        recv_challenge_response(res_rx).await
+    })?;
+
+    // subscribe_events takes an optional sequence number of the last event seen by the client,
+    // events after it which are still buffered are replayed before any new events.
+    module.register_subscription(
+        "subscribe_events",
+        "subscribe_events",
+        "unsubscribe_events",
+        move |params, mut sink, _| {
+            // the params may be left out altogether
+            let since = match params.parse::<Option<SubscribeEventsParams>>() {
+                Ok(params) => params.and_then(|params| params.since),
+                Err(err) => {
+                    let _ = sink.reject(err);
+                    return Ok(());
+                }
+            };
+
+            sink.accept()?;
+
+            events
+                .write()
+                .expect("failed acquiring events lock")
+                .subscribe(sink, since);
+
+            Ok(())
+        },
+    )?;
+
+    let document = openrpc::document();
+    module.register_method("rpc.discover", move |_, _| Ok(document.clone()))?;
+
+    Ok(module)
+}
 
-                let _ = event_tx.send(ServerEvent::AcceptPeerChallengeRequest(peer_id, res_tx));
+/// Transport the API server listens on.
+pub enum ServerTransport {
+    /// WebSocket connections on the given localhost port, restricted by `auth`.
+    #[cfg(feature = "websocket")]
+    WebSocket { port: u16, auth: ServerAuth },
+    /// Newline delimited JSON-RPC over a Unix domain socket created at the given path, only
+    /// accessible by the current user. Clients must send the token of `auth` as their first line.
+    #[cfg(unix)]
+    Unix { path: PathBuf, auth: ServerAuth },
+}
 
-                Ok(res_rx.await.unwrap())
-            }
-            .boxed()
-        })?;
+/// Address the API server is listening at.
+#[derive(Clone, Debug)]
+pub enum ServerAddr {
+    #[cfg(feature = "websocket")]
+    WebSocket(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
 
-        module.register_async_method("cancel_challenge", move |params, event_tx| {
-            let params = OwnedRpcParams::from(params);
+impl fmt::Display for ServerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "websocket")]
+            ServerAddr::WebSocket(addr) => write!(f, "ws://{}", addr),
+            #[cfg(unix)]
+            ServerAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
 
-            async move {
-                let (res_tx, res_rx) = oneshot::channel();
+pub struct Server {
+    event_rx: mpsc::UnboundedReceiver<ServerEvent>,
+    local_addr: ServerAddr,
+    /// The server stops once its handle is dropped.
+    _handle: Box<dyn Any + Send + Sync>,
 
-                let params_str: String = params.borrowed().one()?;
-                let peer_id = libp2p::PeerId::from_str(params_str.as_str())
-                    .map_err(|_| jsonrpsee_types::error::CallError::InvalidParams)?;
+    events: Arc<RwLock<EventsState>>,
+}
 
-                let _ = event_tx.send(ServerEvent::CancelPeerChallengeRequest(peer_id, res_tx));
-                Ok(res_rx.await.unwrap())
-            }
-            .boxed()
-        })?;
+impl Server {
+    pub async fn new(transport: ServerTransport) -> Result<Self, Box<dyn std::error::Error>> {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let events = Arc::new(RwLock::new(EventsState::new()));
 
-        module.register_async_method("decline_peer_challenge", move |params, event_tx| {
-            let params = OwnedRpcParams::from(params);
+        let module = rpc_module(event_tx, events.clone())?;
 
-            async move {
-                let (res_tx, res_rx) = oneshot::channel();
+        let (local_addr, handle): (_, Box<dyn Any + Send + Sync>) = match transport {
+            #[cfg(feature = "websocket")]
+            ServerTransport::WebSocket { port, auth } => {
+                let server = ServerBuilder::default()
+                    .ws_only()
+                    .set_middleware(tower::ServiceBuilder::new().layer(AuthLayer::new(auth)))
+                    .build(format!("127.0.0.1:{}", port))
+                    .await?;
 
-                let params_str: String = params.borrowed().one()?;
-                let peer_id = libp2p::PeerId::from_str(params_str.as_str())
-                    .map_err(|_| jsonrpsee_types::error::CallError::InvalidParams)?;
+                let local_addr = server.local_addr()?;
+                let handle = server.start(module)?;
 
-                let _ = event_tx.send(ServerEvent::DeclinePeerChallengeRequest(peer_id, res_tx));
-                Ok(res_rx.await.unwrap())
+                (ServerAddr::WebSocket(local_addr), Box::new(handle))
             }
-            .boxed()
-        })?;
-
-        let events_subscribers = Arc::new(RwLock::new(vec![]));
-        let events_subscribers_register = events_subscribers.clone();
-
-        module.register_subscription(
-            "subscribe_events",
-            "unsubscribe_events",
-            move |_, sink, _| {
-                events_subscribers_register
-                    .write()
-                    .map_err(|err| jsonrpsee::ws_server::Error::Custom(err.to_string()))?
-                    .push(sink);
-
-                Ok(())
-            },
-        )?;
 
-        server.register_module(module)?;
+            #[cfg(unix)]
+            ServerTransport::Unix { path, auth } => {
+                let handle = unix::UnixServerHandle::bind(&path, auth, module)?;
 
-        let local_addr = server.local_addr()?;
-        tokio::spawn(server.start());
+                (ServerAddr::Unix(path), Box::new(handle))
+            }
+        };
 
         Ok(Server {
             event_rx,
             local_addr,
-            events_subscribers,
+            _handle: handle,
+            events,
         })
     }
 
     pub fn notify_event(&mut self, notification: ServerEventNotification) {
-        let mut events_subscribers = self
-            .events_subscribers
+        self.events
             .write()
-            .expect("failed acquiring subscribers lock");
-
-        for i in (0..events_subscribers.len()).rev() {
-            let mut sub = events_subscribers.swap_remove(i);
-
-            if sub.send(&notification).is_ok() {
-                events_subscribers.push(sub);
-            }
-        }
+            .expect("failed acquiring events lock")
+            .publish(notification);
     }
 
-    pub fn local_addr(&self) -> SocketAddr {
-        self.local_addr
+    pub fn local_addr(&self) -> &ServerAddr {
+        &self.local_addr
     }
 }
 
@@ -216,3 +552,116 @@ impl futures::Stream for Server {
         self.event_rx.poll_recv(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use jsonrpsee::{rpc_params, types::Params};
+    use libp2p::PeerId;
+    use tokio::sync::mpsc;
+
+    use super::{
+        rpc_module, ChallengePeerParams, EventsState, SequencedEventNotification, Server,
+        ServerAuth, ServerEventNotification, ServerTransport, TimeControlInfo,
+    };
+    use crate::{api::Client, utils::SerializablePeerId};
+
+    #[test]
+    fn params_are_taken_by_position_or_name() {
+        let peer_id = PeerId::random();
+
+        let positional = format!(r#"["{}"]"#, peer_id);
+        let params: ChallengePeerParams = Params::new(Some(&positional)).parse().unwrap();
+        assert_eq!(params.peer_id.0, peer_id);
+        assert!(params.variant.is_none());
+        assert!(params.days_per_move.is_none());
+        assert!(params.time_control.is_none());
+
+        let named = format!(r#"{{"peer_id":"{}","days_per_move":3}}"#, peer_id);
+        let params: ChallengePeerParams = Params::new(Some(&named)).parse().unwrap();
+        assert_eq!(params.peer_id.0, peer_id);
+        assert_eq!(params.days_per_move, Some(3));
+
+        let named = format!(
+            r#"{{"peer_id":"{}","time_control":{{"initial_secs":180,"increment_secs":2}}}}"#,
+            peer_id
+        );
+        let params: ChallengePeerParams = Params::new(Some(&named)).parse().unwrap();
+        assert_eq!(
+            params.time_control,
+            Some(TimeControlInfo {
+                initial_secs: 180,
+                increment_secs: 2,
+            })
+        );
+
+        assert!(Params::new(Some("[]"))
+            .parse::<ChallengePeerParams>()
+            .is_err());
+        assert!(Params::new(None).parse::<ChallengePeerParams>().is_err());
+    }
+
+    #[tokio::test]
+    async fn subscribe_events_takes_no_params() {
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let events = Arc::new(RwLock::new(EventsState::new()));
+        let module = rpc_module(event_tx, events.clone()).unwrap();
+
+        let mut subscription = module
+            .subscribe("subscribe_events", rpc_params![])
+            .await
+            .unwrap();
+
+        events
+            .write()
+            .unwrap()
+            .publish(ServerEventNotification::ChallengeCanceled {
+                peer_id: SerializablePeerId(PeerId::random()),
+            });
+
+        let (event, _) = subscription
+            .next::<SequencedEventNotification>()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.sequence, 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_socket_is_private_and_requires_token() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let dir = std::env::temp_dir().join(format!("ipchess-api-test-{}", rand::random::<u64>()));
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("api.sock");
+
+        let auth = ServerAuth::generate(vec![]);
+        let token = auth.token().to_string();
+        let server = Server::new(ServerTransport::Unix {
+            path: path.clone(),
+            auth,
+        })
+        .await
+        .unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // only the socket is left behind by binding
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let client = Client::connect_unix_with_token(&path, &token)
+            .await
+            .unwrap();
+        assert!(client.subscribe_events(None).await.is_ok());
+
+        let client = Client::connect_unix_with_token(&path, "invalid")
+            .await
+            .unwrap();
+        assert!(client.subscribe_events(None).await.is_err());
+
+        drop(server);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+#[cfg(feature = "websocket")]
+use std::{
+    error::Error as StdError,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use std::{io, path::Path};
+
+#[cfg(feature = "websocket")]
+use futures::{
+    future::{self, BoxFuture},
+    FutureExt, TryFutureExt,
+};
+#[cfg(feature = "websocket")]
+use hyper::{header, Body, Request, Response, StatusCode};
+use rand::Rng;
+#[cfg(feature = "websocket")]
+use tower::{Layer, Service};
+
+use crate::utils::write_private_file;
+
+/// Name of the file in the daemon's data directory holding the API token.
+pub const COOKIE_FILE_NAME: &str = "api.cookie";
+
+/// Access control for connections to the API server.
+///
+/// Clients must present a per-launch bearer token, either in the `Authorization` header or in
+/// the `token` query parameter for clients which cannot set headers. Requests carrying an
+/// `Origin` header, i.e. made by web pages, are only accepted from allowlisted origins.
+pub struct ServerAuth {
+    token: String,
+    #[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+    allowed_origins: Vec<String>,
+}
+
+impl ServerAuth {
+    /// Creates access control with a random token, valid only while this daemon process runs.
+    pub fn generate(allowed_origins: Vec<String>) -> Self {
+        let token_bytes = rand::thread_rng().gen::<[u8; 32]>();
+        let token = token_bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+        Self {
+            token,
+            allowed_origins,
+        }
+    }
+
+    /// Token clients must present.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Writes the token to a cookie file only readable by the current user.
+    pub fn write_cookie_file(&self, path: &Path) -> io::Result<()> {
+        write_private_file(path, self.token.as_bytes())
+    }
+
+    #[cfg(feature = "websocket")]
+    fn check(&self, req: &Request<Body>) -> Result<(), StatusCode> {
+        if let Some(origin) = req.headers().get(header::ORIGIN) {
+            let allowed = origin
+                .to_str()
+                .map(|origin| self.allowed_origins.iter().any(|o| o == origin))
+                .unwrap_or(false);
+
+            if !allowed {
+                return Err(StatusCode::FORBIDDEN);
+            }
+        }
+
+        let header_token = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        let query_token = req.uri().query().and_then(|query| {
+            query
+                .split('&')
+                .find_map(|param| param.strip_prefix("token="))
+        });
+
+        match header_token.or(query_token) {
+            Some(token) if self.check_token(token) => Ok(()),
+            _ => Err(StatusCode::UNAUTHORIZED),
+        }
+    }
+
+    pub(super) fn check_token(&self, token: &str) -> bool {
+        constant_time_eq(token.as_bytes(), self.token.as_bytes())
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Tower layer rejecting API requests which do not pass the server's access control.
+#[cfg(feature = "websocket")]
+#[derive(Clone)]
+pub(super) struct AuthLayer {
+    auth: Arc<ServerAuth>,
+}
+
+#[cfg(feature = "websocket")]
+impl AuthLayer {
+    pub(super) fn new(auth: ServerAuth) -> Self {
+        Self {
+            auth: Arc::new(auth),
+        }
+    }
+}
+
+#[cfg(feature = "websocket")]
+impl<S> Layer<S> for AuthLayer {
+    type Service = AuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthService {
+            inner,
+            auth: self.auth.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "websocket")]
+#[derive(Clone)]
+pub(super) struct AuthService<S> {
+    inner: S,
+    auth: Arc<ServerAuth>,
+}
+
+#[cfg(feature = "websocket")]
+impl<S> Service<Request<Body>> for AuthService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn StdError + Send + Sync>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if let Err(status) = self.auth.check(&req) {
+            log::debug!("Rejecting API request with status {}", status);
+
+            let res = Response::builder()
+                .status(status)
+                .body(Body::empty())
+                .expect("failed building API rejection response");
+
+            return future::ok(res).boxed();
+        }
+
+        self.inner.call(req).map_err(Into::into).boxed()
+    }
+}
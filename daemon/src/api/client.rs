@@ -0,0 +1,308 @@
+use std::{fs, io, path::Path};
+
+use futures::{stream::BoxStream, StreamExt};
+use jsonrpsee::{
+    core::{
+        client::{ClientT, SubscriptionClientT},
+        Error,
+    },
+    rpc_params,
+    types::error::CallError,
+    ws_client::{HeaderMap, HeaderValue, WsClientBuilder},
+};
+use libp2p::PeerId;
+use thiserror::Error;
+
+use super::{
+    AcceptPeerChallengeResponse, AcceptRematchResponse, CancelPeerChallengeResponse, ChallengeInfo,
+    ChallengePeerResponse, ClaimDrawResponse, DeclinePeerChallengeResponse, IsConnectedResponse,
+    LegalMovesResponse, ListChallengesResponse, ListMatchesResponse, MakeMoveResponse, MatchInfo,
+    MatchResultInfo, MoveInfo, NodeIdResponse, OfferRematchResponse, SendChatMessageResponse,
+    SequencedEventNotification, TimeControlInfo,
+};
+use crate::{
+    game::{MatchId, TimeControl, Variant},
+    utils::{SerializableMatchId, SerializablePeerId, SerializableVariant},
+};
+
+#[cfg(unix)]
+mod unix;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("failed reading API cookie file: {0}")]
+    Cookie(io::Error),
+    #[error("API token is not a valid header value")]
+    InvalidToken,
+    #[error("failed connecting to the API: {0}")]
+    Connect(io::Error),
+    /// The server answered a request with an error, see [`super::error_code`] for the
+    /// application specific codes.
+    #[error("API error {code}: {message}")]
+    Call { code: i32, message: String },
+    #[error(transparent)]
+    Rpc(Error),
+}
+
+impl From<Error> for ClientError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Call(CallError::Custom(err)) => ClientError::Call {
+                code: err.code(),
+                message: err.message().to_string(),
+            },
+            err => ClientError::Rpc(err),
+        }
+    }
+}
+
+/// Stream of event notifications sent by the daemon, ending when the connection is closed.
+pub type EventStream = BoxStream<'static, Result<SequencedEventNotification, ClientError>>;
+
+/// Typed client for the daemon's JSON-RPC API.
+pub struct Client {
+    inner: jsonrpsee::core::client::Client,
+}
+
+impl Client {
+    /// Connects to the WebSocket API on a localhost port, authenticating with the token in the
+    /// daemon's cookie file.
+    pub async fn connect(port: u16, cookie_path: &Path) -> Result<Self, ClientError> {
+        let token = fs::read_to_string(cookie_path).map_err(ClientError::Cookie)?;
+
+        Self::connect_with_token(port, token.trim()).await
+    }
+
+    /// Connects to the WebSocket API on a localhost port, authenticating with the given token.
+    pub async fn connect_with_token(port: u16, token: &str) -> Result<Self, ClientError> {
+        let authorization = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| ClientError::InvalidToken)?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Authorization", authorization);
+
+        let inner = WsClientBuilder::default()
+            .set_headers(headers)
+            .build(format!("ws://127.0.0.1:{}", port))
+            .await?;
+
+        Ok(Self { inner })
+    }
+
+    /// Connects to the API served on a Unix domain socket, authenticating with the token in the
+    /// daemon's cookie file.
+    #[cfg(unix)]
+    pub async fn connect_unix(path: &Path, cookie_path: &Path) -> Result<Self, ClientError> {
+        let token = fs::read_to_string(cookie_path).map_err(ClientError::Cookie)?;
+
+        Self::connect_unix_with_token(path, token.trim()).await
+    }
+
+    /// Connects to the API served on a Unix domain socket, authenticating with the given token.
+    #[cfg(unix)]
+    pub async fn connect_unix_with_token(path: &Path, token: &str) -> Result<Self, ClientError> {
+        let inner = unix::connect(path, token)
+            .await
+            .map_err(ClientError::Connect)?;
+
+        Ok(Self { inner })
+    }
+
+    pub async fn node_id(&self) -> Result<PeerId, ClientError> {
+        let NodeIdResponse(SerializablePeerId(peer_id)) =
+            self.inner.request("node_id", rpc_params![]).await?;
+
+        Ok(peer_id)
+    }
+
+    pub async fn is_connected(&self) -> Result<bool, ClientError> {
+        let IsConnectedResponse(connected) =
+            self.inner.request("is_connected", rpc_params![]).await?;
+
+        Ok(connected)
+    }
+
+    pub async fn list_challenges(&self) -> Result<Vec<ChallengeInfo>, ClientError> {
+        let ListChallengesResponse(challenges) =
+            self.inner.request("list_challenges", rpc_params![]).await?;
+
+        Ok(challenges)
+    }
+
+    /// Challenges a peer to a match of `variant` played under `time_control`.
+    pub async fn challenge_peer(
+        &self,
+        peer_id: PeerId,
+        variant: Variant,
+        time_control: TimeControl,
+    ) -> Result<(), ClientError> {
+        let (days_per_move, clock, store_peer) = match time_control {
+            TimeControl::Live { initial, increment } => (
+                None,
+                Some(TimeControlInfo {
+                    initial_secs: initial.as_secs() as u32,
+                    increment_secs: increment.as_secs() as u32,
+                }),
+                None,
+            ),
+            TimeControl::Correspondence {
+                days_per_move,
+                store_peer,
+            } => (
+                Some(days_per_move),
+                None,
+                store_peer.map(SerializablePeerId),
+            ),
+        };
+
+        let ChallengePeerResponse = self
+            .inner
+            .request(
+                "challenge_peer",
+                rpc_params![
+                    SerializablePeerId(peer_id),
+                    SerializableVariant(variant),
+                    days_per_move,
+                    clock,
+                    store_peer
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn accept_peer_challenge(&self, peer_id: PeerId) -> Result<(), ClientError> {
+        let AcceptPeerChallengeResponse = self
+            .inner
+            .request(
+                "accept_peer_challenge",
+                rpc_params![SerializablePeerId(peer_id)],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn cancel_challenge(&self, peer_id: PeerId) -> Result<(), ClientError> {
+        let CancelPeerChallengeResponse = self
+            .inner
+            .request("cancel_challenge", rpc_params![SerializablePeerId(peer_id)])
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn decline_peer_challenge(&self, peer_id: PeerId) -> Result<(), ClientError> {
+        let DeclinePeerChallengeResponse = self
+            .inner
+            .request(
+                "decline_peer_challenge",
+                rpc_params![SerializablePeerId(peer_id)],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_matches(&self) -> Result<Vec<MatchInfo>, ClientError> {
+        let ListMatchesResponse(matches) =
+            self.inner.request("list_matches", rpc_params![]).await?;
+
+        Ok(matches)
+    }
+
+    /// Returns the legal moves of the player to move in a match.
+    pub async fn legal_moves(&self, match_id: MatchId) -> Result<Vec<MoveInfo>, ClientError> {
+        let LegalMovesResponse(moves) = self
+            .inner
+            .request("legal_moves", rpc_params![SerializableMatchId(match_id)])
+            .await?;
+
+        Ok(moves)
+    }
+
+    /// Plays a move given in UCI or Standard Algebraic Notation.
+    pub async fn make_move(&self, match_id: MatchId, notation: &str) -> Result<(), ClientError> {
+        let MakeMoveResponse = self
+            .inner
+            .request(
+                "make_move",
+                rpc_params![SerializableMatchId(match_id), notation],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Claims a draw in a match, returning the match's result.
+    pub async fn claim_draw(&self, match_id: MatchId) -> Result<MatchResultInfo, ClientError> {
+        let ClaimDrawResponse(result) = self
+            .inner
+            .request("claim_draw", rpc_params![SerializableMatchId(match_id)])
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Sends a chat message to the opponent of a match.
+    pub async fn send_chat_message(
+        &self,
+        match_id: MatchId,
+        text: &str,
+    ) -> Result<(), ClientError> {
+        let SendChatMessageResponse = self
+            .inner
+            .request(
+                "send_chat_message",
+                rpc_params![SerializableMatchId(match_id), text],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Offers the opponent of a finished match a rematch, swapping the players' colors if
+    /// `swap_colors` is set instead of drawing them anew.
+    pub async fn offer_rematch(
+        &self,
+        match_id: MatchId,
+        swap_colors: bool,
+    ) -> Result<(), ClientError> {
+        let OfferRematchResponse = self
+            .inner
+            .request(
+                "offer_rematch",
+                rpc_params![SerializableMatchId(match_id), swap_colors],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn accept_rematch(&self, match_id: MatchId) -> Result<(), ClientError> {
+        let AcceptRematchResponse = self
+            .inner
+            .request("accept_rematch", rpc_params![SerializableMatchId(match_id)])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Subscribes to the daemon's event notifications, first replaying the buffered events with
+    /// a sequence number greater than `since`.
+    pub async fn subscribe_events(&self, since: Option<u64>) -> Result<EventStream, ClientError> {
+        let subscription = self
+            .inner
+            .subscribe::<SequencedEventNotification, _>(
+                "subscribe_events",
+                rpc_params![since],
+                "unsubscribe_events",
+            )
+            .await?;
+
+        Ok(subscription
+            .map(|event| event.map_err(ClientError::from))
+            .boxed())
+    }
+}
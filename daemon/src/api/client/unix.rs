@@ -0,0 +1,57 @@
+use std::{io, path::Path};
+
+use jsonrpsee::core::{
+    async_trait,
+    client::{Client, ClientBuilder, ReceivedMessage, TransportReceiverT, TransportSenderT},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        unix::{OwnedReadHalf, OwnedWriteHalf},
+        UnixStream,
+    },
+};
+
+/// Connects to the API served on a Unix domain socket using newline delimited messages,
+/// authenticating with the given token.
+pub(super) async fn connect(path: &Path, token: &str) -> io::Result<Client> {
+    let (reader, mut writer) = UnixStream::connect(path).await?.into_split();
+
+    writer.write_all(token.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+
+    let sender = Sender(writer);
+    let receiver = Receiver(BufReader::new(reader).lines());
+
+    Ok(ClientBuilder::default().build_with_tokio(sender, receiver))
+}
+
+struct Sender(OwnedWriteHalf);
+
+#[async_trait]
+impl TransportSenderT for Sender {
+    type Error = io::Error;
+
+    async fn send(&mut self, msg: String) -> Result<(), Self::Error> {
+        self.0.write_all(msg.as_bytes()).await?;
+        self.0.write_all(b"\n").await
+    }
+
+    async fn close(&mut self) -> Result<(), Self::Error> {
+        self.0.shutdown().await
+    }
+}
+
+struct Receiver(Lines<BufReader<OwnedReadHalf>>);
+
+#[async_trait]
+impl TransportReceiverT for Receiver {
+    type Error = io::Error;
+
+    async fn receive(&mut self) -> Result<ReceivedMessage, Self::Error> {
+        match self.0.next_line().await? {
+            Some(line) => Ok(ReceivedMessage::Text(line)),
+            None => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+}
@@ -0,0 +1,342 @@
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+    JsonSchema,
+};
+use serde_json::{json, Value};
+
+use super::{
+    error_code, AcceptPeerChallengeResponse, AcceptRematchResponse, CancelPeerChallengeResponse,
+    ChallengePeerParams, ChallengePeerResponse, ClaimDrawResponse, DeclinePeerChallengeResponse,
+    IsConnectedResponse, LegalMovesResponse, ListChallengesResponse, ListMatchesResponse,
+    MakeMoveParams, MakeMoveResponse, MatchIdParams, NodeIdResponse, OfferRematchParams,
+    OfferRematchResponse, PeerIdParams, SendChatMessageParams, SendChatMessageResponse,
+    SequencedEventNotification, SubscribeEventsParams,
+};
+
+const OPENRPC_VERSION: &str = "1.2.6";
+
+/// Builds the OpenRPC document describing the API, served by the `rpc.discover` method.
+///
+/// OpenRPC has no notion of subscriptions, `subscribe_events` describes its notifications in
+/// the `x-notification` extension field.
+pub(super) fn document() -> Value {
+    let mut gen = SchemaSettings::draft07()
+        .with(|settings| settings.definitions_path = "#/components/schemas/".to_string())
+        .into_generator();
+
+    let methods = vec![
+        method::<(), NodeIdResponse>(
+            &mut gen,
+            "node_id",
+            "Returns the local peer id.",
+            &[],
+        ),
+        method::<(), IsConnectedResponse>(
+            &mut gen,
+            "is_connected",
+            "Returns whether the node is connected to the network.",
+            &[],
+        ),
+        method::<(), ListChallengesResponse>(
+            &mut gen,
+            "list_challenges",
+            "Returns the challenges in progress.",
+            &[],
+        ),
+        method::<ChallengePeerParams, ChallengePeerResponse>(
+            &mut gen,
+            "challenge_peer",
+            "Challenges a peer to a match, of standard chess unless another variant is given.",
+            &[
+                error_code::UNAVAILABLE,
+                error_code::DUPLICATE_CHALLENGE,
+                error_code::INVALID_DAYS_PER_MOVE,
+                error_code::INVALID_TIME_CONTROL,
+                error_code::INVALID_STORE_PEER,
+            ],
+        ),
+        method::<PeerIdParams, AcceptPeerChallengeResponse>(
+            &mut gen,
+            "accept_peer_challenge",
+            "Accepts a challenge received from a peer.",
+            &[
+                error_code::UNAVAILABLE,
+                error_code::NO_SUCH_CHALLENGE,
+                error_code::INVALID_CHALLENGE_STATE,
+            ],
+        ),
+        method::<PeerIdParams, CancelPeerChallengeResponse>(
+            &mut gen,
+            "cancel_challenge",
+            "Cancels a challenge sent to a peer.",
+            &[error_code::UNAVAILABLE, error_code::NO_SUCH_CHALLENGE],
+        ),
+        method::<PeerIdParams, DeclinePeerChallengeResponse>(
+            &mut gen,
+            "decline_peer_challenge",
+            "Declines a challenge received from a peer.",
+            &[
+                error_code::UNAVAILABLE,
+                error_code::NO_SUCH_CHALLENGE,
+                error_code::INVALID_CHALLENGE_STATE,
+            ],
+        ),
+        method::<(), ListMatchesResponse>(
+            &mut gen,
+            "list_matches",
+            "Returns the matches played by this node, in progress or over.",
+            &[],
+        ),
+        method::<MatchIdParams, LegalMovesResponse>(
+            &mut gen,
+            "legal_moves",
+            "Returns the legal moves of the player to move in a match, none once it is over.",
+            &[error_code::UNAVAILABLE, error_code::NO_SUCH_MATCH],
+        ),
+        method::<MakeMoveParams, MakeMoveResponse>(
+            &mut gen,
+            "make_move",
+            "Plays a move in a match, sending it to the opponent.",
+            &[
+                error_code::UNAVAILABLE,
+                error_code::NO_SUCH_MATCH,
+                error_code::MATCH_FINISHED,
+                error_code::NOT_YOUR_TURN,
+                error_code::ILLEGAL_MOVE,
+            ],
+        ),
+        method::<MatchIdParams, ClaimDrawResponse>(
+            &mut gen,
+            "claim_draw",
+            "Claims a draw by threefold repetition or the fifty move rule in a match.",
+            &[
+                error_code::UNAVAILABLE,
+                error_code::NO_SUCH_MATCH,
+                error_code::MATCH_FINISHED,
+                error_code::NOT_YOUR_TURN,
+                error_code::NO_CLAIMABLE_DRAW,
+            ],
+        ),
+        method::<SendChatMessageParams, SendChatMessageResponse>(
+            &mut gen,
+            "send_chat_message",
+            "Sends a chat message to the opponent of a match, in progress or over.",
+            &[
+                error_code::UNAVAILABLE,
+                error_code::NO_SUCH_MATCH,
+                error_code::INVALID_CHAT_MESSAGE,
+            ],
+        ),
+        method::<OfferRematchParams, OfferRematchResponse>(
+            &mut gen,
+            "offer_rematch",
+            "Offers the opponent of a finished match a rematch of the same variant, negotiated like a new challenge.",
+            &[
+                error_code::UNAVAILABLE,
+                error_code::NO_SUCH_MATCH,
+                error_code::MATCH_IN_PROGRESS,
+                error_code::DUPLICATE_CHALLENGE,
+            ],
+        ),
+        method::<MatchIdParams, AcceptRematchResponse>(
+            &mut gen,
+            "accept_rematch",
+            "Accepts the rematch offered by the opponent of a finished match.",
+            &[
+                error_code::UNAVAILABLE,
+                error_code::NO_SUCH_MATCH,
+                error_code::MATCH_IN_PROGRESS,
+                error_code::NO_SUCH_CHALLENGE,
+                error_code::INVALID_CHALLENGE_STATE,
+            ],
+        ),
+        subscribe_events(&mut gen),
+        json!({
+            "name": "unsubscribe_events",
+            "summary": "Cancels an events subscription.",
+            "params": [{
+                "name": "subscription",
+                "required": true,
+                "schema": { "type": "integer", "minimum": 0 },
+            }],
+            "result": { "name": "unsubscribed", "schema": { "type": "boolean" } },
+        }),
+        json!({
+            "name": "rpc.discover",
+            "summary": "Returns this document.",
+            "params": [],
+            "result": {
+                "name": "document",
+                "schema": {
+                    "$ref": "https://raw.githubusercontent.com/open-rpc/meta-schema/master/schema.json",
+                },
+            },
+        }),
+    ];
+
+    json!({
+        "openrpc": OPENRPC_VERSION,
+        "info": {
+            "title": "ipchess daemon API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "methods": methods,
+        "components": {
+            "schemas": gen.take_definitions(),
+        },
+    })
+}
+
+/// Describes a method taking parameters of type `P`, `()` for none, and returning a `R`.
+fn method<P: JsonSchema, R: JsonSchema>(
+    gen: &mut SchemaGenerator,
+    name: &str,
+    summary: &str,
+    error_codes: &[i32],
+) -> Value {
+    json!({
+        "name": name,
+        "summary": summary,
+        "params": params::<P>(gen),
+        "result": { "name": "result", "schema": gen.subschema_for::<R>() },
+        "errors": error_codes.iter().map(|&code| error(code)).collect::<Vec<_>>(),
+    })
+}
+
+/// Describes the parameters of a method from the struct they are parsed into, one per field in
+/// declaration order, taking their descriptions from the fields' doc comments.
+fn params<T: JsonSchema>(gen: &mut SchemaGenerator) -> Vec<Value> {
+    let object = match T::json_schema(gen).into_object().object {
+        Some(object) => object,
+        // methods without parameters take `()`
+        None => return vec![],
+    };
+
+    let required = object.required;
+    object
+        .properties
+        .into_iter()
+        .map(|(name, schema)| {
+            let mut schema = schema.into_object();
+            let description = schema
+                .metadata
+                .as_mut()
+                .and_then(|metadata| metadata.description.take());
+
+            // fields with a doc comment are wrapped in an `allOf` to describe a referenced schema
+            let schema = match schema.subschemas.as_mut().and_then(|s| s.all_of.as_mut()) {
+                Some(all_of) if all_of.len() == 1 => all_of.remove(0),
+                _ => schema.into(),
+            };
+
+            let mut param = json!({
+                "name": name,
+                "required": required.contains(&name),
+                "schema": schema,
+            });
+            if let Some(description) = description {
+                param["description"] = description.into();
+            }
+
+            param
+        })
+        .collect()
+}
+
+fn subscribe_events(gen: &mut SchemaGenerator) -> Value {
+    let notification: Schema = gen.subschema_for::<SequencedEventNotification>();
+
+    json!({
+        "name": "subscribe_events",
+        "summary": "Subscribes to the node's event notifications.",
+        "description": "Events buffered by the node with a sequence number greater than `since` \
+            are replayed before any new events.",
+        "params": params::<SubscribeEventsParams>(gen),
+        "result": {
+            "name": "subscription",
+            "schema": { "type": "integer", "minimum": 0 },
+        },
+        "x-notification": {
+            "name": "subscribe_events",
+            "schema": notification,
+        },
+    })
+}
+
+fn error(code: i32) -> Value {
+    let message = match code {
+        error_code::UNAVAILABLE => "Daemon stopped before answering the request",
+        error_code::NO_SUCH_CHALLENGE => "No challenge with the given peer",
+        error_code::DUPLICATE_CHALLENGE => "Challenge to the given peer already in progress",
+        error_code::INVALID_CHALLENGE_STATE => "Challenge state does not allow the operation",
+        error_code::NO_SUCH_MATCH => "No match with the given id",
+        error_code::MATCH_FINISHED => "Match is already over",
+        error_code::NOT_YOUR_TURN => "It is the opponent's turn to move",
+        error_code::ILLEGAL_MOVE => "Move is not legal in the match's position",
+        error_code::NO_CLAIMABLE_DRAW => "No draw can be claimed in the match's position",
+        error_code::INVALID_DAYS_PER_MOVE => "Days per move out of the allowed range",
+        error_code::MATCH_IN_PROGRESS => "Match is not over yet",
+        error_code::INVALID_TIME_CONTROL => "Clock out of the allowed range",
+        error_code::INVALID_STORE_PEER => "Store peer is a player or given for a live match",
+        error_code::INVALID_CHAT_MESSAGE => "Chat message is blank or too long",
+        _ => unreachable!("undocumented error code {}", code),
+    };
+
+    json!({ "code": code, "message": message })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeSet,
+        fs,
+        path::PathBuf,
+        sync::{Arc, RwLock},
+    };
+
+    use tokio::sync::mpsc;
+
+    use super::document;
+    use crate::api::{rpc_module, EventsState};
+
+    const UPDATE_ENV_VAR: &str = "UPDATE_OPENRPC";
+
+    fn checked_in_path() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("openrpc.json")
+    }
+
+    #[test]
+    fn document_matches_checked_in_file() {
+        let generated = serde_json::to_string_pretty(&document()).unwrap() + "\n";
+
+        if std::env::var_os(UPDATE_ENV_VAR).is_some() {
+            fs::write(checked_in_path(), generated).unwrap();
+            return;
+        }
+
+        let checked_in = fs::read_to_string(checked_in_path()).unwrap();
+        assert!(
+            checked_in == generated,
+            "openrpc.json is out of date, run the tests with {}=1 to regenerate it",
+            UPDATE_ENV_VAR
+        );
+    }
+
+    #[test]
+    fn document_describes_registered_methods() {
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let events = Arc::new(RwLock::new(EventsState::new()));
+        let module = rpc_module(event_tx, events).unwrap();
+
+        let registered: BTreeSet<String> = module.method_names().map(String::from).collect();
+        let documented: BTreeSet<String> = document()["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|method| method["name"].as_str().unwrap().to_string())
+            .collect();
+
+        assert_eq!(registered, documented);
+    }
+}
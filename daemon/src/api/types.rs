@@ -0,0 +1,400 @@
+//! Request, response and notification types of the API, shared by the server and the client.
+
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{Schema, SchemaObject},
+    JsonSchema,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+    SerializableChallengeDirection, SerializableChallengeState, SerializableColor,
+    SerializableMatchId, SerializablePeerId, SerializableVariant,
+};
+
+/// Parameters of the methods acting on the challenge with a peer: `accept_peer_challenge`,
+/// `cancel_challenge` and `decline_peer_challenge`.
+///
+/// Parameter types are parsed from either positional or named parameters, their fields are
+/// described in the OpenRPC document in declaration order.
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct PeerIdParams {
+    pub peer_id: SerializablePeerId,
+}
+
+/// Parameters of the methods acting on a match: `legal_moves`, `claim_draw` and
+/// `accept_rematch`.
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct MatchIdParams {
+    pub match_id: SerializableMatchId,
+}
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct ChallengePeerParams {
+    pub peer_id: SerializablePeerId,
+    /// Variant to play, standard chess if omitted.
+    #[serde(default)]
+    pub variant: Option<SerializableVariant>,
+    /// Days each player has for a move in a correspondence match, whose moves are also stored in
+    /// the DHT. A live match is played if omitted.
+    #[serde(default)]
+    pub days_per_move: Option<u32>,
+    /// Clock of a live match, ten minutes per player without increment if omitted. Cannot be
+    /// given along with `days_per_move`.
+    #[serde(default)]
+    pub time_control: Option<TimeControlInfo>,
+    /// Peer relaying the moves of a correspondence match instead of the DHT, for a player to
+    /// pick them up while its opponent is offline. Only given along with `days_per_move`.
+    #[serde(default)]
+    pub store_peer: Option<SerializablePeerId>,
+}
+
+/// Clock of a live match.
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+pub struct TimeControlInfo {
+    /// Seconds each player starts the match with.
+    pub initial_secs: u32,
+    /// Seconds given back to a player after each of its moves.
+    pub increment_secs: u32,
+}
+
+/// Time left to each player of a live match.
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+pub struct ClockInfo {
+    pub white_ms: u64,
+    pub black_ms: u64,
+}
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct MakeMoveParams {
+    pub match_id: SerializableMatchId,
+    /// Move in UCI or Standard Algebraic Notation, like `g1f3` or `Nf3`.
+    #[serde(rename = "move")]
+    pub mv: String,
+}
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct SendChatMessageParams {
+    pub match_id: SerializableMatchId,
+    pub text: String,
+}
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct OfferRematchParams {
+    pub match_id: SerializableMatchId,
+    /// Whether each player takes the color its opponent played in the finished match, instead
+    /// of drawing colors anew. False if omitted.
+    #[serde(default)]
+    pub swap_colors: bool,
+}
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct SubscribeEventsParams {
+    /// Sequence number of the last event seen, events buffered with a greater one are replayed
+    /// before any new events.
+    #[serde(default)]
+    pub since: Option<u64>,
+}
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct NodeIdResponse(pub SerializablePeerId);
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct IsConnectedResponse(pub bool);
+
+pub struct ChallengePeerResponse;
+
+impl Serialize for ChallengePeerResponse {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str("ok")
+    }
+}
+
+impl<'de> Deserialize<'de> for ChallengePeerResponse {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        match String::deserialize(deserializer)?.as_str() {
+            "ok" => Ok(ChallengePeerResponse),
+            other => Err(serde::de::Error::invalid_value(
+                serde::de::Unexpected::Str(other),
+                &"\"ok\"",
+            )),
+        }
+    }
+}
+
+impl JsonSchema for ChallengePeerResponse {
+    fn schema_name() -> String {
+        "ChallengePeerResponse".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            const_value: Some("ok".into()),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct AcceptPeerChallengeResponse;
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct CancelPeerChallengeResponse;
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct DeclinePeerChallengeResponse;
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct ChallengeInfo {
+    pub peer_id: SerializablePeerId,
+    pub direction: SerializableChallengeDirection,
+    pub state: SerializableChallengeState,
+    /// Variant the match is played in once the challenge is accepted.
+    pub variant: SerializableVariant,
+    /// Days each player has for a move in the correspondence match, `null` for a live match.
+    pub days_per_move: Option<u32>,
+    /// Clock of the live match, `null` for a correspondence match.
+    pub time_control: Option<TimeControlInfo>,
+    /// Peer relaying the moves of the correspondence match, `null` if they go through the DHT.
+    pub store_peer: Option<SerializablePeerId>,
+    /// Finished match the challenge offers a rematch of, `null` for a new match.
+    pub previous_match_id: Option<SerializableMatchId>,
+    /// Whether the players of the rematch swap their colors in the previous match.
+    pub swap_colors: bool,
+    /// Milliseconds since the challenge entered its current state.
+    pub age_ms: u64,
+    /// Milliseconds until the challenge times out, `null` if it cannot time out in its current state.
+    pub remaining_timeout_ms: Option<u64>,
+}
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct ListChallengesResponse(pub Vec<ChallengeInfo>);
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChallengeFailureReason {
+    /// Challenged peer did not accept or decline the challenge in time.
+    AcceptTimeout,
+    /// Challenger did not reveal the commitment's preimage in time.
+    PreimageTimeout,
+    /// Preimage revealed by the challenger does not match its commitment.
+    PreimageMismatch,
+    /// Challenged peer could not be found in the DHT.
+    PeerNotFound,
+    /// None of the peer's known addresses could be dialed.
+    DialFailure,
+    /// Connection to the peer was established but the protocol substream could not be opened.
+    SubstreamFailure,
+}
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct MoveInfo {
+    /// The move in the long algebraic notation of UCI, like `e2e4` or `e7e8q`.
+    pub uci: String,
+    /// The move in Standard Algebraic Notation, like `e4` or `Nxf7+`.
+    pub san: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchEndReason {
+    /// The player to move is checkmated.
+    Checkmate,
+    /// The player to move has no legal moves but is not in check.
+    Stalemate,
+    /// Neither player can win.
+    InsufficientMaterial,
+    /// The loser ran out of time.
+    Timeout,
+    /// A player ran out of time while the opponent could not win.
+    TimeoutVsInsufficientMaterial,
+    /// Draw claimed after the same position occurred three times.
+    ThreefoldRepetition,
+    /// The same position occurred five times.
+    FivefoldRepetition,
+    /// Draw claimed after fifty moves by each player without captures or pawn moves.
+    FiftyMoveRule,
+    /// Seventy-five moves by each player were played without captures or pawn moves.
+    SeventyFiveMoveRule,
+    /// The winner's king reached the center in King of the Hill.
+    KingOfTheHill,
+    /// The winner gave a third check in Three-check.
+    ThreeChecks,
+    /// The winner exploded the loser's king in Atomic.
+    KingExploded,
+}
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct MatchResultInfo {
+    /// Color of the winner, `null` for a draw.
+    pub winner: Option<SerializableColor>,
+    pub reason: MatchEndReason,
+}
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct MatchInfo {
+    pub match_id: SerializableMatchId,
+    pub peer_id: SerializablePeerId,
+    /// Color played by this node.
+    pub color: SerializableColor,
+    pub variant: SerializableVariant,
+    /// Days each player has for a move in a correspondence match, `null` in a live match.
+    pub days_per_move: Option<u32>,
+    /// Milliseconds left for the player to move in a correspondence match, `null` in a live or
+    /// finished match.
+    pub remaining_move_time_ms: Option<u64>,
+    /// Clock of a live match, `null` in a correspondence match.
+    pub time_control: Option<TimeControlInfo>,
+    /// Peer relaying the moves of a correspondence match, `null` if they go through the DHT.
+    pub store_peer: Option<SerializablePeerId>,
+    /// Time left to each player of a live match, as counted by this node, `null` in a
+    /// correspondence match. Frozen once the match ended.
+    pub clock: Option<ClockInfo>,
+    /// Match this one is a rematch of, `null` if it started from a new challenge.
+    pub previous_match_id: Option<SerializableMatchId>,
+    /// Position the match started from in Forsyth-Edwards Notation, with Shredder-FEN castling
+    /// rights in Chess960.
+    pub initial_fen: String,
+    /// Current position in Forsyth-Edwards Notation, with Shredder-FEN castling rights in
+    /// Chess960.
+    pub fen: String,
+    pub moves: Vec<MoveInfo>,
+    /// Result of the match, `null` while it is in progress.
+    pub result: Option<MatchResultInfo>,
+    /// Draw the player to move may claim with `claim_draw`, `null` if there is none.
+    pub claimable_draw: Option<MatchEndReason>,
+}
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct ListMatchesResponse(pub Vec<MatchInfo>);
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct LegalMovesResponse(pub Vec<MoveInfo>);
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct MakeMoveResponse;
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct ClaimDrawResponse(pub MatchResultInfo);
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct SendChatMessageResponse;
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct OfferRematchResponse;
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+pub struct AcceptRematchResponse;
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "snake_case", tag = "event_type", content = "data")]
+pub enum ServerEventNotification {
+    /// A peer challenged this node.
+    PeerChallenge {
+        peer_id: SerializablePeerId,
+        variant: SerializableVariant,
+        /// Days each player has for a move, `null` for a live match.
+        days_per_move: Option<u32>,
+        /// Clock of the live match, `null` for a correspondence match.
+        time_control: Option<TimeControlInfo>,
+        /// Peer relaying the moves of the correspondence match, `null` if they go through the
+        /// DHT.
+        store_peer: Option<SerializablePeerId>,
+    },
+    /// The opponent of a finished match offered a rematch, to accept with `accept_rematch`.
+    RematchOffered {
+        peer_id: SerializablePeerId,
+        /// The finished match.
+        match_id: SerializableMatchId,
+        /// Whether the players swap their colors in the finished match.
+        swap_colors: bool,
+    },
+    /// The challenger canceled its challenge.
+    ChallengeCanceled { peer_id: SerializablePeerId },
+    /// The challenged peer declined the challenge.
+    ChallengeDeclined {
+        peer_id: SerializablePeerId,
+        /// Variants the peer plays, listed when it declined because it does not play the
+        /// challenge's variant.
+        supported_variants: Option<Vec<SerializableVariant>>,
+    },
+    /// The challenge was accepted by both peers.
+    ChallengeAccepted { peer_id: SerializablePeerId },
+    /// The challenge was dropped because a peer did not respond in time.
+    ChallengeTimedOut {
+        peer_id: SerializablePeerId,
+        direction: SerializableChallengeDirection,
+        reason: ChallengeFailureReason,
+    },
+    /// The challenge was dropped because of a protocol failure.
+    ChallengeFailed {
+        peer_id: SerializablePeerId,
+        direction: SerializableChallengeDirection,
+        reason: ChallengeFailureReason,
+    },
+    /// The challenge was dropped because the peer could not be reached.
+    PeerUnreachable {
+        peer_id: SerializablePeerId,
+        direction: SerializableChallengeDirection,
+        reason: ChallengeFailureReason,
+    },
+    /// A match started after its challenge was accepted by both peers.
+    MatchStarted {
+        match_id: SerializableMatchId,
+        peer_id: SerializablePeerId,
+        /// Color played by this node.
+        color: SerializableColor,
+        /// Match the new one is a rematch of, `null` if it started from a new challenge.
+        previous_match_id: Option<SerializableMatchId>,
+    },
+    /// A move was played in a match, by either peer.
+    MovePlayed {
+        match_id: SerializableMatchId,
+        /// Number of half moves played before this one.
+        ply: u32,
+        #[serde(rename = "move")]
+        mv: MoveInfo,
+        /// Position after the move in Forsyth-Edwards Notation.
+        fen: String,
+        /// Draw the player to move may now claim, `null` if there is none.
+        claimable_draw: Option<MatchEndReason>,
+        /// Time left to each player after the move in a live match, `null` in a correspondence
+        /// match.
+        clock: Option<ClockInfo>,
+    },
+    /// A match ended, by checkmate, a draw or a player running out of time.
+    MatchEnded {
+        match_id: SerializableMatchId,
+        result: MatchResultInfo,
+    },
+    /// A chat message was sent in a match, by either player.
+    ChatMessage {
+        match_id: SerializableMatchId,
+        /// Color played by the sender.
+        color: SerializableColor,
+        text: String,
+    },
+    /// The opponent sent a move, draw claim or chat message which is not valid in the match and
+    /// was ignored.
+    InvalidMatchMessage {
+        match_id: SerializableMatchId,
+        peer_id: SerializablePeerId,
+    },
+}
+
+/// Event notification tagged with its position in the stream of events sent by the server.
+#[derive(Deserialize, JsonSchema, Serialize)]
+#[schemars(rename = "EventNotification")]
+pub struct SequencedEventNotification {
+    /// Monotonically increasing sequence number, starting at 1.
+    pub sequence: u64,
+    #[serde(flatten)]
+    pub notification: ServerEventNotification,
+}
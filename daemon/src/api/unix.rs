@@ -0,0 +1,172 @@
+use std::{
+    fs, io,
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use futures::{stream::SelectAll, StreamExt};
+use jsonrpsee::server::RpcModule;
+use rand::Rng;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    task::JoinHandle,
+};
+
+use super::{ServerAuth, ServerEvent};
+
+type Module = RpcModule<tokio::sync::mpsc::UnboundedSender<ServerEvent>>;
+
+const PARSE_ERROR_RESPONSE: &str =
+    r#"{"jsonrpc":"2.0","error":{"code":-32700,"message":"Parse error"},"id":null}"#;
+
+/// Serves the API over a Unix domain socket until dropped.
+///
+/// Access is controlled by the socket file permissions, which only allow the current user to
+/// connect, and by the server's token, which clients must send as their first line. Messages are
+/// newline delimited JSON-RPC requests, responses and notifications.
+pub(super) struct UnixServerHandle {
+    path: PathBuf,
+    task: JoinHandle<()>,
+}
+
+impl UnixServerHandle {
+    pub(super) fn bind(path: &Path, auth: ServerAuth, module: Module) -> io::Result<Self> {
+        // a socket left behind by a previous run would make binding fail
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                ))
+            }
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            Err(_) => {}
+        }
+
+        let listener = bind_private(path)?;
+        let auth = Arc::new(auth);
+
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(serve_connection(stream, auth.clone(), module.clone()));
+                    }
+
+                    Err(err) => {
+                        log::warn!("Failed accepting API connection: {}", err);
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            task,
+        })
+    }
+}
+
+impl Drop for UnixServerHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+
+        if let Err(err) = fs::remove_file(&self.path) {
+            log::warn!("Failed removing API socket file: {}", err);
+        }
+    }
+}
+
+/// Binds a socket at `path` which only the current user can connect to.
+///
+/// The socket is bound inside a new directory only accessible by the current user and moved to
+/// `path` once its permissions are restricted, so it is never reachable with the permissions
+/// given by the process umask.
+fn bind_private(path: &Path) -> io::Result<UnixListener> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let bind_dir = parent.join(format!(
+        ".ipchess-api-{:016x}",
+        rand::thread_rng().gen::<u64>()
+    ));
+    fs::DirBuilder::new().mode(0o700).create(&bind_dir)?;
+
+    let bind_path = bind_dir.join("api.sock");
+    let listener = UnixListener::bind(&bind_path).and_then(|listener| {
+        fs::set_permissions(&bind_path, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&bind_path, path)?;
+        Ok(listener)
+    });
+
+    if let Err(err) = fs::remove_dir_all(&bind_dir) {
+        log::warn!("Failed removing API socket bind directory: {}", err);
+    }
+
+    listener
+}
+
+async fn serve_connection(stream: UnixStream, auth: Arc<ServerAuth>, module: Module) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    match lines.next_line().await {
+        Ok(Some(token)) if auth.check_token(token.trim()) => {}
+        Ok(_) => {
+            log::debug!("Rejecting API connection with an invalid token");
+            return;
+        }
+        Err(err) => {
+            log::debug!("Failed reading API connection token: {}", err);
+            return;
+        }
+    }
+
+    // subscription notifications of this connection, dropped along with it
+    let mut notifications = SelectAll::new();
+
+    loop {
+        let message = tokio::select! {
+            line = lines.next_line() => {
+                let line = match line {
+                    Ok(Some(line)) => line,
+                    Ok(None) => break,
+                    Err(err) => {
+                        log::debug!("Failed reading API connection: {}", err);
+                        break;
+                    }
+                };
+
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                match module.raw_json_request(&line).await {
+                    Ok((response, rx)) => {
+                        notifications.push(rx);
+                        response.result
+                    }
+
+                    Err(_) => PARSE_ERROR_RESPONSE.to_string(),
+                }
+            }
+
+            Some(notification) = notifications.next() => notification,
+        };
+
+        let write = async {
+            writer.write_all(message.as_bytes()).await?;
+            writer.write_all(b"\n").await
+        };
+
+        if let Err(err) = write.await {
+            log::debug!("Failed writing API connection: {}", err);
+            break;
+        }
+    }
+}
@@ -1,19 +1,35 @@
 use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::task::Poll;
+use std::time::{Duration, Instant};
 
+use futures::FutureExt;
 use libp2p::identify::{Identify, IdentifyConfig, IdentifyEvent, IdentifyInfo};
-use libp2p::kad::{self, KademliaConfig};
-use libp2p::kad::{store::MemoryStore, Kademlia, KademliaEvent};
+use libp2p::identity::Keypair;
+use libp2p::kad::{self, record::Record, KademliaConfig, QueryId, QueryResult, Quorum};
+use libp2p::kad::{store::MemoryStore, GetRecordError, GetRecordOk, Kademlia, KademliaEvent};
 use libp2p::swarm::protocols_handler::DummyProtocolsHandler;
 use libp2p::swarm::{
     IntoProtocolsHandler, NetworkBehaviour, NetworkBehaviourAction, NetworkBehaviourEventProcess,
     ProtocolsHandler,
 };
 
-use libp2p::{NetworkBehaviour, PeerId};
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, NetworkBehaviour, PeerId};
 
-use crate::protocol::{Ipchess, IpchessEvent};
+use crate::chess::Move;
+use crate::game::{
+    ClockTimes, DrawReason, Match, MatchError, MatchId, TimeControl, Variant, MAX_DAYS_PER_MOVE,
+};
+use crate::protocol::{
+    move_record_key, sign_match_transcript, sign_move_record, transcript_record_key,
+    verify_move_record, ChallengeDirection, ChallengeError, ChallengeState, ChallengeSummary,
+    Clock, Ipchess, IpchessConfig, IpchessEvent, MatchTranscript, SystemClock, MOVE_POLL_INTERVAL,
+    PEER_LOOKUP_BASE_BACKOFF, PEER_LOOKUP_MAX_ATTEMPTS,
+};
+use crate::store::{MatchStore, StoreError};
 
 const BOOTSTRAP_PEER_ADDRS: [&str; 5] = [
     "/dnsaddr/bootstrap.libp2p.io/p2p/QmbLHAnMoJPWSCR5Zhtx6BHJX9KiKNN6tpvbUcqanj75Nb",
@@ -23,6 +39,18 @@ const BOOTSTRAP_PEER_ADDRS: [&str; 5] = [
     "/dnsaddr/bootstrap.libp2p.io/p2p/QmQCU2EcMqAqQPR2i9bChDtGNJchTbq5TbXJJ16u19uLTa",
 ];
 
+/// Time a DHT query runs for before failing.
+const DHT_QUERY_TIMEOUT: Duration = Duration::from_secs(60);
+/// Longest a challenged peer's lookup can take: every query timing out, with the backoffs between
+/// them.
+const PEER_LOOKUP_TIMEOUT: Duration = Duration::from_secs(
+    PEER_LOOKUP_MAX_ATTEMPTS as u64 * DHT_QUERY_TIMEOUT.as_secs()
+        + PEER_LOOKUP_BASE_BACKOFF.as_secs() * ((1 << (PEER_LOOKUP_MAX_ATTEMPTS - 1)) - 1),
+);
+
+/// Seconds in a day, the unit of correspondence move deadlines.
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
 pub enum PeerStoreEvent {}
 
 pub struct PeerStore {
@@ -110,9 +138,42 @@ impl NetworkBehaviour for PeerStore {
     }
 }
 
+/// DHT lookup for the addresses of a challenged peer.
+struct PeerLookup {
+    /// Variant the peer is challenged to once found.
+    variant: Variant,
+    /// Time control of the match the peer is challenged to.
+    time_control: TimeControl,
+    /// Number of queries started so far.
+    attempts: u32,
+    /// Instant the first query was started at.
+    started_at: Instant,
+    /// Currently running query, if any.
+    query_id: Option<QueryId>,
+    /// Timer for the next query attempt, set while waiting to retry.
+    retry_delay: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+/// Periodic lookup for the opponent's next move in a correspondence match, in the DHT or at the
+/// match's store peer.
+struct MovePoll {
+    opponent: PeerId,
+    store_peer: Option<PeerId>,
+    /// Ply of the awaited move.
+    ply: u32,
+    /// Currently running query, if any.
+    query_id: Option<QueryId>,
+    /// Timer for the next query.
+    next_poll: Pin<Box<tokio::time::Sleep>>,
+}
+
 #[derive(Debug)]
 pub enum BehaviourEvent {
     Ipchess(IpchessEvent),
+    /// Challenged peer could not be found in the DHT after all lookup attempts.
+    ChallengePeerNotFound {
+        peer_id: PeerId,
+    },
 }
 
 #[derive(NetworkBehaviour)]
@@ -125,7 +186,16 @@ pub struct Behaviour {
     peer_store: PeerStore,
 
     #[behaviour(ignore)]
-    challenged_peer_id: Option<PeerId>,
+    clock: Arc<dyn Clock>,
+    #[behaviour(ignore)]
+    keypair: Keypair,
+    #[behaviour(ignore)]
+    peer_lookups: HashMap<PeerId, PeerLookup>,
+    #[behaviour(ignore)]
+    move_polls: HashMap<MatchId, MovePoll>,
+    /// Where correspondence and finished matches are kept across restarts, if anywhere.
+    #[behaviour(ignore)]
+    match_store: Option<MatchStore>,
     #[behaviour(ignore)]
     events: VecDeque<
         NetworkBehaviourAction<
@@ -135,37 +205,70 @@ pub struct Behaviour {
     >,
 }
 
+/// Public libp2p bootstrap nodes, used when no other bootstrap peers are configured.
+pub fn default_bootstrap_peers() -> Vec<(PeerId, Multiaddr)> {
+    BOOTSTRAP_PEER_ADDRS
+        .iter()
+        .map(|addr| {
+            let mut ma = Multiaddr::from_str(addr).expect("invalid bootstrap peer address");
+
+            match ma.pop() {
+                Some(Protocol::P2p(peer_id)) => (
+                    PeerId::from_multihash(peer_id).expect("invalid peer id in bootstrap address"),
+                    ma,
+                ),
+
+                _ => unreachable!("missing /p2p in bootstrap peer address"),
+            }
+        })
+        .collect()
+}
+
 impl Behaviour {
-    pub fn new(peer_id: PeerId, public_key: libp2p::identity::PublicKey) -> Self {
+    pub fn new(
+        peer_id: PeerId,
+        keypair: Keypair,
+        bootstrap_peers: Vec<(PeerId, Multiaddr)>,
+        ipchess_config: IpchessConfig,
+    ) -> Self {
+        Self::with_clock(
+            peer_id,
+            keypair,
+            bootstrap_peers,
+            ipchess_config,
+            Arc::new(SystemClock),
+        )
+    }
+
+    /// Creates the behaviour with the clock its timeouts, move deadlines and the times moves
+    /// are signed with are measured by.
+    pub fn with_clock(
+        peer_id: PeerId,
+        keypair: Keypair,
+        bootstrap_peers: Vec<(PeerId, Multiaddr)>,
+        ipchess_config: IpchessConfig,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
         let mut kad_config = KademliaConfig::default();
-        kad_config.set_record_ttl(Some(std::time::Duration::from_secs(0)));
+        // records hold correspondence moves, needed until the opponent's deadline at most, and
+        // the transcripts of finished matches, kept as long
+        kad_config.set_record_ttl(Some(Duration::from_secs(
+            u64::from(MAX_DAYS_PER_MOVE) * SECS_PER_DAY,
+        )));
         kad_config.set_provider_record_ttl(Some(std::time::Duration::from_secs(0)));
         kad_config.set_kbucket_inserts(kad::KademliaBucketInserts::Manual);
+        kad_config.set_query_timeout(DHT_QUERY_TIMEOUT);
 
         let mut kad = Kademlia::with_config(peer_id, MemoryStore::new(peer_id), kad_config);
 
-        for addr in BOOTSTRAP_PEER_ADDRS.iter() {
-            let mut ma = libp2p::multiaddr::Multiaddr::from_str(addr)
-                .expect("invalid bootstrap peer address");
-            let p2p = ma.pop().unwrap();
-
-            match p2p {
-                libp2p::multiaddr::Protocol::P2p(peer_id) => {
-                    kad.add_address(
-                        &libp2p::PeerId::from_multihash(peer_id)
-                            .expect("missing /p2p in bootstrap peer address"),
-                        ma,
-                    );
-                }
-
-                _ => unreachable!("oh boy"),
-            }
+        for (peer_id, addr) in bootstrap_peers {
+            kad.add_address(&peer_id, addr);
         }
 
-        let identify_config = IdentifyConfig::new("ipchess/libp2p".into(), public_key);
+        let identify_config = IdentifyConfig::new("ipchess/libp2p".into(), keypair.public());
         let identify = Identify::new(identify_config);
 
-        let ipchess = Ipchess::new();
+        let ipchess = Ipchess::with_clock(ipchess_config, clock.clone());
 
         Self {
             identify,
@@ -173,47 +276,374 @@ impl Behaviour {
             ipchess,
             peer_store: PeerStore::new(),
 
-            challenged_peer_id: None,
+            clock,
+            keypair,
+            peer_lookups: HashMap::new(),
+            move_polls: HashMap::new(),
+            match_store: None,
             events: VecDeque::new(),
         }
     }
 
+    /// Restores the matches kept in `match_store`, which matches are saved to from then on, and
+    /// starts polling for the opponent's moves played meanwhile in correspondence matches.
+    pub fn restore_matches(&mut self, match_store: MatchStore) -> Result<(), StoreError> {
+        let now = self.clock.system_time();
+
+        for restored in match_store.load()? {
+            let match_id = restored.game.id();
+            let remaining_move_time = restored
+                .move_deadline
+                .map(|deadline| deadline.duration_since(now).unwrap_or_default());
+
+            self.ipchess
+                .restore_match(restored.game, remaining_move_time, restored.clock);
+            self.update_move_poll(match_id);
+        }
+
+        self.match_store = Some(match_store);
+        Ok(())
+    }
+
     pub fn bootstrap(&mut self) {
-        self.kad.bootstrap().unwrap();
+        if let Err(err) = self.kad.bootstrap() {
+            log::debug!("Skipping DHT bootstrap: {:?}", err);
+        }
     }
 
-    pub fn challenge_peer(&mut self, peer_id: PeerId) {
-        log::debug!("Challenging peer {}", peer_id);
-        self.challenged_peer_id = Some(peer_id);
+    pub fn challenge_peer(
+        &mut self,
+        peer_id: PeerId,
+        variant: Variant,
+        time_control: TimeControl,
+    ) -> Result<(), ChallengeError> {
+        log::debug!("Challenging peer {} to {}", peer_id, variant);
+
+        if self.peer_lookups.contains_key(&peer_id) || self.ipchess.has_outbound_challenge(&peer_id)
+        {
+            return Err(ChallengeError::DuplicateChallenge { peer_id });
+        }
 
         if self.addresses_of_peer(&peer_id).is_empty() {
             log::debug!(
                 "No addresses found for peer {}, starting DHT query",
                 peer_id
             );
-            self.kad.get_closest_peers(peer_id);
+
+            let query_id = self.kad.get_closest_peers(peer_id);
+            self.peer_lookups.insert(
+                peer_id,
+                PeerLookup {
+                    variant,
+                    time_control,
+                    attempts: 1,
+                    started_at: self.clock.now(),
+                    query_id: Some(query_id),
+                    retry_delay: None,
+                },
+            );
+
+            Ok(())
         } else {
             log::debug!(
                 "Addresses for peer {} found, starting challenge request",
                 peer_id
             );
-            self.ipchess.challenge_peer(peer_id);
+            self.ipchess.challenge_peer(peer_id, variant, time_control)
         }
     }
 
-    pub fn accept_peer_challenge(&mut self, peer_id: PeerId) {
+    pub fn accept_peer_challenge(&mut self, peer_id: PeerId) -> Result<(), ChallengeError> {
         log::debug!("Accepting challenge from peer {}", peer_id);
-        self.ipchess.accept_peer_challenge(peer_id);
+        self.ipchess.accept_peer_challenge(peer_id)
     }
 
-    pub fn cancel_challenge(&mut self, peer_id: PeerId) {
+    pub fn cancel_challenge(&mut self, peer_id: PeerId) -> Result<(), ChallengeError> {
         log::debug!("Cancelling challenge to peer {}", peer_id);
-        self.ipchess.cancel_challenge(peer_id);
+
+        // challenge was not sent yet, the peer's addresses are still being looked up
+        if self.peer_lookups.remove(&peer_id).is_some() {
+            return Ok(());
+        }
+
+        self.ipchess.cancel_challenge(peer_id)
     }
 
-    pub fn decline_peer_challenge(&mut self, peer_id: PeerId) {
+    pub fn decline_peer_challenge(&mut self, peer_id: PeerId) -> Result<(), ChallengeError> {
         log::debug!("Declining challenge from peer {}", peer_id);
-        self.ipchess.decline_peer_challenge(peer_id);
+        self.ipchess.decline_peer_challenge(peer_id)
+    }
+
+    /// Challenges in progress, including outbound ones not sent yet while the peer is looked up.
+    pub fn challenges(&self) -> Vec<ChallengeSummary> {
+        let now = self.clock.now();
+        let mut challenges = self.ipchess.challenges();
+
+        challenges.extend(self.peer_lookups.iter().map(|(peer_id, lookup)| {
+            let age = now.duration_since(lookup.started_at);
+
+            ChallengeSummary {
+                peer_id: *peer_id,
+                direction: ChallengeDirection::Outbound,
+                state: ChallengeState::LookingUpPeer,
+                variant: lookup.variant,
+                time_control: lookup.time_control,
+                rematch: None,
+                age,
+                remaining_timeout: Some(PEER_LOOKUP_TIMEOUT.saturating_sub(age)),
+            }
+        }));
+
+        challenges
+    }
+
+    pub fn matches(&self) -> impl Iterator<Item = &Match> {
+        self.ipchess.matches()
+    }
+
+    pub fn remaining_move_time(&self, match_id: MatchId) -> Option<Duration> {
+        self.ipchess.remaining_move_time(match_id)
+    }
+
+    pub fn clock_times(&self, match_id: MatchId) -> Option<ClockTimes> {
+        self.ipchess.clock_times(match_id)
+    }
+
+    /// Plays a move given in UCI or Standard Algebraic Notation.
+    ///
+    /// Moves of correspondence matches are also stored in the DHT, or by the match's store peer,
+    /// for the opponent to find them while this node is offline.
+    pub fn make_move(&mut self, match_id: MatchId, notation: &str) -> Result<(), MatchError> {
+        let game = self
+            .ipchess
+            .matches()
+            .find(|game| game.id() == match_id)
+            .ok_or(MatchError::NoSuchMatch { match_id })?;
+        let mv = game.parse_move(notation)?;
+        let ply = game.ply();
+        let time_control = game.time_control();
+
+        log::debug!("Playing {} in match {}", mv, match_id);
+        self.ipchess.make_move(match_id, mv)?;
+
+        if let TimeControl::Correspondence {
+            days_per_move,
+            store_peer,
+        } = time_control
+        {
+            self.publish_move(match_id, ply, mv, days_per_move, store_peer);
+        }
+
+        Ok(())
+    }
+
+    /// Stores a move of a correspondence match in the DHT until the opponent's deadline, or
+    /// sends it to the match's store peer if it has one, along with the time it was played at.
+    fn publish_move(
+        &mut self,
+        match_id: MatchId,
+        ply: u32,
+        mv: Move,
+        days_per_move: u32,
+        store_peer: Option<PeerId>,
+    ) {
+        let played_at = self.clock.system_time();
+        let value = match sign_move_record(&self.keypair, match_id, ply, mv, played_at) {
+            Ok(value) => value,
+            Err(err) => {
+                log::warn!("Failed signing move {} of match {}: {}", ply, match_id, err);
+                return;
+            }
+        };
+
+        if let Some(store_peer) = store_peer {
+            self.find_store_peer(store_peer);
+            self.ipchess.store_move_record(store_peer, value);
+            return;
+        }
+
+        let mut record = Record::new(move_record_key(match_id, ply), value);
+        record.expires =
+            Some(self.clock.now() + Duration::from_secs(u64::from(days_per_move) * SECS_PER_DAY));
+
+        if let Err(err) = self.kad.put_record(record, Quorum::One) {
+            log::warn!(
+                "Failed storing move {} of match {} in the DHT: {:?}",
+                ply,
+                match_id,
+                err
+            );
+        }
+    }
+
+    /// Looks the store peer of a match up in the DHT when none of its addresses are known, its
+    /// pending messages are sent once the query connects to it.
+    fn find_store_peer(&mut self, store_peer: PeerId) {
+        if self.addresses_of_peer(&store_peer).is_empty() {
+            log::debug!(
+                "No addresses found for store peer {}, starting DHT query",
+                store_peer
+            );
+            self.kad.get_closest_peers(store_peer);
+        }
+    }
+
+    /// Stores the signed transcript of a finished match in the DHT, as a record of its result
+    /// anyone can check against the opponent's.
+    fn publish_transcript(&mut self, match_id: MatchId) {
+        let local_peer_id = PeerId::from(self.keypair.public());
+        let clock = self.ipchess.clock_times(match_id);
+        let transcript = match self
+            .ipchess
+            .matches()
+            .find(|game| game.id() == match_id)
+            .and_then(|game| MatchTranscript::of(game, local_peer_id, clock))
+        {
+            Some(transcript) => transcript,
+            None => return,
+        };
+
+        let value = match sign_match_transcript(&self.keypair, &transcript) {
+            Ok(value) => value,
+            Err(err) => {
+                log::warn!("Failed signing transcript of match {}: {}", match_id, err);
+                return;
+            }
+        };
+
+        let record = Record::new(transcript_record_key(match_id, &local_peer_id), value);
+        if let Err(err) = self.kad.put_record(record, Quorum::One) {
+            log::warn!(
+                "Failed storing transcript of match {} in the DHT: {:?}",
+                match_id,
+                err
+            );
+        }
+    }
+
+    /// Saves a match to the match store, if there is one: correspondence matches as they go,
+    /// for them to go on after a restart, and every match once finished, for rematches to link
+    /// back to it.
+    fn save_match(&self, match_id: MatchId) {
+        let match_store = match &self.match_store {
+            Some(match_store) => match_store,
+            None => return,
+        };
+        let game = match self.ipchess.matches().find(|game| game.id() == match_id) {
+            Some(game) if game.days_per_move().is_some() || game.result().is_some() => game,
+            _ => return,
+        };
+
+        let move_deadline = self
+            .ipchess
+            .remaining_move_time(match_id)
+            .map(|remaining| self.clock.system_time() + remaining);
+        let clock = self.ipchess.clock_times(match_id);
+        if let Err(err) = match_store.save(game, move_deadline, clock) {
+            log::warn!("Failed saving match {}: {}", match_id, err);
+        }
+    }
+
+    /// Starts or stops polling the DHT or the store peer for the opponent's next move in a
+    /// match, depending on whose turn it is.
+    fn update_move_poll(&mut self, match_id: MatchId) {
+        let awaited = self
+            .ipchess
+            .matches()
+            .find(|game| game.id() == match_id)
+            .filter(|game| {
+                game.days_per_move().is_some() && game.result().is_none() && !game.is_local_turn()
+            })
+            .map(|game| {
+                (
+                    game.opponent(),
+                    game.time_control().store_peer(),
+                    game.ply(),
+                )
+            });
+
+        match awaited {
+            Some((opponent, store_peer, ply)) => {
+                self.move_polls.insert(
+                    match_id,
+                    MovePoll {
+                        opponent,
+                        store_peer,
+                        ply,
+                        query_id: None,
+                        next_poll: Box::pin(tokio::time::sleep(MOVE_POLL_INTERVAL)),
+                    },
+                );
+            }
+            None => {
+                self.move_polls.remove(&match_id);
+            }
+        }
+    }
+
+    /// Applies the opponent's move found by a poll of the DHT, `records` being `None` when the
+    /// query failed without searching the DHT through.
+    fn on_move_poll_finished(&mut self, query_id: QueryId, records: Option<Vec<Record>>) {
+        let (match_id, poll) = match self
+            .move_polls
+            .iter_mut()
+            .find(|(_, poll)| poll.query_id == Some(query_id))
+        {
+            Some((match_id, poll)) => (*match_id, poll),
+            None => return,
+        };
+        poll.query_id = None;
+
+        let (opponent, ply) = (poll.opponent, poll.ply);
+        let records = match records {
+            Some(records) => records,
+            None => return,
+        };
+        let stored_move = records.iter().find_map(|record| {
+            verify_move_record(&record.value, &opponent, match_id, ply)
+                .map_err(|err| {
+                    log::debug!(
+                        "Ignoring move record of match {} ply {}: {}",
+                        match_id,
+                        ply,
+                        err
+                    )
+                })
+                .ok()
+        });
+
+        match stored_move {
+            Some((mv, played_at)) => {
+                log::debug!("Found move {} of match {} in the DHT", ply, match_id);
+                self.ipchess
+                    .receive_stored_move(opponent, match_id, ply, mv, played_at);
+            }
+            None => self.ipchess.move_record_missing(match_id),
+        }
+    }
+
+    pub fn offer_rematch(
+        &mut self,
+        match_id: MatchId,
+        swap_colors: bool,
+    ) -> Result<(), ChallengeError> {
+        log::debug!("Offering rematch of match {}", match_id);
+        self.ipchess.offer_rematch(match_id, swap_colors)
+    }
+
+    pub fn accept_rematch(&mut self, match_id: MatchId) -> Result<(), ChallengeError> {
+        log::debug!("Accepting rematch of match {}", match_id);
+        self.ipchess.accept_rematch(match_id)
+    }
+
+    pub fn claim_draw(&mut self, match_id: MatchId) -> Result<DrawReason, MatchError> {
+        log::debug!("Claiming draw in match {}", match_id);
+        self.ipchess.claim_draw(match_id)
+    }
+
+    pub fn send_chat_message(&mut self, match_id: MatchId, text: String) -> Result<(), MatchError> {
+        log::debug!("Sending chat message in match {}", match_id);
+        self.ipchess.send_chat_message(match_id, text)
     }
 
     pub fn is_connected(&self) -> bool {
@@ -228,7 +658,7 @@ impl Behaviour {
 
     fn poll(
         &mut self,
-        _cx: &mut std::task::Context<'_>,
+        cx: &mut std::task::Context<'_>,
         _params: &mut impl libp2p::swarm::PollParameters
     ) -> Poll<NetworkBehaviourAction<<<<Self as NetworkBehaviour>::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::InEvent, <Self as NetworkBehaviour>::OutEvent>>{
         // drain events
@@ -236,8 +666,117 @@ impl Behaviour {
             return Poll::Ready(event);
         }
 
+        // retry peer lookups whose backoff has elapsed
+        let kad = &mut self.kad;
+
+        for (peer_id, lookup) in self.peer_lookups.iter_mut() {
+            let retry = match lookup.retry_delay.as_mut() {
+                Some(delay) => delay.poll_unpin(cx).is_ready(),
+                None => false,
+            };
+
+            if retry {
+                log::debug!(
+                    "Retrying DHT query for peer {}, attempt {}",
+                    peer_id,
+                    lookup.attempts + 1
+                );
+
+                lookup.attempts += 1;
+                lookup.query_id = Some(kad.get_closest_peers(*peer_id));
+                lookup.retry_delay = None;
+            }
+        }
+
+        // look up the opponent's next move in correspondence matches
+        let mut polled_store_peers = vec![];
+
+        for (match_id, poll) in self.move_polls.iter_mut() {
+            if poll.query_id.is_some() || poll.next_poll.poll_unpin(cx).is_pending() {
+                continue;
+            }
+
+            match poll.store_peer {
+                Some(store_peer) => {
+                    log::debug!(
+                        "Fetching move {} of match {} from store peer {}",
+                        poll.ply,
+                        match_id,
+                        store_peer
+                    );
+
+                    self.ipchess
+                        .fetch_move_record(store_peer, *match_id, poll.ply, poll.opponent);
+                    polled_store_peers.push(store_peer);
+                }
+                None => {
+                    log::debug!("Querying DHT for move {} of match {}", poll.ply, match_id);
+
+                    poll.query_id =
+                        Some(kad.get_record(&move_record_key(*match_id, poll.ply), Quorum::One));
+                }
+            }
+
+            poll.next_poll = Box::pin(tokio::time::sleep(MOVE_POLL_INTERVAL));
+            // register the new timer with the task
+            let _ = poll.next_poll.poll_unpin(cx);
+        }
+
+        for store_peer in polled_store_peers {
+            self.find_store_peer(store_peer);
+        }
+
         Poll::Pending
     }
+
+    fn on_peer_lookup_finished(&mut self, query_id: QueryId) {
+        let peer_id = match self
+            .peer_lookups
+            .iter()
+            .find(|(_, lookup)| lookup.query_id == Some(query_id))
+        {
+            Some((peer_id, _)) => *peer_id,
+            None => return,
+        };
+
+        let lookup = self
+            .peer_lookups
+            .get_mut(&peer_id)
+            .expect("peer lookup was just found");
+        lookup.query_id = None;
+
+        // a connected peer may still never identify itself, the lookup keeps counting attempts
+        // while waiting for it
+        if self.peer_store.connected_peers.contains_key(&peer_id) {
+            log::debug!(
+                "Peer {} found by DHT query, waiting for identification",
+                peer_id
+            );
+        }
+
+        if lookup.attempts >= PEER_LOOKUP_MAX_ATTEMPTS {
+            log::debug!(
+                "Peer {} not found after {} DHT queries, giving up",
+                peer_id,
+                lookup.attempts
+            );
+
+            self.peer_lookups.remove(&peer_id);
+            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                BehaviourEvent::ChallengePeerNotFound { peer_id },
+            ));
+            return;
+        }
+
+        let backoff = PEER_LOOKUP_BASE_BACKOFF * 2u32.pow(lookup.attempts - 1);
+        log::debug!(
+            "Peer {} not identified yet, retrying DHT query in {:?}",
+            peer_id,
+            backoff
+        );
+
+        lookup.retry_delay = Some(Box::pin(tokio::time::sleep(backoff)));
+    }
 }
 
 impl NetworkBehaviourEventProcess<IdentifyEvent> for Behaviour {
@@ -249,39 +788,94 @@ impl NetworkBehaviourEventProcess<IdentifyEvent> for Behaviour {
 
             self.peer_store.add_identify_info(peer_id, info.clone());
 
-            let challenged_peer_id = if let Some(challenged_peer_id) = &self.challenged_peer_id {
-                *challenged_peer_id
-            } else {
-                return;
+            let lookup = match self.peer_lookups.remove(&peer_id) {
+                Some(lookup) => lookup,
+                None => return,
             };
 
-            if peer_id != challenged_peer_id {
-                return;
-            }
-
             log::debug!(
                 "Identified challenged peer {} {:?}, starting challenge request",
                 peer_id,
                 info,
             );
 
-            self.challenged_peer_id = None;
-
             for addr in info.listen_addrs {
                 self.ipchess.add_address(peer_id, addr);
             }
 
-            self.ipchess.challenge_peer(peer_id);
+            if let Err(err) =
+                self.ipchess
+                    .challenge_peer(peer_id, lookup.variant, lookup.time_control)
+            {
+                log::debug!("Failed challenging identified peer {}: {}", peer_id, err);
+            }
         }
     }
 }
 
 impl NetworkBehaviourEventProcess<KademliaEvent> for Behaviour {
-    fn inject_event(&mut self, _event: KademliaEvent) {}
+    fn inject_event(&mut self, event: KademliaEvent) {
+        match event {
+            KademliaEvent::QueryResult {
+                id,
+                result: QueryResult::GetClosestPeers(result),
+                ..
+            } => {
+                if let Err(err) = result {
+                    log::debug!("DHT query {:?} failed {:?}", id, err);
+                }
+
+                self.on_peer_lookup_finished(id);
+            }
+
+            KademliaEvent::QueryResult {
+                id,
+                result: QueryResult::GetRecord(result),
+                ..
+            } => {
+                let records = match result {
+                    Ok(GetRecordOk { records, .. }) => {
+                        Some(records.into_iter().map(|record| record.record).collect())
+                    }
+                    Err(GetRecordError::NotFound { .. }) => Some(vec![]),
+                    Err(err) => {
+                        log::debug!("DHT record query {:?} failed {:?}", id, err);
+                        None
+                    }
+                };
+
+                self.on_move_poll_finished(id, records);
+            }
+
+            KademliaEvent::QueryResult {
+                id,
+                result: QueryResult::PutRecord(Err(err)),
+                ..
+            } => {
+                log::debug!("DHT record store {:?} failed {:?}", id, err);
+            }
+
+            _ => {}
+        }
+    }
 }
 
 impl NetworkBehaviourEventProcess<IpchessEvent> for Behaviour {
     fn inject_event(&mut self, event: IpchessEvent) {
+        match &event {
+            IpchessEvent::MatchStarted { match_id, .. }
+            | IpchessEvent::MovePlayed { match_id, .. }
+            | IpchessEvent::MatchEnded { match_id, .. } => {
+                self.update_move_poll(*match_id);
+                self.save_match(*match_id);
+            }
+            _ => {}
+        }
+
+        if let IpchessEvent::MatchEnded { match_id, .. } = &event {
+            self.publish_transcript(*match_id);
+        }
+
         self.events.push_back(NetworkBehaviourAction::GenerateEvent(
             BehaviourEvent::Ipchess(event),
         ));
@@ -291,3 +885,169 @@ impl NetworkBehaviourEventProcess<IpchessEvent> for Behaviour {
 impl NetworkBehaviourEventProcess<PeerStoreEvent> for Behaviour {
     fn inject_event(&mut self, _: PeerStoreEvent) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc, time::Duration};
+
+    use libp2p::{
+        identity::Keypair, kad::record::store::RecordStore, swarm::NetworkBehaviourAction, PeerId,
+    };
+
+    use super::{
+        Behaviour, BehaviourEvent, PEER_LOOKUP_MAX_ATTEMPTS, PEER_LOOKUP_TIMEOUT, SECS_PER_DAY,
+    };
+    use crate::{
+        chess::{Color, Position},
+        game::{Match, MatchId, TimeControl, Variant},
+        protocol::{
+            move_record_key, verify_move_record, ChallengeDirection, ChallengeState, Clock,
+            IpchessConfig, ManualClock,
+        },
+        store::MatchStore,
+    };
+
+    fn new_behaviour() -> Behaviour {
+        let keypair = Keypair::generate_ed25519();
+        let peer_id = PeerId::from(keypair.public());
+
+        Behaviour::new(peer_id, keypair, vec![], IpchessConfig::default())
+    }
+
+    /// Finishes the running DHT query of a peer's lookup.
+    fn finish_lookup_query(behaviour: &mut Behaviour, peer_id: PeerId) {
+        let query_id = behaviour.peer_lookups[&peer_id]
+            .query_id
+            .expect("lookup has no running query");
+        behaviour.on_peer_lookup_finished(query_id);
+    }
+
+    #[test]
+    fn challenges_waiting_for_lookup_are_listed() {
+        let mut behaviour = new_behaviour();
+        let peer_id = PeerId::random();
+
+        behaviour
+            .challenge_peer(peer_id, Variant::Chess960, TimeControl::default())
+            .unwrap();
+
+        let challenges = behaviour.challenges();
+        assert_eq!(challenges.len(), 1);
+        assert_eq!(challenges[0].peer_id, peer_id);
+        assert!(matches!(
+            challenges[0].direction,
+            ChallengeDirection::Outbound
+        ));
+        assert!(matches!(challenges[0].state, ChallengeState::LookingUpPeer));
+        assert_eq!(challenges[0].variant, Variant::Chess960);
+        assert!(challenges[0].remaining_timeout.unwrap() <= PEER_LOOKUP_TIMEOUT);
+
+        // canceling the challenge stops the lookup
+        behaviour.cancel_challenge(peer_id).unwrap();
+        assert!(behaviour.challenges().is_empty());
+    }
+
+    #[tokio::test]
+    async fn lookup_of_connected_peer_which_never_identifies_gives_up() {
+        let mut behaviour = new_behaviour();
+        let peer_id = PeerId::random();
+
+        behaviour
+            .challenge_peer(peer_id, Variant::Standard, TimeControl::default())
+            .unwrap();
+        behaviour.peer_store.connected_peers.insert(peer_id, None);
+
+        // the query finding the peer without it identifying itself counts as an attempt
+        finish_lookup_query(&mut behaviour, peer_id);
+        assert!(behaviour.peer_lookups[&peer_id].retry_delay.is_some());
+
+        let lookup = behaviour.peer_lookups.get_mut(&peer_id).unwrap();
+        lookup.attempts = PEER_LOOKUP_MAX_ATTEMPTS;
+        lookup.query_id = Some(behaviour.kad.get_closest_peers(peer_id));
+        finish_lookup_query(&mut behaviour, peer_id);
+
+        assert!(behaviour.peer_lookups.is_empty());
+        assert!(matches!(
+            behaviour.events.pop_front(),
+            Some(NetworkBehaviourAction::GenerateEvent(
+                BehaviourEvent::ChallengePeerNotFound { peer_id: not_found }
+            )) if not_found == peer_id
+        ));
+
+        // the peer can be challenged again
+        assert!(behaviour
+            .challenge_peer(peer_id, Variant::Standard, TimeControl::default())
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn correspondence_times_are_measured_by_clock() {
+        let clock = ManualClock::new();
+        let keypair = Keypair::generate_ed25519();
+        let mut behaviour = Behaviour::with_clock(
+            PeerId::from(keypair.public()),
+            keypair,
+            vec![],
+            IpchessConfig::default(),
+            Arc::new(clock.clone()),
+        );
+        let days = Duration::from_secs(SECS_PER_DAY);
+        let dir =
+            std::env::temp_dir().join(format!("ipchess-behaviour-test-{}", rand::random::<u64>()));
+
+        let game = Match::new(
+            MatchId::from_bytes(&[9; 32]).unwrap(),
+            PeerId::random(),
+            Color::White,
+            Variant::Standard,
+            TimeControl::Correspondence {
+                days_per_move: 3,
+                store_peer: None,
+            },
+            None,
+            Position::startpos(),
+        );
+        let match_id = game.id();
+        MatchStore::open(dir.clone())
+            .unwrap()
+            .save(&game, Some(clock.system_time() + days * 3), None)
+            .unwrap();
+
+        // the deadline left when restored counts from the clock's time, stored to the second
+        clock.advance(days);
+        behaviour
+            .restore_matches(MatchStore::open(dir.clone()).unwrap())
+            .unwrap();
+        let remaining = behaviour.remaining_move_time(match_id).unwrap();
+        assert!(remaining <= days * 2 && remaining > days * 2 - Duration::from_secs(1));
+
+        // moves are signed with the clock's time and stored until the opponent's deadline
+        clock.advance(days);
+        behaviour.make_move(match_id, "e4").unwrap();
+
+        let record = behaviour
+            .kad
+            .store_mut()
+            .get(&move_record_key(match_id, 0))
+            .unwrap()
+            .into_owned();
+        assert_eq!(record.expires, Some(clock.now() + days * 3));
+        let (_, played_at) = verify_move_record(
+            &record.value,
+            &PeerId::from(behaviour.keypair.public()),
+            match_id,
+            0,
+        )
+        .unwrap();
+        assert!(clock.system_time().duration_since(played_at).unwrap() < Duration::from_secs(1));
+
+        // the saved deadline counts from the clock's time too
+        behaviour.save_match(match_id);
+        let saved = MatchStore::open(dir.clone()).unwrap().load().unwrap();
+        let saved_deadline = saved[0].move_deadline.unwrap();
+        let saved_remaining = saved_deadline.duration_since(clock.system_time()).unwrap();
+        assert!(saved_remaining <= days * 3 && saved_remaining > days * 3 - Duration::from_secs(1));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
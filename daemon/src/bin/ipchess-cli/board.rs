@@ -0,0 +1,85 @@
+use std::fmt::Write;
+
+use thiserror::Error;
+
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Debug, Error)]
+pub enum BoardError {
+    #[error("invalid FEN piece placement: {0}")]
+    InvalidPlacement(String),
+}
+
+/// Renders the position described by a FEN string as a text board.
+///
+/// Only the piece placement and side to move fields are used. The board is drawn from white's
+/// point of view unless `flipped` is set.
+pub fn render_fen(fen: &str, flipped: bool) -> Result<String, BoardError> {
+    let mut fields = fen.split_whitespace();
+    let placement = fields.next().unwrap_or_default();
+    let side_to_move = fields.next();
+
+    let squares = parse_placement(placement)
+        .ok_or_else(|| BoardError::InvalidPlacement(placement.to_string()))?;
+
+    // rows start at the 8th rank and columns at the a file, as in the FEN placement field
+    let mut order: Vec<usize> = (0..8).collect();
+    if flipped {
+        order.reverse();
+    }
+
+    let mut out = String::new();
+    for &row in &order {
+        let _ = write!(out, "{} ", 8 - row);
+
+        for &col in &order {
+            let _ = write!(out, " {}", squares[row][col].unwrap_or('.'));
+        }
+
+        out.push('\n');
+    }
+
+    out.push_str("  ");
+    for &col in &order {
+        let _ = write!(out, " {}", (b'a' + col as u8) as char);
+    }
+    out.push('\n');
+
+    match side_to_move {
+        Some("w") => out.push_str("\nWhite to move\n"),
+        Some("b") => out.push_str("\nBlack to move\n"),
+        _ => {}
+    }
+
+    Ok(out)
+}
+
+fn parse_placement(placement: &str) -> Option<[[Option<char>; 8]; 8]> {
+    let mut squares = [[None; 8]; 8];
+
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return None;
+    }
+
+    for (rank, row) in ranks.into_iter().enumerate() {
+        let mut file = 0;
+
+        for c in row.chars() {
+            match c {
+                '1'..='8' => file += c as usize - '0' as usize,
+                'p' | 'n' | 'b' | 'r' | 'q' | 'k' | 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => {
+                    *squares.get_mut(rank)?.get_mut(file)? = Some(c);
+                    file += 1;
+                }
+                _ => return None,
+            }
+        }
+
+        if file != 8 {
+            return None;
+        }
+    }
+
+    Some(squares)
+}
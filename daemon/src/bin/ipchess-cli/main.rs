@@ -0,0 +1,301 @@
+use std::{collections::HashMap, path::PathBuf, str::FromStr, time::Duration};
+
+use clap::Clap;
+use futures::StreamExt;
+use ipchess::{
+    api::{self, Client, ServerEventNotification},
+    chess::Color,
+    game::{MatchId, TimeControl, Variant},
+    utils::SerializableMatchId,
+};
+use libp2p::PeerId;
+
+mod board;
+mod pgn;
+
+/// Command line client for the ipchess daemon API.
+#[derive(Clap)]
+struct Opts {
+    /// Port of the daemon's WebSocket API
+    #[clap(long, default_value = "3030")]
+    api_port: u16,
+
+    /// Data directory of the daemon, where its API cookie file is read from
+    #[clap(long)]
+    data_dir: Option<PathBuf>,
+
+    /// Connect to the daemon's API on a Unix domain socket instead of its WebSocket port
+    #[clap(long)]
+    api_socket: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Clap)]
+enum Command {
+    /// Prints the daemon's peer id
+    NodeId,
+    /// Prints whether the daemon is connected to the network
+    IsConnected,
+    /// Lists the daemon's in progress challenges
+    ListChallenges,
+    /// Challenges a peer to a match
+    Challenge {
+        peer_id: PeerId,
+        /// Variant to play: standard, chess960, kingofthehill, threecheck or atomic
+        #[clap(long, default_value = "standard")]
+        variant: Variant,
+        /// Days each player has for a move, playing a correspondence match instead of a live one
+        #[clap(long)]
+        days_per_move: Option<u32>,
+        /// Clock of a live match as minutes per player and seconds of increment, e.g. 5+3,
+        /// defaults to 10+0
+        #[clap(long)]
+        clock: Option<Clock>,
+        /// Peer relaying the moves of a correspondence match instead of the DHT
+        #[clap(long)]
+        store_peer: Option<PeerId>,
+    },
+    /// Accepts a challenge received from a peer
+    Accept { peer_id: PeerId },
+    /// Cancels a challenge sent to a peer
+    Cancel { peer_id: PeerId },
+    /// Declines a challenge received from a peer
+    Decline { peer_id: PeerId },
+    /// Lists the daemon's matches, in progress or over
+    ListMatches,
+    /// Plays a move in a match
+    Move {
+        match_id: MatchId,
+        /// The move in UCI or Standard Algebraic Notation, e.g. g1f3 or Nf3
+        notation: String,
+    },
+    /// Claims a draw by threefold repetition or the fifty move rule in a match
+    ClaimDraw { match_id: MatchId },
+    /// Sends a chat message to the opponent of a match
+    Chat { match_id: MatchId, text: String },
+    /// Offers the opponent of a finished match a rematch
+    Rematch {
+        match_id: MatchId,
+        /// Swap the colors played in the finished match instead of drawing them anew
+        #[clap(long)]
+        swap_colors: bool,
+    },
+    /// Accepts the rematch offered by the opponent of a finished match
+    AcceptRematch { match_id: MatchId },
+    /// Prints a match, in progress or over, in Portable Game Notation
+    ExportPgn { match_id: MatchId },
+    /// Prints the daemon's events as they happen, one JSON object per line
+    WatchEvents {
+        /// Replay buffered events with a sequence number after this one
+        #[clap(long)]
+        since: Option<u64>,
+    },
+    /// Renders the board of matches after every move played in them
+    Watch {
+        /// Only render the moves of this match
+        match_id: Option<MatchId>,
+    },
+    /// Renders a position given in FEN as a text board
+    Board {
+        /// Position to render, defaults to the starting position
+        fen: Option<String>,
+        /// Render the board from black's point of view
+        #[clap(long)]
+        flip: bool,
+    },
+}
+
+/// Clock of a live match given as `<minutes>+<increment seconds>`.
+struct Clock {
+    initial: Duration,
+    increment: Duration,
+}
+
+impl FromStr for Clock {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid clock `{}`, expected e.g. 5+3", s);
+        let (minutes, increment) = s.split_once('+').ok_or_else(invalid)?;
+
+        Ok(Clock {
+            initial: Duration::from_secs(minutes.parse::<u64>().map_err(|_| invalid())? * 60),
+            increment: Duration::from_secs(increment.parse().map_err(|_| invalid())?),
+        })
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let opts = Opts::parse();
+
+    if let Err(err) = run(opts).await {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+async fn run(opts: Opts) -> Result<(), Box<dyn std::error::Error>> {
+    if let Command::Board { fen, flip } = &opts.command {
+        let fen = fen.as_deref().unwrap_or(board::STARTING_FEN);
+        print!("{}", board::render_fen(fen, *flip)?);
+
+        return Ok(());
+    }
+
+    let client = connect(&opts).await?;
+
+    match opts.command {
+        Command::NodeId => println!("{}", client.node_id().await?),
+        Command::IsConnected => println!("{}", client.is_connected().await?),
+        Command::ListChallenges => {
+            let challenges = client.list_challenges().await?;
+            println!("{}", serde_json::to_string_pretty(&challenges)?);
+        }
+        Command::Challenge {
+            peer_id,
+            variant,
+            days_per_move,
+            clock,
+            store_peer,
+        } => {
+            if store_peer.is_some() && days_per_move.is_none() {
+                return Err("--store-peer can only be given along with --days-per-move".into());
+            }
+
+            let time_control = match (days_per_move, clock) {
+                (Some(days_per_move), None) => TimeControl::Correspondence {
+                    days_per_move,
+                    store_peer,
+                },
+                (None, Some(Clock { initial, increment })) => {
+                    TimeControl::Live { initial, increment }
+                }
+                (None, None) => TimeControl::default(),
+                (Some(_), Some(_)) => {
+                    return Err("--days-per-move and --clock cannot be given together".into())
+                }
+            };
+
+            client
+                .challenge_peer(peer_id, variant, time_control)
+                .await?
+        }
+        Command::Accept { peer_id } => client.accept_peer_challenge(peer_id).await?,
+        Command::Cancel { peer_id } => client.cancel_challenge(peer_id).await?,
+        Command::Decline { peer_id } => client.decline_peer_challenge(peer_id).await?,
+        Command::ListMatches => {
+            let matches = client.list_matches().await?;
+            println!("{}", serde_json::to_string_pretty(&matches)?);
+        }
+        Command::Move { match_id, notation } => client.make_move(match_id, &notation).await?,
+        Command::ClaimDraw { match_id } => {
+            let result = client.claim_draw(match_id).await?;
+            println!("{}", serde_json::to_string_pretty(&result)?);
+        }
+        Command::Chat { match_id, text } => client.send_chat_message(match_id, &text).await?,
+        Command::Rematch {
+            match_id,
+            swap_colors,
+        } => client.offer_rematch(match_id, swap_colors).await?,
+        Command::AcceptRematch { match_id } => client.accept_rematch(match_id).await?,
+        Command::ExportPgn { match_id } => {
+            let info = client
+                .list_matches()
+                .await?
+                .into_iter()
+                .find(|info| info.match_id.0 == match_id)
+                .ok_or_else(|| format!("no match found with id {}", match_id))?;
+
+            print!("{}", pgn::match_pgn(&info, client.node_id().await?));
+        }
+        Command::WatchEvents { since } => {
+            let mut events = client.subscribe_events(since).await?;
+
+            while let Some(event) = events.next().await {
+                println!("{}", serde_json::to_string(&event?)?);
+            }
+
+            return Err("daemon closed the connection".into());
+        }
+        Command::Watch { match_id } => {
+            watch(&client, match_id).await?;
+
+            return Err("daemon closed the connection".into());
+        }
+        Command::Board { .. } => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// Renders the board after every move played in the node's matches, or only in the given one,
+/// from the side this node plays.
+async fn watch(client: &Client, only: Option<MatchId>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut events = client.subscribe_events(None).await?;
+    let mut colors = HashMap::new();
+
+    while let Some(event) = events.next().await {
+        match event?.notification {
+            ServerEventNotification::MovePlayed {
+                match_id: SerializableMatchId(match_id),
+                ply,
+                mv,
+                fen,
+                ..
+            } if only.map_or(true, |only| only == match_id) => {
+                if !colors.contains_key(&match_id) {
+                    for info in client.list_matches().await? {
+                        colors.insert(info.match_id.0, info.color.0);
+                    }
+                }
+                let flipped = colors.get(&match_id) == Some(&Color::Black);
+
+                let number = ply / 2 + 1;
+                let dots = if ply % 2 == 0 { "." } else { "..." };
+                println!("\n{}: {}{} {}\n", match_id, number, dots, mv.san);
+                print!("{}", board::render_fen(&fen, flipped)?);
+            }
+
+            ServerEventNotification::MatchEnded {
+                match_id: SerializableMatchId(match_id),
+                result,
+            } if only.map_or(true, |only| only == match_id) => {
+                println!(
+                    "\n{}: match ended {}",
+                    match_id,
+                    serde_json::to_string(&result)?
+                );
+            }
+
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+async fn connect(opts: &Opts) -> Result<Client, Box<dyn std::error::Error>> {
+    let data_dir = opts
+        .data_dir
+        .clone()
+        .or_else(ipchess::utils::default_data_dir)
+        .ok_or("failed finding data directory")?;
+    let cookie_path = data_dir.join(api::COOKIE_FILE_NAME);
+
+    if let Some(path) = &opts.api_socket {
+        #[cfg(unix)]
+        return Ok(Client::connect_unix(path, &cookie_path).await?);
+
+        #[cfg(not(unix))]
+        return Err(format!(
+            "cannot connect to {}, Unix domain sockets are not supported",
+            path.display()
+        )
+        .into());
+    }
+
+    Ok(Client::connect(opts.api_port, &cookie_path).await?)
+}
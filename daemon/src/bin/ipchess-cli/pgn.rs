@@ -0,0 +1,65 @@
+use ipchess::{
+    api::MatchInfo,
+    chess::{Color, Pgn, PgnResult, STARTING_FEN},
+    game::Variant,
+};
+use libp2p::PeerId;
+
+/// Builds the PGN of a match played by the node with the given peer id.
+///
+/// Players are named by their peer ids. Matches which did not start from the standard starting
+/// position, like those of Chess960, carry it in the `SetUp` and `FEN` tags.
+pub fn match_pgn(info: &MatchInfo, node_id: PeerId) -> Pgn {
+    let (white, black) = match info.color.0 {
+        Color::White => (node_id, info.peer_id.0),
+        Color::Black => (info.peer_id.0, node_id),
+    };
+
+    let result = match &info.result {
+        None => PgnResult::Unknown,
+        Some(result) => match result.winner.as_ref().map(|winner| winner.0) {
+            Some(Color::White) => PgnResult::WhiteWins,
+            Some(Color::Black) => PgnResult::BlackWins,
+            None => PgnResult::Draw,
+        },
+    };
+
+    let mut tags = vec![
+        ("Event", "ipchess match".to_string()),
+        ("Site", "ipchess".to_string()),
+        ("Date", "????.??.??".to_string()),
+        ("Round", "-".to_string()),
+        ("White", white.to_string()),
+        ("Black", black.to_string()),
+        ("Result", result.as_str().to_string()),
+    ];
+
+    if let Some(variant) = variant_tag(info.variant.0) {
+        tags.push(("Variant", variant.to_string()));
+    }
+
+    if info.initial_fen != STARTING_FEN {
+        tags.push(("SetUp", "1".to_string()));
+        tags.push(("FEN", info.initial_fen.clone()));
+    }
+
+    Pgn {
+        tags: tags
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+        moves: info.moves.iter().map(|mv| mv.san.clone()).collect(),
+        result: Some(result),
+    }
+}
+
+/// Value of the `Variant` tag, `None` for standard chess which goes without it.
+fn variant_tag(variant: Variant) -> Option<&'static str> {
+    match variant {
+        Variant::Standard => None,
+        Variant::Chess960 => Some("Chess960"),
+        Variant::KingOfTheHill => Some("King of the Hill"),
+        Variant::ThreeCheck => Some("Three-check"),
+        Variant::Atomic => Some("Atomic"),
+    }
+}
@@ -0,0 +1,290 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    str::FromStr,
+    time::Instant,
+};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ipchess::{
+    api::{ChallengeInfo, MatchInfo, SequencedEventNotification, ServerEventNotification},
+    chess::Color,
+    game::{MatchId, Variant},
+};
+use libp2p::PeerId;
+use ratatui::widgets::ListState;
+
+const EVENTS_LOG_CAPACITY: usize = 100;
+
+/// Request to the daemon resulting from user input.
+pub enum Action {
+    Challenge(PeerId, Variant),
+    Accept(PeerId),
+    Decline(PeerId),
+    Cancel(PeerId),
+    /// Move in UCI or Standard Algebraic Notation.
+    Move(MatchId, String),
+    Chat(MatchId, String),
+}
+
+/// List the arrow keys move the selection of.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Challenges,
+    Matches,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    /// Peer id to challenge.
+    Challenge,
+    /// Move to play in the selected match.
+    Move,
+    /// Chat message to the opponent of the selected match.
+    Chat,
+}
+
+/// Line of text being typed by the user.
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub text: String,
+}
+
+/// Chat message sent in a match.
+pub struct ChatLine {
+    /// Color played by the sender.
+    pub color: Color,
+    pub text: String,
+}
+
+pub struct App {
+    pub node_id: PeerId,
+    pub connected: bool,
+    pub focus: Focus,
+    pub challenges: Vec<ChallengeInfo>,
+    pub selected: ListState,
+    pub matches: Vec<MatchInfo>,
+    pub selected_match: ListState,
+    /// Instant the matches were listed at, the clocks shown tick from it.
+    pub matches_listed_at: Instant,
+    pub chats: HashMap<MatchId, Vec<ChatLine>>,
+    pub events: VecDeque<String>,
+    /// Variant new challenges are sent for.
+    pub variant: Variant,
+    pub prompt: Option<Prompt>,
+    pub status: Option<String>,
+    pub should_quit: bool,
+}
+
+impl App {
+    pub fn new(node_id: PeerId) -> Self {
+        Self {
+            node_id,
+            connected: false,
+            focus: Focus::Challenges,
+            challenges: vec![],
+            selected: ListState::default(),
+            matches: vec![],
+            selected_match: ListState::default(),
+            matches_listed_at: Instant::now(),
+            chats: HashMap::new(),
+            events: VecDeque::new(),
+            variant: Variant::Standard,
+            prompt: None,
+            status: None,
+            should_quit: false,
+        }
+    }
+
+    pub fn set_challenges(&mut self, challenges: Vec<ChallengeInfo>) {
+        self.challenges = challenges;
+        clamp_selection(&mut self.selected, self.challenges.len());
+    }
+
+    pub fn set_matches(&mut self, matches: Vec<MatchInfo>) {
+        // keep the same match selected when one starts or ends
+        let selected_id = self.current_match().map(|game| game.match_id.0);
+
+        self.matches = matches;
+        self.matches_listed_at = Instant::now();
+
+        if let Some(i) = selected_id.and_then(|match_id| {
+            self.matches
+                .iter()
+                .position(|game| game.match_id.0 == match_id)
+        }) {
+            self.selected_match.select(Some(i));
+        }
+        clamp_selection(&mut self.selected_match, self.matches.len());
+    }
+
+    pub fn current_match(&self) -> Option<&MatchInfo> {
+        self.selected_match
+            .selected()
+            .and_then(|i| self.matches.get(i))
+    }
+
+    pub fn push_event(&mut self, event: &SequencedEventNotification) {
+        if let ServerEventNotification::ChatMessage {
+            match_id,
+            color,
+            text,
+        } = &event.notification
+        {
+            self.chats.entry(match_id.0).or_default().push(ChatLine {
+                color: color.0,
+                text: text.clone(),
+            });
+        }
+
+        let event = serde_json::to_value(event).unwrap_or_default();
+
+        let mut line = format!(
+            "#{} {}",
+            event["sequence"],
+            event["event_type"].as_str().unwrap_or("unknown")
+        );
+
+        if let Some(peer_id) = event["data"]["peer_id"].as_str() {
+            line.push(' ');
+            line.push_str(peer_id);
+        }
+
+        if let Some(san) = event["data"]["move"]["san"].as_str() {
+            line.push(' ');
+            line.push_str(san);
+        }
+
+        if let Some(reason) = event["data"]["reason"].as_str() {
+            line.push_str(&format!(" ({})", reason));
+        }
+
+        if self.events.len() == EVENTS_LOG_CAPACITY {
+            self.events.pop_back();
+        }
+        self.events.push_front(line);
+    }
+
+    pub fn on_key(&mut self, key: KeyEvent) -> Option<Action> {
+        self.status = None;
+
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.should_quit = true;
+            return None;
+        }
+
+        if let Some(prompt) = &mut self.prompt {
+            match key.code {
+                KeyCode::Char(c) => prompt.text.push(c),
+                KeyCode::Backspace => {
+                    prompt.text.pop();
+                }
+                KeyCode::Esc => self.prompt = None,
+                KeyCode::Enter => {
+                    let prompt = self.prompt.take()?;
+                    return self.submit(prompt);
+                }
+                _ => {}
+            }
+
+            return None;
+        }
+
+        let selected = self
+            .selected
+            .selected()
+            .and_then(|i| self.challenges.get(i))
+            .map(|challenge| challenge.peer_id.0);
+
+        match key.code {
+            KeyCode::Char('q') => self.should_quit = true,
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Challenges => Focus::Matches,
+                    Focus::Matches => Focus::Challenges,
+                }
+            }
+            KeyCode::Up => self.select_offset(-1),
+            KeyCode::Down => self.select_offset(1),
+            KeyCode::Char('n') => self.open_prompt(PromptKind::Challenge),
+            KeyCode::Char('v') => self.next_variant(),
+            KeyCode::Char('a') => return selected.map(Action::Accept),
+            KeyCode::Char('d') => return selected.map(Action::Decline),
+            KeyCode::Char('c') => return selected.map(Action::Cancel),
+            KeyCode::Char('m') if self.current_match().is_some() => {
+                self.open_prompt(PromptKind::Move)
+            }
+            KeyCode::Char('t') if self.current_match().is_some() => {
+                self.open_prompt(PromptKind::Chat)
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    fn open_prompt(&mut self, kind: PromptKind) {
+        self.prompt = Some(Prompt {
+            kind,
+            text: String::new(),
+        });
+    }
+
+    fn submit(&mut self, prompt: Prompt) -> Option<Action> {
+        let text = prompt.text.trim().to_string();
+        if text.is_empty() {
+            return None;
+        }
+
+        match prompt.kind {
+            PromptKind::Challenge => match PeerId::from_str(&text) {
+                Ok(peer_id) => return Some(Action::Challenge(peer_id, self.variant)),
+                Err(_) => self.status = Some("Invalid peer id".to_string()),
+            },
+            PromptKind::Move => {
+                let match_id = self.current_match()?.match_id.0;
+                return Some(Action::Move(match_id, text));
+            }
+            PromptKind::Chat => {
+                let match_id = self.current_match()?.match_id.0;
+                return Some(Action::Chat(match_id, text));
+            }
+        }
+
+        None
+    }
+
+    fn next_variant(&mut self) {
+        let i = Variant::ALL
+            .iter()
+            .position(|&variant| variant == self.variant)
+            .unwrap_or(0);
+        self.variant = Variant::ALL[(i + 1) % Variant::ALL.len()];
+    }
+
+    fn select_offset(&mut self, offset: isize) {
+        let (selected, len) = match self.focus {
+            Focus::Challenges => (&mut self.selected, self.challenges.len()),
+            Focus::Matches => (&mut self.selected_match, self.matches.len()),
+        };
+
+        if len == 0 {
+            return;
+        }
+
+        let last = len as isize - 1;
+        let current = selected.selected().unwrap_or(0) as isize;
+
+        selected.select(Some((current + offset).max(0).min(last) as usize));
+    }
+}
+
+/// Keeps the selection of a list within its `len` items, selecting the first one if there was
+/// no selection.
+fn clamp_selection(selected: &mut ListState, len: usize) {
+    let i = match selected.selected() {
+        _ if len == 0 => None,
+        Some(i) => Some(i.min(len - 1)),
+        None => Some(0),
+    };
+    selected.select(i);
+}
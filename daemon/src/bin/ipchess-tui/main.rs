@@ -0,0 +1,176 @@
+use std::{io, path::PathBuf, time::Duration};
+
+use clap::Clap;
+use crossterm::{
+    event::{Event, EventStream, KeyEventKind},
+    execute,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use futures::StreamExt;
+use ipchess::{
+    api::{self, Client},
+    game::TimeControl,
+};
+use ratatui::{backend::CrosstermBackend, Terminal};
+
+use crate::app::{Action, App};
+
+mod app;
+mod ui;
+
+const IS_CONNECTED_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Interval the screen is redrawn at without input or events, for the clocks to tick.
+const REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Terminal client for the ipchess daemon API.
+#[derive(Clap)]
+struct Opts {
+    /// Port of the daemon's WebSocket API
+    #[clap(long, default_value = "3030")]
+    api_port: u16,
+
+    /// Data directory of the daemon, where its API cookie file is read from
+    #[clap(long)]
+    data_dir: Option<PathBuf>,
+
+    /// Connect to the daemon's API on a Unix domain socket instead of its WebSocket port
+    #[clap(long)]
+    api_socket: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() {
+    let opts = Opts::parse();
+
+    let client = match connect(&opts).await {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    terminal::enable_raw_mode().expect("failed enabling terminal raw mode");
+    execute!(io::stdout(), EnterAlternateScreen).expect("failed entering alternate screen");
+
+    // restore the terminal before printing the panic message
+    let default_panic_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_panic_hook(info);
+    }));
+
+    let res = run(client).await;
+
+    restore_terminal();
+
+    if let Err(err) = res {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn restore_terminal() {
+    let _ = terminal::disable_raw_mode();
+    let _ = execute!(io::stdout(), LeaveAlternateScreen);
+}
+
+async fn run(client: Client) -> Result<(), Box<dyn std::error::Error>> {
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    let mut app = App::new(client.node_id().await?);
+
+    // replay the buffered events for the chat messages of recent matches
+    let mut events = client.subscribe_events(Some(0)).await?;
+    app.set_challenges(client.list_challenges().await?);
+    app.set_matches(client.list_matches().await?);
+
+    let mut input = EventStream::new();
+    let mut is_connected_poll = tokio::time::interval(IS_CONNECTED_POLL_INTERVAL);
+    let mut redraw = tokio::time::interval(REDRAW_INTERVAL);
+
+    while !app.should_quit {
+        terminal.draw(|f| ui::draw(f, &mut app))?;
+
+        tokio::select! {
+            Some(input_event) = input.next() => {
+                let key = match input_event? {
+                    Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                    _ => continue,
+                };
+
+                if let Some(action) = app.on_key(key) {
+                    app.status = Some(perform(&client, action).await);
+                    app.set_challenges(client.list_challenges().await?);
+                    app.set_matches(client.list_matches().await?);
+                }
+            }
+
+            event = events.next() => {
+                let event = event.ok_or("daemon closed the connection")??;
+                app.push_event(&event);
+
+                app.set_challenges(client.list_challenges().await?);
+                app.set_matches(client.list_matches().await?);
+            }
+
+            _ = is_connected_poll.tick() => {
+                app.connected = client.is_connected().await?;
+            }
+
+            _ = redraw.tick() => {}
+        }
+    }
+
+    Ok(())
+}
+
+async fn perform(client: &Client, action: Action) -> String {
+    let res = match &action {
+        Action::Challenge(peer_id, variant) => {
+            client
+                .challenge_peer(*peer_id, *variant, TimeControl::default())
+                .await
+        }
+        Action::Accept(peer_id) => client.accept_peer_challenge(*peer_id).await,
+        Action::Decline(peer_id) => client.decline_peer_challenge(*peer_id).await,
+        Action::Cancel(peer_id) => client.cancel_challenge(*peer_id).await,
+        Action::Move(match_id, notation) => client.make_move(*match_id, notation).await,
+        Action::Chat(match_id, text) => client.send_chat_message(*match_id, text).await,
+    };
+
+    match (res, action) {
+        (Err(err), _) => format!("Error: {}", err),
+        (Ok(_), Action::Challenge(peer_id, variant)) => {
+            format!("Challenged {} to {}", peer_id, variant)
+        }
+        (Ok(_), Action::Accept(peer_id)) => format!("Accepted challenge from {}", peer_id),
+        (Ok(_), Action::Decline(peer_id)) => format!("Declined challenge from {}", peer_id),
+        (Ok(_), Action::Cancel(peer_id)) => format!("Canceled challenge to {}", peer_id),
+        (Ok(_), Action::Move(_, notation)) => format!("Played {}", notation),
+        (Ok(_), Action::Chat(..)) => "Sent chat message".to_string(),
+    }
+}
+
+async fn connect(opts: &Opts) -> Result<Client, Box<dyn std::error::Error>> {
+    let data_dir = opts
+        .data_dir
+        .clone()
+        .or_else(ipchess::utils::default_data_dir)
+        .ok_or("failed finding data directory")?;
+    let cookie_path = data_dir.join(api::COOKIE_FILE_NAME);
+
+    if let Some(path) = &opts.api_socket {
+        #[cfg(unix)]
+        return Ok(Client::connect_unix(path, &cookie_path).await?);
+
+        #[cfg(not(unix))]
+        return Err(format!(
+            "cannot connect to {}, Unix domain sockets are not supported",
+            path.display()
+        )
+        .into());
+    }
+
+    Ok(Client::connect(opts.api_port, &cookie_path).await?)
+}
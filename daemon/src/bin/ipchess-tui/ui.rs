@@ -0,0 +1,400 @@
+use ipchess::{
+    api::{MatchEndReason, MatchInfo},
+    chess::{self, Position, Square},
+};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, Paragraph},
+    Frame,
+};
+
+use crate::app::{App, Focus, PromptKind};
+
+/// Width of the board pane: the rank labels and eight squares of three columns, with borders.
+const BOARD_WIDTH: u16 = 2 + 8 * 3 + 2;
+/// Height of the board pane: eight ranks and the file labels, with borders.
+const BOARD_HEIGHT: u16 = 8 + 1 + 2;
+
+const LIGHT_SQUARE: Color = Color::Gray;
+const DARK_SQUARE: Color = Color::DarkGray;
+
+pub fn draw(f: &mut Frame, app: &mut App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(BOARD_HEIGHT + 4),
+            Constraint::Length(3),
+        ])
+        .split(f.size());
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(40),
+            Constraint::Length(BOARD_WIDTH),
+            Constraint::Min(20),
+        ])
+        .split(rows[1]);
+
+    let lists = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Ratio(1, 3),
+            Constraint::Ratio(1, 3),
+            Constraint::Ratio(1, 3),
+        ])
+        .split(columns[0]);
+
+    let panes = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(BOARD_HEIGHT),
+            Constraint::Length(4),
+            Constraint::Min(3),
+        ])
+        .split(columns[1]);
+
+    draw_header(f, app, rows[0]);
+    draw_challenges(f, app, lists[0]);
+    draw_matches(f, app, lists[1]);
+    draw_events(f, app, lists[2]);
+
+    let game = app.current_match();
+    let position = game.and_then(|game| Position::from_fen(&game.fen).ok());
+
+    let board = match (game, &position) {
+        (Some(game), Some(position)) => board_lines(position, game.color.0),
+        _ => vec![],
+    };
+    let board = Paragraph::new(board).block(Block::default().borders(Borders::ALL).title("Board"));
+    f.render_widget(board, panes[0]);
+
+    let clocks = match (game, &position) {
+        (Some(game), Some(position)) => clock_lines(app, game, position.turn()),
+        _ => vec![],
+    };
+    let clocks =
+        Paragraph::new(clocks).block(Block::default().borders(Borders::ALL).title("Clocks"));
+    f.render_widget(clocks, panes[1]);
+
+    let moves = game.map(move_lines).unwrap_or_default();
+    draw_tail(f, "Moves", moves, panes[2]);
+
+    let chat = game.map(|game| chat_lines(app, game)).unwrap_or_default();
+    draw_tail(f, "Chat", chat, columns[2]);
+
+    draw_footer(f, app, rows[2]);
+}
+
+fn draw_header(f: &mut Frame, app: &App, area: Rect) {
+    let network = if app.connected {
+        Span::styled("connected", Style::default().fg(Color::Green))
+    } else {
+        Span::styled("connecting", Style::default().fg(Color::Yellow))
+    };
+    let header = Paragraph::new(Line::from(vec![
+        Span::raw("Node "),
+        Span::styled(
+            app.node_id.to_string(),
+            Style::default().add_modifier(Modifier::BOLD),
+        ),
+        Span::raw("  Network "),
+        network,
+    ]))
+    .block(Block::default().borders(Borders::ALL).title("ipchess"));
+    f.render_widget(header, area);
+}
+
+fn draw_challenges(f: &mut Frame, app: &mut App, area: Rect) {
+    let challenges: Vec<ListItem> = app
+        .challenges
+        .iter()
+        .map(|challenge| {
+            let mut line = format!(
+                "{:<8} {:<16} {:<9} {}",
+                challenge.direction.as_str(),
+                challenge.state.as_str(),
+                challenge.variant.0,
+                challenge.peer_id.0
+            );
+
+            if let Some(remaining_ms) = challenge.remaining_timeout_ms {
+                line.push_str(&format!(" ({}s)", remaining_ms / 1000));
+            }
+
+            ListItem::new(line)
+        })
+        .collect();
+    let challenges = List::new(challenges)
+        .block(Block::default().borders(Borders::ALL).title("Challenges"))
+        .highlight_style(highlight_style(app.focus == Focus::Challenges));
+    f.render_stateful_widget(challenges, area, &mut app.selected);
+}
+
+fn draw_matches(f: &mut Frame, app: &mut App, area: Rect) {
+    let matches: Vec<ListItem> = app
+        .matches
+        .iter()
+        .map(|game| {
+            let state = match &game.result {
+                Some(_) => "over",
+                None => match Position::from_fen(&game.fen) {
+                    Ok(position) if position.turn() == game.color.0 => "your move",
+                    _ => "waiting",
+                },
+            };
+
+            ListItem::new(format!(
+                "{:<5} {:<9} {:<9} {}",
+                game.color.as_str(),
+                state,
+                game.variant.0,
+                game.peer_id.0
+            ))
+        })
+        .collect();
+    let matches = List::new(matches)
+        .block(Block::default().borders(Borders::ALL).title("Matches"))
+        .highlight_style(highlight_style(app.focus == Focus::Matches));
+    f.render_stateful_widget(matches, area, &mut app.selected_match);
+}
+
+fn draw_events(f: &mut Frame, app: &App, area: Rect) {
+    let events: Vec<ListItem> = app
+        .events
+        .iter()
+        .map(|event| ListItem::new(event.as_str()))
+        .collect();
+    let events = List::new(events).block(Block::default().borders(Borders::ALL).title("Events"));
+    f.render_widget(events, area);
+}
+
+fn draw_footer(f: &mut Frame, app: &App, area: Rect) {
+    let footer = match &app.prompt {
+        Some(prompt) => {
+            let title = match prompt.kind {
+                PromptKind::Challenge => format!("Challenge peer to {}", app.variant),
+                PromptKind::Move => "Move in UCI or SAN".to_string(),
+                PromptKind::Chat => "Chat message".to_string(),
+            };
+
+            Paragraph::new(format!("{}_", prompt.text))
+                .block(Block::default().borders(Borders::ALL).title(title))
+        }
+        None => {
+            let help = format!(
+                "tab: switch list  n: challenge peer  v: variant ({})  a: accept  d: decline  \
+                 c: cancel  m: move  t: chat  q: quit",
+                app.variant
+            );
+            let text = app.status.clone().unwrap_or(help);
+
+            Paragraph::new(text).block(Block::default().borders(Borders::ALL))
+        }
+    };
+    f.render_widget(footer, area);
+}
+
+/// Renders the last lines fitting in a bordered pane, so the latest moves and chat messages
+/// stay visible.
+fn draw_tail(f: &mut Frame, title: &str, lines: Vec<Line<'static>>, area: Rect) {
+    let skipped = lines
+        .len()
+        .saturating_sub(area.height.saturating_sub(2) as usize);
+    let lines: Vec<Line> = lines.into_iter().skip(skipped).collect();
+
+    let pane = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(pane, area);
+}
+
+fn highlight_style(focused: bool) -> Style {
+    if focused {
+        Style::default().add_modifier(Modifier::REVERSED)
+    } else {
+        Style::default().add_modifier(Modifier::BOLD)
+    }
+}
+
+/// Board seen from the side of the player of `color`.
+fn board_lines(position: &Position, color: chess::Color) -> Vec<Line<'static>> {
+    let (ranks, files): (Vec<u8>, Vec<u8>) = match color {
+        chess::Color::White => ((0..8).rev().collect(), (0..8).collect()),
+        chess::Color::Black => ((0..8).collect(), (0..8).rev().collect()),
+    };
+
+    let mut lines: Vec<Line> = ranks
+        .iter()
+        .map(|&rank| {
+            let mut spans = vec![Span::raw(format!("{} ", rank + 1))];
+
+            for &file in &files {
+                let background = if (file + rank) % 2 == 1 {
+                    LIGHT_SQUARE
+                } else {
+                    DARK_SQUARE
+                };
+                let piece = Square::new(file, rank).and_then(|square| position.piece_at(square));
+
+                let (text, foreground) = match piece {
+                    Some(piece) if piece.color == chess::Color::White => {
+                        (piece.char(), Color::White)
+                    }
+                    Some(piece) => (piece.char(), Color::Black),
+                    None => (' ', Color::Reset),
+                };
+                spans.push(Span::styled(
+                    format!(" {} ", text),
+                    Style::default()
+                        .fg(foreground)
+                        .bg(background)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            }
+
+            Line::from(spans)
+        })
+        .collect();
+
+    let file_labels: String = files
+        .iter()
+        .map(|&file| format!(" {} ", (b'a' + file) as char))
+        .collect();
+    lines.push(Line::from(format!("  {}", file_labels)));
+
+    lines
+}
+
+/// Time left to each player of a live match, or to the player to move of a correspondence
+/// match, counted down since the matches were listed.
+fn clock_lines(app: &App, game: &MatchInfo, turn: chess::Color) -> Vec<Line<'static>> {
+    let elapsed_ms = app.matches_listed_at.elapsed().as_millis() as u64;
+
+    if let Some(result) = &game.result {
+        let reason = reason_name(result.reason);
+        let line = match &result.winner {
+            Some(winner) => format!("{} won by {}", color_name(winner.0), reason),
+            None => format!("Draw by {}", reason),
+        };
+
+        let mut lines = vec![Line::from(line)];
+        if let Some(clock) = &game.clock {
+            lines.push(Line::from(format!(
+                "White {}  Black {}",
+                format_duration(clock.white_ms),
+                format_duration(clock.black_ms)
+            )));
+        }
+
+        return lines;
+    }
+
+    if let Some(clock) = &game.clock {
+        return [
+            (chess::Color::White, clock.white_ms),
+            (chess::Color::Black, clock.black_ms),
+        ]
+        .iter()
+        .map(|&(color, ms)| {
+            let ms = if color == turn {
+                ms.saturating_sub(elapsed_ms)
+            } else {
+                ms
+            };
+
+            let mut style = Style::default();
+            if color == turn {
+                style = style.add_modifier(Modifier::BOLD);
+            }
+
+            let you = if color == game.color.0 { " (you)" } else { "" };
+            Line::from(Span::styled(
+                format!("{:<5} {}{}", color_name(color), format_duration(ms), you),
+                style,
+            ))
+        })
+        .collect();
+    }
+
+    match game.remaining_move_time_ms {
+        Some(ms) => vec![
+            Line::from(format!("{} to move", color_name(turn))),
+            Line::from(format!(
+                "{} left",
+                format_duration(ms.saturating_sub(elapsed_ms))
+            )),
+        ],
+        None => vec![],
+    }
+}
+
+/// Moves in Standard Algebraic Notation, a line per move number.
+fn move_lines(game: &MatchInfo) -> Vec<Line<'static>> {
+    // in case the match started with black to move
+    let black_first = game.initial_fen.split_whitespace().nth(1) == Some("b");
+
+    let mut sans: Vec<&str> = game.moves.iter().map(|mv| mv.san.as_str()).collect();
+    if black_first {
+        sans.insert(0, "..");
+    }
+
+    sans.chunks(2)
+        .enumerate()
+        .map(|(i, pair)| Line::from(format!("{:>3}. {}", i + 1, pair.join(" "))))
+        .collect()
+}
+
+fn chat_lines(app: &App, game: &MatchInfo) -> Vec<Line<'static>> {
+    app.chats
+        .get(&game.match_id.0)
+        .map(|chat| {
+            chat.iter()
+                .map(|line| {
+                    let sender = if line.color == game.color.0 {
+                        "You"
+                    } else {
+                        "Opponent"
+                    };
+
+                    Line::from(vec![
+                        Span::styled(
+                            format!("{}: ", sender),
+                            Style::default().add_modifier(Modifier::BOLD),
+                        ),
+                        Span::raw(line.text.clone()),
+                    ])
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn color_name(color: chess::Color) -> &'static str {
+    match color {
+        chess::Color::White => "White",
+        chess::Color::Black => "Black",
+    }
+}
+
+fn reason_name(reason: MatchEndReason) -> String {
+    serde_json::to_value(reason)
+        .ok()
+        .and_then(|value| value.as_str().map(|name| name.replace('_', " ")))
+        .unwrap_or_default()
+}
+
+/// Formats a duration as `m:ss`, `h:mm:ss` or `Nd hh:mm:ss`.
+fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    let (days, hours, minutes, secs) = (secs / 86_400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+
+    if days > 0 {
+        format!("{}d {:02}:{:02}:{:02}", days, hours, minutes, secs)
+    } else if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, secs)
+    } else {
+        format!("{}:{:02}", minutes, secs)
+    }
+}
@@ -0,0 +1,23 @@
+//! Chess rules, Chess960 castling included: bitboard positions, legal move generation, perft,
+//! Zobrist hashing, the FEN, PGN, SAN and UCI formats and the rules of variants.
+
+mod attacks;
+mod bitboard;
+mod fen;
+mod perft;
+mod pgn;
+mod position;
+mod san;
+mod types;
+mod variant;
+mod zobrist;
+
+pub use attacks::*;
+pub use bitboard::*;
+pub use fen::*;
+pub use perft::*;
+pub use pgn::*;
+pub use position::*;
+pub use san::*;
+pub use types::*;
+pub use variant::*;
@@ -0,0 +1,411 @@
+use once_cell::sync::Lazy;
+
+use super::{Bitboard, Color, Square};
+
+const KNIGHT_OFFSETS: [(i8, i8); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+
+const KING_OFFSETS: [(i8, i8); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+
+// Multipliers mapping every subset of a slider's relevant occupancy to a slot holding its attacks,
+// found by a trial-and-error search with a fixed seed.
+const ROOK_MAGICS: [u64; 64] = [
+    0x2080002080400010,
+    0x00c0002001401000,
+    0x2100110008402002,
+    0x0880080081041000,
+    0x0200020020041008,
+    0x2300040008010012,
+    0x0c00283004008201,
+    0x0180010000407a80,
+    0x0168800080400020,
+    0x0010400040201000,
+    0x1001002001001048,
+    0x1001002408100100,
+    0x0801000408010012,
+    0x4001000209000400,
+    0x08a20004c8020001,
+    0x2002801145002280,
+    0x0080860021004200,
+    0x001000c009402002,
+    0x00b0002004002800,
+    0x100a808010020800,
+    0x8101010008000410,
+    0x0244008002000480,
+    0x0000040010810208,
+    0x2000020000448534,
+    0x4104400480008033,
+    0x0000810100204000,
+    0x0440430900200010,
+    0x4600240900100100,
+    0x0060080080040080,
+    0x0001000300080400,
+    0x0004084400011002,
+    0x0023040200008041,
+    0x0580050043002080,
+    0x0400804002802008,
+    0x0001002001004010,
+    0x1000200901001000,
+    0x4410800801800c00,
+    0xa012003806001004,
+    0x0020100104008802,
+    0x0004808402000041,
+    0x0010400170898000,
+    0x0080500020004004,
+    0x1040408012020020,
+    0x8010040008004040,
+    0x2001080100110004,
+    0x0000020004008080,
+    0x0021010810040002,
+    0x0800008c43020024,
+    0x0000800021005100,
+    0x0070201040008080,
+    0x0000d04282006a00,
+    0x0010014400080240,
+    0x0001080110050100,
+    0x0012000810240600,
+    0x0402000801040200,
+    0x028100108a004100,
+    0x0050800300102045,
+    0x8208210040120882,
+    0x8010600101183441,
+    0x020b000910006045,
+    0x0241001002480005,
+    0x0081000400880241,
+    0x0000009008024124,
+    0x0048122980410402,
+];
+
+const BISHOP_MAGICS: [u64; 64] = [
+    0x0848020822040013,
+    0x8010a40085821200,
+    0x0008008430840822,
+    0x0808048108040000,
+    0x1304042100008104,
+    0x5001012010204023,
+    0x81048801b8200420,
+    0x200a008084012000,
+    0x0040102001042084,
+    0x840a505042428020,
+    0x0000700102202920,
+    0x44101c0c10800002,
+    0x0040040422000000,
+    0x0180020802090202,
+    0x4020020811041202,
+    0x000104308c042000,
+    0x4140661002424400,
+    0x0028012008010460,
+    0x0188062102002a00,
+    0x0014004840102008,
+    0x0105000290400002,
+    0x8001022200410400,
+    0x104a041918013446,
+    0x008a000082008238,
+    0x04a0060008100430,
+    0x0008220008820801,
+    0x2508041208005010,
+    0x4008080200202020,
+    0x2441001013004000,
+    0x0030008060407000,
+    0x4008108000420800,
+    0x0012021050290100,
+    0x0210080482200500,
+    0xcc01112048100480,
+    0x0020402806500440,
+    0x00048e0080580080,
+    0x0040102020020080,
+    0x0028010440080807,
+    0x4601041108008800,
+    0x8040810e04104200,
+    0x901210110400088a,
+    0xa003080212081050,
+    0x00c1004048401004,
+    0x900000a014400800,
+    0x0008021040405401,
+    0x4020008206002090,
+    0x0004190424030100,
+    0x0424008a02026250,
+    0x8004088250900040,
+    0x1c00430088a04200,
+    0x0001020094040001,
+    0x8040210020880061,
+    0x2010040450442032,
+    0x0800840850044001,
+    0x0004040802140004,
+    0x0004080a04222020,
+    0x8088802110022000,
+    0x1081a10416114400,
+    0x0205010a24060820,
+    0x0000000720411080,
+    0x1008000208430400,
+    0x580c026028810840,
+    0x802020441020a110,
+    0x12c0022401020018,
+];
+
+static TABLES: Lazy<Tables> = Lazy::new(Tables::new);
+
+/// Slider attacks from one square, looked up by the pieces on the squares relevant to them.
+struct Magic {
+    /// Squares whose occupancy can block the slider, excluding the board edges.
+    mask: Bitboard,
+    #[cfg_attr(all(target_arch = "x86_64", target_feature = "bmi2"), allow(dead_code))]
+    magic: u64,
+    #[cfg_attr(all(target_arch = "x86_64", target_feature = "bmi2"), allow(dead_code))]
+    shift: u32,
+    /// Start of this square's slots in the shared slider attack table.
+    offset: usize,
+}
+
+impl Magic {
+    #[cfg(all(target_arch = "x86_64", target_feature = "bmi2"))]
+    fn index(&self, occupied: Bitboard) -> usize {
+        // SAFETY: the bmi2 target feature is enabled at compile time
+        self.offset + unsafe { std::arch::x86_64::_pext_u64(occupied.0, self.mask.0) } as usize
+    }
+
+    #[cfg(not(all(target_arch = "x86_64", target_feature = "bmi2")))]
+    fn index(&self, occupied: Bitboard) -> usize {
+        self.offset + ((occupied & self.mask).0.wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct Tables {
+    knight: Vec<Bitboard>,
+    king: Vec<Bitboard>,
+    /// Pawn captures, white's followed by black's.
+    pawn: Vec<Bitboard>,
+    rook: Vec<Magic>,
+    bishop: Vec<Magic>,
+    sliders: Vec<Bitboard>,
+    /// Squares strictly between two squares on a line, indexed by `a * 64 + b`.
+    between: Vec<Bitboard>,
+    /// Whole line through two squares, including them, indexed by `a * 64 + b`.
+    line: Vec<Bitboard>,
+}
+
+impl Tables {
+    fn new() -> Tables {
+        let steps = |offsets: &[(i8, i8)]| {
+            Square::all()
+                .map(|square| {
+                    offsets
+                        .iter()
+                        .filter_map(|&(files, ranks)| square.offset(files, ranks))
+                        .fold(Bitboard::EMPTY, Bitboard::with)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut pawn = steps(&[(-1, 1), (1, 1)]);
+        pawn.extend(steps(&[(-1, -1), (1, -1)]));
+
+        let mut sliders = vec![];
+        let rook = magics(&ROOK_DIRECTIONS, &ROOK_MAGICS, &mut sliders);
+        let bishop = magics(&BISHOP_DIRECTIONS, &BISHOP_MAGICS, &mut sliders);
+
+        let mut between = vec![Bitboard::EMPTY; 64 * 64];
+        let mut line = vec![Bitboard::EMPTY; 64 * 64];
+
+        for a in Square::all() {
+            for &(files, ranks) in ROOK_DIRECTIONS.iter().chain(BISHOP_DIRECTIONS.iter()) {
+                let whole_line = (ray(a, files, ranks, Bitboard::EMPTY)
+                    | ray(a, -files, -ranks, Bitboard::EMPTY))
+                .with(a);
+                let mut squares_between = Bitboard::EMPTY;
+                let mut b = a;
+
+                while let Some(next) = b.offset(files, ranks) {
+                    b = next;
+                    between[a.index() * 64 + b.index()] = squares_between;
+                    line[a.index() * 64 + b.index()] = whole_line;
+                    squares_between = squares_between.with(b);
+                }
+            }
+        }
+
+        Tables {
+            knight: steps(&KNIGHT_OFFSETS),
+            king: steps(&KING_OFFSETS),
+            pawn,
+            rook,
+            bishop,
+            sliders,
+            between,
+            line,
+        }
+    }
+}
+
+/// Squares a slider reaches from `from` in a direction, up to and including the first occupied one.
+fn ray(from: Square, files: i8, ranks: i8, occupied: Bitboard) -> Bitboard {
+    let mut attacks = Bitboard::EMPTY;
+    let mut square = from;
+
+    while let Some(next) = square.offset(files, ranks) {
+        attacks = attacks.with(next);
+        if occupied.contains(next) {
+            break;
+        }
+        square = next;
+    }
+
+    attacks
+}
+
+fn slider_attacks(from: Square, directions: &[(i8, i8)], occupied: Bitboard) -> Bitboard {
+    directions
+        .iter()
+        .fold(Bitboard::EMPTY, |attacks, &(files, ranks)| {
+            attacks | ray(from, files, ranks, occupied)
+        })
+}
+
+/// Builds the lookups of a slider for every square, appending their attack slots to `table`.
+fn magics(
+    directions: &[(i8, i8)],
+    multipliers: &[u64; 64],
+    table: &mut Vec<Bitboard>,
+) -> Vec<Magic> {
+    Square::all()
+        .map(|square| {
+            let edges = ((Bitboard::rank(0) | Bitboard::rank(7)) & !Bitboard::rank(square.rank()))
+                | ((Bitboard::file(0) | Bitboard::file(7)) & !Bitboard::file(square.file()));
+            let mask = slider_attacks(square, directions, Bitboard::EMPTY) & !edges;
+            let bits = mask.count() as u32;
+
+            let magic = Magic {
+                mask,
+                magic: multipliers[square.index()],
+                shift: 64 - bits,
+                offset: table.len(),
+            };
+            table.resize(table.len() + (1 << bits), Bitboard::EMPTY);
+
+            // visit every subset of the mask
+            let mut occupied = Bitboard::EMPTY;
+            loop {
+                table[magic.index(occupied)] = slider_attacks(square, directions, occupied);
+
+                occupied = Bitboard(occupied.0.wrapping_sub(mask.0) & mask.0);
+                if occupied.is_empty() {
+                    break;
+                }
+            }
+
+            magic
+        })
+        .collect()
+}
+
+pub fn knight_attacks(square: Square) -> Bitboard {
+    TABLES.knight[square.index()]
+}
+
+pub fn king_attacks(square: Square) -> Bitboard {
+    TABLES.king[square.index()]
+}
+
+/// Squares a pawn of the given color on `square` captures on.
+pub fn pawn_attacks(color: Color, square: Square) -> Bitboard {
+    TABLES.pawn[color.index() * 64 + square.index()]
+}
+
+pub fn bishop_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    let tables = &*TABLES;
+    tables.sliders[tables.bishop[square.index()].index(occupied)]
+}
+
+pub fn rook_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    let tables = &*TABLES;
+    tables.sliders[tables.rook[square.index()].index(occupied)]
+}
+
+pub fn queen_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    bishop_attacks(square, occupied) | rook_attacks(square, occupied)
+}
+
+/// Squares strictly between two squares on a rank, file or diagonal, empty if they are not aligned.
+pub fn between(a: Square, b: Square) -> Bitboard {
+    TABLES.between[a.index() * 64 + b.index()]
+}
+
+/// The whole rank, file or diagonal through two squares, empty if they are not aligned.
+pub fn line(a: Square, b: Square) -> Bitboard {
+    TABLES.line[a.index() * 64 + b.index()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        bishop_attacks, rook_attacks, slider_attacks, Magic, BISHOP_DIRECTIONS, ROOK_DIRECTIONS,
+        TABLES,
+    };
+    use crate::chess::{Bitboard, Square};
+
+    /// Checks the lookup of every square against the attacks computed ray by ray, for every
+    /// subset of its blockers, with and without pieces outside of the mask.
+    fn assert_lookups_match(
+        magics: &[Magic],
+        directions: &[(i8, i8)],
+        lookup: fn(Square, Bitboard) -> Bitboard,
+    ) {
+        for square in Square::all() {
+            let mask = magics[square.index()].mask;
+            let mut occupied = Bitboard::EMPTY;
+
+            loop {
+                let expected = slider_attacks(square, directions, occupied);
+                assert_eq!(
+                    lookup(square, occupied),
+                    expected,
+                    "{:?} {:?}",
+                    square,
+                    occupied
+                );
+                assert_eq!(
+                    lookup(square, occupied | !mask),
+                    expected,
+                    "{:?} {:?}",
+                    square,
+                    occupied
+                );
+
+                occupied = Bitboard(occupied.0.wrapping_sub(mask.0) & mask.0);
+                if occupied.is_empty() {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rook_lookups_match_rays() {
+        assert_lookups_match(&TABLES.rook, &ROOK_DIRECTIONS, rook_attacks);
+    }
+
+    #[test]
+    fn bishop_lookups_match_rays() {
+        assert_lookups_match(&TABLES.bishop, &BISHOP_DIRECTIONS, bishop_attacks);
+    }
+}
@@ -0,0 +1,288 @@
+use thiserror::Error;
+
+use super::{Bitboard, CastlingRights, Color, Piece, Position, Role, Square};
+
+/// FEN of the standard starting position.
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum FenError {
+    #[error("expected 4 to 6 fields, found {0}")]
+    FieldCount(usize),
+    #[error("invalid piece placement")]
+    Board,
+    #[error("each side must have exactly one king")]
+    Kings,
+    #[error("pawns cannot be on the first or last rank")]
+    PawnsOnBackRank,
+    #[error("invalid side to move")]
+    Turn,
+    #[error("invalid castling rights")]
+    Castling,
+    #[error("invalid en passant square")]
+    EpSquare,
+    #[error("invalid halfmove clock")]
+    HalfmoveClock,
+    #[error("invalid fullmove number")]
+    FullmoveNumber,
+    #[error("the side not to move is in check")]
+    OppositeCheck,
+}
+
+impl Position {
+    /// Parses a position in Forsyth-Edwards Notation, the move counters may be omitted.
+    pub fn from_fen(fen: &str) -> Result<Position, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 || fields.len() > 6 {
+            return Err(FenError::FieldCount(fields.len()));
+        }
+
+        let mut position = parse_board(fields[0])?;
+
+        position.turn = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            _ => return Err(FenError::Turn),
+        };
+
+        parse_castling(&mut position, fields[2])?;
+
+        position.ep_square = match fields[3] {
+            "-" => None,
+            square => Some(square.parse::<Square>().map_err(|_| FenError::EpSquare)?),
+        };
+
+        position.halfmove_clock = match fields.get(4) {
+            Some(field) => field.parse().map_err(|_| FenError::HalfmoveClock)?,
+            None => 0,
+        };
+
+        position.fullmove_number = match fields.get(5) {
+            Some(field) => match field.parse() {
+                Ok(number) if number > 0 => number,
+                _ => return Err(FenError::FullmoveNumber),
+            },
+            None => 1,
+        };
+
+        position.validate()?;
+        position.zobrist = position.compute_zobrist();
+        Ok(position)
+    }
+
+    pub fn fen(&self) -> String {
+        let mut fen = String::new();
+
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+
+            for file in 0..8 {
+                match self.piece_at(Square::new(file, rank).unwrap()) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(piece.char());
+                    }
+                    None => empty += 1,
+                }
+            }
+
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(match self.turn {
+            Color::White => " w ",
+            Color::Black => " b ",
+        });
+
+        if self.castling.is_empty() {
+            fen.push('-');
+        } else {
+            for &(color, king_side, right, c) in [
+                (Color::White, true, self.castling.white_king_side, 'K'),
+                (Color::White, false, self.castling.white_queen_side, 'Q'),
+                (Color::Black, true, self.castling.black_king_side, 'k'),
+                (Color::Black, false, self.castling.black_queen_side, 'q'),
+            ]
+            .iter()
+            {
+                if !right {
+                    continue;
+                }
+
+                // Chess960 positions name the file of the castling rook, as in Shredder-FEN
+                if self.chess960 {
+                    let file = (b'a' + self.castling_rook(color, king_side).file()) as char;
+                    fen.push(match color {
+                        Color::White => file.to_ascii_uppercase(),
+                        Color::Black => file,
+                    });
+                } else {
+                    fen.push(c);
+                }
+            }
+        }
+
+        match self.ep_square {
+            Some(square) => fen.push_str(&format!(" {}", square)),
+            None => fen.push_str(" -"),
+        }
+
+        fen.push_str(&format!(
+            " {} {}",
+            self.halfmove_clock, self.fullmove_number
+        ));
+        fen
+    }
+
+    fn validate(&self) -> Result<(), FenError> {
+        for &color in Color::ALL.iter() {
+            if self.pieces(color, Role::King).count() != 1 {
+                return Err(FenError::Kings);
+            }
+        }
+
+        let back_ranks = Bitboard::rank(0) | Bitboard::rank(7);
+        if (self.by_role(Role::Pawn) & back_ranks).any() {
+            return Err(FenError::PawnsOnBackRank);
+        }
+
+        if let Some(ep_square) = self.ep_square {
+            // the pawn which just moved two squares must be in front of the en passant square
+            let (ep_rank, pawn_rank) = match self.turn {
+                Color::White => (5, 4),
+                Color::Black => (2, 3),
+            };
+
+            let pawn = Piece {
+                color: !self.turn,
+                role: Role::Pawn,
+            };
+            let pawn_square = Square::new(ep_square.file(), pawn_rank).unwrap();
+
+            if ep_square.rank() != ep_rank
+                || self.piece_at(ep_square).is_some()
+                || self.piece_at(pawn_square) != Some(pawn)
+            {
+                return Err(FenError::EpSquare);
+            }
+        }
+
+        let opponent_king = self.king_square(!self.turn).unwrap();
+        if self.is_attacked(opponent_king, self.turn) {
+            return Err(FenError::OppositeCheck);
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_board(field: &str) -> Result<Position, FenError> {
+    let mut position = Position::empty();
+
+    let ranks: Vec<&str> = field.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenError::Board);
+    }
+
+    for (i, rank) in ranks.iter().enumerate() {
+        let rank_index = 7 - i as u8;
+        let mut file = 0u8;
+
+        for c in rank.chars() {
+            if let Some(empty) = c.to_digit(10) {
+                if empty == 0 || empty > 8 {
+                    return Err(FenError::Board);
+                }
+                file += empty as u8;
+            } else {
+                let piece = Piece::from_char(c).ok_or(FenError::Board)?;
+                let square = Square::new(file, rank_index).ok_or(FenError::Board)?;
+                position.put(square, piece);
+                file += 1;
+            }
+
+            if file > 8 {
+                return Err(FenError::Board);
+            }
+        }
+
+        if file != 8 {
+            return Err(FenError::Board);
+        }
+    }
+
+    Ok(position)
+}
+
+/// Parses castling rights, either as `KQkq` or as the files of the castling rooks like in
+/// Shredder-FEN. `K` and `Q` stand for the rook furthest from the king on that side, as in
+/// X-FEN, and anything but the standard setup makes the position a Chess960 one.
+fn parse_castling(position: &mut Position, field: &str) -> Result<(), FenError> {
+    position.castling = CastlingRights::default();
+
+    if field == "-" {
+        return Ok(());
+    }
+
+    for c in field.chars() {
+        let (color, back_rank) = if c.is_ascii_uppercase() {
+            (Color::White, 0)
+        } else {
+            (Color::Black, 7)
+        };
+
+        let king = position
+            .king_square(color)
+            .filter(|king| king.rank() == back_rank)
+            .ok_or(FenError::Castling)?;
+        let rook_files = (position.pieces(color, Role::Rook) & Bitboard::rank(back_rank))
+            .map(|rook| rook.file());
+
+        let (king_side, file) = match c.to_ascii_lowercase() {
+            'k' => (
+                true,
+                rook_files
+                    .filter(|&file| file > king.file())
+                    .max()
+                    .unwrap_or(7),
+            ),
+            'q' => (
+                false,
+                rook_files
+                    .filter(|&file| file < king.file())
+                    .min()
+                    .unwrap_or(0),
+            ),
+            file @ 'a'..='h' => {
+                let file = file as u8 - b'a';
+                (file > king.file(), file)
+            }
+            _ => return Err(FenError::Castling),
+        };
+
+        let named_file = c.is_ascii_alphabetic() && !"KQkq".contains(c);
+        let standard_rook = file == if king_side { 7 } else { 0 };
+        if named_file || king.file() != 4 || !standard_rook {
+            position.chess960 = true;
+        }
+
+        let right = position.castling.right_mut(color, king_side);
+        if *right {
+            return Err(FenError::Castling);
+        }
+        *right = true;
+
+        let side = if king_side { 0 } else { 1 };
+        position.castling_files[color.index()][side] = file;
+    }
+
+    Ok(())
+}
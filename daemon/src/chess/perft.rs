@@ -0,0 +1,26 @@
+use super::Position;
+
+/// Counts the leaf nodes of the legal move tree of a position down to `depth` plies.
+///
+/// The counts are well known for many positions, so comparing against them checks that move
+/// generation is neither missing moves nor producing illegal ones.
+pub fn perft(position: &Position, depth: u32) -> u64 {
+    perft_in_place(&mut position.clone(), depth)
+}
+
+fn perft_in_place(position: &mut Position, depth: u32) -> u64 {
+    match depth {
+        0 => 1,
+        1 => position.legal_moves().len() as u64,
+        _ => position
+            .legal_moves()
+            .into_iter()
+            .map(|mv| {
+                let undo = position.make_move(mv);
+                let nodes = perft_in_place(position, depth - 1);
+                position.unmake_move(mv, undo);
+                nodes
+            })
+            .sum(),
+    }
+}
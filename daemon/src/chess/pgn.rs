@@ -0,0 +1,288 @@
+use std::{fmt, iter::Peekable, str::Chars};
+
+use thiserror::Error;
+
+/// Length at which lines of exported movetext are wrapped, as recommended by the PGN standard.
+const MAX_LINE_LEN: usize = 79;
+
+/// Result marker terminating a PGN movetext.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgnResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    /// Game in progress, abandoned or with an unknown result.
+    Unknown,
+}
+
+impl PgnResult {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PgnResult::WhiteWins => "1-0",
+            PgnResult::BlackWins => "0-1",
+            PgnResult::Draw => "1/2-1/2",
+            PgnResult::Unknown => "*",
+        }
+    }
+
+    fn parse(token: &str) -> Option<PgnResult> {
+        match token {
+            "1-0" => Some(PgnResult::WhiteWins),
+            "0-1" => Some(PgnResult::BlackWins),
+            "1/2-1/2" => Some(PgnResult::Draw),
+            "*" => Some(PgnResult::Unknown),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum PgnError {
+    #[error("invalid tag pair")]
+    InvalidTag,
+    #[error("unterminated comment")]
+    UnterminatedComment,
+    #[error("unbalanced variation parentheses")]
+    UnbalancedVariation,
+    #[error("invalid movetext token `{0}`")]
+    InvalidToken(String),
+    #[error("movetext continues after the game result")]
+    TokenAfterResult,
+}
+
+/// A game in Portable Game Notation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pgn {
+    /// Tag pairs in the order they appear.
+    pub tags: Vec<(String, String)>,
+    /// Moves of the main line in SAN, stripped of move numbers, annotations, comments and
+    /// variations.
+    pub moves: Vec<String>,
+    pub result: Option<PgnResult>,
+}
+
+impl Pgn {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parses a single game. Moves are only checked to be made of SAN characters, not to be
+    /// legal.
+    pub fn parse(pgn: &str) -> Result<Pgn, PgnError> {
+        let mut game = Pgn::default();
+        let mut chars = pgn.chars().peekable();
+        let mut variation_depth = 0u32;
+        let mut at_line_start = true;
+
+        while let Some(c) = chars.next() {
+            let line_start = at_line_start;
+            at_line_start = c == '\n';
+
+            match c {
+                c if c.is_whitespace() => {}
+
+                // escaped lines are ignored
+                '%' if line_start => skip_line(&mut chars),
+                ';' => skip_line(&mut chars),
+
+                '{' => {
+                    if !chars.by_ref().any(|c| c == '}') {
+                        return Err(PgnError::UnterminatedComment);
+                    }
+                }
+
+                '[' => {
+                    if !game.moves.is_empty() || game.result.is_some() || variation_depth > 0 {
+                        return Err(PgnError::InvalidTag);
+                    }
+
+                    game.tags.push(parse_tag(&mut chars)?);
+                }
+
+                '(' => variation_depth += 1,
+                ')' => {
+                    variation_depth = variation_depth
+                        .checked_sub(1)
+                        .ok_or(PgnError::UnbalancedVariation)?;
+                }
+
+                '$' => {
+                    while chars.peek().map_or(false, char::is_ascii_digit) {
+                        chars.next();
+                    }
+                }
+
+                c => {
+                    let mut token = c.to_string();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() || "{}()[];$".contains(c) {
+                            break;
+                        }
+                        token.push(c);
+                        chars.next();
+                    }
+
+                    if variation_depth > 0 {
+                        continue;
+                    }
+
+                    if game.result.is_some() {
+                        return Err(PgnError::TokenAfterResult);
+                    }
+
+                    if let Some(result) = PgnResult::parse(&token) {
+                        game.result = Some(result);
+                        continue;
+                    }
+
+                    if let Some(san) = strip_move_number(&token) {
+                        game.moves.push(parse_san_token(san, &token)?);
+                    }
+                }
+            }
+        }
+
+        if variation_depth > 0 {
+            return Err(PgnError::UnbalancedVariation);
+        }
+
+        Ok(game)
+    }
+}
+
+/// Writes the game in the PGN export format: one tag pair per line, an empty line, then the
+/// movetext wrapped at 79 characters. Moves are numbered from white's first move and the result
+/// defaults to `*` when unknown.
+impl fmt::Display for Pgn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{} \"{}\"]", name, value)?;
+        }
+
+        if !self.tags.is_empty() {
+            writeln!(f)?;
+        }
+
+        let mut tokens = vec![];
+        for (ply, san) in self.moves.iter().enumerate() {
+            if ply % 2 == 0 {
+                tokens.push(format!("{}.", ply / 2 + 1));
+            }
+            tokens.push(san.clone());
+        }
+        tokens.push(
+            self.result
+                .unwrap_or(PgnResult::Unknown)
+                .as_str()
+                .to_string(),
+        );
+
+        let mut line_len = 0;
+        for token in tokens {
+            if line_len > 0 && line_len + 1 + token.len() > MAX_LINE_LEN {
+                writeln!(f)?;
+                line_len = 0;
+            } else if line_len > 0 {
+                write!(f, " ")?;
+                line_len += 1;
+            }
+
+            write!(f, "{}", token)?;
+            line_len += token.len();
+        }
+
+        writeln!(f)
+    }
+}
+
+/// Skips to the end of the line, leaving the newline to be read.
+fn skip_line(chars: &mut Peekable<Chars>) {
+    while chars.peek().map_or(false, |&c| c != '\n') {
+        chars.next();
+    }
+}
+
+fn parse_tag(chars: &mut Peekable<Chars>) -> Result<(String, String), PgnError> {
+    skip_whitespace(chars);
+
+    let mut name = String::new();
+    while let Some(&c) = chars.peek() {
+        if !(c.is_ascii_alphanumeric() || c == '_') {
+            break;
+        }
+        name.push(c);
+        chars.next();
+    }
+
+    skip_whitespace(chars);
+
+    if name.is_empty() || chars.next() != Some('"') {
+        return Err(PgnError::InvalidTag);
+    }
+
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => break,
+            Some('\\') => match chars.next() {
+                Some(c @ '"') | Some(c @ '\\') => value.push(c),
+                _ => return Err(PgnError::InvalidTag),
+            },
+            Some(c) => value.push(c),
+            None => return Err(PgnError::InvalidTag),
+        }
+    }
+
+    skip_whitespace(chars);
+
+    if chars.next() != Some(']') {
+        return Err(PgnError::InvalidTag);
+    }
+
+    Ok((name, value))
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().map_or(false, |c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+/// Strips a leading move number like `12.` or `12...`, returning `None` if nothing is left.
+fn strip_move_number(token: &str) -> Option<&str> {
+    let digits = token
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(token.len());
+    let rest = &token[digits..];
+
+    if digits > 0 && (rest.is_empty() || rest.starts_with('.')) {
+        let rest = rest.trim_start_matches('.');
+        if rest.is_empty() {
+            None
+        } else {
+            Some(rest)
+        }
+    } else {
+        Some(token)
+    }
+}
+
+/// Validates the characters of a SAN move, dropping trailing `!` and `?` annotations.
+fn parse_san_token(san: &str, token: &str) -> Result<String, PgnError> {
+    let san = san.trim_end_matches(|c| c == '!' || c == '?');
+
+    let valid = !san.is_empty()
+        && san
+            .chars()
+            .all(|c| matches!(c, 'a'..='h' | '1'..='8' | 'x' | 'N' | 'B' | 'R' | 'Q' | 'K' | 'O' | '0' | '-' | '=' | '+' | '#'));
+
+    if valid {
+        Ok(san.to_string())
+    } else {
+        Err(PgnError::InvalidToken(token.to_string()))
+    }
+}
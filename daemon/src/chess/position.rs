@@ -0,0 +1,805 @@
+use thiserror::Error;
+
+use super::{
+    attacks::{
+        between, bishop_attacks, king_attacks, knight_attacks, line, pawn_attacks, queen_attacks,
+        rook_attacks,
+    },
+    zobrist::{castling_key, ep_file_key, piece_key, turn_key},
+    Bitboard, CastlingRights, Color, Move, Outcome, Piece, Role, Square,
+};
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("illegal move")]
+pub struct IllegalMoveError;
+
+/// A chess position, with everything needed to generate its legal moves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub(super) by_color: [Bitboard; 2],
+    pub(super) by_role: [Bitboard; 6],
+    /// Piece on each square, mirroring the bitboards for quick lookups.
+    pub(super) board: [Option<Piece>; 64],
+    pub(super) turn: Color,
+    pub(super) castling: CastlingRights,
+    /// Files of the rooks castling king side and queen side, for each color.
+    pub(super) castling_files: [[u8; 2]; 2],
+    /// Whether castling moves are written as the king moving onto its rook, as in Chess960.
+    pub(super) chess960: bool,
+    /// Square a pawn which just moved two squares can be captured on.
+    pub(super) ep_square: Option<Square>,
+    pub(super) halfmove_clock: u32,
+    pub(super) fullmove_number: u32,
+    /// Zobrist hash of the position, updated incrementally as pieces move.
+    pub(super) zobrist: u64,
+}
+
+/// State lost when making a move, needed to unmake it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Undo {
+    captured: Option<Piece>,
+    castling: CastlingRights,
+    castled: bool,
+    ep_square: Option<Square>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    zobrist: u64,
+}
+
+impl Position {
+    /// A position without pieces, white to move.
+    pub(super) fn empty() -> Position {
+        Position {
+            by_color: [Bitboard::EMPTY; 2],
+            by_role: [Bitboard::EMPTY; 6],
+            board: [None; 64],
+            turn: Color::White,
+            castling: CastlingRights::default(),
+            castling_files: [[7, 0]; 2],
+            chess960: false,
+            ep_square: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            zobrist: 0,
+        }
+    }
+
+    /// The standard starting position.
+    pub fn startpos() -> Position {
+        const BACK_RANK: [Role; 8] = [
+            Role::Rook,
+            Role::Knight,
+            Role::Bishop,
+            Role::Queen,
+            Role::King,
+            Role::Bishop,
+            Role::Knight,
+            Role::Rook,
+        ];
+
+        Self::with_back_rank(BACK_RANK)
+    }
+
+    /// Starting position number `index` of Chess960, from 0 to 959 in the Scharnagl numbering,
+    /// 518 being the standard setup.
+    ///
+    /// Castling moves of the returned position are written as the king moving onto its rook.
+    pub fn chess960(index: u16) -> Option<Position> {
+        // pairs of files taken by the knights among the five left after bishops and queen
+        const KNIGHTS: [(usize, usize); 10] = [
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (0, 4),
+            (1, 2),
+            (1, 3),
+            (1, 4),
+            (2, 3),
+            (2, 4),
+            (3, 4),
+        ];
+
+        if index >= 960 {
+            return None;
+        }
+
+        let mut back_rank = [None; 8];
+        let mut n = index as usize;
+
+        back_rank[n % 4 * 2 + 1] = Some(Role::Bishop);
+        n /= 4;
+        back_rank[n % 4 * 2] = Some(Role::Bishop);
+        n /= 4;
+
+        let free = |back_rank: &[Option<Role>; 8]| -> Vec<usize> {
+            (0..8).filter(|&file| back_rank[file].is_none()).collect()
+        };
+
+        back_rank[free(&back_rank)[n % 6]] = Some(Role::Queen);
+        n /= 6;
+
+        let (first, second) = KNIGHTS[n];
+        let files = free(&back_rank);
+        back_rank[files[first]] = Some(Role::Knight);
+        back_rank[files[second]] = Some(Role::Knight);
+
+        let files = free(&back_rank);
+        for (&file, &role) in files
+            .iter()
+            .zip([Role::Rook, Role::King, Role::Rook].iter())
+        {
+            back_rank[file] = Some(role);
+        }
+
+        let mut roles = [Role::Pawn; 8];
+        for (role, placed) in roles.iter_mut().zip(back_rank.iter()) {
+            *role = placed.unwrap();
+        }
+
+        let mut position = Self::with_back_rank(roles);
+        position.castling_files = [[files[2] as u8, files[0] as u8]; 2];
+        position.chess960 = true;
+        Some(position)
+    }
+
+    /// A starting position with the given pieces on the first and last ranks, all castling
+    /// rights and the rooks closest to the corners castling.
+    fn with_back_rank(back_rank: [Role; 8]) -> Position {
+        let mut position = Position::empty();
+
+        for (file, &role) in back_rank.iter().enumerate() {
+            let file = file as u8;
+
+            for &(color, back_rank, pawn_rank) in
+                [(Color::White, 0, 1), (Color::Black, 7, 6)].iter()
+            {
+                position.put(Square::new(file, back_rank).unwrap(), Piece { color, role });
+                position.put(
+                    Square::new(file, pawn_rank).unwrap(),
+                    Piece {
+                        color,
+                        role: Role::Pawn,
+                    },
+                );
+            }
+        }
+
+        position.castling = CastlingRights::ALL;
+        position.zobrist ^= castling_key(CastlingRights::ALL);
+        position
+    }
+
+    pub fn piece_at(&self, square: Square) -> Option<Piece> {
+        self.board[square.index()]
+    }
+
+    /// Side to move.
+    pub fn turn(&self) -> Color {
+        self.turn
+    }
+
+    pub fn castling_rights(&self) -> CastlingRights {
+        self.castling
+    }
+
+    /// Whether castling moves are written as the king moving onto its rook, as in Chess960,
+    /// rather than two squares towards it.
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    /// Starting square of the rook castling on one side.
+    pub(super) fn castling_rook(&self, color: Color, king_side: bool) -> Square {
+        let side = if king_side { 0 } else { 1 };
+        let back_rank = match color {
+            Color::White => 0,
+            Color::Black => 7,
+        };
+
+        Square::new(self.castling_files[color.index()][side], back_rank).unwrap()
+    }
+
+    pub fn ep_square(&self) -> Option<Square> {
+        self.ep_square
+    }
+
+    /// Number of half moves since the last capture or pawn move.
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    pub fn fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+
+    /// Zobrist hash of the position, equal for positions that repeat in the sense of the
+    /// repetition rules: same pieces, side to move, castling rights and en passant possibilities.
+    pub fn zobrist_key(&self) -> u64 {
+        self.zobrist
+    }
+
+    /// Computes the Zobrist hash from scratch rather than incrementally.
+    pub(super) fn compute_zobrist(&self) -> u64 {
+        let pieces = Square::all()
+            .filter_map(|square| Some(piece_key(self.piece_at(square)?, square)))
+            .fold(0, |hash, key| hash ^ key);
+
+        pieces ^ turn_key(self.turn) ^ castling_key(self.castling) ^ self.ep_key()
+    }
+
+    /// Hash contribution of the en passant square, only counted when a pawn of the side to move
+    /// attacks it so that positions differing by an unusable en passant square hash the same.
+    fn ep_key(&self) -> u64 {
+        match self.ep_square {
+            Some(square)
+                if (pawn_attacks(!self.turn, square) & self.pieces(self.turn, Role::Pawn))
+                    .any() =>
+            {
+                ep_file_key(square.file())
+            }
+            _ => 0,
+        }
+    }
+
+    /// Squares occupied by any piece.
+    pub fn occupied(&self) -> Bitboard {
+        self.by_color[0] | self.by_color[1]
+    }
+
+    pub fn by_color(&self, color: Color) -> Bitboard {
+        self.by_color[color.index()]
+    }
+
+    pub fn by_role(&self, role: Role) -> Bitboard {
+        self.by_role[role.index()]
+    }
+
+    pub fn pieces(&self, color: Color, role: Role) -> Bitboard {
+        self.by_color(color) & self.by_role(role)
+    }
+
+    pub fn king_square(&self, color: Color) -> Option<Square> {
+        self.pieces(color, Role::King).first()
+    }
+
+    /// Whether the side to move is in check.
+    pub fn is_check(&self) -> bool {
+        self.checkers().any()
+    }
+
+    /// Pieces giving check to the side to move.
+    pub fn checkers(&self) -> Bitboard {
+        match self.king_square(self.turn) {
+            Some(king) => self.attackers(king, !self.turn, self.occupied()),
+            None => Bitboard::EMPTY,
+        }
+    }
+
+    /// Whether any piece of color `by` attacks `square`.
+    pub fn is_attacked(&self, square: Square, by: Color) -> bool {
+        self.attackers(square, by, self.occupied()).any()
+    }
+
+    /// Pieces of color `by` attacking `square`, with sliders blocked by `occupied`.
+    pub(super) fn attackers(&self, square: Square, by: Color, occupied: Bitboard) -> Bitboard {
+        let queens = self.by_role(Role::Queen);
+
+        let attackers = (pawn_attacks(!by, square) & self.by_role(Role::Pawn))
+            | (knight_attacks(square) & self.by_role(Role::Knight))
+            | (king_attacks(square) & self.by_role(Role::King))
+            | (bishop_attacks(square, occupied) & (self.by_role(Role::Bishop) | queens))
+            | (rook_attacks(square, occupied) & (self.by_role(Role::Rook) | queens));
+
+        attackers & self.by_color(by)
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(64);
+        self.generate_legal_moves(&mut moves);
+        moves
+    }
+
+    pub fn is_legal(&self, mv: Move) -> bool {
+        self.legal_moves().contains(&mv)
+    }
+
+    /// Plays a move, failing if it is not legal in this position.
+    pub fn play(&mut self, mv: Move) -> Result<(), IllegalMoveError> {
+        if !self.is_legal(mv) {
+            return Err(IllegalMoveError);
+        }
+
+        self.make_move(mv);
+        Ok(())
+    }
+
+    /// How the game ended, if the side to move has no legal moves.
+    pub fn outcome(&self) -> Option<Outcome> {
+        if !self.legal_moves().is_empty() {
+            return None;
+        }
+
+        if self.is_check() {
+            Some(Outcome::Checkmate { winner: !self.turn })
+        } else {
+            Some(Outcome::Stalemate)
+        }
+    }
+
+    /// Whether neither side can checkmate: bare kings, a single knight or bishop against a bare
+    /// king, or any number of bishops all on squares of the same color.
+    pub fn is_insufficient_material(&self) -> bool {
+        let heavy = self.by_role(Role::Pawn) | self.by_role(Role::Rook) | self.by_role(Role::Queen);
+        if heavy.any() {
+            return false;
+        }
+
+        let knights = self.by_role(Role::Knight);
+        let bishops = self.by_role(Role::Bishop);
+
+        if (knights | bishops).count() <= 1 {
+            return true;
+        }
+
+        knights.is_empty()
+            && ((bishops & Bitboard::DARK_SQUARES).is_empty()
+                || (bishops & !Bitboard::DARK_SQUARES).is_empty())
+    }
+
+    /// Whether `color` cannot checkmate by any sequence of legal moves.
+    ///
+    /// Any piece of the opponent may end up blocking its own king, so unless neither side can
+    /// checkmate this only holds for a bare king.
+    pub fn has_insufficient_material(&self, color: Color) -> bool {
+        self.is_insufficient_material()
+            || (self.by_color(color) & !self.by_role(Role::King)).is_empty()
+    }
+
+    /// Places a piece on an empty square.
+    pub(super) fn put(&mut self, square: Square, piece: Piece) {
+        self.by_color[piece.color.index()].toggle(square);
+        self.by_role[piece.role.index()].toggle(square);
+        self.board[square.index()] = Some(piece);
+        self.zobrist ^= piece_key(piece, square);
+    }
+
+    pub(super) fn remove(&mut self, square: Square) -> Option<Piece> {
+        let piece = self.board[square.index()].take()?;
+        self.by_color[piece.color.index()].toggle(square);
+        self.by_role[piece.role.index()].toggle(square);
+        self.zobrist ^= piece_key(piece, square);
+        Some(piece)
+    }
+
+    /// Pieces of `color` which cannot leave the line between their king and an enemy slider.
+    fn pinned(&self, color: Color, king: Square) -> Bitboard {
+        let them = self.by_color(!color);
+        let queens = self.by_role(Role::Queen);
+        let snipers = ((rook_attacks(king, Bitboard::EMPTY) & (self.by_role(Role::Rook) | queens))
+            | (bishop_attacks(king, Bitboard::EMPTY) & (self.by_role(Role::Bishop) | queens)))
+            & them;
+
+        let occupied = self.occupied();
+        let mut pinned = Bitboard::EMPTY;
+
+        for sniper in snipers {
+            let blockers = between(king, sniper) & occupied;
+            if !blockers.more_than_one() {
+                pinned |= blockers & self.by_color(color);
+            }
+        }
+
+        pinned
+    }
+
+    fn generate_legal_moves(&self, moves: &mut Vec<Move>) {
+        let us = self.turn;
+        let king = match self.king_square(us) {
+            Some(king) => king,
+            None => return,
+        };
+
+        let ours = self.by_color(us);
+        let occupied = self.occupied();
+        let checkers = self.attackers(king, !us, occupied);
+
+        // the king cannot step back along the line of a checking slider either
+        let without_king = occupied.without(king);
+        for to in king_attacks(king) & !ours {
+            if self.attackers(to, !us, without_king).is_empty() {
+                moves.push(Move {
+                    from: king,
+                    to,
+                    promotion: None,
+                });
+            }
+        }
+
+        if checkers.more_than_one() {
+            return;
+        }
+
+        // with a single checker, other pieces must capture it or block its line
+        let target = match checkers.first() {
+            Some(checker) => between(king, checker).with(checker),
+            None => {
+                self.castling_moves(king, moves);
+                Bitboard::ALL
+            }
+        };
+
+        let pins = Some((king, self.pinned(us, king)));
+        self.piece_moves(target, pins, moves);
+        self.pawn_moves(target, pins, moves);
+    }
+
+    /// Moves following how each piece moves, castling included, without regard to whether they
+    /// leave the mover's king attacked, for variants telling legal moves apart by their own rules.
+    pub(super) fn pseudo_legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(64);
+        let king = match self.king_square(self.turn) {
+            Some(king) => king,
+            None => return moves,
+        };
+
+        for to in king_attacks(king) & !self.by_color(self.turn) {
+            moves.push(Move {
+                from: king,
+                to,
+                promotion: None,
+            });
+        }
+        if !self.is_check() {
+            self.castling_moves(king, &mut moves);
+        }
+
+        self.piece_moves(Bitboard::ALL, None, &mut moves);
+        self.pawn_moves(Bitboard::ALL, None, &mut moves);
+        moves
+    }
+
+    /// Moves of the knights, bishops, rooks and queens of the side to move onto `target`.
+    ///
+    /// `pins` holds the king of the side to move and the pieces pinned to it, which then only
+    /// move along their pin.
+    fn piece_moves(
+        &self,
+        target: Bitboard,
+        pins: Option<(Square, Bitboard)>,
+        moves: &mut Vec<Move>,
+    ) {
+        let ours = self.by_color(self.turn);
+        let occupied = self.occupied();
+        let pieces = ours & !self.by_role(Role::King) & !self.by_role(Role::Pawn);
+
+        for from in pieces {
+            let mut destinations = match self.board[from.index()].map(|piece| piece.role) {
+                Some(Role::Knight) => knight_attacks(from),
+                Some(Role::Bishop) => bishop_attacks(from, occupied),
+                Some(Role::Rook) => rook_attacks(from, occupied),
+                Some(Role::Queen) => queen_attacks(from, occupied),
+                _ => Bitboard::EMPTY,
+            } & !ours
+                & target;
+
+            if let Some((king, _)) = pins.filter(|(_, pinned)| pinned.contains(from)) {
+                destinations &= line(king, from);
+            }
+
+            for to in destinations {
+                moves.push(Move {
+                    from,
+                    to,
+                    promotion: None,
+                });
+            }
+        }
+    }
+
+    /// Pawn moves of the side to move onto `target`, with `pins` as for
+    /// [`Position::piece_moves`]. Without them en passant captures are not checked for exposing
+    /// the king either.
+    fn pawn_moves(
+        &self,
+        target: Bitboard,
+        pins: Option<(Square, Bitboard)>,
+        moves: &mut Vec<Move>,
+    ) {
+        let us = self.turn;
+        let (forward, start_rank, last_rank) = match us {
+            Color::White => (1, 1, 7),
+            Color::Black => (-1, 6, 0),
+        };
+
+        let occupied = self.occupied();
+        let theirs = self.by_color(!us);
+
+        for from in self.pieces(us, Role::Pawn) {
+            let mut destinations = pawn_attacks(us, from) & theirs;
+
+            if let Some(to) = from.offset(0, forward).filter(|&to| !occupied.contains(to)) {
+                destinations = destinations.with(to);
+
+                if from.rank() == start_rank {
+                    if let Some(to) = to.offset(0, forward).filter(|&to| !occupied.contains(to)) {
+                        destinations = destinations.with(to);
+                    }
+                }
+            }
+
+            destinations &= target;
+            if let Some((king, _)) = pins.filter(|(_, pinned)| pinned.contains(from)) {
+                destinations &= line(king, from);
+            }
+
+            for to in destinations {
+                if to.rank() == last_rank {
+                    for &role in Role::PROMOTIONS.iter() {
+                        moves.push(Move {
+                            from,
+                            to,
+                            promotion: Some(role),
+                        });
+                    }
+                } else {
+                    moves.push(Move {
+                        from,
+                        to,
+                        promotion: None,
+                    });
+                }
+            }
+
+            if let Some(ep_square) = self.ep_square {
+                if pawn_attacks(us, from).contains(ep_square)
+                    && pins.map_or(true, |(king, _)| {
+                        self.is_safe_en_passant(king, from, ep_square)
+                    })
+                {
+                    moves.push(Move {
+                        from,
+                        to: ep_square,
+                        promotion: None,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Whether an en passant capture leaves the king safe, removing two pieces from a line at
+    /// once is not covered by the pin and check logic.
+    fn is_safe_en_passant(&self, king: Square, from: Square, to: Square) -> bool {
+        let captured = Square::new(to.file(), from.rank()).unwrap();
+        let occupied = self.occupied().without(from).without(captured).with(to);
+        let us = self.turn;
+
+        let attackers = self.attackers(king, !us, occupied).without(captured);
+        attackers.is_empty()
+    }
+
+    fn castling_moves(&self, king: Square, moves: &mut Vec<Move>) {
+        let us = self.turn;
+        let back_rank = king.rank();
+        let rooks = self.pieces(us, Role::Rook);
+
+        for &king_side in [true, false].iter() {
+            let has_right = if king_side {
+                self.castling.king_side(us)
+            } else {
+                self.castling.queen_side(us)
+            };
+
+            if !has_right {
+                continue;
+            }
+
+            let rook = self.castling_rook(us, king_side);
+            if !rooks.contains(rook) || rook.rank() != back_rank {
+                continue;
+            }
+
+            let (king_to, rook_to) = Self::castling_destinations(back_rank, king_side);
+
+            // both pieces travel to their destination, only each other may stand in the way
+            let path = (between(king, king_to).with(king_to)
+                | between(rook, rook_to).with(rook_to))
+            .without(king)
+            .without(rook);
+            if (path & self.occupied()).any() {
+                continue;
+            }
+
+            // the castling rook may have been shielding the king's path
+            let occupied = self.occupied().without(rook);
+            let mut king_path = between(king, king_to).with(king_to);
+            if !king_path.all(|square| self.attackers(square, !us, occupied).is_empty()) {
+                continue;
+            }
+
+            moves.push(Move {
+                from: king,
+                to: if self.chess960 { rook } else { king_to },
+                promotion: None,
+            });
+        }
+    }
+
+    /// King and rook destination squares when castling on a rank.
+    fn castling_destinations(rank: u8, king_side: bool) -> (Square, Square) {
+        let (king_file, rook_file) = if king_side { (6, 5) } else { (2, 3) };
+
+        (
+            Square::new(king_file, rank).unwrap(),
+            Square::new(rook_file, rank).unwrap(),
+        )
+    }
+
+    /// Whether a move is castling: the king moving onto a rook of its color, or two files in
+    /// standard chess.
+    pub(super) fn is_castling(&self, mv: Move) -> bool {
+        match self.piece_at(mv.from) {
+            Some(Piece {
+                color,
+                role: Role::King,
+            }) => {
+                if self.chess960 {
+                    self.piece_at(mv.to)
+                        == Some(Piece {
+                            color,
+                            role: Role::Rook,
+                        })
+                } else {
+                    (mv.from.file() as i8 - mv.to.file() as i8).abs() == 2
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Rook origin square and the king and rook destination squares of a castling move.
+    fn castling_squares(&self, color: Color, mv: Move) -> (Square, Square, Square) {
+        let king_side = mv.to.file() > mv.from.file();
+        let (king_to, rook_to) = Self::castling_destinations(mv.from.rank(), king_side);
+
+        (self.castling_rook(color, king_side), king_to, rook_to)
+    }
+
+    /// Plays a move generated for this position without checking its legality, returning what
+    /// [`Position::unmake_move`] needs to take it back.
+    pub fn make_move(&mut self, mv: Move) -> Undo {
+        let undo = Undo {
+            captured: None,
+            castling: self.castling,
+            castled: false,
+            ep_square: self.ep_square,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            zobrist: self.zobrist,
+        };
+
+        let us = self.turn;
+        let mut piece = match self.piece_at(mv.from) {
+            Some(piece) => piece,
+            None => return undo,
+        };
+
+        let castled = piece.role == Role::King && self.is_castling(mv);
+        let mut to = mv.to;
+
+        // state hashed besides the pieces is xored out here and back in once the move is made
+        self.zobrist ^= castling_key(self.castling) ^ self.ep_key() ^ turn_key(us);
+        self.remove(mv.from);
+
+        let is_pawn_move = piece.role == Role::Pawn;
+        let mut captured = None;
+
+        if castled {
+            let (rook_from, king_to, rook_to) = self.castling_squares(us, mv);
+            if let Some(rook) = self.remove(rook_from) {
+                self.put(rook_to, rook);
+            }
+            to = king_to;
+        } else {
+            captured = self.remove(mv.to);
+        }
+
+        if is_pawn_move {
+            // en passant captures the pawn next to the origin square
+            if Some(mv.to) == self.ep_square && mv.from.file() != mv.to.file() {
+                captured = self.remove(Square::new(mv.to.file(), mv.from.rank()).unwrap());
+            }
+
+            if let Some(role) = mv.promotion {
+                piece.role = role;
+            }
+        }
+
+        // moving the king, or moving or capturing a castling rook on its starting square, loses
+        // the corresponding rights
+        if !self.castling.is_empty() {
+            for &color in Color::ALL.iter() {
+                for &king_side in [true, false].iter() {
+                    let rook = self.castling_rook(color, king_side);
+                    let king_moved = color == us && piece.role == Role::King;
+
+                    if king_moved || mv.from == rook || mv.to == rook {
+                        self.castling.remove(color, king_side);
+                    }
+                }
+            }
+        }
+
+        self.ep_square = if is_pawn_move && (mv.from.rank() as i8 - mv.to.rank() as i8).abs() == 2 {
+            Square::new(mv.from.file(), (mv.from.rank() + mv.to.rank()) / 2)
+        } else {
+            None
+        };
+
+        self.halfmove_clock = if captured.is_some() || is_pawn_move {
+            0
+        } else {
+            self.halfmove_clock.saturating_add(1)
+        };
+
+        if us == Color::Black {
+            self.fullmove_number = self.fullmove_number.saturating_add(1);
+        }
+
+        self.put(to, piece);
+        self.turn = !us;
+        self.zobrist ^= castling_key(self.castling) ^ self.ep_key() ^ turn_key(self.turn);
+
+        Undo {
+            captured,
+            castled,
+            ..undo
+        }
+    }
+
+    /// Takes back the last move made with [`Position::make_move`].
+    pub fn unmake_move(&mut self, mv: Move, undo: Undo) {
+        self.turn = !self.turn;
+
+        if undo.castled {
+            let (rook_from, king_to, rook_to) = self.castling_squares(self.turn, mv);
+            let king = self.remove(king_to);
+            let rook = self.remove(rook_to);
+
+            if let Some(king) = king {
+                self.put(mv.from, king);
+            }
+            if let Some(rook) = rook {
+                self.put(rook_from, rook);
+            }
+        } else if let Some(mut piece) = self.remove(mv.to) {
+            if mv.promotion.is_some() {
+                piece.role = Role::Pawn;
+            }
+
+            if let Some(captured) = undo.captured {
+                let is_en_passant = piece.role == Role::Pawn && Some(mv.to) == undo.ep_square;
+                let square = if is_en_passant {
+                    Square::new(mv.to.file(), mv.from.rank()).unwrap()
+                } else {
+                    mv.to
+                };
+
+                self.put(square, captured);
+            }
+
+            self.put(mv.from, piece);
+        }
+
+        self.castling = undo.castling;
+        self.ep_square = undo.ep_square;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.fullmove_number = undo.fullmove_number;
+        self.zobrist = undo.zobrist;
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::startpos()
+    }
+}
@@ -0,0 +1,193 @@
+use thiserror::Error;
+
+use super::{Move, Position, Role, Square};
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum SanError {
+    #[error("invalid move notation")]
+    Invalid,
+    #[error("illegal move")]
+    Illegal,
+    #[error("ambiguous move")]
+    Ambiguous,
+}
+
+impl Position {
+    /// Standard Algebraic Notation of a legal move, with a `+` or `#` suffix if it gives check
+    /// or checkmate.
+    pub fn san(&self, mv: Move) -> String {
+        let mut san = self.san_without_suffix(mv);
+
+        let mut after = self.clone();
+        after.make_move(mv);
+        if after.is_check() {
+            san.push(if after.legal_moves().is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+
+        san
+    }
+
+    fn san_without_suffix(&self, mv: Move) -> String {
+        let role = match self.piece_at(mv.from) {
+            Some(piece) => piece.role,
+            None => return mv.to_string(),
+        };
+
+        if self.is_castling(mv) {
+            return if mv.to.file() > mv.from.file() {
+                "O-O".to_string()
+            } else {
+                "O-O-O".to_string()
+            };
+        }
+
+        let mut san = String::new();
+
+        if role == Role::Pawn {
+            // pawns only change file when capturing, en passant included
+            if mv.from.file() != mv.to.file() {
+                san.push(file_char(mv.from));
+                san.push('x');
+            }
+        } else {
+            san.push(role.char().to_ascii_uppercase());
+            san.push_str(&self.disambiguation(role, mv));
+            if self.piece_at(mv.to).is_some() {
+                san.push('x');
+            }
+        }
+
+        san.push_str(&mv.to.to_string());
+
+        if let Some(promotion) = mv.promotion {
+            san.push('=');
+            san.push(promotion.char().to_ascii_uppercase());
+        }
+
+        san
+    }
+
+    /// Origin file, rank or square needed to tell a piece move apart from moves of other pieces
+    /// of the same role to the same square.
+    fn disambiguation(&self, role: Role, mv: Move) -> String {
+        let others: Vec<Square> = self
+            .legal_moves()
+            .into_iter()
+            .filter(|other| {
+                other.to == mv.to
+                    && other.from != mv.from
+                    && self.piece_at(other.from).map(|piece| piece.role) == Some(role)
+            })
+            .map(|other| other.from)
+            .collect();
+
+        if others.is_empty() {
+            String::new()
+        } else if others.iter().all(|other| other.file() != mv.from.file()) {
+            file_char(mv.from).to_string()
+        } else if others.iter().all(|other| other.rank() != mv.from.rank()) {
+            rank_char(mv.from).to_string()
+        } else {
+            mv.from.to_string()
+        }
+    }
+
+    /// Parses a legal move in Standard Algebraic Notation. Check and annotation suffixes are
+    /// ignored and promotions may omit the `=`.
+    pub fn parse_san(&self, san: &str) -> Result<Move, SanError> {
+        self.parse_san_among(san, &self.legal_moves())
+    }
+
+    fn parse_san_among(&self, san: &str, legal_moves: &[Move]) -> Result<Move, SanError> {
+        let san = san.trim_end_matches(&['+', '#', '!', '?'][..]);
+
+        match san {
+            "O-O" | "0-0" => return self.find_castling(legal_moves, true),
+            "O-O-O" | "0-0-0" => return self.find_castling(legal_moves, false),
+            _ => {}
+        }
+
+        let mut rest = san;
+
+        let role = match rest.chars().next() {
+            Some(c @ 'N') | Some(c @ 'B') | Some(c @ 'R') | Some(c @ 'Q') | Some(c @ 'K') => {
+                rest = &rest[1..];
+                Role::from_char(c).unwrap()
+            }
+            _ => Role::Pawn,
+        };
+
+        let promotion = match rest.char_indices().last() {
+            Some((i, c)) if role == Role::Pawn && "NBRQ".contains(c) => {
+                rest = rest[..i].strip_suffix('=').unwrap_or(&rest[..i]);
+                Role::from_char(c)
+            }
+            _ => None,
+        };
+
+        if rest.len() < 2 || !rest.is_ascii() {
+            return Err(SanError::Invalid);
+        }
+        let (origin, to) = rest.split_at(rest.len() - 2);
+        let to = to.parse::<Square>().map_err(|_| SanError::Invalid)?;
+        let origin = origin.strip_suffix('x').unwrap_or(origin);
+
+        let (from_file, from_rank) = match origin.as_bytes() {
+            [] => (None, None),
+            [file @ b'a'..=b'h'] => (Some(file - b'a'), None),
+            [rank @ b'1'..=b'8'] => (None, Some(rank - b'1')),
+            [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => (Some(file - b'a'), Some(rank - b'1')),
+            _ => return Err(SanError::Invalid),
+        };
+
+        let mut candidates = legal_moves.iter().copied().filter(|mv| {
+            mv.to == to
+                && mv.promotion == promotion
+                && self.piece_at(mv.from).map(|piece| piece.role) == Some(role)
+                && from_file.iter().all(|&file| mv.from.file() == file)
+                && from_rank.iter().all(|&rank| mv.from.rank() == rank)
+                && !self.is_castling(*mv)
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(mv), None) => Ok(mv),
+            (Some(_), Some(_)) => Err(SanError::Ambiguous),
+            (None, _) => Err(SanError::Illegal),
+        }
+    }
+
+    fn find_castling(&self, legal_moves: &[Move], king_side: bool) -> Result<Move, SanError> {
+        legal_moves
+            .iter()
+            .copied()
+            .find(|&mv| self.is_castling(mv) && (mv.to.file() > mv.from.file()) == king_side)
+            .ok_or(SanError::Illegal)
+    }
+
+    /// Parses a legal move in either UCI or Standard Algebraic Notation.
+    pub fn parse_move(&self, notation: &str) -> Result<Move, SanError> {
+        self.parse_move_among(notation, &self.legal_moves())
+    }
+
+    /// Parses one of `legal_moves` in either UCI or Standard Algebraic Notation, for variants
+    /// whose legal moves differ from those of standard chess.
+    pub fn parse_move_among(&self, notation: &str, legal_moves: &[Move]) -> Result<Move, SanError> {
+        match notation.parse::<Move>() {
+            Ok(mv) if legal_moves.contains(&mv) => Ok(mv),
+            Ok(_) => Err(SanError::Illegal),
+            Err(_) => self.parse_san_among(notation, legal_moves),
+        }
+    }
+}
+
+fn file_char(square: Square) -> char {
+    (b'a' + square.file()) as char
+}
+
+fn rank_char(square: Square) -> char {
+    (b'1' + square.rank()) as char
+}
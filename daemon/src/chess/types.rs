@@ -0,0 +1,309 @@
+use std::{fmt, ops::Not, str::FromStr};
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    pub const ALL: [Color; 2] = [Color::White, Color::Black];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl Not for Color {
+    type Output = Color;
+
+    fn not(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+impl Role {
+    pub const ALL: [Role; 6] = [
+        Role::Pawn,
+        Role::Knight,
+        Role::Bishop,
+        Role::Rook,
+        Role::Queen,
+        Role::King,
+    ];
+
+    pub const PROMOTIONS: [Role; 4] = [Role::Queen, Role::Rook, Role::Bishop, Role::Knight];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Lowercase letter of the role as used in FEN and UCI notation.
+    pub fn char(self) -> char {
+        match self {
+            Role::Pawn => 'p',
+            Role::Knight => 'n',
+            Role::Bishop => 'b',
+            Role::Rook => 'r',
+            Role::Queen => 'q',
+            Role::King => 'k',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Role> {
+        match c.to_ascii_lowercase() {
+            'p' => Some(Role::Pawn),
+            'n' => Some(Role::Knight),
+            'b' => Some(Role::Bishop),
+            'r' => Some(Role::Rook),
+            'q' => Some(Role::Queen),
+            'k' => Some(Role::King),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Piece {
+    pub color: Color,
+    pub role: Role,
+}
+
+impl Piece {
+    /// FEN letter of the piece, uppercase for white.
+    pub fn char(self) -> char {
+        match self.color {
+            Color::White => self.role.char().to_ascii_uppercase(),
+            Color::Black => self.role.char(),
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Piece> {
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+
+        Role::from_char(c).map(|role| Piece { color, role })
+    }
+}
+
+/// A square of the board, `a1` is 0 and `h8` is 63.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Square(u8);
+
+impl Square {
+    pub fn new(file: u8, rank: u8) -> Option<Square> {
+        if file < 8 && rank < 8 {
+            Some(Square(rank * 8 + file))
+        } else {
+            None
+        }
+    }
+
+    pub fn from_index(index: u8) -> Option<Square> {
+        if index < 64 {
+            Some(Square(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn all() -> impl Iterator<Item = Square> {
+        (0..64).map(Square)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    /// File of the square, 0 for the a-file.
+    pub fn file(self) -> u8 {
+        self.0 % 8
+    }
+
+    /// Rank of the square, 0 for the first rank.
+    pub fn rank(self) -> u8 {
+        self.0 / 8
+    }
+
+    /// Square at the given file and rank distance, if it is on the board.
+    pub fn offset(self, files: i8, ranks: i8) -> Option<Square> {
+        let file = self.file() as i8 + files;
+        let rank = self.rank() as i8 + ranks;
+
+        if (0..8).contains(&file) && (0..8).contains(&rank) {
+            Square::new(file as u8, rank as u8)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            (b'a' + self.file()) as char,
+            (b'1' + self.rank()) as char
+        )
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("invalid square")]
+pub struct ParseSquareError;
+
+impl FromStr for Square {
+    type Err = ParseSquareError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.as_bytes() {
+            [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => {
+                Ok(Square((rank - b'1') * 8 + (file - b'a')))
+            }
+            _ => Err(ParseSquareError),
+        }
+    }
+}
+
+/// A move, castling is represented as the king moving two squares, or onto its rook in
+/// Chess960.
+///
+/// Formatted and parsed in the long algebraic notation of UCI, like `e2e4` or `e7e8q`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+    pub promotion: Option<Role>,
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+        if let Some(promotion) = self.promotion {
+            write!(f, "{}", promotion.char())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("invalid UCI move")]
+pub struct ParseMoveError;
+
+impl FromStr for Move {
+    type Err = ParseMoveError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.is_ascii() || !(s.len() == 4 || s.len() == 5) {
+            return Err(ParseMoveError);
+        }
+
+        let from = s[0..2].parse().map_err(|_| ParseMoveError)?;
+        let to = s[2..4].parse().map_err(|_| ParseMoveError)?;
+        let promotion = match s[4..].chars().next() {
+            None => None,
+            Some(c) => match Role::from_char(c) {
+                Some(role) if c.is_ascii_lowercase() && Role::PROMOTIONS.contains(&role) => {
+                    Some(role)
+                }
+                _ => return Err(ParseMoveError),
+            },
+        };
+
+        Ok(Move {
+            from,
+            to,
+            promotion,
+        })
+    }
+}
+
+/// Castling rights of both sides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CastlingRights {
+    pub white_king_side: bool,
+    pub white_queen_side: bool,
+    pub black_king_side: bool,
+    pub black_queen_side: bool,
+}
+
+impl CastlingRights {
+    pub const ALL: CastlingRights = CastlingRights {
+        white_king_side: true,
+        white_queen_side: true,
+        black_king_side: true,
+        black_queen_side: true,
+    };
+
+    pub fn king_side(&self, color: Color) -> bool {
+        match color {
+            Color::White => self.white_king_side,
+            Color::Black => self.black_king_side,
+        }
+    }
+
+    pub fn queen_side(&self, color: Color) -> bool {
+        match color {
+            Color::White => self.white_queen_side,
+            Color::Black => self.black_queen_side,
+        }
+    }
+
+    /// Removes the right of `color` to castle on one side.
+    pub fn remove(&mut self, color: Color, king_side: bool) {
+        *self.right_mut(color, king_side) = false;
+    }
+
+    pub(super) fn right_mut(&mut self, color: Color, king_side: bool) -> &mut bool {
+        match (color, king_side) {
+            (Color::White, true) => &mut self.white_king_side,
+            (Color::White, false) => &mut self.white_queen_side,
+            (Color::Black, true) => &mut self.black_king_side,
+            (Color::Black, false) => &mut self.black_queen_side,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == CastlingRights::default()
+    }
+}
+
+/// How a game ended on the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Checkmate {
+        winner: Color,
+    },
+    Stalemate,
+    /// The winner's king reached the center in King of the Hill.
+    KingOfTheHill {
+        winner: Color,
+    },
+    /// The winner gave a third check in Three-check.
+    ThreeChecks {
+        winner: Color,
+    },
+    /// The winner exploded the loser's king in Atomic.
+    KingExploded {
+        winner: Color,
+    },
+}
@@ -0,0 +1,305 @@
+use std::fmt;
+
+use super::{
+    attacks::king_attacks,
+    zobrist::{castling_key, checks_key},
+    Bitboard, Color, Move, Outcome, Piece, Position, Role, Square,
+};
+
+/// Number of starting positions of Chess960.
+const CHESS960_POSITIONS: u16 = 960;
+
+/// Number of checks winning a game of Three-check.
+const WINNING_CHECKS: u8 = 3;
+
+/// Rules of a chess variant played on the standard board, as hooks over [`Position`].
+///
+/// Every hook defaults to the standard rules, so a variant only overrides what it changes.
+/// Variants keeping state besides the position, like the checks given in Three-check, update it
+/// as moves are made, and variants changing what a move does to the board, like the explosions
+/// of Atomic, make it on the position themselves. Moves still go from one square to another, so
+/// variants dropping pieces onto the board like Crazyhouse do not fit yet.
+pub trait Variant: fmt::Debug + Send + Sync {
+    /// Number of starting positions games of this variant are picked from.
+    fn starting_positions(&self) -> u16 {
+        1
+    }
+
+    /// Starting position numbered `index`, below [`Variant::starting_positions`].
+    fn starting_position(&self, _index: u16) -> Position {
+        Position::startpos()
+    }
+
+    fn legal_moves(&self, position: &Position) -> Vec<Move> {
+        position.legal_moves()
+    }
+
+    fn is_legal(&self, position: &Position, mv: Move) -> bool {
+        self.legal_moves(position).contains(&mv)
+    }
+
+    /// Makes a legal move in `position`.
+    fn make_move(&mut self, position: &mut Position, mv: Move) {
+        position.make_move(mv);
+    }
+
+    /// How the game ended in `position`, if it did.
+    fn outcome(&self, position: &Position) -> Option<Outcome> {
+        position.outcome()
+    }
+
+    /// Whether neither side can win by any sequence of legal moves.
+    fn is_insufficient_material(&self, position: &Position) -> bool {
+        position.is_insufficient_material()
+    }
+
+    /// Whether `color` cannot win by any sequence of legal moves.
+    fn has_insufficient_material(&self, position: &Position, color: Color) -> bool {
+        position.has_insufficient_material(color)
+    }
+
+    /// Key telling positions apart when counting repetitions, covering the variant's own state.
+    fn position_key(&self, position: &Position) -> u64 {
+        position.zobrist_key()
+    }
+
+    fn box_clone(&self) -> Box<dyn Variant>;
+}
+
+impl Clone for Box<dyn Variant> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Standard chess.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Standard;
+
+impl Variant for Standard {
+    fn box_clone(&self) -> Box<dyn Variant> {
+        Box::new(*self)
+    }
+}
+
+/// Chess960, starting from one of 960 shuffled back ranks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Chess960;
+
+impl Variant for Chess960 {
+    fn starting_positions(&self) -> u16 {
+        CHESS960_POSITIONS
+    }
+
+    fn starting_position(&self, index: u16) -> Position {
+        Position::chess960(index).expect("Chess960 indices are below 960")
+    }
+
+    fn box_clone(&self) -> Box<dyn Variant> {
+        Box::new(*self)
+    }
+}
+
+/// King of the Hill, also won by bringing one's king to one of the four central squares.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KingOfTheHill;
+
+impl Variant for KingOfTheHill {
+    fn outcome(&self, position: &Position) -> Option<Outcome> {
+        // only the side which just moved can have reached the center
+        let mover = !position.turn();
+        let king = position.pieces(mover, Role::King);
+
+        if (king & Bitboard::CENTER).any() {
+            Some(Outcome::KingOfTheHill { winner: mover })
+        } else {
+            position.outcome()
+        }
+    }
+
+    /// A bare king may still walk to the center.
+    fn is_insufficient_material(&self, _position: &Position) -> bool {
+        false
+    }
+
+    fn has_insufficient_material(&self, _position: &Position, _color: Color) -> bool {
+        false
+    }
+
+    fn box_clone(&self) -> Box<dyn Variant> {
+        Box::new(*self)
+    }
+}
+
+/// Three-check, also won by checking the opponent's king a third time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreeCheck {
+    /// Checks given by each color.
+    checks: [u8; 2],
+}
+
+impl ThreeCheck {
+    /// Number of checks given by `color`.
+    pub fn checks(&self, color: Color) -> u8 {
+        self.checks[color.index()]
+    }
+}
+
+impl Variant for ThreeCheck {
+    fn make_move(&mut self, position: &mut Position, mv: Move) {
+        let mover = position.turn();
+        position.make_move(mv);
+
+        if position.is_check() {
+            self.checks[mover.index()] += 1;
+        }
+    }
+
+    fn outcome(&self, position: &Position) -> Option<Outcome> {
+        Color::ALL
+            .iter()
+            .find(|&&color| self.checks(color) >= WINNING_CHECKS)
+            .map(|&winner| Outcome::ThreeChecks { winner })
+            .or_else(|| position.outcome())
+    }
+
+    /// Any piece besides the king may give checks, so only bare kings cannot win.
+    fn is_insufficient_material(&self, position: &Position) -> bool {
+        Color::ALL
+            .iter()
+            .all(|&color| self.has_insufficient_material(position, color))
+    }
+
+    fn has_insufficient_material(&self, position: &Position, color: Color) -> bool {
+        (position.by_color(color) & !position.by_role(Role::King)).is_empty()
+    }
+
+    fn position_key(&self, position: &Position) -> u64 {
+        Color::ALL
+            .iter()
+            .fold(position.zobrist_key(), |key, &color| {
+                key ^ checks_key(color, self.checks(color))
+            })
+    }
+
+    fn box_clone(&self) -> Box<dyn Variant> {
+        Box::new(*self)
+    }
+}
+
+/// Atomic, where a capture explodes the capturing piece along with every piece but pawns around
+/// the capture square, and exploding the opponent's king wins.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Atomic;
+
+impl Atomic {
+    /// Whether a move of the side to move captures, en passant included.
+    fn is_capture(position: &Position, mv: Move) -> bool {
+        match position.piece_at(mv.to) {
+            Some(piece) => piece.color != position.turn(),
+            None => {
+                position.piece_at(mv.from).map(|piece| piece.role) == Some(Role::Pawn)
+                    && mv.from.file() != mv.to.file()
+            }
+        }
+    }
+
+    /// Whether the king of `color` is attacked. Kings next to each other never are, capturing
+    /// one would explode the other.
+    fn is_king_attacked(position: &Position, color: Color) -> bool {
+        match position.king_square(color) {
+            Some(king) => {
+                (king_attacks(king) & position.pieces(!color, Role::King)).is_empty()
+                    && position.is_attacked(king, !color)
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the capturing piece which landed on `square` and the pieces it exploded.
+    fn explode(position: &mut Position, square: Square) {
+        let castling = position.castling;
+
+        position.remove(square);
+        for around in king_attacks(square) {
+            if position
+                .piece_at(around)
+                .map_or(false, |piece| piece.role != Role::Pawn)
+            {
+                position.remove(around);
+            }
+        }
+
+        // exploded rooks take their castling rights with them
+        for &color in Color::ALL.iter() {
+            for &king_side in [true, false].iter() {
+                let rook = Piece {
+                    color,
+                    role: Role::Rook,
+                };
+                if position.piece_at(position.castling_rook(color, king_side)) != Some(rook) {
+                    position.castling.remove(color, king_side);
+                }
+            }
+        }
+        position.zobrist ^= castling_key(castling) ^ castling_key(position.castling);
+    }
+}
+
+impl Variant for Atomic {
+    /// Moves leaving the mover's king attacked are legal when they explode the opponent's king,
+    /// while moves exploding the mover's own king never are, kings capturing included.
+    fn legal_moves(&self, position: &Position) -> Vec<Move> {
+        let us = position.turn();
+
+        position
+            .pseudo_legal_moves()
+            .into_iter()
+            .filter(|&mv| {
+                let mut after = position.clone();
+                Atomic.make_move(&mut after, mv);
+
+                after.king_square(us).is_some()
+                    && (after.king_square(!us).is_none() || !Self::is_king_attacked(&after, us))
+            })
+            .collect()
+    }
+
+    fn make_move(&mut self, position: &mut Position, mv: Move) {
+        let capture = Self::is_capture(position, mv);
+        position.make_move(mv);
+
+        if capture {
+            Self::explode(position, mv.to);
+        }
+    }
+
+    fn outcome(&self, position: &Position) -> Option<Outcome> {
+        let turn = position.turn();
+
+        if position.king_square(turn).is_none() {
+            Some(Outcome::KingExploded { winner: !turn })
+        } else if !self.legal_moves(position).is_empty() {
+            None
+        } else if Self::is_king_attacked(position, turn) {
+            Some(Outcome::Checkmate { winner: !turn })
+        } else {
+            Some(Outcome::Stalemate)
+        }
+    }
+
+    /// Kings cannot capture, so only bare kings cannot explode the opponent's.
+    fn is_insufficient_material(&self, position: &Position) -> bool {
+        Color::ALL
+            .iter()
+            .all(|&color| self.has_insufficient_material(position, color))
+    }
+
+    fn has_insufficient_material(&self, position: &Position, color: Color) -> bool {
+        (position.by_color(color) & !position.by_role(Role::King)).is_empty()
+    }
+
+    fn box_clone(&self) -> Box<dyn Variant> {
+        Box::new(*self)
+    }
+}
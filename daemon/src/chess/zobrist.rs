@@ -0,0 +1,94 @@
+use once_cell::sync::Lazy;
+
+use super::{CastlingRights, Color, Piece, Square};
+
+/// Random keys xored together into a position's hash, one per piece on each square, castling
+/// right and en passant file, one for black to move and one per number of checks given by each
+/// side in Three-check.
+struct Keys {
+    pieces: [[u64; 64]; 12],
+    castling: [u64; 4],
+    ep_file: [u64; 8],
+    black_to_move: u64,
+    checks: [[u64; 3]; 2],
+}
+
+impl Keys {
+    fn new() -> Keys {
+        // splitmix64 with a fixed seed, keys must be the same on every run and every peer
+        let mut state: u64 = 0x6970_6368_6573_7321;
+        let mut next = || {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+
+        let mut keys = Keys {
+            pieces: [[0; 64]; 12],
+            castling: [0; 4],
+            ep_file: [0; 8],
+            black_to_move: 0,
+            checks: [[0; 3]; 2],
+        };
+
+        for square_keys in keys.pieces.iter_mut() {
+            for key in square_keys.iter_mut() {
+                *key = next();
+            }
+        }
+
+        for key in keys.castling.iter_mut().chain(keys.ep_file.iter_mut()) {
+            *key = next();
+        }
+
+        keys.black_to_move = next();
+
+        for key in keys.checks.iter_mut().flatten() {
+            *key = next();
+        }
+
+        keys
+    }
+}
+
+static KEYS: Lazy<Keys> = Lazy::new(Keys::new);
+
+pub(super) fn piece_key(piece: Piece, square: Square) -> u64 {
+    KEYS.pieces[piece.color.index() * 6 + piece.role.index()][square.index()]
+}
+
+pub(super) fn castling_key(castling: CastlingRights) -> u64 {
+    let rights = [
+        castling.white_king_side,
+        castling.white_queen_side,
+        castling.black_king_side,
+        castling.black_queen_side,
+    ];
+
+    rights
+        .iter()
+        .zip(KEYS.castling.iter())
+        .filter(|(&right, _)| right)
+        .fold(0, |hash, (_, key)| hash ^ key)
+}
+
+pub(super) fn ep_file_key(file: u8) -> u64 {
+    KEYS.ep_file[file as usize]
+}
+
+pub(super) fn turn_key(turn: Color) -> u64 {
+    match turn {
+        Color::White => 0,
+        Color::Black => KEYS.black_to_move,
+    }
+}
+
+/// Key of `color` having given `checks` checks, none for no checks.
+pub(super) fn checks_key(color: Color, checks: u8) -> u64 {
+    match checks {
+        0 => 0,
+        n => KEYS.checks[color.index()][(n as usize).min(3) - 1],
+    }
+}
@@ -0,0 +1,139 @@
+use std::{fs, io, path::Path, time::Duration};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    game::{ParseVariantError, Variant},
+    protocol::{IpchessConfig, MAX_FRAME_SIZE_LIMIT},
+};
+
+/// Name of the config file looked up in the daemon's data directory.
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed reading config file: {0}")]
+    Read(io::Error),
+    #[error("failed parsing config file: {0}")]
+    Parse(toml::de::Error),
+    #[error("max_frame_size must be at most {limit}, got {value}")]
+    FrameSizeTooLarge { value: usize, limit: usize },
+    #[error("invalid variants: {0}")]
+    Variant(ParseVariantError),
+}
+
+/// Daemon settings read from the config file, unset fields keep their defaults.
+///
+/// ```toml
+/// [protocol]
+/// challenge_accept_timeout_secs = 300
+/// max_pending_challenges = 32
+/// variants = ["standard", "chess960"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub protocol: ProtocolConfig,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(ConfigError::Read)?;
+
+        toml::from_str(&contents).map_err(ConfigError::Parse)
+    }
+
+    /// Loads the config file at `path`, or the default config if there is no such file.
+    pub fn load_or_default(path: &Path) -> Result<Self, ConfigError> {
+        match Self::load(path) {
+            Err(ConfigError::Read(err)) if err.kind() == io::ErrorKind::NotFound => {
+                Ok(Self::default())
+            }
+            res => res,
+        }
+    }
+}
+
+/// Overrides of the [`IpchessConfig`] defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProtocolConfig {
+    pub challenge_accept_timeout_secs: Option<u64>,
+    pub challenge_preimage_timeout_secs: Option<u64>,
+    pub idle_keep_alive_secs: Option<u64>,
+    pub max_pending_challenges: Option<usize>,
+    pub max_frame_size: Option<usize>,
+    /// Names of the variants challenges are accepted for.
+    pub variants: Option<Vec<String>>,
+    /// Maximum number of move records kept for other peers as a store peer, 0 to keep none.
+    pub max_stored_move_records: Option<usize>,
+}
+
+impl ProtocolConfig {
+    /// Returns this config with the fields set in `overrides` replaced.
+    pub fn merge(self, overrides: ProtocolConfig) -> Self {
+        Self {
+            challenge_accept_timeout_secs: overrides
+                .challenge_accept_timeout_secs
+                .or(self.challenge_accept_timeout_secs),
+            challenge_preimage_timeout_secs: overrides
+                .challenge_preimage_timeout_secs
+                .or(self.challenge_preimage_timeout_secs),
+            idle_keep_alive_secs: overrides.idle_keep_alive_secs.or(self.idle_keep_alive_secs),
+            max_pending_challenges: overrides
+                .max_pending_challenges
+                .or(self.max_pending_challenges),
+            max_frame_size: overrides.max_frame_size.or(self.max_frame_size),
+            variants: overrides.variants.or(self.variants),
+            max_stored_move_records: overrides
+                .max_stored_move_records
+                .or(self.max_stored_move_records),
+        }
+    }
+
+    pub fn ipchess_config(&self) -> Result<IpchessConfig, ConfigError> {
+        let mut config = IpchessConfig::default();
+
+        if let Some(secs) = self.challenge_accept_timeout_secs {
+            config.challenge_accept_timeout = Duration::from_secs(secs);
+        }
+
+        if let Some(secs) = self.challenge_preimage_timeout_secs {
+            config.challenge_preimage_timeout = Duration::from_secs(secs);
+        }
+
+        if let Some(secs) = self.idle_keep_alive_secs {
+            config.idle_keep_alive = Duration::from_secs(secs);
+        }
+
+        if let Some(max) = self.max_pending_challenges {
+            config.max_pending_challenges = max;
+        }
+
+        if let Some(size) = self.max_frame_size {
+            if size > MAX_FRAME_SIZE_LIMIT {
+                return Err(ConfigError::FrameSizeTooLarge {
+                    value: size,
+                    limit: MAX_FRAME_SIZE_LIMIT,
+                });
+            }
+
+            config.max_frame_size = size;
+        }
+
+        if let Some(names) = &self.variants {
+            config.variants = names
+                .iter()
+                .map(|name| name.parse::<Variant>())
+                .collect::<Result<_, _>>()
+                .map_err(ConfigError::Variant)?;
+        }
+
+        if let Some(max) = self.max_stored_move_records {
+            config.max_stored_move_records = max;
+        }
+
+        Ok(config)
+    }
+}
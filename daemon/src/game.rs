@@ -0,0 +1,11 @@
+//! Matches played between two peers: their identity, variant, moves, position history and result.
+
+mod id;
+mod state;
+mod time_control;
+mod variant;
+
+pub use id::*;
+pub use state::*;
+pub use time_control::*;
+pub use variant::*;
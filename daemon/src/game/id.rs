@@ -0,0 +1,109 @@
+use std::{fmt, str::FromStr};
+
+use libp2p::multihash::{Hasher, Sha2_256};
+use thiserror::Error;
+
+use super::{TimeControl, Variant};
+use crate::chess::{Color, Position};
+
+/// Identifier of a match, shared by both peers playing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MatchId([u8; 32]);
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("invalid match id, expected 64 hexadecimal digits")]
+pub struct ParseMatchIdError;
+
+impl MatchId {
+    pub fn from_bytes(bytes: &[u8]) -> Option<MatchId> {
+        let mut id = [0; 32];
+        if bytes.len() != id.len() {
+            return None;
+        }
+
+        id.copy_from_slice(bytes);
+        Some(MatchId(id))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for MatchId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for MatchId {
+    type Err = ParseMatchIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 64 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseMatchIdError);
+        }
+
+        let mut id = [0; 32];
+        for (byte, digits) in id.iter_mut().zip(s.as_bytes().chunks(2)) {
+            let digits = std::str::from_utf8(digits).map_err(|_| ParseMatchIdError)?;
+            *byte = u8::from_str_radix(digits, 16).map_err(|_| ParseMatchIdError)?;
+        }
+
+        Ok(MatchId(id))
+    }
+}
+
+/// Match parameters derived from the random bytes both peers contributed to a challenge, which
+/// neither peer could choose on its own.
+#[derive(Debug, Clone)]
+pub struct MatchSetup {
+    pub id: MatchId,
+    /// Color played by the peer who sent the challenge.
+    pub challenger_color: Color,
+    pub variant: Variant,
+    pub time_control: TimeControl,
+    /// Position the match starts from, one of the variant's starting positions.
+    pub position: Position,
+}
+
+impl MatchSetup {
+    /// Derives the setup of a match of `variant` from the challenger's commitment preimage and the
+    /// challenged peer's random bytes.
+    pub fn from_challenge(
+        preimage: &[u8],
+        random: &[u8],
+        variant: Variant,
+        time_control: TimeControl,
+    ) -> MatchSetup {
+        let seed = Sha2_256::digest(&[preimage, random].concat());
+        let id = MatchId::from_bytes(seed.as_ref()).expect("SHA-256 digests are 32 bytes long");
+
+        let challenger_color = if id.0[31] & 1 == 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+
+        MatchSetup {
+            id,
+            challenger_color,
+            variant,
+            time_control,
+            position: variant.starting_position(&id.0),
+        }
+    }
+}
+
+/// Link of a challenge to the finished match it offers a rematch of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rematch {
+    pub previous_match: MatchId,
+    /// Whether each player takes the color its opponent played in the previous match, instead
+    /// of the color drawn from the challenge.
+    pub swap_colors: bool,
+}
@@ -0,0 +1,653 @@
+use libp2p::PeerId;
+use thiserror::Error;
+
+use super::{MatchId, TimeControl, Variant};
+use crate::chess::{self, Color, Move, Outcome, Position};
+
+/// Number of occurrences of a position after which either player may claim a draw.
+const CLAIMABLE_REPETITIONS: usize = 3;
+/// Number of occurrences of a position after which the game is drawn.
+const AUTOMATIC_REPETITIONS: usize = 5;
+/// Half moves without captures or pawn moves after which either player may claim a draw.
+const CLAIMABLE_HALFMOVES: u32 = 100;
+/// Half moves without captures or pawn moves after which the game is drawn.
+const AUTOMATIC_HALFMOVES: u32 = 150;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WinReason {
+    Checkmate,
+    /// The loser ran out of time.
+    Timeout,
+    /// The winner's king reached the center in King of the Hill.
+    KingOfTheHill,
+    /// The winner gave a third check in Three-check.
+    ThreeChecks,
+    /// The winner exploded the loser's king in Atomic.
+    KingExploded,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawReason {
+    Stalemate,
+    /// Neither player can win.
+    InsufficientMaterial,
+    /// A player ran out of time while the opponent could not win.
+    TimeoutVsInsufficientMaterial,
+    /// Claimed by a player after the same position occurred three times.
+    ThreefoldRepetition,
+    /// The same position occurred five times.
+    FivefoldRepetition,
+    /// Claimed by a player after fifty moves by each side without captures or pawn moves.
+    FiftyMoveRule,
+    /// Seventy-five moves by each side were played without captures or pawn moves.
+    SeventyFiveMoveRule,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchResult {
+    Win { winner: Color, reason: WinReason },
+    Draw { reason: DrawReason },
+}
+
+/// Errors returned by operations on matches.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum MatchError {
+    #[error("No match with id {match_id}")]
+    NoSuchMatch { match_id: MatchId },
+    #[error("Match is already over")]
+    Finished,
+    #[error("It is not this player's turn")]
+    NotYourTurn,
+    #[error("Illegal move")]
+    IllegalMove,
+    #[error("No draw can be claimed in the current position")]
+    NoClaimableDraw,
+    #[error("Chat message is empty or too long")]
+    InvalidChatMessage,
+}
+
+/// A match against a peer, from the point of view of this node.
+#[derive(Debug, Clone)]
+pub struct Match {
+    id: MatchId,
+    opponent: PeerId,
+    /// Color played by this node.
+    color: Color,
+    variant: Variant,
+    /// Rules of the variant, with any state they keep besides the position.
+    rules: Box<dyn chess::Variant>,
+    time_control: TimeControl,
+    /// Match this one is a rematch of.
+    previous_match: Option<MatchId>,
+    /// Position the match started from.
+    initial_position: Position,
+    position: Position,
+    moves: Vec<Move>,
+    /// Moves played in Standard Algebraic Notation.
+    san_moves: Vec<String>,
+    /// Keys of every position reached as told apart by the variant, starting with the initial
+    /// one.
+    history: Vec<u64>,
+    result: Option<MatchResult>,
+}
+
+impl Match {
+    pub fn new(
+        id: MatchId,
+        opponent: PeerId,
+        color: Color,
+        variant: Variant,
+        time_control: TimeControl,
+        previous_match: Option<MatchId>,
+        position: Position,
+    ) -> Match {
+        let rules = variant.rules();
+        let history = vec![rules.position_key(&position)];
+
+        Match {
+            id,
+            opponent,
+            color,
+            variant,
+            rules,
+            time_control,
+            previous_match,
+            initial_position: position.clone(),
+            position,
+            moves: vec![],
+            san_moves: vec![],
+            history,
+            result: None,
+        }
+    }
+
+    pub fn id(&self) -> MatchId {
+        self.id
+    }
+
+    pub fn opponent(&self) -> PeerId {
+        self.opponent
+    }
+
+    /// Color played by this node.
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn time_control(&self) -> TimeControl {
+        self.time_control
+    }
+
+    /// Days each player has for a move in a correspondence match, `None` in a live match.
+    pub fn days_per_move(&self) -> Option<u32> {
+        self.time_control.days_per_move()
+    }
+
+    /// Match this one is a rematch of, `None` if it started from a new challenge.
+    pub fn previous_match(&self) -> Option<MatchId> {
+        self.previous_match
+    }
+
+    pub fn initial_position(&self) -> &Position {
+        &self.initial_position
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// Moves played in Standard Algebraic Notation.
+    pub fn san_moves(&self) -> &[String] {
+        &self.san_moves
+    }
+
+    /// Number of half moves played.
+    pub fn ply(&self) -> u32 {
+        self.moves.len() as u32
+    }
+
+    pub fn result(&self) -> Option<MatchResult> {
+        self.result
+    }
+
+    /// Whether this node is the one to move next.
+    pub fn is_local_turn(&self) -> bool {
+        self.result.is_none() && self.position.turn() == self.color
+    }
+
+    /// Plays a move for `color`, ending the match if it leads to checkmate, stalemate, a win
+    /// by the variant's rules, insufficient material, a fivefold repetition or the seventy-five
+    /// move rule.
+    pub fn play(&mut self, color: Color, mv: Move) -> Result<(), MatchError> {
+        self.ensure_turn(color)?;
+        if !self.rules.is_legal(&self.position, mv) {
+            return Err(MatchError::IllegalMove);
+        }
+
+        self.san_moves.push(self.position.san(mv));
+        self.rules.make_move(&mut self.position, mv);
+        self.moves.push(mv);
+        self.history.push(self.rules.position_key(&self.position));
+        self.result = self.automatic_result();
+
+        Ok(())
+    }
+
+    /// Legal moves of the player to move, none once the match is over.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        if self.result.is_some() {
+            return vec![];
+        }
+
+        self.rules.legal_moves(&self.position)
+    }
+
+    /// Parses a move of this node in UCI or Standard Algebraic Notation.
+    pub fn parse_move(&self, notation: &str) -> Result<Move, MatchError> {
+        self.ensure_turn(self.color)?;
+        self.position
+            .parse_move_among(notation, &self.rules.legal_moves(&self.position))
+            .map_err(|_| MatchError::IllegalMove)
+    }
+
+    /// Number of times the current position occurred, including this one.
+    pub fn repetitions(&self) -> usize {
+        let key = self.rules.position_key(&self.position);
+        // positions before the last capture or pawn move cannot repeat
+        let reversible = self.position.halfmove_clock() as usize;
+
+        self.history
+            .iter()
+            .rev()
+            .take(reversible + 1)
+            .step_by(2)
+            .filter(|&&past| past == key)
+            .count()
+    }
+
+    /// Draw the player to move may claim in the current position.
+    pub fn claimable_draw(&self) -> Option<DrawReason> {
+        if self.result.is_some() {
+            None
+        } else if self.repetitions() >= CLAIMABLE_REPETITIONS {
+            Some(DrawReason::ThreefoldRepetition)
+        } else if self.position.halfmove_clock() >= CLAIMABLE_HALFMOVES {
+            Some(DrawReason::FiftyMoveRule)
+        } else {
+            None
+        }
+    }
+
+    /// Ends the match in a draw claimed by `color`, which must be the player to move.
+    pub fn claim_draw(&mut self, color: Color) -> Result<DrawReason, MatchError> {
+        self.ensure_turn(color)?;
+
+        let reason = self.claimable_draw().ok_or(MatchError::NoClaimableDraw)?;
+        self.result = Some(MatchResult::Draw { reason });
+
+        Ok(reason)
+    }
+
+    /// Ends the match after `color` ran out of time, in a draw if the opponent cannot win.
+    pub fn time_out(&mut self, color: Color) -> Result<MatchResult, MatchError> {
+        if self.result.is_some() {
+            return Err(MatchError::Finished);
+        }
+
+        let result = if self.rules.has_insufficient_material(&self.position, !color) {
+            MatchResult::Draw {
+                reason: DrawReason::TimeoutVsInsufficientMaterial,
+            }
+        } else {
+            MatchResult::Win {
+                winner: !color,
+                reason: WinReason::Timeout,
+            }
+        };
+        self.result = Some(result);
+
+        Ok(result)
+    }
+
+    fn ensure_turn(&self, color: Color) -> Result<(), MatchError> {
+        if self.result.is_some() {
+            Err(MatchError::Finished)
+        } else if self.position.turn() != color {
+            Err(MatchError::NotYourTurn)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn automatic_result(&self) -> Option<MatchResult> {
+        // wins and stalemate take precedence over the draws below
+        match self.rules.outcome(&self.position) {
+            Some(Outcome::Checkmate { winner }) => {
+                return Some(MatchResult::Win {
+                    winner,
+                    reason: WinReason::Checkmate,
+                })
+            }
+            Some(Outcome::KingOfTheHill { winner }) => {
+                return Some(MatchResult::Win {
+                    winner,
+                    reason: WinReason::KingOfTheHill,
+                })
+            }
+            Some(Outcome::ThreeChecks { winner }) => {
+                return Some(MatchResult::Win {
+                    winner,
+                    reason: WinReason::ThreeChecks,
+                })
+            }
+            Some(Outcome::KingExploded { winner }) => {
+                return Some(MatchResult::Win {
+                    winner,
+                    reason: WinReason::KingExploded,
+                })
+            }
+            Some(Outcome::Stalemate) => {
+                return Some(MatchResult::Draw {
+                    reason: DrawReason::Stalemate,
+                })
+            }
+            None => {}
+        }
+
+        let reason = if self.rules.is_insufficient_material(&self.position) {
+            DrawReason::InsufficientMaterial
+        } else if self.repetitions() >= AUTOMATIC_REPETITIONS {
+            DrawReason::FivefoldRepetition
+        } else if self.position.halfmove_clock() >= AUTOMATIC_HALFMOVES {
+            DrawReason::SeventyFiveMoveRule
+        } else {
+            return None;
+        };
+
+        Some(MatchResult::Draw { reason })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::PeerId;
+
+    use super::{DrawReason, Match, MatchError, MatchResult, WinReason};
+    use crate::{
+        chess::{Color, Move, Position, Square, STARTING_FEN},
+        game::{MatchId, TimeControl, Variant},
+    };
+
+    fn new_match(fen: &str) -> Match {
+        new_variant_match(Variant::Standard, fen)
+    }
+
+    fn new_variant_match(variant: Variant, fen: &str) -> Match {
+        Match::new(
+            MatchId::from_bytes(&[7; 32]).unwrap(),
+            PeerId::random(),
+            Color::White,
+            variant,
+            TimeControl::default(),
+            None,
+            Position::from_fen(fen).unwrap(),
+        )
+    }
+
+    fn mv(uci: &str) -> Move {
+        Move {
+            from: uci[0..2].parse::<Square>().unwrap(),
+            to: uci[2..4].parse::<Square>().unwrap(),
+            promotion: None,
+        }
+    }
+
+    fn play(game: &mut Match, moves: &[&str]) {
+        for uci in moves {
+            let color = game.position().turn();
+            game.play(color, mv(uci)).unwrap();
+        }
+    }
+
+    /// Knight moves returning to the starting position after four half moves.
+    const KNIGHT_SHUFFLE: [&str; 4] = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+    #[test]
+    fn threefold_repetition_is_claimable_by_player_to_move() {
+        let mut game = new_match(STARTING_FEN);
+
+        play(&mut game, &KNIGHT_SHUFFLE);
+        assert_eq!(game.repetitions(), 2);
+        assert_eq!(game.claimable_draw(), None);
+        assert_eq!(
+            game.claim_draw(Color::White),
+            Err(MatchError::NoClaimableDraw)
+        );
+
+        play(&mut game, &KNIGHT_SHUFFLE);
+        assert_eq!(game.repetitions(), 3);
+        assert_eq!(game.claimable_draw(), Some(DrawReason::ThreefoldRepetition));
+        assert_eq!(game.claim_draw(Color::Black), Err(MatchError::NotYourTurn));
+        assert_eq!(
+            game.claim_draw(Color::White),
+            Ok(DrawReason::ThreefoldRepetition)
+        );
+        assert_eq!(
+            game.result(),
+            Some(MatchResult::Draw {
+                reason: DrawReason::ThreefoldRepetition
+            })
+        );
+        assert_eq!(
+            game.play(Color::White, mv("e2e4")),
+            Err(MatchError::Finished)
+        );
+    }
+
+    #[test]
+    fn fivefold_repetition_ends_the_match() {
+        let mut game = new_match(STARTING_FEN);
+
+        for _ in 0..3 {
+            play(&mut game, &KNIGHT_SHUFFLE);
+        }
+        assert_eq!(game.result(), None);
+
+        play(&mut game, &KNIGHT_SHUFFLE);
+        assert_eq!(
+            game.result(),
+            Some(MatchResult::Draw {
+                reason: DrawReason::FivefoldRepetition
+            })
+        );
+    }
+
+    #[test]
+    fn irreversible_moves_reset_repetitions() {
+        let mut game = new_match(STARTING_FEN);
+
+        play(&mut game, &KNIGHT_SHUFFLE);
+        play(&mut game, &["e2e4", "e7e5"]);
+        play(&mut game, &KNIGHT_SHUFFLE);
+        assert_eq!(game.repetitions(), 2);
+    }
+
+    #[test]
+    fn lost_castling_rights_make_positions_different() {
+        let mut game = new_match("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+
+        // the rooks return to their squares, but castling rights are gone
+        play(&mut game, &["a1a2", "a8a7", "a2a1", "a7a8"]);
+        play(&mut game, &["a1a2", "a8a7", "a2a1", "a7a8"]);
+        assert_eq!(game.repetitions(), 2);
+        play(&mut game, &["a1a2", "a8a7", "a2a1", "a7a8"]);
+        assert_eq!(game.repetitions(), 3);
+    }
+
+    #[test]
+    fn fifty_move_rule_is_claimable() {
+        let mut game = new_match("8/8/4k3/8/8/4K3/8/R7 w - - 98 80");
+
+        play(&mut game, &["a1a2"]);
+        assert_eq!(game.claimable_draw(), None);
+
+        play(&mut game, &["e6e7"]);
+        assert_eq!(game.claimable_draw(), Some(DrawReason::FiftyMoveRule));
+        assert_eq!(game.claim_draw(Color::White), Ok(DrawReason::FiftyMoveRule));
+    }
+
+    #[test]
+    fn seventy_five_move_rule_ends_the_match() {
+        let mut game = new_match("8/8/4k3/8/8/4K3/8/R7 w - - 148 80");
+
+        play(&mut game, &["a1a2"]);
+        assert_eq!(game.result(), None);
+
+        play(&mut game, &["e6e7"]);
+        assert_eq!(
+            game.result(),
+            Some(MatchResult::Draw {
+                reason: DrawReason::SeventyFiveMoveRule
+            })
+        );
+    }
+
+    #[test]
+    fn checkmate_takes_precedence_over_seventy_five_move_rule() {
+        let mut game = new_match("6k1/5ppp/8/8/8/8/8/R5K1 w - - 149 90");
+
+        play(&mut game, &["a1a8"]);
+        assert_eq!(
+            game.result(),
+            Some(MatchResult::Win {
+                winner: Color::White,
+                reason: WinReason::Checkmate
+            })
+        );
+    }
+
+    #[test]
+    fn insufficient_material_ends_the_match() {
+        for (fen, capture) in [
+            // king against king
+            ("8/8/4k3/8/8/3nK3/8/8 w - - 0 60", "e3d3"),
+            // king and knight against king
+            ("8/8/4k3/8/8/3rK3/8/1N6 w - - 0 60", "e3d3"),
+            // king and bishop against king
+            ("8/8/4k3/8/8/3qK3/8/1B6 w - - 0 60", "e3d3"),
+            // bishops on squares of the same color
+            ("8/8/4k3/2b5/8/3rK3/8/2B5 w - - 0 60", "e3d3"),
+        ]
+        .iter()
+        {
+            let mut game = new_match(fen);
+            play(&mut game, &[capture]);
+            assert_eq!(
+                game.result(),
+                Some(MatchResult::Draw {
+                    reason: DrawReason::InsufficientMaterial
+                }),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn mating_material_keeps_the_match_going() {
+        for fen in [
+            // bishops on squares of different colors
+            "8/8/4k3/3b4/8/3rK3/8/2B5 w - - 0 60",
+            // two knights
+            "8/8/4k3/8/8/3rK3/8/1NN5 w - - 0 60",
+            // a pawn
+            "8/8/4k3/8/8/3rK3/P7/8 w - - 0 60",
+        ]
+        .iter()
+        {
+            let mut game = new_match(fen);
+            play(&mut game, &["e3d3"]);
+            assert_eq!(game.result(), None, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn legal_moves_are_those_of_the_player_to_move() {
+        let mut game = new_match(STARTING_FEN);
+        assert_eq!(game.legal_moves().len(), 20);
+
+        play(&mut game, &["f2f3", "e7e5", "g2g4"]);
+        assert!(game.legal_moves().contains(&mv("d8h4")));
+        assert!(!game.legal_moves().contains(&mv("g4g5")));
+
+        play(&mut game, &["d8h4"]);
+        assert!(game.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn timeout_is_a_draw_if_opponent_cannot_checkmate() {
+        let mut game = new_match("8/8/4k3/8/8/4K3/8/R7 w - - 0 60");
+        assert_eq!(
+            game.time_out(Color::White),
+            Ok(MatchResult::Draw {
+                reason: DrawReason::TimeoutVsInsufficientMaterial
+            })
+        );
+        assert_eq!(game.time_out(Color::Black), Err(MatchError::Finished));
+
+        let mut game = new_match("8/8/4k3/8/8/4K3/8/R7 w - - 0 60");
+        assert_eq!(
+            game.time_out(Color::Black),
+            Ok(MatchResult::Win {
+                winner: Color::White,
+                reason: WinReason::Timeout
+            })
+        );
+    }
+
+    #[test]
+    fn king_reaching_the_center_wins_king_of_the_hill() {
+        let mut game = new_variant_match(Variant::KingOfTheHill, "8/8/8/2k5/8/8/4K3/8 w - - 0 60");
+
+        // bare kings are no draw, either of them may still reach the center
+        play(&mut game, &["e2e3", "c5c6"]);
+        assert_eq!(game.result(), None);
+
+        play(&mut game, &["e3e4"]);
+        assert_eq!(
+            game.result(),
+            Some(MatchResult::Win {
+                winner: Color::White,
+                reason: WinReason::KingOfTheHill
+            })
+        );
+    }
+
+    #[test]
+    fn third_check_wins_three_check() {
+        let mut game = new_variant_match(Variant::ThreeCheck, "4k3/8/8/8/8/8/8/4K2R w - - 0 60");
+
+        play(&mut game, &["h1h8", "e8d7", "h8h7", "d7d6"]);
+        assert_eq!(game.result(), None);
+
+        play(&mut game, &["h7h6"]);
+        assert_eq!(
+            game.result(),
+            Some(MatchResult::Win {
+                winner: Color::White,
+                reason: WinReason::ThreeChecks
+            })
+        );
+
+        // a lone knight can still give three checks
+        let mut game = new_variant_match(Variant::ThreeCheck, "4k3/8/8/8/8/8/8/4K1N1 w - - 0 60");
+        assert_eq!(
+            game.time_out(Color::Black),
+            Ok(MatchResult::Win {
+                winner: Color::White,
+                reason: WinReason::Timeout
+            })
+        );
+    }
+
+    #[test]
+    fn exploding_the_king_wins_atomic() {
+        let mut game = new_variant_match(Variant::Atomic, "4k3/3n4/8/8/8/8/8/3RK3 w - - 0 60");
+
+        // the explosion takes the capturing rook and the king next to the knight with it
+        play(&mut game, &["d1d7"]);
+        assert_eq!(game.position().piece_at(mv("d7d7").to), None);
+        assert_eq!(game.position().king_square(Color::Black), None);
+        assert_eq!(
+            game.result(),
+            Some(MatchResult::Win {
+                winner: Color::White,
+                reason: WinReason::KingExploded
+            })
+        );
+    }
+
+    #[test]
+    fn kings_neither_capture_nor_check_each_other_in_atomic() {
+        let game = new_variant_match(Variant::Atomic, "4k3/8/8/8/8/8/4p3/4K3 w - - 0 60");
+        assert!(!game.legal_moves().contains(&mv("e1e2")));
+        assert!(game.legal_moves().contains(&mv("e1d2")));
+
+        // the rook's check discovered by the king does not count while the kings stand next to
+        // each other
+        let mut game = new_variant_match(Variant::Atomic, "8/8/8/8/r1k1K3/8/7P/8 b - - 0 60");
+        play(&mut game, &["c4d5"]);
+        assert!(!game.position().is_legal(mv("h2h3")));
+        assert_eq!(game.parse_move("h3"), Ok(mv("h2h3")));
+        play(&mut game, &["h2h3"]);
+        assert_eq!(game.result(), None);
+    }
+}
@@ -0,0 +1,288 @@
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+
+use crate::chess::Color;
+
+/// Largest number of days a player may be given for each move of a correspondence match.
+pub const MAX_DAYS_PER_MOVE: u32 = 14;
+/// Largest time each player may start a live match with.
+pub const MAX_INITIAL_TIME: Duration = Duration::from_secs(3 * 60 * 60);
+/// Largest time a player may be given back after each of its moves in a live match.
+pub const MAX_INCREMENT: Duration = Duration::from_secs(60);
+
+/// How long players have to move, and how moves reach an offline opponent in correspondence
+/// matches, agreed on when challenging a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeControl {
+    /// Live match played on a chess clock: each player starts with `initial` time for the whole
+    /// match and is given `increment` back after each of its moves.
+    Live {
+        initial: Duration,
+        increment: Duration,
+    },
+    /// Correspondence match, each player having `days_per_move` days for every move. Moves are
+    /// stored in the DHT for the opponent to pick up, or relayed by `store_peer` if set.
+    Correspondence {
+        days_per_move: u32,
+        store_peer: Option<PeerId>,
+    },
+}
+
+impl TimeControl {
+    /// Days each player has for a move in a correspondence match, `None` in a live match.
+    pub fn days_per_move(self) -> Option<u32> {
+        match self {
+            TimeControl::Live { .. } => None,
+            TimeControl::Correspondence { days_per_move, .. } => Some(days_per_move),
+        }
+    }
+
+    /// Peer relaying the moves of a correspondence match, `None` if they go through the DHT.
+    pub fn store_peer(self) -> Option<PeerId> {
+        match self {
+            TimeControl::Live { .. } => None,
+            TimeControl::Correspondence { store_peer, .. } => store_peer,
+        }
+    }
+
+    /// Whether a live match's initial time is neither zero nor above [`MAX_INITIAL_TIME`] and
+    /// its increment is not above [`MAX_INCREMENT`], or a correspondence match's days per move
+    /// are neither zero nor above [`MAX_DAYS_PER_MOVE`].
+    pub fn is_valid(self) -> bool {
+        match self {
+            TimeControl::Live { initial, increment } => {
+                initial > Duration::from_secs(0)
+                    && initial <= MAX_INITIAL_TIME
+                    && increment <= MAX_INCREMENT
+            }
+            TimeControl::Correspondence { days_per_move, .. } => {
+                days_per_move > 0 && days_per_move <= MAX_DAYS_PER_MOVE
+            }
+        }
+    }
+}
+
+impl Default for TimeControl {
+    /// Ten minutes for each player of a live match, without increment.
+    fn default() -> Self {
+        TimeControl::Live {
+            initial: Duration::from_secs(10 * 60),
+            increment: Duration::from_secs(0),
+        }
+    }
+}
+
+/// Time left to each player of a live match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockTimes {
+    pub white: Duration,
+    pub black: Duration,
+}
+
+impl ClockTimes {
+    pub fn get(&self, color: Color) -> Duration {
+        match color {
+            Color::White => self.white,
+            Color::Black => self.black,
+        }
+    }
+}
+
+/// Chess clock of a live match, counting down the time of the player to move.
+///
+/// Each node runs its own clock of a match, starting a player's time when it sends or receives
+/// the opponent's move, so the two clocks of a match differ by the time moves take to reach
+/// the peers.
+#[derive(Debug, Clone)]
+pub struct MatchClock {
+    increment: Duration,
+    /// Time left to each player as of the instant the running player's time started.
+    remaining: [Duration; 2],
+    /// Player whose time is running and the instant it started running, `None` once stopped.
+    running: Option<(Color, Instant)>,
+}
+
+impl MatchClock {
+    /// Starts the clock of a live match at `now`, white's time running first.
+    pub fn start(initial: Duration, increment: Duration, now: Instant) -> Self {
+        Self {
+            increment,
+            remaining: [initial; 2],
+            running: Some((Color::White, now)),
+        }
+    }
+
+    /// Stopped clock of a live match which ended with `times` left to the players, as read back
+    /// after a restart.
+    pub fn stopped(increment: Duration, times: ClockTimes) -> Self {
+        let mut remaining = [Duration::from_secs(0); 2];
+        remaining[Color::White.index()] = times.white;
+        remaining[Color::Black.index()] = times.black;
+
+        Self {
+            increment,
+            remaining,
+            running: None,
+        }
+    }
+
+    /// Time left to each player at `now`, zero for a player whose time ran out.
+    pub fn times(&self, now: Instant) -> ClockTimes {
+        ClockTimes {
+            white: self.remaining(Color::White, now),
+            black: self.remaining(Color::Black, now),
+        }
+    }
+
+    fn remaining(&self, color: Color, now: Instant) -> Duration {
+        let left = self.remaining[color.index()];
+
+        match self.running {
+            Some((running, since)) if running == color => {
+                left.saturating_sub(now.saturating_duration_since(since))
+            }
+            _ => left,
+        }
+    }
+
+    /// Player whose time is running and the instant it runs out, `None` once stopped.
+    pub fn flag_fall(&self) -> Option<(Color, Instant)> {
+        self.running
+            .map(|(color, since)| (color, since + self.remaining[color.index()]))
+    }
+
+    /// Ends the turn of the running player at `now`: the time it took is taken from its time,
+    /// the increment is given back and the opponent's time starts running. Returns the time
+    /// left to the player.
+    pub fn press(&mut self, now: Instant) -> Duration {
+        let (color, _) = match self.running {
+            Some(running) => running,
+            None => return Duration::from_secs(0),
+        };
+
+        let left = self.remaining(color, now) + self.increment;
+        self.remaining[color.index()] = left;
+        self.running = Some((!color, now));
+
+        left
+    }
+
+    /// Sets the time left to `color`, whose time is not running, to the time its own clock
+    /// showed after its last move, unless it is above this clock's time by more than
+    /// `tolerance`, in which case it is capped there.
+    ///
+    /// A player's own clock is started when the opponent's move reaches it, so it shows more
+    /// time than the opponent's clock by the time that move took to arrive. Following it keeps
+    /// the two clocks of a match from drifting apart over its moves, while the cap keeps a
+    /// player from giving itself time.
+    pub fn sync(&mut self, color: Color, reported: Duration, tolerance: Duration) {
+        if matches!(self.running, Some((running, _)) if running == color) {
+            return;
+        }
+
+        let left = &mut self.remaining[color.index()];
+        *left = reported.min(*left + tolerance);
+    }
+
+    /// Stops the clock at `now`, freezing the time left to both players.
+    pub fn stop(&mut self, now: Instant) {
+        if let Some((color, _)) = self.running {
+            self.remaining[color.index()] = self.remaining(color, now);
+            self.running = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{ClockTimes, MatchClock, TimeControl};
+    use crate::chess::Color;
+
+    const SEC: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn clock_counts_down_the_player_to_move() {
+        let start = Instant::now();
+        let mut clock = MatchClock::start(SEC * 60, SEC * 2, start);
+        assert_eq!(clock.flag_fall(), Some((Color::White, start + SEC * 60)));
+
+        // white takes 10 seconds and gets the increment back
+        assert_eq!(clock.press(start + SEC * 10), SEC * 52);
+        assert_eq!(
+            clock.times(start + SEC * 15),
+            ClockTimes {
+                white: SEC * 52,
+                black: SEC * 55,
+            }
+        );
+        assert_eq!(
+            clock.flag_fall(),
+            Some((Color::Black, start + SEC * 10 + SEC * 60))
+        );
+
+        clock.stop(start + SEC * 100);
+        assert_eq!(
+            clock.times(start + SEC * 200).get(Color::Black),
+            Duration::from_secs(0)
+        );
+        assert_eq!(clock.flag_fall(), None);
+    }
+
+    #[test]
+    fn stopped_clock_keeps_its_times() {
+        let times = ClockTimes {
+            white: SEC * 12,
+            black: SEC * 34,
+        };
+        let mut clock = MatchClock::stopped(SEC, times);
+
+        assert_eq!(clock.press(Instant::now()), Duration::from_secs(0));
+        assert_eq!(clock.times(Instant::now() + SEC * 100), times);
+        assert_eq!(clock.flag_fall(), None);
+    }
+
+    #[test]
+    fn reported_time_is_followed_up_to_tolerance() {
+        let start = Instant::now();
+        let mut clock = MatchClock::start(SEC * 60, Duration::from_secs(0), start);
+        clock.press(start + SEC * 10);
+
+        // the running player's time is left alone
+        clock.sync(Color::Black, SEC * 60, SEC);
+        assert_eq!(clock.times(start + SEC * 10).black, SEC * 60);
+
+        clock.sync(Color::White, SEC * 49, SEC);
+        assert_eq!(clock.times(start + SEC * 10).white, SEC * 49);
+
+        clock.sync(Color::White, SEC * 60, SEC);
+        assert_eq!(clock.times(start + SEC * 10).white, SEC * 50);
+    }
+
+    #[test]
+    fn time_controls_are_validated() {
+        assert!(TimeControl::default().is_valid());
+        assert!(!TimeControl::Live {
+            initial: Duration::from_secs(0),
+            increment: SEC,
+        }
+        .is_valid());
+        assert!(!TimeControl::Live {
+            initial: SEC * 60,
+            increment: SEC * 61,
+        }
+        .is_valid());
+        assert!(!TimeControl::Correspondence {
+            days_per_move: 0,
+            store_peer: None,
+        }
+        .is_valid());
+        assert!(TimeControl::Correspondence {
+            days_per_move: 3,
+            store_peer: None,
+        }
+        .is_valid());
+    }
+}
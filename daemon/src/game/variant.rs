@@ -0,0 +1,126 @@
+use std::{fmt, str::FromStr};
+
+use libp2p::multihash::{Hasher, Sha2_256};
+use thiserror::Error;
+
+use crate::chess::{self, Position};
+
+/// Rules a match is played by, agreed on when challenging a peer.
+///
+/// Crazyhouse is not supported yet, challenges to play it are declined: its drops cannot be
+/// written as a [`chess::Move`], which only moves a piece between two squares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Variant {
+    Standard,
+    /// Chess960, starting from one of 960 shuffled back ranks.
+    Chess960,
+    /// King of the Hill, also won by bringing one's king to the center.
+    KingOfTheHill,
+    /// Three-check, also won by checking the opponent a third time.
+    ThreeCheck,
+    /// Atomic, where captures explode the pieces around them and exploding the opponent's king
+    /// wins.
+    Atomic,
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("unknown variant `{0}`")]
+pub struct ParseVariantError(pub String);
+
+impl Variant {
+    /// Every variant this node can play.
+    pub const ALL: [Variant; 5] = [
+        Variant::Standard,
+        Variant::Chess960,
+        Variant::KingOfTheHill,
+        Variant::ThreeCheck,
+        Variant::Atomic,
+    ];
+
+    /// Name of the variant, as sent in challenges.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Variant::Standard => "standard",
+            Variant::Chess960 => "chess960",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::ThreeCheck => "threecheck",
+            Variant::Atomic => "atomic",
+        }
+    }
+
+    /// Rules of the variant, for a match starting afresh.
+    pub fn rules(self) -> Box<dyn chess::Variant> {
+        match self {
+            Variant::Standard => Box::new(chess::Standard),
+            Variant::Chess960 => Box::new(chess::Chess960),
+            Variant::KingOfTheHill => Box::new(chess::KingOfTheHill),
+            Variant::ThreeCheck => Box::new(chess::ThreeCheck::default()),
+            Variant::Atomic => Box::new(chess::Atomic),
+        }
+    }
+
+    /// Position a match of this variant starts from, picked from `seed` when the variant has
+    /// several of them.
+    ///
+    /// The seed's last byte is left out, it decides the colors of the players.
+    pub fn starting_position(self, seed: &[u8; 32]) -> Position {
+        let rules = self.rules();
+
+        match rules.starting_positions() {
+            1 => rules.starting_position(0),
+            count => rules.starting_position(uniform_index(seed, count)),
+        }
+    }
+}
+
+impl Default for Variant {
+    fn default() -> Self {
+        Variant::Standard
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Variant {
+    type Err = ParseVariantError;
+
+    /// Parses a variant name, an empty one standing for standard chess as sent by peers which do
+    /// not know about variants.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" | "standard" => Ok(Variant::Standard),
+            "chess960" => Ok(Variant::Chess960),
+            "kingofthehill" => Ok(Variant::KingOfTheHill),
+            "threecheck" => Ok(Variant::ThreeCheck),
+            "atomic" => Ok(Variant::Atomic),
+            _ => Err(ParseVariantError(s.to_string())),
+        }
+    }
+}
+
+/// Picks a number below `count` uniformly from the first 30 bytes of a seed.
+///
+/// Every two bytes are read as a number below 65536, which need not be a multiple of `count`, so
+/// numbers from the largest multiple up are skipped to keep all numbers equally likely. The seed
+/// is hashed again in the unlikely case every number is skipped.
+fn uniform_index(seed: &[u8; 32], count: u16) -> u16 {
+    let limit = u16::MAX - u16::MAX % count;
+    let mut bytes = seed.to_vec();
+
+    loop {
+        let index = bytes[..30]
+            .chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .find(|&n| n < limit);
+
+        if let Some(n) = index {
+            return n % count;
+        }
+
+        bytes = Sha2_256::digest(&bytes).as_ref().to_vec();
+    }
+}
@@ -0,0 +1,17 @@
+//! The ipchess library: the network behaviour and protocol, the chess rules engine and the
+//! matches played with it, the JSON-RPC API with a typed client, and a [`Node`] tying them
+//! together, as run by the `ipchessd` daemon.
+
+pub mod api;
+pub mod behaviour;
+pub mod chess;
+pub mod config;
+pub mod game;
+pub mod node;
+pub mod protocol;
+pub mod store;
+pub mod utils;
+
+pub use behaviour::{Behaviour, BehaviourEvent};
+pub use node::{Node, NodeBuilder, NodeError};
+pub use protocol::Ipchess;
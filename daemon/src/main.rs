@@ -1,51 +1,133 @@
-use std::str::FromStr;
+use std::path::PathBuf;
 
 use clap::Clap;
-use libp2p::futures::StreamExt;
-
-use crate::{protocol::IpchessEvent, utils::SerializablePeerId};
-
-mod api;
-mod behaviour;
-mod protocol;
-mod utils;
+use ipchess::{
+    api,
+    config::{self, Config, ProtocolConfig},
+    store::{self, MatchStore},
+    Node,
+};
 
 #[derive(Clap)]
 struct Opts {
     #[clap(long, default_value = "3030")]
     api_port: u16,
-}
 
-#[tokio::main]
-async fn main() {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    /// Origins allowed to connect to the API from a browser context, may be repeated
+    #[clap(long = "api-allowed-origin")]
+    api_allowed_origins: Vec<String>,
 
-    let opts = Opts::parse();
+    /// Directory where the API cookie file is written, the config file is read from and the node's
+    /// identity and matches are kept, defaults to the user's data directory
+    #[clap(long)]
+    data_dir: Option<PathBuf>,
+
+    /// Serve the API on a Unix domain socket at this path instead of opening a WebSocket port
+    #[clap(long)]
+    api_socket: Option<PathBuf>,
+
+    /// Config file, defaults to config.toml in the data directory if it exists
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// Seconds a challenged peer has to accept or decline a challenge
+    #[clap(long)]
+    challenge_accept_timeout: Option<u64>,
+
+    /// Seconds a challenger has to reveal its commitment once its challenge is accepted
+    #[clap(long)]
+    challenge_preimage_timeout: Option<u64>,
 
-    let id_key_pair = libp2p::identity::Keypair::generate_ed25519();
-    let local_peer_id = libp2p::PeerId::from(id_key_pair.public());
+    /// Seconds an idle connection to a peer is kept open
+    #[clap(long)]
+    idle_keep_alive: Option<u64>,
 
-    log::info!("Local peer id {}", local_peer_id);
+    /// Maximum number of received challenges waiting for an answer
+    #[clap(long)]
+    max_pending_challenges: Option<usize>,
 
-    let behaviour = behaviour::Behaviour::new(local_peer_id, id_key_pair.public());
+    /// Maximum size in bytes of a message read from a peer
+    #[clap(long)]
+    max_frame_size: Option<usize>,
 
-    let transport =
-        libp2p::tokio_development_transport(id_key_pair).expect("failed creating transport");
+    /// Comma separated variants challenges are accepted for
+    #[clap(long, use_delimiter = true)]
+    variants: Option<Vec<String>>,
 
-    let mut swarm = libp2p::swarm::SwarmBuilder::new(transport, behaviour, local_peer_id)
-        .executor(Box::new(|fut| {
-            tokio::spawn(fut);
-        }))
-        .build();
-    swarm
-        .listen_on(libp2p::Multiaddr::from_str("/ip4/0.0.0.0/tcp/0").unwrap())
-        .expect("swarm listen_on failed");
+    /// Maximum number of move records kept for other peers' correspondence matches, 0 to not
+    /// relay moves as a store peer
+    #[clap(long)]
+    max_stored_move_records: Option<usize>,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
-    let mut api_server = api::Server::new(opts.api_port)
+    let opts = Opts::parse();
+    let data_dir = opts
+        .data_dir
+        .clone()
+        .or_else(ipchess::utils::default_data_dir);
+
+    let config = match (&opts.config, &data_dir) {
+        (Some(path), _) => Config::load(path),
+        (None, Some(data_dir)) => Config::load_or_default(&data_dir.join(config::CONFIG_FILE_NAME)),
+        (None, None) => Ok(Config::default()),
+    }
+    .expect("failed loading config");
+
+    let ipchess_config = config
+        .protocol
+        .merge(ProtocolConfig {
+            challenge_accept_timeout_secs: opts.challenge_accept_timeout,
+            challenge_preimage_timeout_secs: opts.challenge_preimage_timeout,
+            idle_keep_alive_secs: opts.idle_keep_alive,
+            max_pending_challenges: opts.max_pending_challenges,
+            max_frame_size: opts.max_frame_size,
+            variants: opts.variants,
+            max_stored_move_records: opts.max_stored_move_records,
+        })
+        .ipchess_config()
+        .expect("invalid protocol config");
+
+    let data_dir = data_dir.expect("failed finding data directory");
+    let cookie_path = data_dir.join(api::COOKIE_FILE_NAME);
+
+    let auth = api::ServerAuth::generate(opts.api_allowed_origins);
+    auth.write_cookie_file(&cookie_path)
+        .expect("failed writing API cookie file");
+
+    log::info!("API cookie file written at {}", cookie_path.display());
+
+    let api_transport = match opts.api_socket {
+        #[cfg(unix)]
+        Some(path) => api::ServerTransport::Unix { path, auth },
+
+        #[cfg(not(unix))]
+        Some(_) => panic!("Unix domain sockets are not supported on this platform"),
+
+        None => api::ServerTransport::WebSocket {
+            port: opts.api_port,
+            auth,
+        },
+    };
+
+    let keypair = store::load_or_generate_keypair(&data_dir.join(store::IDENTITY_FILE_NAME))
+        .expect("failed loading node identity");
+    let match_store = MatchStore::open(data_dir.join(store::MATCHES_DIR_NAME))
+        .expect("failed opening match store");
+
+    let node = Node::builder(api_transport)
+        .keypair(keypair)
+        .ipchess_config(ipchess_config)
+        .match_store(match_store)
+        .build()
         .await
-        .expect("failed starting API server");
+        .expect("failed starting node");
 
-    log::info!("API listening at ws://{:?}", api_server.local_addr());
+    log::info!("Local peer id {}", node.local_peer_id());
+    log::info!("API listening at {}", node.api_addr());
 
     let (signal_tx, mut signal_rx) = tokio::sync::mpsc::unbounded_channel();
     ctrlc::set_handler(move || {
@@ -53,91 +135,14 @@ async fn main() {
     })
     .expect("failed setting signal handler");
 
-    loop {
-        tokio::select! {
-            swarm_event = swarm.select_next_some() => {
-                match swarm_event {
-                    libp2p::swarm::SwarmEvent::NewListenAddr(addr) => {
-                        log::info!("Swarm listening at {}", addr);
-                        swarm.behaviour_mut().bootstrap();
-                    }
-
-                    libp2p::swarm::SwarmEvent::Behaviour(e) => {
-                        match e {
-                            behaviour::BehaviourEvent::Ipchess(IpchessEvent::PeerChallenge { peer_id }) => {
-                                api_server.notify_event(api::ServerEventNotification::PeerChallenge {
-                                    peer_id: SerializablePeerId(peer_id),
-                                });
-                            }
-
-                            behaviour::BehaviourEvent::Ipchess(IpchessEvent::ChallengeAccepted { peer_id, .. }) => {
-                                api_server.notify_event(api::ServerEventNotification::ChallengeAccepted {
-                                    peer_id: SerializablePeerId(peer_id),
-                                });
-                            },
-
-                            behaviour::BehaviourEvent::Ipchess(IpchessEvent::ChallengeCanceled { peer_id }) => {
-                                api_server.notify_event(api::ServerEventNotification::ChallengeCanceled {
-                                    peer_id: SerializablePeerId(peer_id),
-                                });
-                            }
-
-                            behaviour::BehaviourEvent::Ipchess(IpchessEvent::ChallengeDeclined { peer_id }) => {
-                                api_server.notify_event(api::ServerEventNotification::ChallengeDeclined {
-                                    peer_id: SerializablePeerId(peer_id),
-                                });
-                            }
-
-                            behaviour::BehaviourEvent::Ipchess(IpchessEvent::Error(err)) => {
-                                log::debug!("Ipchess error {:?}", err);
-                            }
-
-                        }
-                    }
-
-                    e => {
-                        log::debug!("Swarm event {:?}", e);
-                    }
-                }
-            }
-
-            Some(api_event) = api_server.next() => {
-                match api_event {
-                    api::ServerEvent::NodeIdRequest(res_tx) => {
-                        let _ = res_tx.send(api::NodeIdResponse(SerializablePeerId(*swarm.local_peer_id())));
-                    }
-
-                    api::ServerEvent::IsConnectedRequest(res_tx) => {
-                        let _ = res_tx.send(api::IsConnectedResponse(swarm.behaviour_mut().is_connected()));
-                    }
-
-                    api::ServerEvent::ChallengePeerRequest(peer_id, res_tx) => {
-                        swarm.behaviour_mut().challenge_peer(peer_id);
-                        let _ = res_tx.send(api::ChallengePeerResponse);
-                    }
-
-                    api::ServerEvent::AcceptPeerChallengeRequest(peer_id, res_tx) => {
-                        swarm.behaviour_mut().accept_peer_challenge(peer_id);
-                        let _ = res_tx.send(api::AcceptPeerChallengeResponse);
-                    }
-
-                    api::ServerEvent::CancelPeerChallengeRequest(peer_id, res_tx) => {
-                        swarm.behaviour_mut().cancel_challenge(peer_id);
-                        let _ = res_tx.send(api::CancelPeerChallengeResponse);
-                    }
-
-                    api::ServerEvent::DeclinePeerChallengeRequest(peer_id, res_tx) => {
-                        swarm.behaviour_mut().decline_peer_challenge(peer_id);
-                        let _ = res_tx.send(api::DeclinePeerChallengeResponse);
-                    }
-                }
-            }
-
-            _ = signal_rx.recv() => {
-                break;
-            }
-        }
-    }
+    node.run(async move {
+        let _ = signal_rx.recv().await;
+    })
+    .await;
 
     log::info!("shutting down...");
+
+    if let Err(err) = std::fs::remove_file(cookie_path) {
+        log::warn!("Failed removing API cookie file: {}", err);
+    }
 }
@@ -0,0 +1,582 @@
+use std::{future::Future, io, time::Duration};
+
+use futures::StreamExt;
+use libp2p::{
+    core::{muxing::StreamMuxerBox, transport::Boxed},
+    identity::Keypair,
+    swarm::{Swarm, SwarmBuilder, SwarmEvent},
+    Multiaddr, PeerId,
+};
+use thiserror::Error;
+
+use crate::{
+    api,
+    behaviour::{self, Behaviour, BehaviourEvent},
+    chess::Move,
+    game::{ClockTimes, DrawReason, Match, MatchError, MatchResult, TimeControl, WinReason},
+    protocol::{ChallengeDirection, IpchessConfig, IpchessError, IpchessEvent},
+    store::{MatchStore, StoreError},
+    utils::{
+        SerializableChallengeDirection, SerializableChallengeState, SerializableColor,
+        SerializableMatchId, SerializablePeerId, SerializableVariant,
+    },
+};
+
+#[derive(Debug, Error)]
+pub enum NodeError {
+    #[error("failed creating transport: {0}")]
+    Transport(io::Error),
+    #[error("failed listening on {addr}: {reason}")]
+    Listen { addr: Multiaddr, reason: String },
+    #[error("failed starting API server: {0}")]
+    Api(String),
+    #[error("failed restoring matches: {0}")]
+    Store(StoreError),
+}
+
+/// Configures and starts a [`Node`].
+pub struct NodeBuilder {
+    keypair: Option<Keypair>,
+    transport: Option<Boxed<(PeerId, StreamMuxerBox)>>,
+    listen_addrs: Vec<Multiaddr>,
+    bootstrap_peers: Option<Vec<(PeerId, Multiaddr)>>,
+    ipchess_config: IpchessConfig,
+    match_store: Option<MatchStore>,
+    api_transport: api::ServerTransport,
+}
+
+impl NodeBuilder {
+    pub fn new(api_transport: api::ServerTransport) -> Self {
+        Self {
+            keypair: None,
+            transport: None,
+            listen_addrs: vec![],
+            bootstrap_peers: None,
+            ipchess_config: IpchessConfig::default(),
+            match_store: None,
+            api_transport,
+        }
+    }
+
+    /// Sets the node's identity, a random ed25519 keypair is generated by default.
+    pub fn keypair(mut self, keypair: Keypair) -> Self {
+        self.keypair = Some(keypair);
+        self
+    }
+
+    /// Sets the transport used to reach peers, which must authenticate with the node's keypair.
+    /// Defaults to TCP and DNS with noise encryption.
+    pub fn transport(mut self, transport: Boxed<(PeerId, StreamMuxerBox)>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Adds an address for the node to listen on, `/ip4/0.0.0.0/tcp/0` is used if none is given.
+    pub fn listen_on(mut self, addr: Multiaddr) -> Self {
+        self.listen_addrs.push(addr);
+        self
+    }
+
+    /// Sets the peers used to join the DHT, replacing the public libp2p bootstrap nodes.
+    pub fn bootstrap_peers(mut self, peers: Vec<(PeerId, Multiaddr)>) -> Self {
+        self.bootstrap_peers = Some(peers);
+        self
+    }
+
+    /// Sets the challenge protocol's timeouts and limits.
+    pub fn ipchess_config(mut self, config: IpchessConfig) -> Self {
+        self.ipchess_config = config;
+        self
+    }
+
+    /// Sets where correspondence and finished matches are kept across restarts, restoring the
+    /// ones already there. Matches are only kept in memory by default.
+    pub fn match_store(mut self, match_store: MatchStore) -> Self {
+        self.match_store = Some(match_store);
+        self
+    }
+
+    pub async fn build(self) -> Result<Node, NodeError> {
+        let keypair = self.keypair.unwrap_or_else(Keypair::generate_ed25519);
+        let local_peer_id = PeerId::from(keypair.public());
+
+        let bootstrap_peers = self
+            .bootstrap_peers
+            .unwrap_or_else(behaviour::default_bootstrap_peers);
+        let mut behaviour = Behaviour::new(
+            local_peer_id,
+            keypair.clone(),
+            bootstrap_peers,
+            self.ipchess_config,
+        );
+        if let Some(match_store) = self.match_store {
+            behaviour
+                .restore_matches(match_store)
+                .map_err(NodeError::Store)?;
+        }
+
+        let transport = match self.transport {
+            Some(transport) => transport,
+            None => libp2p::tokio_development_transport(keypair).map_err(NodeError::Transport)?,
+        };
+
+        let mut swarm = SwarmBuilder::new(transport, behaviour, local_peer_id)
+            .executor(Box::new(|fut| {
+                tokio::spawn(fut);
+            }))
+            .build();
+
+        let mut listen_addrs = self.listen_addrs;
+        if listen_addrs.is_empty() {
+            listen_addrs.push("/ip4/0.0.0.0/tcp/0".parse().unwrap());
+        }
+
+        for addr in listen_addrs {
+            swarm
+                .listen_on(addr.clone())
+                .map_err(|err| NodeError::Listen {
+                    addr,
+                    reason: err.to_string(),
+                })?;
+        }
+
+        let api_server = api::Server::new(self.api_transport)
+            .await
+            .map_err(|err| NodeError::Api(err.to_string()))?;
+
+        Ok(Node { swarm, api_server })
+    }
+}
+
+/// An ipchess node, connecting the network behaviour to the API server.
+pub struct Node {
+    swarm: Swarm<Behaviour>,
+    api_server: api::Server,
+}
+
+impl Node {
+    pub fn builder(api_transport: api::ServerTransport) -> NodeBuilder {
+        NodeBuilder::new(api_transport)
+    }
+
+    pub fn local_peer_id(&self) -> &PeerId {
+        self.swarm.local_peer_id()
+    }
+
+    pub fn api_addr(&self) -> &api::ServerAddr {
+        self.api_server.local_addr()
+    }
+
+    /// Runs the node until `shutdown` completes.
+    pub async fn run(mut self, shutdown: impl Future<Output = ()>) {
+        futures::pin_mut!(shutdown);
+
+        loop {
+            tokio::select! {
+                swarm_event = self.swarm.select_next_some() => {
+                    self.handle_swarm_event(swarm_event);
+                }
+
+                Some(api_event) = self.api_server.next() => {
+                    self.handle_api_event(api_event);
+                }
+
+                _ = &mut shutdown => {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn handle_swarm_event<E: std::fmt::Debug>(&mut self, event: SwarmEvent<BehaviourEvent, E>) {
+        match event {
+            SwarmEvent::NewListenAddr(addr) => {
+                log::info!("Swarm listening at {}", addr);
+                self.swarm.behaviour_mut().bootstrap();
+            }
+
+            SwarmEvent::Behaviour(event) => {
+                self.api_server.notify_event(event_notification(event));
+            }
+
+            e => {
+                log::debug!("Swarm event {:?}", e);
+            }
+        }
+    }
+
+    fn handle_api_event(&mut self, event: api::ServerEvent) {
+        match event {
+            api::ServerEvent::NodeIdRequest(res_tx) => {
+                let _ = res_tx.send(api::NodeIdResponse(SerializablePeerId(
+                    *self.swarm.local_peer_id(),
+                )));
+            }
+
+            api::ServerEvent::IsConnectedRequest(res_tx) => {
+                let _ = res_tx.send(api::IsConnectedResponse(
+                    self.swarm.behaviour_mut().is_connected(),
+                ));
+            }
+
+            api::ServerEvent::ListChallengesRequest(res_tx) => {
+                let challenges = self
+                    .swarm
+                    .behaviour()
+                    .challenges()
+                    .into_iter()
+                    .map(|challenge| api::ChallengeInfo {
+                        peer_id: SerializablePeerId(challenge.peer_id),
+                        direction: SerializableChallengeDirection(challenge.direction),
+                        state: SerializableChallengeState(challenge.state),
+                        variant: SerializableVariant(challenge.variant),
+                        days_per_move: challenge.time_control.days_per_move(),
+                        time_control: time_control_info(challenge.time_control),
+                        store_peer: challenge.time_control.store_peer().map(SerializablePeerId),
+                        previous_match_id: challenge
+                            .rematch
+                            .map(|rematch| SerializableMatchId(rematch.previous_match)),
+                        swap_colors: challenge
+                            .rematch
+                            .map_or(false, |rematch| rematch.swap_colors),
+                        age_ms: challenge.age.as_millis() as u64,
+                        remaining_timeout_ms: challenge
+                            .remaining_timeout
+                            .map(|timeout| timeout.as_millis() as u64),
+                    })
+                    .collect();
+
+                let _ = res_tx.send(api::ListChallengesResponse(challenges));
+            }
+
+            api::ServerEvent::ChallengePeerRequest(peer_id, variant, time_control, res_tx) => {
+                let res = self
+                    .swarm
+                    .behaviour_mut()
+                    .challenge_peer(peer_id, variant, time_control);
+                let _ = res_tx.send(res.map(|_| api::ChallengePeerResponse));
+            }
+
+            api::ServerEvent::AcceptPeerChallengeRequest(peer_id, res_tx) => {
+                let res = self.swarm.behaviour_mut().accept_peer_challenge(peer_id);
+                let _ = res_tx.send(res.map(|_| api::AcceptPeerChallengeResponse));
+            }
+
+            api::ServerEvent::CancelPeerChallengeRequest(peer_id, res_tx) => {
+                let res = self.swarm.behaviour_mut().cancel_challenge(peer_id);
+                let _ = res_tx.send(res.map(|_| api::CancelPeerChallengeResponse));
+            }
+
+            api::ServerEvent::DeclinePeerChallengeRequest(peer_id, res_tx) => {
+                let res = self.swarm.behaviour_mut().decline_peer_challenge(peer_id);
+                let _ = res_tx.send(res.map(|_| api::DeclinePeerChallengeResponse));
+            }
+
+            api::ServerEvent::ListMatchesRequest(res_tx) => {
+                let behaviour = self.swarm.behaviour();
+                let matches = behaviour
+                    .matches()
+                    .map(|game| {
+                        match_info(
+                            game,
+                            behaviour.remaining_move_time(game.id()),
+                            behaviour.clock_times(game.id()),
+                        )
+                    })
+                    .collect();
+                let _ = res_tx.send(api::ListMatchesResponse(matches));
+            }
+
+            api::ServerEvent::LegalMovesRequest(match_id, res_tx) => {
+                let res = self
+                    .swarm
+                    .behaviour()
+                    .matches()
+                    .find(|game| game.id() == match_id)
+                    .map(|game| {
+                        let moves = game
+                            .legal_moves()
+                            .into_iter()
+                            .map(|mv| move_info(mv, game.position().san(mv)))
+                            .collect();
+                        api::LegalMovesResponse(moves)
+                    })
+                    .ok_or(MatchError::NoSuchMatch { match_id });
+                let _ = res_tx.send(res);
+            }
+
+            api::ServerEvent::MakeMoveRequest(match_id, notation, res_tx) => {
+                let res = self.swarm.behaviour_mut().make_move(match_id, &notation);
+                let _ = res_tx.send(res.map(|_| api::MakeMoveResponse));
+            }
+
+            api::ServerEvent::ClaimDrawRequest(match_id, res_tx) => {
+                let res = self.swarm.behaviour_mut().claim_draw(match_id);
+                let _ = res_tx.send(res.map(|reason| {
+                    api::ClaimDrawResponse(result_info(MatchResult::Draw { reason }))
+                }));
+            }
+
+            api::ServerEvent::SendChatMessageRequest(match_id, text, res_tx) => {
+                let res = self.swarm.behaviour_mut().send_chat_message(match_id, text);
+                let _ = res_tx.send(res.map(|_| api::SendChatMessageResponse));
+            }
+
+            api::ServerEvent::OfferRematchRequest(match_id, swap_colors, res_tx) => {
+                let res = self
+                    .swarm
+                    .behaviour_mut()
+                    .offer_rematch(match_id, swap_colors);
+                let _ = res_tx.send(res.map(|_| api::OfferRematchResponse));
+            }
+
+            api::ServerEvent::AcceptRematchRequest(match_id, res_tx) => {
+                let res = self.swarm.behaviour_mut().accept_rematch(match_id);
+                let _ = res_tx.send(res.map(|_| api::AcceptRematchResponse));
+            }
+        }
+    }
+}
+
+fn move_info(mv: Move, san: String) -> api::MoveInfo {
+    api::MoveInfo {
+        uci: mv.to_string(),
+        san,
+    }
+}
+
+fn draw_reason(reason: DrawReason) -> api::MatchEndReason {
+    match reason {
+        DrawReason::Stalemate => api::MatchEndReason::Stalemate,
+        DrawReason::InsufficientMaterial => api::MatchEndReason::InsufficientMaterial,
+        DrawReason::TimeoutVsInsufficientMaterial => {
+            api::MatchEndReason::TimeoutVsInsufficientMaterial
+        }
+        DrawReason::ThreefoldRepetition => api::MatchEndReason::ThreefoldRepetition,
+        DrawReason::FivefoldRepetition => api::MatchEndReason::FivefoldRepetition,
+        DrawReason::FiftyMoveRule => api::MatchEndReason::FiftyMoveRule,
+        DrawReason::SeventyFiveMoveRule => api::MatchEndReason::SeventyFiveMoveRule,
+    }
+}
+
+fn result_info(result: MatchResult) -> api::MatchResultInfo {
+    match result {
+        MatchResult::Win { winner, reason } => api::MatchResultInfo {
+            winner: Some(SerializableColor(winner)),
+            reason: match reason {
+                WinReason::Checkmate => api::MatchEndReason::Checkmate,
+                WinReason::Timeout => api::MatchEndReason::Timeout,
+                WinReason::KingOfTheHill => api::MatchEndReason::KingOfTheHill,
+                WinReason::ThreeChecks => api::MatchEndReason::ThreeChecks,
+                WinReason::KingExploded => api::MatchEndReason::KingExploded,
+            },
+        },
+
+        MatchResult::Draw { reason } => api::MatchResultInfo {
+            winner: None,
+            reason: draw_reason(reason),
+        },
+    }
+}
+
+/// Clock settings of a live match's time control, `None` for a correspondence match.
+fn time_control_info(time_control: TimeControl) -> Option<api::TimeControlInfo> {
+    match time_control {
+        TimeControl::Live { initial, increment } => Some(api::TimeControlInfo {
+            initial_secs: initial.as_secs() as u32,
+            increment_secs: increment.as_secs() as u32,
+        }),
+        TimeControl::Correspondence { .. } => None,
+    }
+}
+
+fn clock_info(clock: ClockTimes) -> api::ClockInfo {
+    api::ClockInfo {
+        white_ms: clock.white.as_millis() as u64,
+        black_ms: clock.black.as_millis() as u64,
+    }
+}
+
+fn match_info(
+    game: &Match,
+    remaining_move_time: Option<Duration>,
+    clock: Option<ClockTimes>,
+) -> api::MatchInfo {
+    api::MatchInfo {
+        match_id: SerializableMatchId(game.id()),
+        peer_id: SerializablePeerId(game.opponent()),
+        color: SerializableColor(game.color()),
+        variant: SerializableVariant(game.variant()),
+        days_per_move: game.days_per_move(),
+        remaining_move_time_ms: remaining_move_time.map(|time| time.as_millis() as u64),
+        time_control: time_control_info(game.time_control()),
+        store_peer: game.time_control().store_peer().map(SerializablePeerId),
+        clock: clock.map(clock_info),
+        previous_match_id: game.previous_match().map(SerializableMatchId),
+        initial_fen: game.initial_position().fen(),
+        fen: game.position().fen(),
+        moves: game
+            .moves()
+            .iter()
+            .zip(game.san_moves())
+            .map(|(&mv, san)| move_info(mv, san.clone()))
+            .collect(),
+        result: game.result().map(result_info),
+        claimable_draw: game.claimable_draw().map(draw_reason),
+    }
+}
+
+fn event_notification(event: BehaviourEvent) -> api::ServerEventNotification {
+    match event {
+        BehaviourEvent::Ipchess(IpchessEvent::PeerChallenge {
+            peer_id,
+            rematch: Some(rematch),
+            ..
+        }) => api::ServerEventNotification::RematchOffered {
+            peer_id: SerializablePeerId(peer_id),
+            match_id: SerializableMatchId(rematch.previous_match),
+            swap_colors: rematch.swap_colors,
+        },
+
+        BehaviourEvent::Ipchess(IpchessEvent::PeerChallenge {
+            peer_id,
+            variant,
+            time_control,
+            rematch: None,
+        }) => api::ServerEventNotification::PeerChallenge {
+            peer_id: SerializablePeerId(peer_id),
+            variant: SerializableVariant(variant),
+            days_per_move: time_control.days_per_move(),
+            time_control: time_control_info(time_control),
+            store_peer: time_control.store_peer().map(SerializablePeerId),
+        },
+
+        BehaviourEvent::Ipchess(IpchessEvent::ChallengeAccepted { peer_id, .. }) => {
+            api::ServerEventNotification::ChallengeAccepted {
+                peer_id: SerializablePeerId(peer_id),
+            }
+        }
+
+        BehaviourEvent::Ipchess(IpchessEvent::ChallengeCanceled { peer_id }) => {
+            api::ServerEventNotification::ChallengeCanceled {
+                peer_id: SerializablePeerId(peer_id),
+            }
+        }
+
+        BehaviourEvent::Ipchess(IpchessEvent::ChallengeDeclined {
+            peer_id,
+            supported_variants,
+        }) => api::ServerEventNotification::ChallengeDeclined {
+            peer_id: SerializablePeerId(peer_id),
+            supported_variants: supported_variants
+                .map(|variants| variants.into_iter().map(SerializableVariant).collect()),
+        },
+
+        BehaviourEvent::Ipchess(IpchessEvent::MatchStarted {
+            match_id,
+            peer_id,
+            color,
+            previous_match,
+        }) => api::ServerEventNotification::MatchStarted {
+            match_id: SerializableMatchId(match_id),
+            peer_id: SerializablePeerId(peer_id),
+            color: SerializableColor(color),
+            previous_match_id: previous_match.map(SerializableMatchId),
+        },
+
+        BehaviourEvent::Ipchess(IpchessEvent::MovePlayed {
+            match_id,
+            ply,
+            mv,
+            san,
+            position,
+            claimable_draw,
+            clock,
+        }) => api::ServerEventNotification::MovePlayed {
+            match_id: SerializableMatchId(match_id),
+            ply,
+            mv: move_info(mv, san),
+            fen: position.fen(),
+            claimable_draw: claimable_draw.map(draw_reason),
+            clock: clock.map(clock_info),
+        },
+
+        BehaviourEvent::Ipchess(IpchessEvent::MatchEnded { match_id, result }) => {
+            api::ServerEventNotification::MatchEnded {
+                match_id: SerializableMatchId(match_id),
+                result: result_info(result),
+            }
+        }
+
+        BehaviourEvent::Ipchess(IpchessEvent::ChatMessage {
+            match_id,
+            color,
+            text,
+        }) => api::ServerEventNotification::ChatMessage {
+            match_id: SerializableMatchId(match_id),
+            color: SerializableColor(color),
+            text,
+        },
+
+        BehaviourEvent::Ipchess(IpchessEvent::Error(err)) => {
+            log::debug!("Ipchess error {:?}", err);
+            error_notification(err)
+        }
+
+        BehaviourEvent::ChallengePeerNotFound { peer_id } => {
+            api::ServerEventNotification::ChallengeFailed {
+                peer_id: SerializablePeerId(peer_id),
+                direction: SerializableChallengeDirection(ChallengeDirection::Outbound),
+                reason: api::ChallengeFailureReason::PeerNotFound,
+            }
+        }
+    }
+}
+
+fn error_notification(err: IpchessError) -> api::ServerEventNotification {
+    match err {
+        IpchessError::ChallengeTimeout { peer_id, direction } => {
+            let reason = match direction {
+                ChallengeDirection::Outbound => api::ChallengeFailureReason::AcceptTimeout,
+                ChallengeDirection::Inbound => api::ChallengeFailureReason::PreimageTimeout,
+            };
+
+            api::ServerEventNotification::ChallengeTimedOut {
+                peer_id: SerializablePeerId(peer_id),
+                direction: SerializableChallengeDirection(direction),
+                reason,
+            }
+        }
+
+        IpchessError::ChallengeCommitmentPreimageMismatch { peer_id } => {
+            api::ServerEventNotification::ChallengeFailed {
+                peer_id: SerializablePeerId(peer_id),
+                direction: SerializableChallengeDirection(ChallengeDirection::Inbound),
+                reason: api::ChallengeFailureReason::PreimageMismatch,
+            }
+        }
+
+        IpchessError::PeerDialFailure { peer_id, direction } => {
+            api::ServerEventNotification::PeerUnreachable {
+                peer_id: SerializablePeerId(peer_id),
+                direction: SerializableChallengeDirection(direction),
+                reason: api::ChallengeFailureReason::DialFailure,
+            }
+        }
+
+        IpchessError::PeerSubstreamFailure { peer_id, direction } => {
+            api::ServerEventNotification::PeerUnreachable {
+                peer_id: SerializablePeerId(peer_id),
+                direction: SerializableChallengeDirection(direction),
+                reason: api::ChallengeFailureReason::SubstreamFailure,
+            }
+        }
+
+        IpchessError::InvalidMatchMessage {
+            peer_id, match_id, ..
+        } => api::ServerEventNotification::InvalidMatchMessage {
+            match_id: SerializableMatchId(match_id),
+            peer_id: SerializablePeerId(peer_id),
+        },
+    }
+}
@@ -1,6 +1,10 @@
 mod behaviour;
+mod clock;
 mod handler;
 mod ipchessproto;
+mod record;
 
 pub use behaviour::*;
+pub use clock::*;
 pub use handler::*;
+pub use record::*;
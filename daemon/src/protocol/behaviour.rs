@@ -1,34 +1,93 @@
 use std::{
     collections::{HashMap, HashSet, VecDeque},
+    pin::Pin,
+    sync::Arc,
     task::Poll,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
+use futures::FutureExt;
 use libp2p::{
     core::connection::ConnectionId,
     multihash::Hasher,
-    swarm::{NetworkBehaviour, NetworkBehaviourAction, NotifyHandler, ProtocolsHandler},
+    swarm::{
+        DialPeerCondition, NetworkBehaviour, NetworkBehaviourAction, NotifyHandler,
+        ProtocolsHandler,
+    },
     Multiaddr, PeerId,
 };
 use rand::Rng;
 use thiserror::Error;
 
-use super::{IpchessHandler, IpchessHandlerEventIn, IpchessHandlerEventOut};
+use super::{
+    decode_move_record, Clock, IpchessHandler, IpchessHandlerEventIn, IpchessHandlerEventOut,
+    SystemClock,
+};
+use crate::{
+    chess::{Color, Move, Position},
+    game::{
+        ClockTimes, DrawReason, Match, MatchClock, MatchError, MatchId, MatchResult, MatchSetup,
+        Rematch, TimeControl, Variant, MAX_DAYS_PER_MOVE, MAX_INCREMENT, MAX_INITIAL_TIME,
+    },
+};
+
+/// Seconds in a day, the unit of correspondence move deadlines.
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+/// Delay between DHT queries for the opponent's next move in a correspondence match.
+pub(crate) const MOVE_POLL_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Slack given to the opponent's move deadline in correspondence matches, for a move played in
+/// time to still be picked up from the DHT.
+const MOVE_RECORD_GRACE_PERIOD: Duration = Duration::from_secs(2 * MOVE_POLL_INTERVAL.as_secs());
+/// Time a store peer keeps a move record for, until the latest deadline a correspondence move
+/// can have.
+const STORED_MOVE_RECORD_TTL: Duration = Duration::from_secs(
+    MAX_DAYS_PER_MOVE as u64 * SECS_PER_DAY + MOVE_RECORD_GRACE_PERIOD.as_secs(),
+);
+/// Number of attempts made to find or dial a challenged peer before giving up.
+pub(crate) const PEER_LOOKUP_MAX_ATTEMPTS: u32 = 3;
+/// Delay before the first retry of a challenged peer's lookup or dial, doubled on every
+/// subsequent retry.
+pub(crate) const PEER_LOOKUP_BASE_BACKOFF: Duration = Duration::from_secs(2);
+/// Maximum number of characters in a chat message.
+pub const MAX_CHAT_MESSAGE_LEN: usize = 500;
 
 /// Challenge sent to a peer.
 struct OutboundChallenge {
     /// Preimage of the commitment sent to the challenged peer.
     preimage: Vec<u8>,
+    variant: Variant,
+    time_control: TimeControl,
+    rematch: Option<Rematch>,
     /// Instant the challenge was sent to the peer.
     timestamp: Instant,
 }
 
+/// Move record of a correspondence match kept for the players as its store peer.
+struct StoredMoveRecord {
+    /// Encoded signed record, served as is.
+    value: Vec<u8>,
+    expires: Instant,
+}
+
+/// Dial of a peer an outbound challenge waits on to be sent.
+struct PendingDial {
+    /// Number of dials started so far.
+    attempts: u32,
+    /// Instant of the next dial, set while waiting to retry.
+    retry_at: Option<Instant>,
+}
+
 /// States a challenge received from a peer is allowed to be in.
 enum InboundChallenge {
     /// Challenge was received by this peer and is ready to be accepted or declined.
     Received {
         /// Commitment for the random bytes chosen by the peer.
         commitment: Vec<u8>,
+        variant: Variant,
+        time_control: TimeControl,
+        rematch: Option<Rematch>,
+        /// Instant the challenge was received.
+        timestamp: Instant,
     },
 
     /// Challenge was accepted by this peer but it has not received the pre image for the challenger's commitment yet.
@@ -37,11 +96,23 @@ enum InboundChallenge {
         commitment: Vec<u8>,
         /// Random bytes chosen by the challenged peer.
         random: Vec<u8>,
+        variant: Variant,
+        time_control: TimeControl,
+        rematch: Option<Rematch>,
         /// Instant the random bytes were sent to the challenger.
         timestamp: Instant,
     },
 }
 
+impl InboundChallenge {
+    fn rematch(&self) -> Option<Rematch> {
+        match self {
+            InboundChallenge::Received { rematch, .. }
+            | InboundChallenge::PendingPreimage { rematch, .. } => *rematch,
+        }
+    }
+}
+
 /// A accepted challenge containing all information about the match's negotiation.
 #[derive(Debug)]
 pub struct AcceptedChallenge {
@@ -51,27 +122,103 @@ pub struct AcceptedChallenge {
     random: Vec<u8>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum ChallengeDirection {
     Inbound,
     Outbound,
 }
 
+/// State of a challenge as reported by [`Ipchess::challenges`], or by the node while the
+/// challenged peer is looked up.
+#[derive(Debug, Clone, Copy)]
+pub enum ChallengeState {
+    /// Outbound challenge not sent yet, the peer's addresses are being looked up in the DHT.
+    LookingUpPeer,
+    /// Outbound challenge waiting for the peer to accept or decline it.
+    PendingAccept,
+    /// Inbound challenge waiting to be accepted or declined by this peer.
+    Received,
+    /// Inbound challenge accepted by this peer, waiting for the challenger's commitment preimage.
+    PendingPreimage,
+}
+
+/// Snapshot of a challenge in progress.
+#[derive(Debug)]
+pub struct ChallengeSummary {
+    pub peer_id: PeerId,
+    pub direction: ChallengeDirection,
+    pub state: ChallengeState,
+    pub variant: Variant,
+    /// How long players have to move once the match started.
+    pub time_control: TimeControl,
+    /// Finished match the challenge offers a rematch of.
+    pub rematch: Option<Rematch>,
+    /// Time elapsed since the challenge entered its current state.
+    pub age: Duration,
+    /// Time left until the challenge times out, `None` if it cannot time out in its current state.
+    pub remaining_timeout: Option<Duration>,
+}
+
 #[derive(Error, Debug)]
 pub enum IpchessError {
     #[error("Preimage revealed by peer does not match previously sent commitment")]
-    ChallengeCommitmentPreimageMismatch,
+    ChallengeCommitmentPreimageMismatch { peer_id: PeerId },
     #[error("Challenge timed out")]
     ChallengeTimeout {
         peer_id: PeerId,
         direction: ChallengeDirection,
     },
+    #[error("Failed dialing peer")]
+    PeerDialFailure {
+        peer_id: PeerId,
+        direction: ChallengeDirection,
+    },
+    #[error("Failed opening substream to peer")]
+    PeerSubstreamFailure {
+        peer_id: PeerId,
+        direction: ChallengeDirection,
+    },
+    #[error("Peer sent a move, draw claim or chat message which is not valid in the match")]
+    InvalidMatchMessage {
+        peer_id: PeerId,
+        match_id: MatchId,
+        error: MatchError,
+    },
+}
+
+/// Errors returned by operations requested to the behaviour.
+#[derive(Error, Debug)]
+pub enum ChallengeError {
+    #[error("No challenge found for peer {peer_id}")]
+    NoSuchChallenge { peer_id: PeerId },
+    #[error("A challenge to peer {peer_id} is already in progress")]
+    DuplicateChallenge { peer_id: PeerId },
+    #[error("Challenge with peer {peer_id} is in an invalid state for this operation")]
+    InvalidState { peer_id: PeerId },
+    #[error("No match found with id {match_id}")]
+    NoSuchMatch { match_id: MatchId },
+    #[error("Match {match_id} is still in progress")]
+    MatchInProgress { match_id: MatchId },
+    #[error("Days per move must be between 1 and {max}, got {days_per_move}", max = MAX_DAYS_PER_MOVE)]
+    InvalidDaysPerMove { days_per_move: u32 },
+    #[error(
+        "Live matches must start with between 1 second and {max_initial:?} per player and an increment of at most {max_increment:?}",
+        max_initial = MAX_INITIAL_TIME,
+        max_increment = MAX_INCREMENT
+    )]
+    InvalidTimeControl,
+    #[error("The store peer of a correspondence match must not be one of its players")]
+    InvalidStorePeer,
 }
 
 #[derive(Debug)]
 pub enum IpchessEvent {
     PeerChallenge {
         peer_id: PeerId,
+        variant: Variant,
+        time_control: TimeControl,
+        /// Finished match the challenge offers a rematch of.
+        rematch: Option<Rematch>,
     },
 
     ChallengeAccepted {
@@ -81,21 +228,82 @@ pub enum IpchessEvent {
 
     ChallengeDeclined {
         peer_id: PeerId,
+        /// Variants the peer plays, of those known to this node, listed when it declined because
+        /// it does not play the challenge's variant.
+        supported_variants: Option<Vec<Variant>>,
     },
 
     ChallengeCanceled {
         peer_id: PeerId,
     },
 
+    /// A match started after a challenge was accepted by both peers.
+    MatchStarted {
+        match_id: MatchId,
+        peer_id: PeerId,
+        /// Color played by this node.
+        color: Color,
+        /// Match the new one is a rematch of.
+        previous_match: Option<MatchId>,
+    },
+
+    /// A move was played in a match, by either peer.
+    MovePlayed {
+        match_id: MatchId,
+        /// Number of half moves played before this one.
+        ply: u32,
+        mv: Move,
+        /// The move in Standard Algebraic Notation.
+        san: String,
+        /// Position after the move.
+        position: Position,
+        /// Draw the player to move may now claim.
+        claimable_draw: Option<DrawReason>,
+        /// Time left to each player after the move, `None` unless the match is live.
+        clock: Option<ClockTimes>,
+    },
+
+    MatchEnded {
+        match_id: MatchId,
+        result: MatchResult,
+    },
+
+    /// A chat message was sent in a match, by either peer.
+    ChatMessage {
+        match_id: MatchId,
+        /// Color played by the sender.
+        color: Color,
+        text: String,
+    },
+
     Error(IpchessError),
 }
 
 /// Behaviour configuration.
+#[derive(Debug, Clone)]
 pub struct IpchessConfig {
     /// Amount of time a peer has until an outbound challenge is considered timed out.
-    challenge_accept_timeout: Duration,
-    /// Amount of time a peer to sent back the challenge's commitment preimage.
-    challenge_preimage_timeout: Duration,
+    pub challenge_accept_timeout: Duration,
+    /// Amount of time a peer has to send back the challenge's commitment preimage.
+    pub challenge_preimage_timeout: Duration,
+    /// Amount of time an idle connection is kept open after its last substream finished.
+    pub idle_keep_alive: Duration,
+    /// Maximum number of inbound challenges waiting for an answer, further challenges are
+    /// declined.
+    pub max_pending_challenges: usize,
+    /// Maximum size in bytes of a message read from a peer, at most
+    /// [`MAX_FRAME_SIZE_LIMIT`](super::MAX_FRAME_SIZE_LIMIT).
+    pub max_frame_size: usize,
+    /// Variants challenges are accepted for, challenges to play others are declined with this
+    /// list.
+    pub variants: Vec<Variant>,
+    /// Slack given to the opponent's clock in live matches, for the time moves take to reach
+    /// the peers: the opponent is flagged once its time ran past zero by this much, and the time
+    /// it reports after a move may exceed this node's count by as much.
+    pub flag_grace_period: Duration,
+    /// Maximum number of move records kept for other peers' correspondence matches as their
+    /// store peer, further records are dropped. 0 to not act as a store peer.
+    pub max_stored_move_records: usize,
 }
 
 impl Default for IpchessConfig {
@@ -103,31 +311,85 @@ impl Default for IpchessConfig {
         Self {
             challenge_accept_timeout: Duration::from_secs(5 * 60),
             challenge_preimage_timeout: Duration::from_secs(15),
+            idle_keep_alive: Duration::from_secs(30),
+            max_pending_challenges: 32,
+            max_frame_size: 4096,
+            variants: Variant::ALL.to_vec(),
+            flag_grace_period: Duration::from_secs(2),
+            max_stored_move_records: 1024,
         }
     }
 }
 
 pub struct Ipchess {
     config: IpchessConfig,
+    clock: Arc<dyn Clock>,
 
     events: VecDeque<NetworkBehaviourAction<IpchessHandlerEventIn, IpchessEvent>>,
 
     outbound_challenges: HashMap<PeerId, OutboundChallenge>,
     inbound_challenges: HashMap<PeerId, InboundChallenge>,
+    /// Commitments of outbound challenges waiting for a connection to the peer to be sent.
+    pending_challenges: HashMap<PeerId, Vec<u8>>,
+    /// Dials of the peers pending challenges wait on.
+    pending_dials: HashMap<PeerId, PendingDial>,
+    /// Wakes the task up for the earliest dial retry, move deadline or flag fall.
+    wake_timer: Option<Pin<Box<tokio::time::Sleep>>>,
 
+    matches: HashMap<MatchId, Match>,
+    /// Instants by which the player to move must have moved, for correspondence matches.
+    move_deadlines: HashMap<MatchId, Instant>,
+    /// Last instants polls for the opponent's next move in correspondence matches came back
+    /// without it: the move is timed from then at the earliest.
+    move_missed_at: HashMap<MatchId, Instant>,
+    /// Instants the store peers of correspondence matches were last asked, while connected, for
+    /// the opponent's next move.
+    move_fetched_at: HashMap<MatchId, Instant>,
+    /// Clocks of live matches, stopped once the match ended.
+    clocks: HashMap<MatchId, MatchClock>,
+    /// Match messages waiting for a connection to the peer to be sent.
+    pending_match_messages: HashMap<PeerId, Vec<IpchessHandlerEventIn>>,
+    /// Move records kept for other peers' matches, by match id, ply and player who made the
+    /// move.
+    stored_move_records: HashMap<(MatchId, u32, PeerId), StoredMoveRecord>,
+
+    connected_peers: HashSet<PeerId>,
     peer_addresses: HashMap<PeerId, HashSet<Multiaddr>>,
 }
 
 impl Ipchess {
     pub fn new() -> Self {
+        Self::with_config(IpchessConfig::default())
+    }
+
+    pub fn with_config(config: IpchessConfig) -> Self {
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Creates the behaviour with the clock challenge timeouts and connection keep-alive are
+    /// measured by.
+    pub fn with_clock(config: IpchessConfig, clock: Arc<dyn Clock>) -> Self {
         Ipchess {
-            config: IpchessConfig::default(),
+            config,
+            clock,
 
             events: VecDeque::new(),
 
             outbound_challenges: HashMap::new(),
             inbound_challenges: HashMap::new(),
+            pending_challenges: HashMap::new(),
+            pending_dials: HashMap::new(),
+            wake_timer: None,
+
+            matches: HashMap::new(),
+            move_deadlines: HashMap::new(),
+            move_missed_at: HashMap::new(),
+            move_fetched_at: HashMap::new(),
+            clocks: HashMap::new(),
+            pending_match_messages: HashMap::new(),
+            stored_move_records: HashMap::new(),
 
+            connected_peers: HashSet::new(),
             peer_addresses: HashMap::new(),
         }
     }
@@ -136,10 +398,146 @@ impl Ipchess {
         self.peer_addresses.entry(peer_id).or_default().insert(addr);
     }
 
-    pub fn challenge_peer(&mut self, peer_id: PeerId) {
+    /// Returns a snapshot of all inbound and outbound challenges in progress.
+    pub fn challenges(&self) -> Vec<ChallengeSummary> {
+        let now = self.clock.now();
+
+        let outbound = self.outbound_challenges.iter().map(|(peer_id, challenge)| {
+            let age = now.duration_since(challenge.timestamp);
+
+            ChallengeSummary {
+                peer_id: *peer_id,
+                direction: ChallengeDirection::Outbound,
+                state: ChallengeState::PendingAccept,
+                variant: challenge.variant,
+                time_control: challenge.time_control,
+                rematch: challenge.rematch,
+                age,
+                remaining_timeout: Some(self.config.challenge_accept_timeout.saturating_sub(age)),
+            }
+        });
+
+        let inbound = self
+            .inbound_challenges
+            .iter()
+            .map(|(peer_id, challenge)| match challenge {
+                InboundChallenge::Received {
+                    variant,
+                    time_control,
+                    rematch,
+                    timestamp,
+                    ..
+                } => ChallengeSummary {
+                    peer_id: *peer_id,
+                    direction: ChallengeDirection::Inbound,
+                    state: ChallengeState::Received,
+                    variant: *variant,
+                    time_control: *time_control,
+                    rematch: *rematch,
+                    age: now.duration_since(*timestamp),
+                    remaining_timeout: None,
+                },
+
+                InboundChallenge::PendingPreimage {
+                    variant,
+                    time_control,
+                    rematch,
+                    timestamp,
+                    ..
+                } => {
+                    let age = now.duration_since(*timestamp);
+
+                    ChallengeSummary {
+                        peer_id: *peer_id,
+                        direction: ChallengeDirection::Inbound,
+                        state: ChallengeState::PendingPreimage,
+                        variant: *variant,
+                        time_control: *time_control,
+                        rematch: *rematch,
+                        age,
+                        remaining_timeout: Some(
+                            self.config.challenge_preimage_timeout.saturating_sub(age),
+                        ),
+                    }
+                }
+            });
+
+        outbound.chain(inbound).collect()
+    }
+
+    pub fn has_outbound_challenge(&self, peer_id: &PeerId) -> bool {
+        self.outbound_challenges.contains_key(peer_id)
+    }
+
+    /// Challenges a peer to a match of `variant` played under `time_control`.
+    pub fn challenge_peer(
+        &mut self,
+        peer_id: PeerId,
+        variant: Variant,
+        time_control: TimeControl,
+    ) -> Result<(), ChallengeError> {
+        self.send_challenge(peer_id, variant, time_control, None)
+    }
+
+    /// Offers the opponent of a finished match a rematch, of the same variant and time control.
+    ///
+    /// The rematch is negotiated like any challenge, drawing colors anew unless `swap_colors` is
+    /// set, in which case each player takes the color its opponent played.
+    pub fn offer_rematch(
+        &mut self,
+        match_id: MatchId,
+        swap_colors: bool,
+    ) -> Result<(), ChallengeError> {
+        let game = self.finished_match(match_id)?;
+        let peer_id = game.opponent();
+        let variant = game.variant();
+        let time_control = game.time_control();
+
+        let rematch = Rematch {
+            previous_match: match_id,
+            swap_colors,
+        };
+        self.send_challenge(peer_id, variant, time_control, Some(rematch))
+    }
+
+    /// Accepts the rematch offered by the opponent of a finished match.
+    pub fn accept_rematch(&mut self, match_id: MatchId) -> Result<(), ChallengeError> {
+        let peer_id = self.finished_match(match_id)?.opponent();
+
+        let offered = self
+            .inbound_challenges
+            .get(&peer_id)
+            .and_then(InboundChallenge::rematch)
+            .map_or(false, |rematch| rematch.previous_match == match_id);
+        if !offered {
+            return Err(ChallengeError::NoSuchChallenge { peer_id });
+        }
+
+        self.accept_peer_challenge(peer_id)
+    }
+
+    fn finished_match(&self, match_id: MatchId) -> Result<&Match, ChallengeError> {
+        match self.matches.get(&match_id) {
+            Some(game) if game.result().is_some() => Ok(game),
+            Some(_) => Err(ChallengeError::MatchInProgress { match_id }),
+            None => Err(ChallengeError::NoSuchMatch { match_id }),
+        }
+    }
+
+    fn send_challenge(
+        &mut self,
+        peer_id: PeerId,
+        variant: Variant,
+        time_control: TimeControl,
+        rematch: Option<Rematch>,
+    ) -> Result<(), ChallengeError> {
         if self.outbound_challenges.contains_key(&peer_id) {
-            log::debug!("Duplicate outbound challenge to peer {}, ignoring", peer_id);
-            return;
+            return Err(ChallengeError::DuplicateChallenge { peer_id });
+        }
+
+        validate_time_control(time_control)?;
+        if time_control.store_peer() == Some(peer_id) {
+            return Err(ChallengeError::InvalidStorePeer);
         }
 
         let mut thread_rng = rand::thread_rng();
@@ -153,34 +551,64 @@ impl Ipchess {
             peer_id,
             OutboundChallenge {
                 preimage,
+                variant,
+                time_control,
+                rematch,
                 // timestamp is set to now but this could be changed to be set to the
                 // instant at which the handler sent the challenge through the network.
-                timestamp: Instant::now(),
+                timestamp: self.clock.now(),
             },
         );
 
-        self.events
-            .push_back(NetworkBehaviourAction::NotifyHandler {
-                peer_id: peer_id,
-                handler: NotifyHandler::Any,
-                event: IpchessHandlerEventIn::Challenge { commitment },
+        if self.connected_peers.contains(&peer_id) {
+            self.events
+                .push_back(NetworkBehaviourAction::NotifyHandler {
+                    peer_id: peer_id,
+                    handler: NotifyHandler::Any,
+                    event: IpchessHandlerEventIn::Challenge {
+                        commitment,
+                        variant,
+                        time_control,
+                        rematch,
+                    },
+                });
+        } else {
+            log::debug!(
+                "Peer {} is not connected, dialing before challenging",
+                peer_id
+            );
+
+            self.pending_challenges.insert(peer_id, commitment);
+            self.pending_dials.insert(
+                peer_id,
+                PendingDial {
+                    attempts: 1,
+                    retry_at: None,
+                },
+            );
+            self.events.push_back(NetworkBehaviourAction::DialPeer {
+                peer_id,
+                condition: DialPeerCondition::Disconnected,
             });
+        }
+
+        Ok(())
     }
 
-    pub fn accept_peer_challenge(&mut self, peer_id: PeerId) {
+    pub fn accept_peer_challenge(&mut self, peer_id: PeerId) -> Result<(), ChallengeError> {
         let challenge_data = match self.inbound_challenges.remove(&peer_id) {
             Some(challenge_data) => challenge_data,
-            None => {
-                log::warn!(
-                    "Ignoring accept_peer_challenge, there are no inbound challenges from peer {}",
-                    peer_id
-                );
-                return;
-            }
+            None => return Err(ChallengeError::NoSuchChallenge { peer_id }),
         };
 
-        let updated_challenge_data = match challenge_data {
-            InboundChallenge::Received { commitment } => {
+        match challenge_data {
+            InboundChallenge::Received {
+                commitment,
+                variant,
+                time_control,
+                rematch,
+                ..
+            } => {
                 let mut thread_rng = rand::thread_rng();
                 let random = thread_rng.gen::<[u8; 32]>().to_vec();
 
@@ -193,195 +621,814 @@ impl Ipchess {
                         },
                     });
 
-                InboundChallenge::PendingPreimage {
-                    commitment,
-                    random,
-                    timestamp: Instant::now(),
-                }
-            }
-
-            InboundChallenge::PendingPreimage { .. } => {
-                log::warn!(
-                    "Ignoring accept_peer_challenge for peer {}, challenge was already accepted and is pending the receipt of the preimage",
-                    peer_id
+                self.inbound_challenges.insert(
+                    peer_id,
+                    InboundChallenge::PendingPreimage {
+                        commitment,
+                        random,
+                        variant,
+                        time_control,
+                        rematch,
+                        timestamp: self.clock.now(),
+                    },
                 );
 
-                challenge_data
+                Ok(())
             }
-        };
 
-        self.inbound_challenges
-            .insert(peer_id, updated_challenge_data);
+            // challenge was already accepted and is pending the receipt of the preimage
+            InboundChallenge::PendingPreimage { .. } => {
+                self.inbound_challenges.insert(peer_id, challenge_data);
+                Err(ChallengeError::InvalidState { peer_id })
+            }
+        }
     }
 
-    pub fn cancel_challenge(&mut self, peer_id: PeerId) {
-        if self.outbound_challenges.remove(&peer_id).is_some() {
-            self.events
-                .push_back(NetworkBehaviourAction::NotifyHandler {
-                    peer_id,
-                    handler: NotifyHandler::Any,
-                    event: IpchessHandlerEventIn::ChallengeCanceled,
-                });
-        } else {
-            log::debug!(
-                "Ignoring cancel_challenge, no challenge for peer {}",
-                peer_id
-            );
+    pub fn cancel_challenge(&mut self, peer_id: PeerId) -> Result<(), ChallengeError> {
+        if self.outbound_challenges.remove(&peer_id).is_none() {
+            return Err(ChallengeError::NoSuchChallenge { peer_id });
+        }
+
+        // the challenge never reached the peer, there is nothing to cancel on its side
+        if self.pending_challenges.remove(&peer_id).is_some() {
+            self.pending_dials.remove(&peer_id);
+            return Ok(());
         }
+
+        self.events
+            .push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id,
+                handler: NotifyHandler::Any,
+                event: IpchessHandlerEventIn::ChallengeCanceled,
+            });
+
+        Ok(())
     }
 
-    pub fn decline_peer_challenge(&mut self, peer_id: PeerId) {
-        if self.inbound_challenges.remove(&peer_id).is_some() {
-            self.events
-                .push_back(NetworkBehaviourAction::NotifyHandler {
-                    peer_id,
-                    handler: NotifyHandler::Any,
-                    event: IpchessHandlerEventIn::ChallengeDeclined,
-                });
-        } else {
-            log::debug!(
-                "Ignoring decline_peer_challenge, no challenge from peer {}",
-                peer_id
-            );
+    /// Removes all pending challenges with the peer, returning the directions of the removed
+    /// challenges.
+    fn remove_challenges(&mut self, peer_id: &PeerId) -> Vec<ChallengeDirection> {
+        let mut directions = vec![];
+
+        if self.outbound_challenges.remove(peer_id).is_some() {
+            directions.push(ChallengeDirection::Outbound);
+        }
+
+        if self.inbound_challenges.remove(peer_id).is_some() {
+            directions.push(ChallengeDirection::Inbound);
         }
+
+        directions
     }
-}
 
-impl NetworkBehaviour for Ipchess {
-    type ProtocolsHandler = IpchessHandler;
-    type OutEvent = IpchessEvent;
+    pub fn decline_peer_challenge(&mut self, peer_id: PeerId) -> Result<(), ChallengeError> {
+        match self.inbound_challenges.get(&peer_id) {
+            Some(InboundChallenge::Received { .. }) => {}
+            Some(InboundChallenge::PendingPreimage { .. }) => {
+                return Err(ChallengeError::InvalidState { peer_id })
+            }
+            None => return Err(ChallengeError::NoSuchChallenge { peer_id }),
+        }
 
-    fn new_handler(&mut self) -> Self::ProtocolsHandler {
-        IpchessHandler::new()
+        self.inbound_challenges.remove(&peer_id);
+        self.refuse_challenge(peer_id, vec![]);
+
+        Ok(())
     }
 
-    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<libp2p::Multiaddr> {
-        self.peer_addresses
-            .get(peer_id)
-            .map_or(vec![], |addrs| addrs.clone().into_iter().collect())
+    /// Declines a challenge from a peer, listing the variants this node plays if the challenge
+    /// was refused for its variant.
+    fn refuse_challenge(&mut self, peer_id: PeerId, supported_variants: Vec<Variant>) {
+        self.events
+            .push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id,
+                handler: NotifyHandler::Any,
+                event: IpchessHandlerEventIn::ChallengeDeclined { supported_variants },
+            });
     }
 
-    fn inject_connected(&mut self, _peer_id: &PeerId) {}
+    pub fn matches(&self) -> impl Iterator<Item = &Match> {
+        self.matches.values()
+    }
 
-    fn inject_disconnected(&mut self, _peer_id: &PeerId) {}
+    /// Plays a move for this node in a match, sending it to the opponent.
+    ///
+    /// In a live match the move is refused once this node's time ran out.
+    pub fn make_move(&mut self, match_id: MatchId, mv: Move) -> Result<(), MatchError> {
+        self.end_flagged_matches();
 
-    fn inject_event(
-        &mut self,
-        peer_id: PeerId,
-        _conn_id: ConnectionId,
-        event: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent,
-    ) {
-        match event {
-            IpchessHandlerEventOut::ChallengeReceived { commitment } => {
-                self.inbound_challenges
-                    .insert(peer_id, InboundChallenge::Received { commitment });
+        let game = self
+            .matches
+            .get_mut(&match_id)
+            .ok_or(MatchError::NoSuchMatch { match_id })?;
 
-                self.events.push_back(NetworkBehaviourAction::GenerateEvent(
-                    IpchessEvent::PeerChallenge { peer_id },
-                ));
-            }
+        let ply = game.ply();
+        let color = game.color();
+        game.play(color, mv)?;
 
-            IpchessHandlerEventOut::ChallengeRevealReceived { preimage } => {
-                if let Some(inbound_challenge) = self.inbound_challenges.remove(&peer_id) {
-                    match inbound_challenge {
-                        InboundChallenge::PendingPreimage {
-                            commitment, random, ..
-                        } => {
-                            let preimage_hash = libp2p::multihash::Sha2_256::digest(&preimage);
+        let peer_id = game.opponent();
+        let now = self.clock.now();
+        let clock = self
+            .clocks
+            .get_mut(&match_id)
+            .map(|match_clock| match_clock.press(now));
+        self.send_match_message(
+            peer_id,
+            IpchessHandlerEventIn::MatchMove {
+                match_id,
+                ply,
+                mv,
+                clock,
+            },
+        );
+        self.on_move_played(match_id, ply, mv);
 
-                            if preimage_hash.as_ref().to_vec() == commitment {
-                                self.events.push_back(NetworkBehaviourAction::GenerateEvent(
-                                    IpchessEvent::ChallengeAccepted {
-                                        peer_id,
-                                        challenge: AcceptedChallenge { preimage, random },
-                                    },
-                                ));
-                            } else {
-                                self.events
-                                    .push_back(NetworkBehaviourAction::NotifyHandler {
-                                        peer_id,
-                                        handler: NotifyHandler::Any,
-                                        event: IpchessHandlerEventIn::ChallengePoisoned,
-                                    });
+        Ok(())
+    }
 
-                                self.events.push_back(NetworkBehaviourAction::GenerateEvent(
-                                    IpchessEvent::Error(
-                                        IpchessError::ChallengeCommitmentPreimageMismatch,
-                                    ),
-                                ));
-                            }
-                        }
+    /// Claims a draw for this node in a match, telling the opponent.
+    pub fn claim_draw(&mut self, match_id: MatchId) -> Result<DrawReason, MatchError> {
+        let game = self
+            .matches
+            .get_mut(&match_id)
+            .ok_or(MatchError::NoSuchMatch { match_id })?;
 
-                        InboundChallenge::Received { .. } => {
-                            self.events
-                                .push_back(NetworkBehaviourAction::NotifyHandler {
-                                    peer_id,
-                                    handler: NotifyHandler::Any,
-                                    event: IpchessHandlerEventIn::ChallengePoisoned,
-                                });
-                        }
-                    };
-                }
-            }
+        let ply = game.ply();
+        let color = game.color();
+        let reason = game.claim_draw(color)?;
 
-            IpchessHandlerEventOut::ChallengeAccepted { random } => {
-                if let Some(sent_challenge) = self.outbound_challenges.remove(&peer_id) {
-                    self.events
-                        .push_back(NetworkBehaviourAction::NotifyHandler {
-                            peer_id,
-                            handler: NotifyHandler::Any,
-                            event: IpchessHandlerEventIn::ChallengeReveal {
-                                preimage: sent_challenge.preimage.clone(),
-                            },
-                        });
+        let peer_id = game.opponent();
+        self.send_match_message(
+            peer_id,
+            IpchessHandlerEventIn::MatchDrawClaim { match_id, ply },
+        );
+        self.end_match(match_id, MatchResult::Draw { reason });
 
-                    self.events.push_back(NetworkBehaviourAction::GenerateEvent(
-                        IpchessEvent::ChallengeAccepted {
-                            peer_id,
-                            challenge: AcceptedChallenge {
-                                preimage: sent_challenge.preimage,
-                                random,
-                            },
-                        },
-                    ));
-                }
-            }
+        Ok(reason)
+    }
 
-            IpchessHandlerEventOut::ChallengeCanceled => {
-                if self.inbound_challenges.remove(&peer_id).is_some() {
-                    self.events.push_back(NetworkBehaviourAction::GenerateEvent(
-                        IpchessEvent::ChallengeCanceled { peer_id },
-                    ));
-                }
-            }
+    /// Sends a chat message to the opponent of a match, which may be over already.
+    pub fn send_chat_message(&mut self, match_id: MatchId, text: String) -> Result<(), MatchError> {
+        let game = self
+            .matches
+            .get(&match_id)
+            .ok_or(MatchError::NoSuchMatch { match_id })?;
 
-            IpchessHandlerEventOut::ChallengeDeclined => {
-                if self.outbound_challenges.remove(&peer_id).is_some() {
-                    self.events.push_back(NetworkBehaviourAction::GenerateEvent(
-                        IpchessEvent::ChallengeDeclined { peer_id },
-                    ));
-                }
-            }
+        if !is_valid_chat_message(&text) {
+            return Err(MatchError::InvalidChatMessage);
         }
-    }
 
-    fn poll(
+        let peer_id = game.opponent();
+        let color = game.color();
+        self.send_match_message(
+            peer_id,
+            IpchessHandlerEventIn::MatchChat {
+                match_id,
+                text: text.clone(),
+            },
+        );
+        self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+            IpchessEvent::ChatMessage {
+                match_id,
+                color,
+                text,
+            },
+        ));
+
+        Ok(())
+    }
+
+    /// Adds back a match played before this node restarted: the player to move of a
+    /// correspondence match in progress has `remaining_move_time` left to move, and the clock of
+    /// a finished live match shows `clock`.
+    ///
+    /// Live matches in progress are not restored, as their clocks kept running while the node
+    /// was down. Matches already known are left as they are.
+    pub fn restore_match(
         &mut self,
-        _cx: &mut std::task::Context<'_>,
-        _params: &mut impl libp2p::swarm::PollParameters,
-    ) -> Poll<
-        NetworkBehaviourAction<
-            <Self::ProtocolsHandler as ProtocolsHandler>::InEvent,
-            Self::OutEvent,
-        >,
-    > {
-        // drain pending events
-        if let Some(event) = self.events.pop_front() {
-            return Poll::Ready(event);
+        game: Match,
+        remaining_move_time: Option<Duration>,
+        clock: Option<ClockTimes>,
+    ) {
+        let match_id = game.id();
+        if self.matches.contains_key(&match_id) {
+            return;
+        }
+
+        match (game.time_control(), game.result()) {
+            (TimeControl::Live { .. }, None) => {
+                log::debug!("Not restoring live match {} in progress", match_id);
+                return;
+            }
+            (TimeControl::Live { increment, .. }, Some(_)) => {
+                if let Some(clock) = clock {
+                    self.clocks
+                        .insert(match_id, MatchClock::stopped(increment, clock));
+                }
+            }
+            (TimeControl::Correspondence { days_per_move, .. }, None) => {
+                let period = Duration::from_secs(u64::from(days_per_move) * SECS_PER_DAY);
+                let remaining =
+                    remaining_move_time.map_or(period, |remaining| remaining.min(period));
+                self.move_deadlines
+                    .insert(match_id, self.clock.now() + remaining);
+            }
+            (TimeControl::Correspondence { .. }, Some(_)) => {}
+        }
+
+        self.matches.insert(match_id, game);
+    }
+
+    /// Starts the match negotiated by a challenge both peers accepted.
+    fn start_match(
+        &mut self,
+        peer_id: PeerId,
+        setup: MatchSetup,
+        direction: ChallengeDirection,
+        rematch: Option<Rematch>,
+    ) {
+        let mut color = match direction {
+            ChallengeDirection::Outbound => setup.challenger_color,
+            ChallengeDirection::Inbound => !setup.challenger_color,
+        };
+
+        let previous_match = rematch.map(|rematch| rematch.previous_match);
+        if let Some(previous) = rematch
+            .filter(|rematch| rematch.swap_colors)
+            .and_then(|rematch| self.matches.get(&rematch.previous_match))
+        {
+            color = !previous.color();
+        }
+
+        self.matches.insert(
+            setup.id,
+            Match::new(
+                setup.id,
+                peer_id,
+                color,
+                setup.variant,
+                setup.time_control,
+                previous_match,
+                setup.position,
+            ),
+        );
+        self.update_move_deadline(setup.id);
+
+        if let TimeControl::Live { initial, increment } = setup.time_control {
+            self.clocks.insert(
+                setup.id,
+                MatchClock::start(initial, increment, self.clock.now()),
+            );
+        }
+
+        self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+            IpchessEvent::MatchStarted {
+                match_id: setup.id,
+                peer_id,
+                color,
+                previous_match,
+            },
+        ));
+    }
+
+    /// Time left for the player to move in a correspondence match, `None` for live or finished
+    /// matches.
+    ///
+    /// Moves picked up from the DHT are timed from when their record says they were played, so
+    /// both players see the same deadline up to the difference between their wall clocks.
+    pub fn remaining_move_time(&self, match_id: MatchId) -> Option<Duration> {
+        self.move_deadlines
+            .get(&match_id)
+            .map(|deadline| deadline.saturating_duration_since(self.clock.now()))
+    }
+
+    /// Time left to each player of a live match as counted by this node's clock, `None` for
+    /// correspondence matches. The clock stops once the match ended.
+    pub fn clock_times(&self, match_id: MatchId) -> Option<ClockTimes> {
+        self.clocks
+            .get(&match_id)
+            .map(|match_clock| match_clock.times(self.clock.now()))
+    }
+
+    /// Applies a move of a correspondence match the opponent stored in the DHT, as if the
+    /// opponent had sent it directly.
+    ///
+    /// The move's record must have been checked to be signed by `peer_id`, `played_at` is the
+    /// time it says the move was played at. The opponent signs that time, so this node's
+    /// deadline is only moved back to it as far as the last poll which missed the move. Moves
+    /// already played are ignored, since they may be received both directly and from the DHT.
+    pub fn receive_stored_move(
+        &mut self,
+        peer_id: PeerId,
+        match_id: MatchId,
+        ply: u32,
+        mv: Move,
+        played_at: SystemTime,
+    ) {
+        self.on_peer_move(peer_id, match_id, ply, mv, None, Some(played_at));
+    }
+
+    /// Notes that a poll for the opponent's next move in a correspondence match came back
+    /// without it, so the move was not played before now.
+    pub fn move_record_missing(&mut self, match_id: MatchId) {
+        self.move_missed_at.insert(match_id, self.clock.now());
+    }
+
+    /// Sends the signed record of a move of a correspondence match to the match's store peer,
+    /// for the opponent to fetch it from while this node is offline.
+    pub fn store_move_record(&mut self, store_peer: PeerId, record: Vec<u8>) {
+        self.send_match_message(
+            store_peer,
+            IpchessHandlerEventIn::MoveRecordStore { record },
+        );
+    }
+
+    /// Asks the store peer of a correspondence match for the record of the move `signer` made
+    /// at `ply`. A record found is applied like a move picked up from the DHT.
+    pub fn fetch_move_record(
+        &mut self,
+        store_peer: PeerId,
+        match_id: MatchId,
+        ply: u32,
+        signer: PeerId,
+    ) {
+        // the store peer may stay unreachable for a while, a single fetch waits for it
+        let pending = self
+            .pending_match_messages
+            .get(&store_peer)
+            .map_or(false, |events| {
+                events.iter().any(|event| {
+                    matches!(
+                        event,
+                        IpchessHandlerEventIn::MoveRecordFetch {
+                            match_id: pending_match_id,
+                            ply: pending_ply,
+                            ..
+                        } if *pending_match_id == match_id && *pending_ply == ply
+                    )
+                })
+            });
+        if pending {
+            return;
+        }
+
+        // a connected store peer answers right away when it has the record, one asked at the
+        // previous poll which did not answer had none then
+        if self.connected_peers.contains(&store_peer) {
+            if let Some(fetched_at) = self.move_fetched_at.insert(match_id, self.clock.now()) {
+                self.move_missed_at.insert(match_id, fetched_at);
+            }
+        }
+
+        self.send_match_message(
+            store_peer,
+            IpchessHandlerEventIn::MoveRecordFetch {
+                match_id,
+                ply,
+                signer,
+            },
+        );
+    }
+
+    /// Keeps a move record a player sent to this node as the store peer of its match, unless
+    /// the record is invalid or too many are kept already.
+    fn on_move_record_store(&mut self, peer_id: PeerId, value: Vec<u8>) {
+        if self.config.max_stored_move_records == 0 {
+            log::debug!(
+                "Ignoring move record from peer {}, not a store peer",
+                peer_id
+            );
+            return;
+        }
+
+        let record = match decode_move_record(&value) {
+            // only the player who made the move stores it, for the record to come from the match
+            Ok(record) if record.signer == peer_id => record,
+            Ok(_) => {
+                log::debug!(
+                    "Ignoring move record from peer {}, signed by another peer",
+                    peer_id
+                );
+                return;
+            }
+            Err(err) => {
+                log::debug!("Ignoring move record from peer {}: {}", peer_id, err);
+                return;
+            }
+        };
+
+        let now = self.clock.now();
+        self.stored_move_records
+            .retain(|_, stored| stored.expires > now);
+
+        let key = (record.match_id, record.ply, record.signer);
+        if !self.stored_move_records.contains_key(&key)
+            && self.stored_move_records.len() >= self.config.max_stored_move_records
+        {
+            log::debug!(
+                "Dropping move record from peer {}, too many stored records",
+                peer_id
+            );
+            return;
+        }
+
+        self.stored_move_records.insert(
+            key,
+            StoredMoveRecord {
+                value,
+                expires: now + STORED_MOVE_RECORD_TTL,
+            },
+        );
+    }
+
+    /// Applies a move record fetched from a store peer to the match it is for, if it was signed
+    /// by the match's opponent.
+    fn on_move_record_found(&mut self, peer_id: PeerId, value: Vec<u8>) {
+        let record = match decode_move_record(&value) {
+            Ok(record) => record,
+            Err(err) => {
+                log::debug!("Ignoring move record from store peer {}: {}", peer_id, err);
+                return;
+            }
+        };
+
+        let from_opponent = self
+            .matches
+            .get(&record.match_id)
+            .map_or(false, |game| game.opponent() == record.signer);
+        if !from_opponent {
+            log::debug!(
+                "Ignoring move record from store peer {}, not signed by the opponent of match {}",
+                peer_id,
+                record.match_id
+            );
+            return;
+        }
+
+        log::debug!(
+            "Found move {} of match {} at store peer {}",
+            record.ply,
+            record.match_id,
+            peer_id
+        );
+        self.receive_stored_move(
+            record.signer,
+            record.match_id,
+            record.ply,
+            record.mv,
+            record.played_at,
+        );
+    }
+
+    /// Plays a move received from a peer in one of its matches, along with the time the peer's
+    /// clock showed after the move in a live match, or the time a move picked up from the DHT
+    /// was played at in a correspondence match.
+    fn on_peer_move(
+        &mut self,
+        peer_id: PeerId,
+        match_id: MatchId,
+        ply: u32,
+        mv: Move,
+        clock: Option<Duration>,
+        played_at: Option<SystemTime>,
+    ) {
+        // a move arriving after the peer was flagged is too late
+        self.end_flagged_matches();
+
+        let already_played = self.matches.get(&match_id).map_or(false, |game| {
+            game.days_per_move().is_some() && ply < game.ply()
+        });
+        if already_played {
+            log::debug!("Ignoring move {} of match {} already played", ply, match_id);
+            return;
+        }
+
+        // moves received directly were just played, the grace period given to the peer's
+        // deadline is only for moves played in time but picked up late from the DHT
+        let now = self.clock.now();
+        let since_played = played_at.map_or(Duration::from_secs(0), |played_at| {
+            self.clock
+                .system_time()
+                .duration_since(played_at)
+                .unwrap_or_default()
+        });
+        if let Some(&deadline) = self.move_deadlines.get(&match_id) {
+            if now >= deadline && now.duration_since(deadline) >= since_played {
+                log::debug!(
+                    "Ignoring move {} of match {} played too late",
+                    ply,
+                    match_id
+                );
+                return;
+            }
+        }
+
+        let missed_at = self.move_missed_at.get(&match_id).copied();
+        let played =
+            self.on_match_message(peer_id, match_id, ply, |game, color| game.play(color, mv));
+
+        if played.is_err() {
+            return;
+        }
+
+        let tolerance = self.config.flag_grace_period;
+        let mover = self.matches.get(&match_id).map(|game| !game.color());
+        if let (Some(match_clock), Some(mover)) = (self.clocks.get_mut(&match_id), mover) {
+            match_clock.press(now);
+            if let Some(reported) = clock {
+                match_clock.sync(mover, reported, tolerance);
+            }
         }
 
+        self.on_move_played(match_id, ply, mv);
+
+        // moves picked up from the DHT may have been played well before, but only as long
+        // before as this node saw the move missing
+        if let (Some(missed_at), Some(_)) = (missed_at, played_at) {
+            let since_missed = now.saturating_duration_since(missed_at);
+            self.backdate_move_deadline(match_id, since_played.min(since_missed));
+        }
+    }
+
+    /// Moves the deadline of a correspondence match back by `since_played`, to count from when
+    /// the opponent's last move was played rather than from when this node picked it up.
+    fn backdate_move_deadline(&mut self, match_id: MatchId, since_played: Duration) {
+        if let Some(deadline) = self.move_deadlines.get_mut(&match_id) {
+            *deadline -= since_played;
+        }
+    }
+
+    /// Restarts the move deadline of a correspondence match, dropping it once the match ended.
+    fn update_move_deadline(&mut self, match_id: MatchId) {
+        self.move_missed_at.remove(&match_id);
+        self.move_fetched_at.remove(&match_id);
+
+        let days_per_move = match self.matches.get(&match_id) {
+            Some(game) if game.result().is_none() => game.days_per_move(),
+            _ => None,
+        };
+
+        match days_per_move {
+            Some(days) => {
+                let deadline =
+                    self.clock.now() + Duration::from_secs(u64::from(days) * SECS_PER_DAY);
+                self.move_deadlines.insert(match_id, deadline);
+            }
+            None => {
+                self.move_deadlines.remove(&match_id);
+            }
+        }
+    }
+
+    /// Instants correspondence matches' players to move lose on time at: their deadline for
+    /// this node's player, and the grace period later for the opponent.
+    fn overdue_times(&self) -> impl Iterator<Item = (MatchId, Instant)> + '_ {
+        self.move_deadlines
+            .iter()
+            .filter_map(move |(match_id, deadline)| {
+                if self.matches.get(match_id)?.is_local_turn() {
+                    Some((*match_id, *deadline))
+                } else {
+                    Some((*match_id, *deadline + MOVE_RECORD_GRACE_PERIOD))
+                }
+            })
+    }
+
+    /// Ends correspondence matches whose player to move let the deadline pass.
+    fn end_overdue_matches(&mut self) {
+        let now = self.clock.now();
+
+        let overdue: Vec<_> = self
+            .overdue_times()
+            .filter(|(_, overdue_at)| now >= *overdue_at)
+            .map(|(match_id, _)| match_id)
+            .collect();
+
+        for match_id in overdue {
+            self.move_deadlines.remove(&match_id);
+
+            let result = match self.matches.get_mut(&match_id) {
+                Some(game) => {
+                    let turn = game.position().turn();
+                    game.time_out(turn)
+                }
+                None => continue,
+            };
+
+            if let Ok(result) = result {
+                self.end_match(match_id, result);
+            }
+        }
+    }
+
+    /// Instants live matches' players to move are flagged at: when their time runs out for
+    /// this node's player, and the grace period later for the opponent.
+    fn flag_falls(&self) -> impl Iterator<Item = (MatchId, Color, Instant)> + '_ {
+        self.clocks
+            .iter()
+            .filter_map(move |(match_id, match_clock)| {
+                let (color, flag_fall) = match_clock.flag_fall()?;
+                let local = self.matches.get(match_id)?.color() == color;
+
+                if local {
+                    Some((*match_id, color, flag_fall))
+                } else {
+                    Some((*match_id, color, flag_fall + self.config.flag_grace_period))
+                }
+            })
+    }
+
+    /// Ends live matches whose player to move ran out of time.
+    fn end_flagged_matches(&mut self) {
+        let now = self.clock.now();
+
+        let flagged: Vec<_> = self
+            .flag_falls()
+            .filter(|(_, _, flag_fall)| now >= *flag_fall)
+            .map(|(match_id, color, _)| (match_id, color))
+            .collect();
+
+        for (match_id, color) in flagged {
+            let result = match self.matches.get_mut(&match_id) {
+                Some(game) => game.time_out(color),
+                None => continue,
+            };
+
+            match result {
+                Ok(result) => self.end_match(match_id, result),
+                Err(_) => self.stop_clock(match_id),
+            }
+        }
+    }
+
+    /// Stops the clock of a live match.
+    fn stop_clock(&mut self, match_id: MatchId) {
+        let now = self.clock.now();
+
+        if let Some(match_clock) = self.clocks.get_mut(&match_id) {
+            match_clock.stop(now);
+        }
+    }
+
+    /// Stops the deadline and clock of a match which just ended, queueing its end event.
+    fn end_match(&mut self, match_id: MatchId, result: MatchResult) {
+        self.move_deadlines.remove(&match_id);
+        self.stop_clock(match_id);
+        self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+            IpchessEvent::MatchEnded { match_id, result },
+        ));
+    }
+
+    /// Queues events for a move just played in a match, and for the match's end if it ended.
+    fn on_move_played(&mut self, match_id: MatchId, ply: u32, mv: Move) {
+        self.update_move_deadline(match_id);
+
+        let game = match self.matches.get(&match_id) {
+            Some(game) => game,
+            None => return,
+        };
+        let result = game.result();
+
+        self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+            IpchessEvent::MovePlayed {
+                match_id,
+                ply,
+                mv,
+                san: game.san_moves().last().cloned().unwrap_or_default(),
+                position: game.position().clone(),
+                claimable_draw: game.claimable_draw(),
+                clock: self.clock_times(match_id),
+            },
+        ));
+
+        if let Some(result) = result {
+            self.end_match(match_id, result);
+        }
+    }
+
+    /// Applies a move or draw claim received from a peer to one of its matches.
+    fn on_match_message<T>(
+        &mut self,
+        peer_id: PeerId,
+        match_id: MatchId,
+        ply: u32,
+        apply: impl FnOnce(&mut Match, Color) -> Result<T, MatchError>,
+    ) -> Result<T, MatchError> {
+        let game = match self.matches.get_mut(&match_id) {
+            Some(game) if game.opponent() == peer_id => game,
+            _ => {
+                log::debug!(
+                    "Ignoring message for unknown match {} from peer {}",
+                    match_id,
+                    peer_id
+                );
+                return Err(MatchError::NoSuchMatch { match_id });
+            }
+        };
+
+        let result = if game.ply() != ply {
+            Err(MatchError::NotYourTurn)
+        } else {
+            let color = !game.color();
+            apply(game, color)
+        };
+
+        if let Err(error) = &result {
+            self.events
+                .push_back(NetworkBehaviourAction::GenerateEvent(IpchessEvent::Error(
+                    IpchessError::InvalidMatchMessage {
+                        peer_id,
+                        match_id,
+                        error: error.clone(),
+                    },
+                )));
+        }
+
+        result
+    }
+
+    /// Passes on a chat message received from the opponent of a match.
+    fn on_chat_message(&mut self, peer_id: PeerId, match_id: MatchId, text: String) {
+        let color = match self.matches.get(&match_id) {
+            Some(game) if game.opponent() == peer_id => !game.color(),
+            _ => {
+                log::debug!(
+                    "Ignoring chat message for unknown match {} from peer {}",
+                    match_id,
+                    peer_id
+                );
+                return;
+            }
+        };
+
+        let event = if is_valid_chat_message(&text) {
+            IpchessEvent::ChatMessage {
+                match_id,
+                color,
+                text,
+            }
+        } else {
+            IpchessEvent::Error(IpchessError::InvalidMatchMessage {
+                peer_id,
+                match_id,
+                error: MatchError::InvalidChatMessage,
+            })
+        };
+        self.events
+            .push_back(NetworkBehaviourAction::GenerateEvent(event));
+    }
+
+    /// Sends a match message to a peer, dialing it first if the connection was closed.
+    fn send_match_message(&mut self, peer_id: PeerId, event: IpchessHandlerEventIn) {
+        if self.connected_peers.contains(&peer_id) {
+            self.events
+                .push_back(NetworkBehaviourAction::NotifyHandler {
+                    peer_id,
+                    handler: NotifyHandler::Any,
+                    event,
+                });
+        } else {
+            log::debug!(
+                "Peer {} is not connected, dialing before sending match message",
+                peer_id
+            );
+
+            self.pending_match_messages
+                .entry(peer_id)
+                .or_default()
+                .push(event);
+            self.events.push_back(NetworkBehaviourAction::DialPeer {
+                peer_id,
+                condition: DialPeerCondition::Disconnected,
+            });
+        }
+    }
+
+    /// Dials again the peers of pending challenges whose retry backoff has elapsed.
+    fn retry_dials(&mut self) {
+        let now = self.clock.now();
+
+        for (peer_id, dial) in self.pending_dials.iter_mut() {
+            match dial.retry_at {
+                Some(retry_at) if retry_at <= now => {}
+                _ => continue,
+            }
+
+            dial.attempts += 1;
+            dial.retry_at = None;
+            log::debug!("Dialing peer {} again, attempt {}", peer_id, dial.attempts);
+
+            self.events.push_back(NetworkBehaviourAction::DialPeer {
+                peer_id: *peer_id,
+                condition: DialPeerCondition::Disconnected,
+            });
+        }
+    }
+
+    /// Drops challenges whose timeout has elapsed, queueing a timeout error for each one.
+    fn clear_timed_out_challenges(&mut self) {
         // clear timed out outbound challenge
-        let now = Instant::now();
+        let now = self.clock.now();
 
         let timedout_outbound_challenge_keys: Vec<_> = self
             .outbound_challenges
@@ -397,6 +1444,8 @@ impl NetworkBehaviour for Ipchess {
 
         for peer_id in timedout_outbound_challenge_keys {
             self.outbound_challenges.remove(&peer_id);
+            self.pending_challenges.remove(&peer_id);
+            self.pending_dials.remove(&peer_id);
             self.events
                 .push_back(NetworkBehaviourAction::GenerateEvent(IpchessEvent::Error(
                     IpchessError::ChallengeTimeout {
@@ -432,7 +1481,1865 @@ impl NetworkBehaviour for Ipchess {
                     },
                 )));
         }
+    }
+}
 
-        Poll::Pending
+/// Checks the times of a time control are within the allowed ranges.
+fn validate_time_control(time_control: TimeControl) -> Result<(), ChallengeError> {
+    match time_control {
+        _ if time_control.is_valid() => Ok(()),
+        TimeControl::Live { .. } => Err(ChallengeError::InvalidTimeControl),
+        TimeControl::Correspondence { days_per_move, .. } => {
+            Err(ChallengeError::InvalidDaysPerMove { days_per_move })
+        }
+    }
+}
+
+/// Checks a chat message is neither empty nor longer than [`MAX_CHAT_MESSAGE_LEN`] characters.
+fn is_valid_chat_message(text: &str) -> bool {
+    !text.trim().is_empty() && text.chars().count() <= MAX_CHAT_MESSAGE_LEN
+}
+
+impl NetworkBehaviour for Ipchess {
+    type ProtocolsHandler = IpchessHandler;
+    type OutEvent = IpchessEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        IpchessHandler::new(
+            self.config.idle_keep_alive,
+            self.config.max_frame_size,
+            self.clock.clone(),
+        )
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<libp2p::Multiaddr> {
+        self.peer_addresses
+            .get(peer_id)
+            .map_or(vec![], |addrs| addrs.clone().into_iter().collect())
+    }
+
+    fn inject_connected(&mut self, peer_id: &PeerId) {
+        self.connected_peers.insert(*peer_id);
+        self.pending_dials.remove(peer_id);
+
+        if let Some(commitment) = self.pending_challenges.remove(peer_id) {
+            let (variant, time_control, rematch) = self.outbound_challenges.get(peer_id).map_or(
+                (Variant::default(), TimeControl::default(), None),
+                |challenge| (challenge.variant, challenge.time_control, challenge.rematch),
+            );
+
+            self.events
+                .push_back(NetworkBehaviourAction::NotifyHandler {
+                    peer_id: *peer_id,
+                    handler: NotifyHandler::Any,
+                    event: IpchessHandlerEventIn::Challenge {
+                        commitment,
+                        variant,
+                        time_control,
+                        rematch,
+                    },
+                });
+        }
+
+        for event in self
+            .pending_match_messages
+            .remove(peer_id)
+            .into_iter()
+            .flatten()
+        {
+            self.events
+                .push_back(NetworkBehaviourAction::NotifyHandler {
+                    peer_id: *peer_id,
+                    handler: NotifyHandler::Any,
+                    event,
+                });
+        }
+    }
+
+    fn inject_disconnected(&mut self, peer_id: &PeerId) {
+        self.connected_peers.remove(peer_id);
+    }
+
+    fn inject_dial_failure(&mut self, peer_id: &PeerId) {
+        if let Some(dial) = self.pending_dials.get_mut(peer_id) {
+            if dial.attempts < PEER_LOOKUP_MAX_ATTEMPTS {
+                let backoff = PEER_LOOKUP_BASE_BACKOFF * 2u32.pow(dial.attempts - 1);
+                log::debug!("Failed dialing peer {}, retrying in {:?}", peer_id, backoff);
+
+                dial.retry_at = Some(self.clock.now() + backoff);
+                return;
+            }
+
+            log::debug!(
+                "Failed dialing peer {} {} times, giving up",
+                peer_id,
+                dial.attempts
+            );
+        }
+
+        self.pending_challenges.remove(peer_id);
+        self.pending_dials.remove(peer_id);
+
+        for direction in self.remove_challenges(peer_id) {
+            self.events
+                .push_back(NetworkBehaviourAction::GenerateEvent(IpchessEvent::Error(
+                    IpchessError::PeerDialFailure {
+                        peer_id: *peer_id,
+                        direction,
+                    },
+                )));
+        }
+    }
+
+    fn inject_event(
+        &mut self,
+        peer_id: PeerId,
+        _conn_id: ConnectionId,
+        event: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent,
+    ) {
+        match event {
+            IpchessHandlerEventOut::ChallengeReceived {
+                commitment,
+                variant,
+                time_control,
+                rematch,
+            } => {
+                if !self.inbound_challenges.contains_key(&peer_id)
+                    && self.inbound_challenges.len() >= self.config.max_pending_challenges
+                {
+                    log::debug!(
+                        "Declining challenge from peer {}, too many pending challenges",
+                        peer_id
+                    );
+
+                    self.refuse_challenge(peer_id, vec![]);
+                    return;
+                }
+
+                let supported = variant
+                    .parse::<Variant>()
+                    .ok()
+                    .filter(|variant| self.config.variants.contains(variant));
+
+                let variant = match supported {
+                    Some(variant) => variant,
+                    None => {
+                        log::debug!(
+                            "Declining challenge from peer {}, unsupported variant `{}`",
+                            peer_id,
+                            variant
+                        );
+
+                        self.refuse_challenge(peer_id, self.config.variants.clone());
+                        return;
+                    }
+                };
+
+                if validate_time_control(time_control).is_err() {
+                    log::debug!(
+                        "Declining challenge from peer {}, invalid time control {:?}",
+                        peer_id,
+                        time_control
+                    );
+
+                    self.refuse_challenge(peer_id, vec![]);
+                    return;
+                }
+
+                if time_control.store_peer() == Some(peer_id) {
+                    log::debug!(
+                        "Declining challenge from peer {}, naming itself as store peer",
+                        peer_id
+                    );
+
+                    self.refuse_challenge(peer_id, vec![]);
+                    return;
+                }
+
+                // a rematch can only follow a finished match against the same peer
+                if let Some(rematch) = rematch {
+                    let known = self
+                        .matches
+                        .get(&rematch.previous_match)
+                        .map_or(false, |game| {
+                            game.opponent() == peer_id && game.result().is_some()
+                        });
+
+                    if !known {
+                        log::debug!(
+                            "Declining rematch from peer {}, unknown or unfinished match {}",
+                            peer_id,
+                            rematch.previous_match
+                        );
+
+                        self.refuse_challenge(peer_id, vec![]);
+                        return;
+                    }
+                }
+
+                self.inbound_challenges.insert(
+                    peer_id,
+                    InboundChallenge::Received {
+                        commitment,
+                        variant,
+                        time_control,
+                        rematch,
+                        timestamp: self.clock.now(),
+                    },
+                );
+
+                self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                    IpchessEvent::PeerChallenge {
+                        peer_id,
+                        variant,
+                        time_control,
+                        rematch,
+                    },
+                ));
+            }
+
+            IpchessHandlerEventOut::ChallengeRevealReceived { preimage } => {
+                if let Some(inbound_challenge) = self.inbound_challenges.remove(&peer_id) {
+                    match inbound_challenge {
+                        InboundChallenge::PendingPreimage {
+                            commitment,
+                            random,
+                            variant,
+                            time_control,
+                            rematch,
+                            ..
+                        } => {
+                            let preimage_hash = libp2p::multihash::Sha2_256::digest(&preimage);
+
+                            if preimage_hash.as_ref().to_vec() == commitment {
+                                let setup = MatchSetup::from_challenge(
+                                    &preimage,
+                                    &random,
+                                    variant,
+                                    time_control,
+                                );
+
+                                self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                                    IpchessEvent::ChallengeAccepted {
+                                        peer_id,
+                                        challenge: AcceptedChallenge { preimage, random },
+                                    },
+                                ));
+                                self.start_match(
+                                    peer_id,
+                                    setup,
+                                    ChallengeDirection::Inbound,
+                                    rematch,
+                                );
+                            } else {
+                                self.events
+                                    .push_back(NetworkBehaviourAction::NotifyHandler {
+                                        peer_id,
+                                        handler: NotifyHandler::Any,
+                                        event: IpchessHandlerEventIn::ChallengePoisoned,
+                                    });
+
+                                self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                                    IpchessEvent::Error(
+                                        IpchessError::ChallengeCommitmentPreimageMismatch {
+                                            peer_id,
+                                        },
+                                    ),
+                                ));
+                            }
+                        }
+
+                        InboundChallenge::Received { .. } => {
+                            self.events
+                                .push_back(NetworkBehaviourAction::NotifyHandler {
+                                    peer_id,
+                                    handler: NotifyHandler::Any,
+                                    event: IpchessHandlerEventIn::ChallengePoisoned,
+                                });
+                        }
+                    };
+                }
+            }
+
+            IpchessHandlerEventOut::ChallengeAccepted { random } => {
+                if let Some(sent_challenge) = self.outbound_challenges.remove(&peer_id) {
+                    self.events
+                        .push_back(NetworkBehaviourAction::NotifyHandler {
+                            peer_id,
+                            handler: NotifyHandler::Any,
+                            event: IpchessHandlerEventIn::ChallengeReveal {
+                                preimage: sent_challenge.preimage.clone(),
+                            },
+                        });
+
+                    let setup = MatchSetup::from_challenge(
+                        &sent_challenge.preimage,
+                        &random,
+                        sent_challenge.variant,
+                        sent_challenge.time_control,
+                    );
+
+                    self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                        IpchessEvent::ChallengeAccepted {
+                            peer_id,
+                            challenge: AcceptedChallenge {
+                                preimage: sent_challenge.preimage,
+                                random,
+                            },
+                        },
+                    ));
+                    self.start_match(
+                        peer_id,
+                        setup,
+                        ChallengeDirection::Outbound,
+                        sent_challenge.rematch,
+                    );
+                }
+            }
+
+            IpchessHandlerEventOut::ChallengeCanceled => {
+                if self.inbound_challenges.remove(&peer_id).is_some() {
+                    self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                        IpchessEvent::ChallengeCanceled { peer_id },
+                    ));
+                }
+            }
+
+            IpchessHandlerEventOut::ChallengeDeclined { supported_variants } => {
+                if self.outbound_challenges.remove(&peer_id).is_some() {
+                    let supported_variants = if supported_variants.is_empty() {
+                        None
+                    } else {
+                        // variants unknown to this node could not be challenged to anyway
+                        Some(
+                            supported_variants
+                                .iter()
+                                .filter_map(|name| name.parse().ok())
+                                .collect(),
+                        )
+                    };
+
+                    self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                        IpchessEvent::ChallengeDeclined {
+                            peer_id,
+                            supported_variants,
+                        },
+                    ));
+                }
+            }
+
+            IpchessHandlerEventOut::MatchMoveReceived {
+                match_id,
+                ply,
+                mv,
+                clock,
+            } => {
+                self.on_peer_move(peer_id, match_id, ply, mv, clock, None);
+            }
+
+            IpchessHandlerEventOut::MatchDrawClaimReceived { match_id, ply } => {
+                let claimed = self
+                    .on_match_message(peer_id, match_id, ply, |game, color| game.claim_draw(color));
+
+                if let Ok(reason) = claimed {
+                    self.end_match(match_id, MatchResult::Draw { reason });
+                }
+            }
+
+            IpchessHandlerEventOut::MoveRecordStoreReceived { record } => {
+                self.on_move_record_store(peer_id, record);
+            }
+
+            IpchessHandlerEventOut::MoveRecordFetchReceived {
+                match_id,
+                ply,
+                signer,
+            } => {
+                let now = self.clock.now();
+                let stored = self
+                    .stored_move_records
+                    .get(&(match_id, ply, signer))
+                    .filter(|stored| stored.expires > now)
+                    .map(|stored| stored.value.clone());
+
+                match stored {
+                    Some(record) => self.send_match_message(
+                        peer_id,
+                        IpchessHandlerEventIn::MoveRecordFound { record },
+                    ),
+                    None => log::debug!(
+                        "No record of move {} of match {} stored for peer {}",
+                        ply,
+                        match_id,
+                        peer_id
+                    ),
+                }
+            }
+
+            IpchessHandlerEventOut::MoveRecordFoundReceived { record } => {
+                self.on_move_record_found(peer_id, record);
+            }
+
+            IpchessHandlerEventOut::MatchChatReceived { match_id, text } => {
+                self.on_chat_message(peer_id, match_id, text);
+            }
+
+            IpchessHandlerEventOut::OutboundSubstreamFailed => {
+                for direction in self.remove_challenges(&peer_id) {
+                    self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                        IpchessEvent::Error(IpchessError::PeerSubstreamFailure {
+                            peer_id,
+                            direction,
+                        }),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+        _params: &mut impl libp2p::swarm::PollParameters,
+    ) -> Poll<
+        NetworkBehaviourAction<
+            <Self::ProtocolsHandler as ProtocolsHandler>::InEvent,
+            Self::OutEvent,
+        >,
+    > {
+        // drain pending events
+        if let Some(event) = self.events.pop_front() {
+            return Poll::Ready(event);
+        }
+
+        self.clear_timed_out_challenges();
+        self.end_overdue_matches();
+        self.end_flagged_matches();
+        self.retry_dials();
+
+        if let Some(event) = self.events.pop_front() {
+            return Poll::Ready(event);
+        }
+
+        // wake up for the earliest dial retry, move deadline or flag fall, the clock alone does
+        // not wake the task
+        let dial_retries = self.pending_dials.values().filter_map(|dial| dial.retry_at);
+        let overdue_times = self.overdue_times().map(|(_, overdue_at)| overdue_at);
+        let flag_falls = self.flag_falls().map(|(_, _, flag_fall)| flag_fall);
+        self.wake_timer = dial_retries
+            .chain(overdue_times)
+            .chain(flag_falls)
+            .min()
+            .map(|wake_at| {
+                let delay = wake_at.saturating_duration_since(self.clock.now());
+                Box::pin(tokio::time::sleep(delay))
+            });
+        if let Some(timer) = self.wake_timer.as_mut() {
+            let _ = timer.poll_unpin(cx);
+        }
+
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use futures::StreamExt;
+    use libp2p::{
+        core::{connection::ConnectionId, transport::MemoryTransport, upgrade},
+        identity::Keypair,
+        mplex,
+        multiaddr::Protocol,
+        noise,
+        swarm::{NetworkBehaviour, NetworkBehaviourAction, Swarm, SwarmBuilder, SwarmEvent},
+        Multiaddr, PeerId, Transport,
+    };
+
+    use super::{
+        ChallengeDirection, ChallengeError, Ipchess, IpchessConfig, IpchessError, IpchessEvent,
+        PEER_LOOKUP_BASE_BACKOFF, PEER_LOOKUP_MAX_ATTEMPTS, SECS_PER_DAY,
+    };
+    use crate::{
+        chess::{Color, Move, Position, Square},
+        game::{
+            ClockTimes, DrawReason, Match, MatchError, MatchId, MatchResult, MatchSetup, Rematch,
+            TimeControl, Variant, WinReason,
+        },
+        protocol::{
+            sign_move_record, Clock, IpchessHandlerEventIn, IpchessHandlerEventOut, ManualClock,
+        },
+    };
+
+    const EVENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn swarm() -> Swarm<Ipchess> {
+        let keypair = Keypair::generate_ed25519();
+        let peer_id = PeerId::from(keypair.public());
+
+        let noise_keys = noise::Keypair::<noise::X25519Spec>::new()
+            .into_authentic(&keypair)
+            .unwrap();
+        let transport = MemoryTransport::default()
+            .upgrade(upgrade::Version::V1)
+            .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
+            .multiplex(mplex::MplexConfig::new())
+            .boxed();
+
+        SwarmBuilder::new(transport, Ipchess::new(), peer_id)
+            .executor(Box::new(|fut| {
+                tokio::spawn(fut);
+            }))
+            .build()
+    }
+
+    /// Returns a challenger and a challenged swarm, with the challenger knowing the other's address.
+    async fn connectable_swarms() -> (Swarm<Ipchess>, Swarm<Ipchess>) {
+        let mut challenger = swarm();
+        let mut challenged = swarm();
+
+        challenged
+            .listen_on(Multiaddr::from(Protocol::Memory(0)))
+            .unwrap();
+
+        let addr = loop {
+            if let SwarmEvent::NewListenAddr(addr) = challenged.select_next_some().await {
+                break addr;
+            }
+        };
+
+        let challenged_peer_id = *challenged.local_peer_id();
+        challenger
+            .behaviour_mut()
+            .add_address(challenged_peer_id, addr);
+
+        (challenger, challenged)
+    }
+
+    /// Drives both swarms until `b` emits an event, returning the events emitted by `a` meanwhile
+    /// along with it.
+    async fn next_event_of_b(
+        a: &mut Swarm<Ipchess>,
+        b: &mut Swarm<Ipchess>,
+    ) -> (Vec<IpchessEvent>, IpchessEvent) {
+        let mut a_events = vec![];
+
+        let b_event = async {
+            loop {
+                tokio::select! {
+                    event = a.select_next_some() => {
+                        if let SwarmEvent::Behaviour(event) = event {
+                            a_events.push(event);
+                        }
+                    }
+
+                    event = b.select_next_some() => {
+                        if let SwarmEvent::Behaviour(event) = event {
+                            return event;
+                        }
+                    }
+                }
+            }
+        };
+
+        let b_event = tokio::time::timeout(EVENT_TIMEOUT, b_event)
+            .await
+            .expect("timed out waiting for event");
+
+        (a_events, b_event)
+    }
+
+    #[tokio::test]
+    async fn accepted_challenge_shares_preimage_and_random() {
+        let (mut challenger, mut challenged) = connectable_swarms().await;
+        let challenger_peer_id = *challenger.local_peer_id();
+        let challenged_peer_id = *challenged.local_peer_id();
+
+        challenger
+            .behaviour_mut()
+            .challenge_peer(
+                challenged_peer_id,
+                Variant::Chess960,
+                TimeControl::default(),
+            )
+            .unwrap();
+
+        match next_event_of_b(&mut challenger, &mut challenged).await.1 {
+            IpchessEvent::PeerChallenge {
+                peer_id,
+                variant,
+                time_control,
+                rematch,
+            } => {
+                assert_eq!(peer_id, challenger_peer_id);
+                assert_eq!(variant, Variant::Chess960);
+                assert_eq!(time_control, TimeControl::default());
+                assert_eq!(rematch, None);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        challenged
+            .behaviour_mut()
+            .accept_peer_challenge(challenger_peer_id)
+            .unwrap();
+
+        let (mut challenger_events, challenged_event) =
+            next_event_of_b(&mut challenger, &mut challenged).await;
+
+        // the challenger may not have been polled after sending the reveal yet
+        if challenger_events.is_empty() {
+            let (_, event) = next_event_of_b(&mut challenged, &mut challenger).await;
+            challenger_events.push(event);
+        }
+
+        let challenged_view = match challenged_event {
+            IpchessEvent::ChallengeAccepted { peer_id, challenge } => {
+                assert_eq!(peer_id, challenger_peer_id);
+                challenge
+            }
+            event => panic!("unexpected event {:?}", event),
+        };
+
+        let challenger_view = match challenger_events.remove(0) {
+            IpchessEvent::ChallengeAccepted { peer_id, challenge } => {
+                assert_eq!(peer_id, challenged_peer_id);
+                challenge
+            }
+            event => panic!("unexpected event {:?}", event),
+        };
+
+        assert_eq!(challenger_view.preimage, challenged_view.preimage);
+        assert_eq!(challenger_view.random, challenged_view.random);
+        assert!(challenger.behaviour().challenges().is_empty());
+        assert!(challenged.behaviour().challenges().is_empty());
+
+        // both peers start the same match, playing opposite colors
+        let challenger_match = challenger.behaviour().matches().next().unwrap();
+        let challenged_match = challenged.behaviour().matches().next().unwrap();
+        assert_eq!(challenger_match.id(), challenged_match.id());
+        assert_eq!(challenger_match.opponent(), challenged_peer_id);
+        assert_eq!(challenged_match.opponent(), challenger_peer_id);
+        assert_eq!(challenger_match.color(), !challenged_match.color());
+
+        // from the same Chess960 starting position
+        assert_eq!(challenger_match.variant(), Variant::Chess960);
+        assert_eq!(challenged_match.variant(), Variant::Chess960);
+        assert_eq!(
+            challenger_match.position().fen(),
+            challenged_match.position().fen()
+        );
+        assert!(challenger_match.position().is_chess960());
+    }
+
+    #[tokio::test]
+    async fn mismatched_preimage_fails_challenge() {
+        let (mut challenger, mut challenged) = connectable_swarms().await;
+        let challenger_peer_id = *challenger.local_peer_id();
+        let challenged_peer_id = *challenged.local_peer_id();
+
+        challenger
+            .behaviour_mut()
+            .challenge_peer(
+                challenged_peer_id,
+                Variant::Standard,
+                TimeControl::default(),
+            )
+            .unwrap();
+
+        // reveal something other than the preimage of the commitment that was sent
+        challenger
+            .behaviour_mut()
+            .outbound_challenges
+            .get_mut(&challenged_peer_id)
+            .unwrap()
+            .preimage = vec![0; 32];
+
+        match next_event_of_b(&mut challenger, &mut challenged).await.1 {
+            IpchessEvent::PeerChallenge { peer_id, .. } => {
+                assert_eq!(peer_id, challenger_peer_id)
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        challenged
+            .behaviour_mut()
+            .accept_peer_challenge(challenger_peer_id)
+            .unwrap();
+
+        match next_event_of_b(&mut challenger, &mut challenged).await.1 {
+            IpchessEvent::Error(IpchessError::ChallengeCommitmentPreimageMismatch { peer_id }) => {
+                assert_eq!(peer_id, challenger_peer_id)
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        assert!(challenged.behaviour().challenges().is_empty());
+    }
+
+    /// Returns the timeout errors queued by the behaviour, discarding any other event.
+    fn timed_out_challenges(ipchess: &mut Ipchess) -> Vec<(PeerId, ChallengeDirection)> {
+        ipchess.clear_timed_out_challenges();
+
+        ipchess
+            .events
+            .drain(..)
+            .filter_map(|event| match event {
+                NetworkBehaviourAction::GenerateEvent(IpchessEvent::Error(
+                    IpchessError::ChallengeTimeout { peer_id, direction },
+                )) => Some((peer_id, direction)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn receive_challenge(ipchess: &mut Ipchess, peer_id: PeerId) {
+        receive_variant_challenge(ipchess, peer_id, "");
+    }
+
+    fn receive_variant_challenge(ipchess: &mut Ipchess, peer_id: PeerId, variant: &str) {
+        ipchess.inject_event(
+            peer_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::ChallengeReceived {
+                commitment: vec![0; 32],
+                variant: variant.to_string(),
+                time_control: TimeControl::default(),
+                rematch: None,
+            },
+        );
+    }
+
+    #[test]
+    fn outbound_challenge_times_out_without_accept() {
+        let clock = ManualClock::new();
+        let mut ipchess = Ipchess::with_clock(IpchessConfig::default(), Arc::new(clock.clone()));
+        let peer_id = PeerId::random();
+
+        ipchess
+            .challenge_peer(peer_id, Variant::Standard, TimeControl::default())
+            .unwrap();
+        ipchess.events.clear();
+
+        clock.advance(ipchess.config.challenge_accept_timeout);
+        assert!(timed_out_challenges(&mut ipchess).is_empty());
+        assert_eq!(
+            ipchess.challenges()[0].remaining_timeout,
+            Some(Duration::from_secs(0))
+        );
+
+        clock.advance(Duration::from_millis(1));
+        let timed_out = timed_out_challenges(&mut ipchess);
+        assert_eq!(timed_out.len(), 1);
+        assert_eq!(timed_out[0].0, peer_id);
+        assert!(matches!(timed_out[0].1, ChallengeDirection::Outbound));
+
+        assert!(ipchess.challenges().is_empty());
+        // the commitment waiting for a connection to the peer is dropped as well
+        assert!(ipchess.pending_challenges.is_empty());
+    }
+
+    #[test]
+    fn accepted_inbound_challenge_times_out_without_preimage() {
+        let clock = ManualClock::new();
+        let mut ipchess = Ipchess::with_clock(IpchessConfig::default(), Arc::new(clock.clone()));
+        let peer_id = PeerId::random();
+
+        receive_challenge(&mut ipchess, peer_id);
+        ipchess.accept_peer_challenge(peer_id).unwrap();
+        ipchess.events.clear();
+
+        clock.advance(ipchess.config.challenge_preimage_timeout);
+        assert!(timed_out_challenges(&mut ipchess).is_empty());
+
+        clock.advance(Duration::from_millis(1));
+        let timed_out = timed_out_challenges(&mut ipchess);
+        assert_eq!(timed_out.len(), 1);
+        assert_eq!(timed_out[0].0, peer_id);
+        assert!(matches!(timed_out[0].1, ChallengeDirection::Inbound));
+
+        assert!(ipchess.challenges().is_empty());
+    }
+
+    #[test]
+    fn received_inbound_challenge_does_not_time_out() {
+        let clock = ManualClock::new();
+        let mut ipchess = Ipchess::with_clock(IpchessConfig::default(), Arc::new(clock.clone()));
+        let peer_id = PeerId::random();
+
+        receive_challenge(&mut ipchess, peer_id);
+
+        clock.advance(ipchess.config.challenge_accept_timeout * 10);
+        assert!(timed_out_challenges(&mut ipchess).is_empty());
+
+        let challenges = ipchess.challenges();
+        assert_eq!(challenges.len(), 1);
+        assert_eq!(
+            challenges[0].age,
+            ipchess.config.challenge_accept_timeout * 10
+        );
+        assert_eq!(challenges[0].remaining_timeout, None);
+    }
+
+    #[test]
+    fn substream_failure_removes_challenges_in_both_directions() {
+        let mut ipchess = Ipchess::new();
+        let peer_id = PeerId::random();
+
+        receive_challenge(&mut ipchess, peer_id);
+        ipchess
+            .challenge_peer(peer_id, Variant::Standard, TimeControl::default())
+            .unwrap();
+        ipchess.events.clear();
+
+        ipchess.inject_event(
+            peer_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::OutboundSubstreamFailed,
+        );
+
+        let failed: Vec<_> = generated_events(&mut ipchess)
+            .into_iter()
+            .filter_map(|event| match event {
+                IpchessEvent::Error(IpchessError::PeerSubstreamFailure {
+                    peer_id: failed_peer_id,
+                    direction,
+                }) => {
+                    assert_eq!(failed_peer_id, peer_id);
+                    Some(direction)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(failed.len(), 2);
+        assert!(matches!(failed[0], ChallengeDirection::Outbound));
+        assert!(matches!(failed[1], ChallengeDirection::Inbound));
+
+        assert!(ipchess.challenges().is_empty());
+    }
+
+    fn dialed_peers(ipchess: &mut Ipchess) -> Vec<PeerId> {
+        ipchess.retry_dials();
+
+        ipchess
+            .events
+            .drain(..)
+            .filter_map(|event| match event {
+                NetworkBehaviourAction::DialPeer { peer_id, .. } => Some(peer_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn failed_dial_is_retried_with_backoff_before_giving_up() {
+        let clock = ManualClock::new();
+        let mut ipchess = Ipchess::with_clock(IpchessConfig::default(), Arc::new(clock.clone()));
+        let peer_id = PeerId::random();
+
+        ipchess
+            .challenge_peer(peer_id, Variant::Standard, TimeControl::default())
+            .unwrap();
+        assert_eq!(dialed_peers(&mut ipchess), vec![peer_id]);
+
+        for attempt in 1..PEER_LOOKUP_MAX_ATTEMPTS {
+            ipchess.inject_dial_failure(&peer_id);
+            assert_eq!(ipchess.challenges().len(), 1);
+
+            let backoff = PEER_LOOKUP_BASE_BACKOFF * 2u32.pow(attempt - 1);
+            clock.advance(backoff - Duration::from_millis(1));
+            assert!(dialed_peers(&mut ipchess).is_empty());
+
+            clock.advance(Duration::from_millis(1));
+            assert_eq!(dialed_peers(&mut ipchess), vec![peer_id]);
+        }
+
+        ipchess.inject_dial_failure(&peer_id);
+        let events = generated_events(&mut ipchess);
+        assert!(matches!(
+            events.as_slice(),
+            [IpchessEvent::Error(IpchessError::PeerDialFailure {
+                direction: ChallengeDirection::Outbound,
+                ..
+            })]
+        ));
+        assert!(ipchess.challenges().is_empty());
+        assert!(ipchess.pending_dials.is_empty());
+    }
+
+    #[test]
+    fn challenges_over_limit_are_declined() {
+        let config = IpchessConfig {
+            max_pending_challenges: 1,
+            ..IpchessConfig::default()
+        };
+        let mut ipchess = Ipchess::with_config(config);
+        let first_peer_id = PeerId::random();
+        let second_peer_id = PeerId::random();
+
+        receive_challenge(&mut ipchess, first_peer_id);
+        ipchess.events.clear();
+
+        receive_challenge(&mut ipchess, second_peer_id);
+        assert!(matches!(
+            ipchess.events.pop_front(),
+            Some(NetworkBehaviourAction::NotifyHandler {
+                peer_id,
+                event: IpchessHandlerEventIn::ChallengeDeclined { supported_variants },
+                ..
+            }) if peer_id == second_peer_id && supported_variants.is_empty()
+        ));
+        assert!(ipchess.events.is_empty());
+
+        let challenges = ipchess.challenges();
+        assert_eq!(challenges.len(), 1);
+        assert_eq!(challenges[0].peer_id, first_peer_id);
+    }
+
+    #[test]
+    fn challenges_of_unknown_variants_are_declined() {
+        let mut ipchess = Ipchess::new();
+        let peer_id = PeerId::random();
+
+        receive_variant_challenge(&mut ipchess, peer_id, "chess1024");
+        assert!(matches!(
+            ipchess.events.pop_front(),
+            Some(NetworkBehaviourAction::NotifyHandler {
+                event: IpchessHandlerEventIn::ChallengeDeclined { supported_variants },
+                ..
+            }) if supported_variants == Variant::ALL
+        ));
+        assert!(ipchess.events.is_empty());
+        assert!(ipchess.challenges().is_empty());
+
+        // peers which do not send a variant challenge to standard chess
+        receive_challenge(&mut ipchess, peer_id);
+        assert!(matches!(
+            ipchess.events.pop_front(),
+            Some(NetworkBehaviourAction::GenerateEvent(
+                IpchessEvent::PeerChallenge {
+                    variant: Variant::Standard,
+                    ..
+                }
+            ))
+        ));
+    }
+
+    #[test]
+    fn challenges_of_variants_not_played_are_declined_listing_played_ones() {
+        let config = IpchessConfig {
+            variants: vec![Variant::Standard, Variant::ThreeCheck],
+            ..IpchessConfig::default()
+        };
+        let mut ipchess = Ipchess::with_config(config);
+        let peer_id = PeerId::random();
+
+        receive_variant_challenge(&mut ipchess, peer_id, "kingofthehill");
+        assert!(matches!(
+            ipchess.events.pop_front(),
+            Some(NetworkBehaviourAction::NotifyHandler {
+                event: IpchessHandlerEventIn::ChallengeDeclined { supported_variants },
+                ..
+            }) if supported_variants == [Variant::Standard, Variant::ThreeCheck]
+        ));
+        assert!(ipchess.challenges().is_empty());
+    }
+
+    #[test]
+    fn declined_challenge_reports_variants_played_by_peer() {
+        let mut ipchess = Ipchess::new();
+        let peer_id = PeerId::random();
+
+        ipchess
+            .challenge_peer(peer_id, Variant::KingOfTheHill, TimeControl::default())
+            .unwrap();
+        ipchess.events.clear();
+
+        ipchess.inject_event(
+            peer_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::ChallengeDeclined {
+                supported_variants: vec!["standard".to_string(), "crazyhouse".to_string()],
+            },
+        );
+        assert!(matches!(
+            generated_events(&mut ipchess)[..],
+            [IpchessEvent::ChallengeDeclined {
+                supported_variants: Some(ref variants),
+                ..
+            }] if variants == &[Variant::Standard]
+        ));
+        assert!(ipchess.challenges().is_empty());
+    }
+
+    /// Inserts a match against a connected peer from the starting position, as if its challenge
+    /// had just been accepted.
+    fn insert_match(ipchess: &mut Ipchess, peer_id: PeerId, color: Color) -> MatchId {
+        let match_id = MatchId::from_bytes(&[1; 32]).unwrap();
+
+        ipchess.matches.insert(
+            match_id,
+            Match::new(
+                match_id,
+                peer_id,
+                color,
+                Variant::Standard,
+                TimeControl::default(),
+                None,
+                Position::startpos(),
+            ),
+        );
+        ipchess.connected_peers.insert(peer_id);
+
+        match_id
+    }
+
+    fn mv(from: &str, to: &str) -> Move {
+        Move {
+            from: from.parse::<Square>().unwrap(),
+            to: to.parse::<Square>().unwrap(),
+            promotion: None,
+        }
+    }
+
+    fn receive_move(ipchess: &mut Ipchess, peer_id: PeerId, match_id: MatchId, mv: Move) {
+        let ply = ipchess.matches[&match_id].ply();
+
+        ipchess.inject_event(
+            peer_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::MatchMoveReceived {
+                match_id,
+                ply,
+                mv,
+                clock: None,
+            },
+        );
+    }
+
+    /// Returns the events generated by the behaviour, discarding handler notifications.
+    fn generated_events(ipchess: &mut Ipchess) -> Vec<IpchessEvent> {
+        ipchess
+            .events
+            .drain(..)
+            .filter_map(|event| match event {
+                NetworkBehaviourAction::GenerateEvent(event) => Some(event),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn moves_are_exchanged_in_turn() {
+        let mut ipchess = Ipchess::new();
+        let peer_id = PeerId::random();
+        let match_id = insert_match(&mut ipchess, peer_id, Color::White);
+
+        ipchess.make_move(match_id, mv("e2", "e4")).unwrap();
+        assert!(matches!(
+            ipchess.events.pop_front(),
+            Some(NetworkBehaviourAction::NotifyHandler {
+                event: IpchessHandlerEventIn::MatchMove { ply: 0, .. },
+                ..
+            })
+        ));
+        assert!(matches!(
+            generated_events(&mut ipchess)[..],
+            [IpchessEvent::MovePlayed { ply: 0, .. }]
+        ));
+
+        assert_eq!(
+            ipchess.make_move(match_id, mv("d2", "d4")),
+            Err(MatchError::NotYourTurn)
+        );
+
+        receive_move(&mut ipchess, peer_id, match_id, mv("e7", "e5"));
+        assert!(matches!(
+            generated_events(&mut ipchess)[..],
+            [IpchessEvent::MovePlayed { ply: 1, .. }]
+        ));
+
+        // a move sent out of turn is reported and leaves the match untouched
+        ipchess.inject_event(
+            peer_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::MatchMoveReceived {
+                match_id,
+                ply: 2,
+                mv: mv("d7", "d5"),
+                clock: None,
+            },
+        );
+        assert!(matches!(
+            generated_events(&mut ipchess)[..],
+            [IpchessEvent::Error(IpchessError::InvalidMatchMessage {
+                error: MatchError::NotYourTurn,
+                ..
+            })]
+        ));
+        assert_eq!(ipchess.matches[&match_id].ply(), 2);
+    }
+
+    #[test]
+    fn illegal_peer_move_is_rejected() {
+        let mut ipchess = Ipchess::new();
+        let peer_id = PeerId::random();
+        let match_id = insert_match(&mut ipchess, peer_id, Color::Black);
+
+        receive_move(&mut ipchess, peer_id, match_id, mv("e2", "e5"));
+        assert!(matches!(
+            generated_events(&mut ipchess)[..],
+            [IpchessEvent::Error(IpchessError::InvalidMatchMessage {
+                error: MatchError::IllegalMove,
+                ..
+            })]
+        ));
+        assert_eq!(ipchess.matches[&match_id].ply(), 0);
+    }
+
+    #[test]
+    fn draw_claimed_by_peer_ends_match() {
+        let mut ipchess = Ipchess::new();
+        let peer_id = PeerId::random();
+        let match_id = insert_match(&mut ipchess, peer_id, Color::Black);
+
+        for _ in 0..2 {
+            receive_move(&mut ipchess, peer_id, match_id, mv("g1", "f3"));
+            ipchess.make_move(match_id, mv("g8", "f6")).unwrap();
+            receive_move(&mut ipchess, peer_id, match_id, mv("f3", "g1"));
+            ipchess.make_move(match_id, mv("f6", "g8")).unwrap();
+        }
+
+        match generated_events(&mut ipchess).last() {
+            Some(IpchessEvent::MovePlayed { claimable_draw, .. }) => {
+                assert_eq!(*claimable_draw, Some(DrawReason::ThreefoldRepetition))
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        // only the player to move may claim the draw
+        assert_eq!(ipchess.claim_draw(match_id), Err(MatchError::NotYourTurn));
+
+        ipchess.inject_event(
+            peer_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::MatchDrawClaimReceived { match_id, ply: 8 },
+        );
+        assert!(matches!(
+            generated_events(&mut ipchess)[..],
+            [IpchessEvent::MatchEnded {
+                result: MatchResult::Draw {
+                    reason: DrawReason::ThreefoldRepetition
+                },
+                ..
+            }]
+        ));
+        assert_eq!(
+            ipchess.make_move(match_id, mv("e7", "e5")),
+            Err(MatchError::Finished)
+        );
+    }
+
+    #[test]
+    fn chat_messages_are_exchanged_with_the_opponent() {
+        let mut ipchess = Ipchess::new();
+        let peer_id = PeerId::random();
+        let match_id = insert_match(&mut ipchess, peer_id, Color::White);
+
+        ipchess
+            .send_chat_message(match_id, "good luck".into())
+            .unwrap();
+        assert!(matches!(
+            ipchess.events.pop_front(),
+            Some(NetworkBehaviourAction::NotifyHandler {
+                event: IpchessHandlerEventIn::MatchChat { ref text, .. },
+                ..
+            }) if text == "good luck"
+        ));
+        assert!(matches!(
+            generated_events(&mut ipchess)[..],
+            [IpchessEvent::ChatMessage {
+                color: Color::White,
+                ..
+            }]
+        ));
+
+        assert_eq!(
+            ipchess.send_chat_message(match_id, " ".into()),
+            Err(MatchError::InvalidChatMessage)
+        );
+        assert_eq!(
+            ipchess.send_chat_message(match_id, "a".repeat(MAX_CHAT_MESSAGE_LEN + 1)),
+            Err(MatchError::InvalidChatMessage)
+        );
+
+        ipchess.inject_event(
+            peer_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::MatchChatReceived {
+                match_id,
+                text: "thanks".into(),
+            },
+        );
+        ipchess.inject_event(
+            peer_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::MatchChatReceived {
+                match_id,
+                text: String::new(),
+            },
+        );
+        // only the opponent may chat in a match
+        ipchess.inject_event(
+            PeerId::random(),
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::MatchChatReceived {
+                match_id,
+                text: "hi".into(),
+            },
+        );
+        assert!(matches!(
+            generated_events(&mut ipchess)[..],
+            [
+                IpchessEvent::ChatMessage {
+                    color: Color::Black,
+                    ..
+                },
+                IpchessEvent::Error(IpchessError::InvalidMatchMessage {
+                    error: MatchError::InvalidChatMessage,
+                    ..
+                })
+            ]
+        ));
+    }
+
+    #[test]
+    fn match_messages_wait_for_connection() {
+        let mut ipchess = Ipchess::new();
+        let peer_id = PeerId::random();
+        let match_id = insert_match(&mut ipchess, peer_id, Color::White);
+        ipchess.inject_disconnected(&peer_id);
+
+        ipchess.make_move(match_id, mv("e2", "e4")).unwrap();
+        assert!(matches!(
+            ipchess.events.pop_front(),
+            Some(NetworkBehaviourAction::DialPeer { peer_id: dialed, .. }) if dialed == peer_id
+        ));
+        ipchess.events.clear();
+
+        ipchess.inject_connected(&peer_id);
+        assert!(matches!(
+            ipchess.events.pop_front(),
+            Some(NetworkBehaviourAction::NotifyHandler {
+                peer_id: notified,
+                event: IpchessHandlerEventIn::MatchMove { ply: 0, .. },
+                ..
+            }) if notified == peer_id
+        ));
+    }
+
+    #[test]
+    fn challenges_with_invalid_time_control_are_rejected() {
+        let mut ipchess = Ipchess::new();
+        let peer_id = PeerId::random();
+
+        assert!(matches!(
+            ipchess.challenge_peer(
+                peer_id,
+                Variant::Standard,
+                TimeControl::Correspondence {
+                    days_per_move: 0,
+                    store_peer: None,
+                }
+            ),
+            Err(ChallengeError::InvalidDaysPerMove { days_per_move: 0 })
+        ));
+        assert!(matches!(
+            ipchess.challenge_peer(
+                peer_id,
+                Variant::Standard,
+                TimeControl::Live {
+                    initial: Duration::from_secs(0),
+                    increment: Duration::from_secs(0),
+                }
+            ),
+            Err(ChallengeError::InvalidTimeControl)
+        ));
+
+        ipchess.inject_event(
+            peer_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::ChallengeReceived {
+                commitment: vec![0; 32],
+                variant: String::new(),
+                time_control: TimeControl::Correspondence {
+                    days_per_move: 365,
+                    store_peer: None,
+                },
+                rematch: None,
+            },
+        );
+        assert!(matches!(
+            ipchess.events.pop_front(),
+            Some(NetworkBehaviourAction::NotifyHandler {
+                event: IpchessHandlerEventIn::ChallengeDeclined { .. },
+                ..
+            })
+        ));
+        assert!(ipchess.challenges().is_empty());
+    }
+
+    /// Starts a match of `time_control` against a connected peer, this node playing `color`.
+    fn start_match_playing(
+        ipchess: &mut Ipchess,
+        peer_id: PeerId,
+        color: Color,
+        time_control: TimeControl,
+    ) -> MatchId {
+        // colors are drawn from the random bytes, try until this node gets the wanted one
+        let setup = (0..=u8::MAX)
+            .map(|byte| {
+                MatchSetup::from_challenge(&[1; 32], &[byte; 32], Variant::Standard, time_control)
+            })
+            .find(|setup| setup.challenger_color == color)
+            .unwrap();
+        let match_id = setup.id;
+
+        ipchess.start_match(peer_id, setup, ChallengeDirection::Outbound, None);
+        ipchess.connected_peers.insert(peer_id);
+        ipchess.events.clear();
+
+        match_id
+    }
+
+    #[test]
+    fn correspondence_match_is_lost_by_missing_move_deadline() {
+        let clock = ManualClock::new();
+        let mut ipchess = Ipchess::with_clock(IpchessConfig::default(), Arc::new(clock.clone()));
+        let peer_id = PeerId::random();
+        let days = Duration::from_secs(SECS_PER_DAY);
+
+        let time_control = TimeControl::Correspondence {
+            days_per_move: 3,
+            store_peer: None,
+        };
+        let match_id = start_match_playing(&mut ipchess, peer_id, Color::White, time_control);
+        assert_eq!(ipchess.remaining_move_time(match_id), Some(days * 3));
+
+        // each move restarts the deadline
+        clock.advance(days * 2);
+        ipchess.make_move(match_id, mv("e2", "e4")).unwrap();
+        ipchess.events.clear();
+        assert_eq!(ipchess.remaining_move_time(match_id), Some(days * 3));
+
+        clock.advance(days);
+        receive_move(&mut ipchess, peer_id, match_id, mv("e7", "e5"));
+        ipchess.events.clear();
+        assert_eq!(ipchess.remaining_move_time(match_id), Some(days * 3));
+
+        // moves picked up again, from the DHT, are ignored
+        let played_at = clock.system_time();
+        ipchess.receive_stored_move(peer_id, match_id, 1, mv("e7", "e5"), played_at);
+        assert!(ipchess.events.is_empty());
+
+        clock.advance(days * 3);
+        ipchess.end_overdue_matches();
+        assert!(matches!(
+            generated_events(&mut ipchess)[..],
+            [IpchessEvent::MatchEnded {
+                result: MatchResult::Win {
+                    winner: Color::Black,
+                    reason: WinReason::Timeout
+                },
+                ..
+            }]
+        ));
+        assert_eq!(ipchess.remaining_move_time(match_id), None);
+    }
+
+    #[test]
+    fn backdated_stored_moves_are_timed_from_missed_polls() {
+        let clock = ManualClock::new();
+        let mut ipchess = Ipchess::with_clock(IpchessConfig::default(), Arc::new(clock.clone()));
+        let peer_id = PeerId::random();
+        let hours = |hours| Duration::from_secs(hours * 60 * 60);
+
+        let time_control = TimeControl::Correspondence {
+            days_per_move: 3,
+            store_peer: None,
+        };
+        let match_id = start_match_playing(&mut ipchess, peer_id, Color::Black, time_control);
+
+        // the time a move says it was played at is ignored until a poll missed the move
+        clock.advance(hours(24));
+        let played_at = clock.system_time() - hours(12);
+        ipchess.receive_stored_move(peer_id, match_id, 0, mv("e2", "e4"), played_at);
+        ipchess.events.clear();
+        assert_eq!(ipchess.remaining_move_time(match_id), Some(hours(72)));
+
+        // a move backdated past the last poll which missed it is timed from that poll
+        ipchess.make_move(match_id, mv("e7", "e5")).unwrap();
+        clock.advance(hours(6));
+        ipchess.move_record_missing(match_id);
+        clock.advance(hours(18));
+        let played_at = clock.system_time() - hours(100);
+        ipchess.receive_stored_move(peer_id, match_id, 2, mv("g1", "f3"), played_at);
+        ipchess.events.clear();
+        assert_eq!(ipchess.remaining_move_time(match_id), Some(hours(54)));
+
+        // otherwise it is timed from when it says it was played
+        ipchess.make_move(match_id, mv("b8", "c6")).unwrap();
+        clock.advance(hours(2));
+        ipchess.move_record_missing(match_id);
+        clock.advance(hours(8));
+        let played_at = clock.system_time() - hours(1);
+        ipchess.receive_stored_move(peer_id, match_id, 4, mv("f1", "c4"), played_at);
+        ipchess.events.clear();
+        assert_eq!(ipchess.remaining_move_time(match_id), Some(hours(71)));
+
+        // past the peer's deadline, moves played in time may still be picked up
+        ipchess.make_move(match_id, mv("g8", "f6")).unwrap();
+        ipchess.events.clear();
+        clock.advance(hours(73));
+        ipchess.end_overdue_matches();
+        assert!(ipchess.events.is_empty());
+
+        let played_at = clock.system_time() - Duration::from_secs(60);
+        ipchess.receive_stored_move(peer_id, match_id, 6, mv("d2", "d3"), played_at);
+        assert!(ipchess.events.is_empty());
+
+        let played_at = clock.system_time() - hours(2);
+        ipchess.receive_stored_move(peer_id, match_id, 6, mv("d2", "d3"), played_at);
+        assert!(matches!(
+            generated_events(&mut ipchess)[..],
+            [IpchessEvent::MovePlayed { ply: 6, .. }]
+        ));
+        assert_eq!(ipchess.remaining_move_time(match_id), Some(hours(72)));
+    }
+
+    #[test]
+    fn unanswered_fetches_from_store_peer_count_as_missed_polls() {
+        let clock = ManualClock::new();
+        let mut ipchess = Ipchess::with_clock(IpchessConfig::default(), Arc::new(clock.clone()));
+        let store_id = PeerId::random();
+        let peer_id = PeerId::random();
+        let hours = |hours| Duration::from_secs(hours * 60 * 60);
+
+        let time_control = TimeControl::Correspondence {
+            days_per_move: 3,
+            store_peer: Some(store_id),
+        };
+        let match_id = start_match_playing(&mut ipchess, peer_id, Color::Black, time_control);
+        ipchess.connected_peers.insert(store_id);
+
+        clock.advance(hours(1));
+        ipchess.fetch_move_record(store_id, match_id, 0, peer_id);
+        clock.advance(hours(1));
+        ipchess.fetch_move_record(store_id, match_id, 0, peer_id);
+        ipchess.events.clear();
+
+        // the move was not stored yet when the first fetch was answered
+        clock.advance(hours(1));
+        let played_at = clock.system_time() - hours(3);
+        ipchess.receive_stored_move(peer_id, match_id, 0, mv("e2", "e4"), played_at);
+        ipchess.events.clear();
+        assert_eq!(ipchess.remaining_move_time(match_id), Some(hours(70)));
+    }
+
+    #[test]
+    fn restored_correspondence_match_keeps_its_deadline() {
+        let clock = ManualClock::new();
+        let mut ipchess = Ipchess::with_clock(IpchessConfig::default(), Arc::new(clock.clone()));
+        let peer_id = PeerId::random();
+        let days = Duration::from_secs(SECS_PER_DAY);
+
+        let restored = |match_id, time_control| {
+            Match::new(
+                MatchId::from_bytes(&[match_id; 32]).unwrap(),
+                peer_id,
+                Color::White,
+                Variant::Standard,
+                time_control,
+                None,
+                Position::startpos(),
+            )
+        };
+        let correspondence = TimeControl::Correspondence {
+            days_per_move: 3,
+            store_peer: None,
+        };
+
+        let game = restored(1, correspondence);
+        let match_id = game.id();
+        ipchess.restore_match(game, Some(days), None);
+        assert_eq!(ipchess.remaining_move_time(match_id), Some(days));
+
+        // deadlines are capped to the time control's
+        let game = restored(2, correspondence);
+        let capped_id = game.id();
+        ipchess.restore_match(game, Some(days * 10), None);
+        assert_eq!(ipchess.remaining_move_time(capped_id), Some(days * 3));
+
+        // live matches in progress are not restored
+        ipchess.restore_match(restored(3, TimeControl::default()), None, None);
+        assert_eq!(ipchess.matches().count(), 2);
+
+        clock.advance(days);
+        ipchess.end_overdue_matches();
+        assert!(matches!(
+            generated_events(&mut ipchess)[..],
+            [IpchessEvent::MatchEnded {
+                result: MatchResult::Win {
+                    winner: Color::Black,
+                    reason: WinReason::Timeout
+                },
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn store_peer_relays_move_records() {
+        let clock = ManualClock::new();
+        let mut store = Ipchess::with_clock(IpchessConfig::default(), Arc::new(clock.clone()));
+        let mut ipchess = Ipchess::with_clock(IpchessConfig::default(), Arc::new(clock.clone()));
+        let store_id = PeerId::random();
+        let ipchess_id = PeerId::random();
+        let keypair = Keypair::generate_ed25519();
+        let peer_id = PeerId::from(keypair.public());
+
+        let time_control = TimeControl::Correspondence {
+            days_per_move: 3,
+            store_peer: Some(store_id),
+        };
+        let match_id = start_match_playing(&mut ipchess, peer_id, Color::Black, time_control);
+        let played_at = clock.system_time();
+        let record = sign_move_record(&keypair, match_id, 0, mv("e2", "e4"), played_at).unwrap();
+
+        // records are only kept when sent by the player who made the move
+        store.inject_event(
+            ipchess_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::MoveRecordStoreReceived {
+                record: record.clone(),
+            },
+        );
+        assert!(store.stored_move_records.is_empty());
+
+        store.inject_event(
+            peer_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::MoveRecordStoreReceived {
+                record: record.clone(),
+            },
+        );
+        store.connected_peers.insert(ipchess_id);
+        store.inject_event(
+            ipchess_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::MoveRecordFetchReceived {
+                match_id,
+                ply: 0,
+                signer: peer_id,
+            },
+        );
+        let found = match next_notification(&mut store) {
+            IpchessHandlerEventIn::MoveRecordFound { record } => record,
+            event => panic!("unexpected notification {:?}", event),
+        };
+        assert_eq!(found, record);
+
+        // a record of the right match signed by anyone but the opponent is ignored
+        let forged = sign_move_record(
+            &Keypair::generate_ed25519(),
+            match_id,
+            0,
+            mv("d2", "d4"),
+            played_at,
+        )
+        .unwrap();
+        ipchess.inject_event(
+            store_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::MoveRecordFoundReceived { record: forged },
+        );
+        assert!(ipchess.events.is_empty());
+
+        ipchess.inject_event(
+            store_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::MoveRecordFoundReceived { record: found },
+        );
+        assert!(matches!(
+            generated_events(&mut ipchess)[..],
+            [IpchessEvent::MovePlayed { ply: 0, .. }]
+        ));
+
+        // stored records expire after the longest move deadline
+        clock.advance(Duration::from_secs(15 * SECS_PER_DAY));
+        store.inject_event(
+            ipchess_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::MoveRecordFetchReceived {
+                match_id,
+                ply: 0,
+                signer: peer_id,
+            },
+        );
+        assert!(store.events.is_empty());
+    }
+
+    /// Starts a live match against a connected peer of a minute per player and a second of
+    /// increment, this node playing `color`.
+    fn start_live_match(ipchess: &mut Ipchess, peer_id: PeerId, color: Color) -> MatchId {
+        let time_control = TimeControl::Live {
+            initial: Duration::from_secs(60),
+            increment: Duration::from_secs(1),
+        };
+
+        start_match_playing(ipchess, peer_id, color, time_control)
+    }
+
+    #[test]
+    fn live_match_clock_follows_moves() {
+        let clock = ManualClock::new();
+        let mut ipchess = Ipchess::with_clock(IpchessConfig::default(), Arc::new(clock.clone()));
+        let peer_id = PeerId::random();
+        let match_id = start_live_match(&mut ipchess, peer_id, Color::White);
+        let secs = Duration::from_secs;
+
+        clock.advance(secs(10));
+        ipchess.make_move(match_id, mv("e2", "e4")).unwrap();
+        assert!(matches!(
+            ipchess.events.pop_front(),
+            Some(NetworkBehaviourAction::NotifyHandler {
+                event: IpchessHandlerEventIn::MatchMove { clock: Some(left), .. },
+                ..
+            }) if left == secs(51)
+        ));
+        assert!(matches!(
+            generated_events(&mut ipchess)[..],
+            [IpchessEvent::MovePlayed {
+                clock: Some(ClockTimes { white, black }),
+                ..
+            }] if white == secs(51) && black == secs(60)
+        ));
+
+        // the peer's reported time is followed, up to the grace period above this node's count
+        clock.advance(secs(20));
+        ipchess.inject_event(
+            peer_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::MatchMoveReceived {
+                match_id,
+                ply: 1,
+                mv: mv("e7", "e5"),
+                clock: Some(secs(60)),
+            },
+        );
+        assert_eq!(
+            ipchess.clock_times(match_id),
+            Some(ClockTimes {
+                white: secs(51),
+                black: secs(41) + ipchess.config.flag_grace_period,
+            })
+        );
+    }
+
+    #[test]
+    fn live_match_is_lost_on_time() {
+        let clock = ManualClock::new();
+        let mut ipchess = Ipchess::with_clock(IpchessConfig::default(), Arc::new(clock.clone()));
+        let peer_id = PeerId::random();
+        let match_id = start_live_match(&mut ipchess, peer_id, Color::Black);
+
+        // the peer is given the grace period for its move to arrive
+        clock.advance(Duration::from_secs(60));
+        ipchess.end_flagged_matches();
+        assert!(ipchess.events.is_empty());
+
+        receive_move(&mut ipchess, peer_id, match_id, mv("e2", "e4"));
+        ipchess.events.clear();
+
+        // this node's own time runs out at zero
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(
+            ipchess.make_move(match_id, mv("e7", "e5")),
+            Err(MatchError::Finished)
+        );
+        assert!(matches!(
+            generated_events(&mut ipchess)[..],
+            [IpchessEvent::MatchEnded {
+                result: MatchResult::Win {
+                    winner: Color::White,
+                    reason: WinReason::Timeout
+                },
+                ..
+            }]
+        ));
+
+        // the clock stopped with the match
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(
+            ipchess.clock_times(match_id),
+            Some(ClockTimes {
+                white: Duration::from_secs(1),
+                black: Duration::from_secs(0),
+            })
+        );
+    }
+
+    /// Pops the behaviour's next handler notification, discarding generated events.
+    fn next_notification(ipchess: &mut Ipchess) -> IpchessHandlerEventIn {
+        loop {
+            match ipchess.events.pop_front() {
+                Some(NetworkBehaviourAction::NotifyHandler { event, .. }) => return event,
+                Some(_) => {}
+                None => panic!("no handler notification"),
+            }
+        }
+    }
+
+    #[test]
+    fn rematch_swaps_colors_and_links_previous_match() {
+        let mut white = Ipchess::new();
+        let mut black = Ipchess::new();
+        let (white_peer_id, black_peer_id) = (PeerId::random(), PeerId::random());
+
+        let previous = insert_match(&mut white, black_peer_id, Color::White);
+        insert_match(&mut black, white_peer_id, Color::Black);
+
+        assert!(matches!(
+            white.offer_rematch(previous, true),
+            Err(ChallengeError::MatchInProgress { .. })
+        ));
+
+        for ipchess in [&mut white, &mut black].iter_mut() {
+            ipchess
+                .matches
+                .get_mut(&previous)
+                .unwrap()
+                .time_out(Color::Black)
+                .unwrap();
+        }
+
+        white.offer_rematch(previous, true).unwrap();
+        let event = match next_notification(&mut white) {
+            IpchessHandlerEventIn::Challenge {
+                commitment,
+                variant,
+                time_control,
+                rematch,
+            } => IpchessHandlerEventOut::ChallengeReceived {
+                commitment,
+                variant: variant.to_string(),
+                time_control,
+                rematch,
+            },
+            event => panic!("unexpected notification {:?}", event),
+        };
+        black.inject_event(white_peer_id, ConnectionId::new(0), event);
+        assert!(matches!(
+            generated_events(&mut black)[..],
+            [IpchessEvent::PeerChallenge {
+                rematch: Some(Rematch {
+                    previous_match,
+                    swap_colors: true,
+                }),
+                ..
+            }] if previous_match == previous
+        ));
+
+        black.accept_rematch(previous).unwrap();
+        let event = match next_notification(&mut black) {
+            IpchessHandlerEventIn::ChallengeAccept { random } => {
+                IpchessHandlerEventOut::ChallengeAccepted { random }
+            }
+            event => panic!("unexpected notification {:?}", event),
+        };
+        white.inject_event(black_peer_id, ConnectionId::new(0), event);
+
+        let event = match next_notification(&mut white) {
+            IpchessHandlerEventIn::ChallengeReveal { preimage } => {
+                IpchessHandlerEventOut::ChallengeRevealReceived { preimage }
+            }
+            event => panic!("unexpected notification {:?}", event),
+        };
+        black.inject_event(white_peer_id, ConnectionId::new(0), event);
+
+        for (ipchess, color) in [(&white, Color::Black), (&black, Color::White)].iter() {
+            let rematch = ipchess
+                .matches()
+                .find(|game| game.id() != previous)
+                .unwrap();
+
+            assert_eq!(rematch.color(), *color);
+            assert_eq!(rematch.previous_match(), Some(previous));
+        }
+    }
+
+    #[test]
+    fn restored_finished_match_keeps_its_clock_and_can_be_rematched() {
+        let mut ipchess = Ipchess::new();
+        let peer_id = PeerId::random();
+        let previous = MatchId::from_bytes(&[2; 32]).unwrap();
+
+        let mut game = Match::new(
+            MatchId::from_bytes(&[1; 32]).unwrap(),
+            peer_id,
+            Color::White,
+            Variant::Standard,
+            TimeControl::default(),
+            Some(previous),
+            Position::startpos(),
+        );
+        game.time_out(Color::White).unwrap();
+        let match_id = game.id();
+
+        let clock = ClockTimes {
+            white: Duration::from_secs(0),
+            black: Duration::from_secs(42),
+        };
+        ipchess.restore_match(game, None, Some(clock));
+        assert_eq!(ipchess.clock_times(match_id), Some(clock));
+        assert_eq!(ipchess.matches[&match_id].previous_match(), Some(previous));
+
+        ipchess.offer_rematch(match_id, true).unwrap();
+        assert!(ipchess.has_outbound_challenge(&peer_id));
+    }
+
+    #[test]
+    fn rematch_of_unknown_match_is_declined() {
+        let mut ipchess = Ipchess::new();
+        let peer_id = PeerId::random();
+        let previous = MatchId::from_bytes(&[9; 32]).unwrap();
+
+        assert!(matches!(
+            ipchess.offer_rematch(previous, false),
+            Err(ChallengeError::NoSuchMatch { .. })
+        ));
+
+        ipchess.inject_event(
+            peer_id,
+            ConnectionId::new(0),
+            IpchessHandlerEventOut::ChallengeReceived {
+                commitment: vec![0; 32],
+                variant: String::new(),
+                time_control: TimeControl::default(),
+                rematch: Some(Rematch {
+                    previous_match: previous,
+                    swap_colors: false,
+                }),
+            },
+        );
+        assert!(matches!(
+            ipchess.events.pop_front(),
+            Some(NetworkBehaviourAction::NotifyHandler {
+                event: IpchessHandlerEventIn::ChallengeDeclined { .. },
+                ..
+            })
+        ));
+        assert!(ipchess.challenges().is_empty());
     }
 }
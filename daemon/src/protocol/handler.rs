@@ -1,5 +1,5 @@
-use core::iter;
-use std::{ops::Add, task::Poll, time};
+use core::{convert::TryFrom, iter};
+use std::{collections::VecDeque, io, ops::Add, sync::Arc, task::Poll, time};
 
 use futures::{
     future::{self, BoxFuture},
@@ -9,28 +9,122 @@ use libp2p::swarm::{
     protocols_handler::{InboundUpgradeSend, OutboundUpgradeSend, UpgradeInfoSend},
     KeepAlive, NegotiatedSubstream, ProtocolsHandler, ProtocolsHandlerEvent, SubstreamProtocol,
 };
+use libp2p::PeerId;
 use prost::Message;
 use thiserror::Error;
 
-use super::ipchessproto;
+use super::{ipchessproto, Clock};
+use crate::{
+    chess::{Move, Role, Square},
+    game::{MatchId, Rematch, TimeControl, Variant},
+};
+
+/// Largest message size representable by the two byte length prefix of a frame.
+pub const MAX_FRAME_SIZE_LIMIT: usize = u16::MAX as usize;
 
 #[derive(Debug)]
 pub enum IpchessHandlerEventIn {
-    Challenge { commitment: Vec<u8> },
-    ChallengeAccept { random: Vec<u8> },
-    ChallengeReveal { preimage: Vec<u8> },
+    Challenge {
+        commitment: Vec<u8>,
+        variant: Variant,
+        time_control: TimeControl,
+        rematch: Option<Rematch>,
+    },
+    ChallengeAccept {
+        random: Vec<u8>,
+    },
+    ChallengeReveal {
+        preimage: Vec<u8>,
+    },
     ChallengeCanceled,
-    ChallengeDeclined,
+    ChallengeDeclined {
+        /// Variants this node plays, empty unless the challenge is declined for its variant.
+        supported_variants: Vec<Variant>,
+    },
     ChallengePoisoned,
+    MatchMove {
+        match_id: MatchId,
+        ply: u32,
+        mv: Move,
+        /// Time left on this node's clock after the move, in a live match.
+        clock: Option<time::Duration>,
+    },
+    MatchDrawClaim {
+        match_id: MatchId,
+        ply: u32,
+    },
+    /// Signed move record for the peer to keep as the store peer of the record's match.
+    MoveRecordStore {
+        record: Vec<u8>,
+    },
+    /// Request to the store peer of a match for the record of a move made by `signer`.
+    MoveRecordFetch {
+        match_id: MatchId,
+        ply: u32,
+        signer: PeerId,
+    },
+    /// Record kept as a store peer, answering a fetch.
+    MoveRecordFound {
+        record: Vec<u8>,
+    },
+    MatchChat {
+        match_id: MatchId,
+        text: String,
+    },
 }
 
 #[derive(Debug)]
 pub enum IpchessHandlerEventOut {
-    ChallengeReceived { commitment: Vec<u8> },
-    ChallengeRevealReceived { preimage: Vec<u8> },
-    ChallengeAccepted { random: Vec<u8> },
+    ChallengeReceived {
+        commitment: Vec<u8>,
+        /// Name of the requested variant, left for the behaviour to decline if it is unknown.
+        variant: String,
+        /// Time control of the match, left for the behaviour to check.
+        time_control: TimeControl,
+        /// Previous match of a rematch, left for the behaviour to check.
+        rematch: Option<Rematch>,
+    },
+    ChallengeRevealReceived {
+        preimage: Vec<u8>,
+    },
+    ChallengeAccepted {
+        random: Vec<u8>,
+    },
     ChallengeCanceled,
-    ChallengeDeclined,
+    ChallengeDeclined {
+        /// Names of the variants the peer plays, empty unless it declined for the variant.
+        supported_variants: Vec<String>,
+    },
+    MatchMoveReceived {
+        match_id: MatchId,
+        ply: u32,
+        mv: Move,
+        /// Time the peer's clock showed after the move, in a live match.
+        clock: Option<time::Duration>,
+    },
+    MatchDrawClaimReceived {
+        match_id: MatchId,
+        ply: u32,
+    },
+    /// Move record to keep, left for the behaviour to verify.
+    MoveRecordStoreReceived {
+        record: Vec<u8>,
+    },
+    MoveRecordFetchReceived {
+        match_id: MatchId,
+        ply: u32,
+        signer: PeerId,
+    },
+    /// Move record fetched from a store peer, left for the behaviour to verify.
+    MoveRecordFoundReceived {
+        record: Vec<u8>,
+    },
+    /// Chat message, left for the behaviour to check its length.
+    MatchChatReceived {
+        match_id: MatchId,
+        text: String,
+    },
+    OutboundSubstreamFailed,
 }
 
 #[derive(Error, Debug)]
@@ -47,6 +141,11 @@ pub enum IpchessHandlerError {
     #[error("failed flusing substream, reason: `{0}`")]
     SubstreamFlush(std::io::Error),
 
+    #[error("message of {0} bytes exceeds the maximum frame size")]
+    FrameTooLarge(usize),
+    #[error("invalid `{0}` field in message")]
+    InvalidField(&'static str),
+
     #[error("poisoned")]
     Poisoned,
 }
@@ -85,21 +184,33 @@ impl OutboundUpgradeSend for IpchessProtocol {
 enum SubstreamState {
     PendingOpen(ipchessproto::Message),
     PendingSend(BoxFuture<'static, Result<(), IpchessHandlerError>>),
-    WaitingMessage(BoxFuture<'static, Result<ipchessproto::Message, IpchessHandlerError>>),
+    WaitingMessage(BoxFuture<'static, Result<Option<IpchessHandlerEventOut>, IpchessHandlerError>>),
 }
 
 pub struct IpchessHandler {
     substream_states: Vec<SubstreamState>,
+    out_events: VecDeque<IpchessHandlerEventOut>,
     handler_error_received: bool,
     keep_alive: KeepAlive,
+    idle_keep_alive: time::Duration,
+    max_frame_size: usize,
+    clock: Arc<dyn Clock>,
 }
 
 impl IpchessHandler {
-    pub fn new() -> Self {
+    pub fn new(
+        idle_keep_alive: time::Duration,
+        max_frame_size: usize,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
         IpchessHandler {
             substream_states: vec![],
+            out_events: VecDeque::new(),
             handler_error_received: false,
             keep_alive: KeepAlive::Yes,
+            idle_keep_alive,
+            max_frame_size,
+            clock,
         }
     }
 }
@@ -128,7 +239,7 @@ impl ProtocolsHandler for IpchessHandler {
         log::debug!("Ipchess inbound negotiated");
 
         self.substream_states.push(SubstreamState::WaitingMessage(
-            read_message(protocol).boxed(),
+            read_message(protocol, self.max_frame_size).boxed(),
         ));
     }
 
@@ -146,13 +257,34 @@ impl ProtocolsHandler for IpchessHandler {
 
     fn inject_event(&mut self, event: Self::InEvent) {
         match event {
-            IpchessHandlerEventIn::Challenge { commitment } => {
+            IpchessHandlerEventIn::Challenge {
+                commitment,
+                variant,
+                time_control,
+                rematch,
+            } => {
                 log::debug!("Initiating peer challenge");
 
+                let (days_per_move, initial_time_secs, increment_secs) =
+                    encode_time_control(time_control);
+
                 self.substream_states
                     .push(SubstreamState::PendingOpen(ipchessproto::Message {
                         payload: Some(ipchessproto::message::Payload::Challenge(
-                            ipchessproto::message::Challenge { commitment },
+                            ipchessproto::message::Challenge {
+                                commitment,
+                                variant: variant.to_string(),
+                                days_per_move,
+                                previous_match_id: rematch.map_or_else(Vec::new, |rematch| {
+                                    rematch.previous_match.as_bytes().to_vec()
+                                }),
+                                swap_colors: rematch.map_or(false, |rematch| rematch.swap_colors),
+                                initial_time_secs,
+                                increment_secs,
+                                store_peer: time_control
+                                    .store_peer()
+                                    .map_or_else(Vec::new, |store_peer| store_peer.to_bytes()),
+                            },
                         )),
                     }));
             }
@@ -190,13 +322,18 @@ impl ProtocolsHandler for IpchessHandler {
                     }));
             }
 
-            IpchessHandlerEventIn::ChallengeDeclined => {
+            IpchessHandlerEventIn::ChallengeDeclined { supported_variants } => {
                 log::debug!("Declining challenge");
 
                 self.substream_states
                     .push(SubstreamState::PendingOpen(ipchessproto::Message {
                         payload: Some(ipchessproto::message::Payload::ChallengeDecline(
-                            ipchessproto::message::ChallengeDecline {},
+                            ipchessproto::message::ChallengeDecline {
+                                supported_variants: supported_variants
+                                    .iter()
+                                    .map(|variant| variant.as_str().to_string())
+                                    .collect(),
+                            },
                         )),
                     }));
             }
@@ -204,6 +341,94 @@ impl ProtocolsHandler for IpchessHandler {
             IpchessHandlerEventIn::ChallengePoisoned => {
                 self.handler_error_received = true;
             }
+
+            IpchessHandlerEventIn::MatchMove {
+                match_id,
+                ply,
+                mv,
+                clock,
+            } => {
+                log::debug!("Sending move {} of match {}", ply, match_id);
+
+                let mut msg = encode_match_move(match_id, ply, mv);
+                msg.clock = clock.map(|clock| ipchessproto::message::ClockReading {
+                    ms: clock.as_millis() as u64,
+                });
+
+                self.substream_states
+                    .push(SubstreamState::PendingOpen(ipchessproto::Message {
+                        payload: Some(ipchessproto::message::Payload::MatchMove(msg)),
+                    }));
+            }
+
+            IpchessHandlerEventIn::MatchDrawClaim { match_id, ply } => {
+                log::debug!("Claiming draw in match {}", match_id);
+
+                self.substream_states
+                    .push(SubstreamState::PendingOpen(ipchessproto::Message {
+                        payload: Some(ipchessproto::message::Payload::MatchDrawClaim(
+                            ipchessproto::message::MatchDrawClaim {
+                                match_id: match_id.as_bytes().to_vec(),
+                                ply,
+                            },
+                        )),
+                    }));
+            }
+
+            IpchessHandlerEventIn::MoveRecordStore { record } => {
+                log::debug!("Sending move record to store");
+
+                self.substream_states
+                    .push(SubstreamState::PendingOpen(ipchessproto::Message {
+                        payload: Some(ipchessproto::message::Payload::MoveRecordStore(
+                            ipchessproto::message::MoveRecordStore { record },
+                        )),
+                    }));
+            }
+
+            IpchessHandlerEventIn::MoveRecordFetch {
+                match_id,
+                ply,
+                signer,
+            } => {
+                log::debug!("Fetching record of move {} of match {}", ply, match_id);
+
+                self.substream_states
+                    .push(SubstreamState::PendingOpen(ipchessproto::Message {
+                        payload: Some(ipchessproto::message::Payload::MoveRecordFetch(
+                            ipchessproto::message::MoveRecordFetch {
+                                match_id: match_id.as_bytes().to_vec(),
+                                ply,
+                                signer: signer.to_bytes(),
+                            },
+                        )),
+                    }));
+            }
+
+            IpchessHandlerEventIn::MoveRecordFound { record } => {
+                log::debug!("Sending stored move record");
+
+                self.substream_states
+                    .push(SubstreamState::PendingOpen(ipchessproto::Message {
+                        payload: Some(ipchessproto::message::Payload::MoveRecordFound(
+                            ipchessproto::message::MoveRecordFound { record },
+                        )),
+                    }));
+            }
+
+            IpchessHandlerEventIn::MatchChat { match_id, text } => {
+                log::debug!("Sending chat message in match {}", match_id);
+
+                self.substream_states
+                    .push(SubstreamState::PendingOpen(ipchessproto::Message {
+                        payload: Some(ipchessproto::message::Payload::MatchChat(
+                            ipchessproto::message::MatchChat {
+                                match_id: match_id.as_bytes().to_vec(),
+                                text,
+                            },
+                        )),
+                    }));
+            }
         }
     }
 
@@ -215,6 +440,8 @@ impl ProtocolsHandler for IpchessHandler {
         >,
     ) {
         log::debug!("Dial upgrade error: {:?}", error);
+        self.out_events
+            .push_back(IpchessHandlerEventOut::OutboundSubstreamFailed);
         self.keep_alive = KeepAlive::No;
     }
 
@@ -237,6 +464,10 @@ impl ProtocolsHandler for IpchessHandler {
             return Poll::Ready(ProtocolsHandlerEvent::Close(IpchessHandlerError::Poisoned));
         }
 
+        if let Some(event) = self.out_events.pop_front() {
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(event));
+        }
+
         if self.substream_states.is_empty() {
             return Poll::Pending;
         }
@@ -260,54 +491,14 @@ impl ProtocolsHandler for IpchessHandler {
                 },
 
                 SubstreamState::WaitingMessage(mut fut) => match fut.poll_unpin(cx) {
-                    Poll::Ready(Ok(msg)) => match msg.payload {
-                        Some(payload) => match payload {
-                            ipchessproto::message::Payload::Challenge(
-                                ipchessproto::message::Challenge { commitment },
-                            ) => {
-                                return Poll::Ready(ProtocolsHandlerEvent::Custom(
-                                    IpchessHandlerEventOut::ChallengeReceived { commitment },
-                                ));
-                            }
-
-                            ipchessproto::message::Payload::ChallengeAccept(
-                                ipchessproto::message::ChallengeAccept { random },
-                            ) => {
-                                return Poll::Ready(ProtocolsHandlerEvent::Custom(
-                                    IpchessHandlerEventOut::ChallengeAccepted { random },
-                                ));
-                            }
-
-                            ipchessproto::message::Payload::ChallengeReveal(
-                                ipchessproto::message::ChallengeReveal { preimage },
-                            ) => {
-                                return Poll::Ready(ProtocolsHandlerEvent::Custom(
-                                    IpchessHandlerEventOut::ChallengeRevealReceived { preimage },
-                                ));
-                            }
-
-                            ipchessproto::message::Payload::ChallengeCancel(
-                                ipchessproto::message::ChallengeCancel {},
-                            ) => {
-                                return Poll::Ready(ProtocolsHandlerEvent::Custom(
-                                    IpchessHandlerEventOut::ChallengeCanceled,
-                                ));
-                            }
-
-                            ipchessproto::message::Payload::ChallengeDecline(
-                                ipchessproto::message::ChallengeDecline {},
-                            ) => {
-                                return Poll::Ready(ProtocolsHandlerEvent::Custom(
-                                    IpchessHandlerEventOut::ChallengeDeclined,
-                                ));
-                            }
-                        },
-
-                        None => {
-                            log::debug!("Ignoring message without payload");
-                            None
-                        }
-                    },
+                    Poll::Ready(Ok(Some(event))) => {
+                        return Poll::Ready(ProtocolsHandlerEvent::Custom(event));
+                    }
+
+                    Poll::Ready(Ok(None)) => {
+                        log::debug!("Ignoring message without payload");
+                        None
+                    }
 
                     Poll::Ready(Err(err)) => return Poll::Ready(ProtocolsHandlerEvent::Close(err)),
 
@@ -322,8 +513,7 @@ impl ProtocolsHandler for IpchessHandler {
 
         // We have processed all substreams
         if self.substream_states.is_empty() {
-            self.keep_alive =
-                KeepAlive::Until(time::Instant::now().add(time::Duration::from_secs(30)));
+            self.keep_alive = KeepAlive::Until(self.clock.now().add(self.idle_keep_alive));
         } else {
             self.keep_alive = KeepAlive::Yes;
         }
@@ -332,49 +522,287 @@ impl ProtocolsHandler for IpchessHandler {
     }
 }
 
+/// Decodes a length prefixed frame, as peers write them to substreams, into the event it produces.
+/// Returns `None` for messages without payload, bytes following the frame are ignored.
+pub fn decode_frame(
+    frame: &[u8],
+    max_frame_size: usize,
+) -> Result<Option<IpchessHandlerEventOut>, IpchessHandlerError> {
+    let unexpected_eof =
+        |what| IpchessHandlerError::SubstreamRead(what, io::ErrorKind::UnexpectedEof.into());
+
+    let msg_len = match frame {
+        [a, b, ..] => frame_len([*a, *b], max_frame_size)?,
+        _ => return Err(unexpected_eof("message length")),
+    };
+
+    let msg_buf = frame
+        .get(2..2 + msg_len)
+        .ok_or_else(|| unexpected_eof("message content"))?;
+
+    decode_message(msg_buf)
+}
+
 async fn read_message(
     mut stream: NegotiatedSubstream,
-) -> Result<ipchessproto::Message, IpchessHandlerError> {
-    let mut msg_len_buf = [0u8, 2];
+    max_frame_size: usize,
+) -> Result<Option<IpchessHandlerEventOut>, IpchessHandlerError> {
+    let mut msg_len_buf = [0u8; 2];
 
     stream
         .read_exact(&mut msg_len_buf)
         .await
         .map_err(|err| IpchessHandlerError::SubstreamRead("message length", err))?;
 
-    let msg_len = u16::from_be_bytes(msg_len_buf);
-    let mut msg_buf = vec![0; msg_len as usize];
+    let mut msg_buf = vec![0; frame_len(msg_len_buf, max_frame_size)?];
 
     stream
         .read_exact(&mut msg_buf)
         .await
         .map_err(|err| IpchessHandlerError::SubstreamRead("message content", err))?;
 
-    let msg = ipchessproto::Message::decode(std::io::Cursor::new(msg_buf))
-        .map_err(IpchessHandlerError::ProtobufDecode)?;
+    decode_message(&msg_buf)
+}
 
-    match msg.payload {
-        Some(ipchessproto::message::Payload::Challenge(_)) => {
+/// Encodes a message into a length prefixed frame, failing if it is too long for its length to
+/// fit the prefix.
+fn encode_frame(msg: &ipchessproto::Message) -> Result<Vec<u8>, IpchessHandlerError> {
+    let msg_len = msg.encoded_len();
+    if msg_len > MAX_FRAME_SIZE_LIMIT {
+        return Err(IpchessHandlerError::FrameTooLarge(msg_len));
+    }
+
+    let mut frame = Vec::with_capacity(2 + msg_len);
+    frame.extend_from_slice(&(msg_len as u16).to_be_bytes());
+    msg.encode(&mut frame)
+        .map_err(IpchessHandlerError::ProtobufEncode)?;
+
+    Ok(frame)
+}
+
+fn frame_len(prefix: [u8; 2], max_frame_size: usize) -> Result<usize, IpchessHandlerError> {
+    let msg_len = u16::from_be_bytes(prefix) as usize;
+
+    if msg_len > max_frame_size {
+        return Err(IpchessHandlerError::FrameTooLarge(msg_len));
+    }
+
+    Ok(msg_len)
+}
+
+fn decode_message(msg_buf: &[u8]) -> Result<Option<IpchessHandlerEventOut>, IpchessHandlerError> {
+    let msg =
+        ipchessproto::Message::decode(msg_buf).map_err(IpchessHandlerError::ProtobufDecode)?;
+
+    let event = match msg.payload {
+        Some(ipchessproto::message::Payload::Challenge(ipchessproto::message::Challenge {
+            commitment,
+            variant,
+            days_per_move,
+            previous_match_id,
+            swap_colors,
+            initial_time_secs,
+            increment_secs,
+            store_peer,
+        })) => {
             log::debug!("Read Challenge message");
+            let rematch = if previous_match_id.is_empty() {
+                None
+            } else {
+                Some(Rematch {
+                    previous_match: decode_match_id(&previous_match_id)?,
+                    swap_colors,
+                })
+            };
+
+            IpchessHandlerEventOut::ChallengeReceived {
+                commitment,
+                variant,
+                time_control: decode_time_control(
+                    days_per_move,
+                    initial_time_secs,
+                    increment_secs,
+                    decode_store_peer(&store_peer)?,
+                ),
+                rematch,
+            }
         }
-        Some(ipchessproto::message::Payload::ChallengeAccept(_)) => {
+        Some(ipchessproto::message::Payload::ChallengeAccept(
+            ipchessproto::message::ChallengeAccept { random },
+        )) => {
             log::debug!("Read ChallengeAccept message");
+            IpchessHandlerEventOut::ChallengeAccepted { random }
         }
-        Some(ipchessproto::message::Payload::ChallengeReveal(_)) => {
+        Some(ipchessproto::message::Payload::ChallengeReveal(
+            ipchessproto::message::ChallengeReveal { preimage },
+        )) => {
             log::debug!("Read ChallengeReveal message");
+            IpchessHandlerEventOut::ChallengeRevealReceived { preimage }
         }
         Some(ipchessproto::message::Payload::ChallengeCancel(_)) => {
             log::debug!("Read ChallengeCancel message");
+            IpchessHandlerEventOut::ChallengeCanceled
         }
-        Some(ipchessproto::message::Payload::ChallengeDecline(_)) => {
+        Some(ipchessproto::message::Payload::ChallengeDecline(
+            ipchessproto::message::ChallengeDecline { supported_variants },
+        )) => {
             log::debug!("Read ChallengeDecline message");
+            IpchessHandlerEventOut::ChallengeDeclined { supported_variants }
+        }
+        Some(ipchessproto::message::Payload::MatchMove(msg)) => {
+            log::debug!("Read MatchMove message");
+            let (match_id, ply, mv) = decode_match_move(&msg)?;
+            IpchessHandlerEventOut::MatchMoveReceived {
+                match_id,
+                ply,
+                mv,
+                clock: msg.clock.map(|clock| time::Duration::from_millis(clock.ms)),
+            }
+        }
+        Some(ipchessproto::message::Payload::MatchDrawClaim(msg)) => {
+            log::debug!("Read MatchDrawClaim message");
+            IpchessHandlerEventOut::MatchDrawClaimReceived {
+                match_id: decode_match_id(&msg.match_id)?,
+                ply: msg.ply,
+            }
+        }
+        Some(ipchessproto::message::Payload::MoveRecordStore(msg)) => {
+            log::debug!("Read MoveRecordStore message");
+            IpchessHandlerEventOut::MoveRecordStoreReceived { record: msg.record }
+        }
+        Some(ipchessproto::message::Payload::MoveRecordFetch(msg)) => {
+            log::debug!("Read MoveRecordFetch message");
+            IpchessHandlerEventOut::MoveRecordFetchReceived {
+                match_id: decode_match_id(&msg.match_id)?,
+                ply: msg.ply,
+                signer: PeerId::from_bytes(&msg.signer)
+                    .map_err(|_| IpchessHandlerError::InvalidField("signer"))?,
+            }
+        }
+        Some(ipchessproto::message::Payload::MoveRecordFound(msg)) => {
+            log::debug!("Read MoveRecordFound message");
+            IpchessHandlerEventOut::MoveRecordFoundReceived { record: msg.record }
+        }
+        Some(ipchessproto::message::Payload::MatchChat(msg)) => {
+            log::debug!("Read MatchChat message");
+            IpchessHandlerEventOut::MatchChatReceived {
+                match_id: decode_match_id(&msg.match_id)?,
+                text: msg.text,
+            }
         }
         None => {
             log::debug!("Read empty message");
+            return Ok(None);
         }
+    };
+
+    Ok(Some(event))
+}
+
+pub(super) fn encode_match_move(
+    match_id: MatchId,
+    ply: u32,
+    mv: Move,
+) -> ipchessproto::message::MatchMove {
+    ipchessproto::message::MatchMove {
+        match_id: match_id.as_bytes().to_vec(),
+        ply,
+        from: mv.from.index() as u32,
+        to: mv.to.index() as u32,
+        promotion: encode_promotion(mv.promotion),
+        clock: None,
+        played_at_secs: 0,
+    }
+}
+
+/// Decodes a move message into the match id, ply and move it carries.
+pub(super) fn decode_match_move(
+    msg: &ipchessproto::message::MatchMove,
+) -> Result<(MatchId, u32, Move), IpchessHandlerError> {
+    let mv = Move {
+        from: decode_square(msg.from, "from")?,
+        to: decode_square(msg.to, "to")?,
+        promotion: decode_promotion(msg.promotion)?,
+    };
+
+    Ok((decode_match_id(&msg.match_id)?, msg.ply, mv))
+}
+
+/// Encodes a time control into the days per move, initial time and increment fields of a
+/// challenge, the days per move being 0 in a live match.
+pub(super) fn encode_time_control(time_control: TimeControl) -> (u32, u32, u32) {
+    match time_control {
+        TimeControl::Live { initial, increment } => {
+            (0, initial.as_secs() as u32, increment.as_secs() as u32)
+        }
+        TimeControl::Correspondence { days_per_move, .. } => (days_per_move, 0, 0),
+    }
+}
+
+/// Decodes the time control fields of a challenge, a correspondence match when days per move
+/// are given. The times are left for the behaviour to check.
+pub(super) fn decode_time_control(
+    days_per_move: u32,
+    initial_time_secs: u32,
+    increment_secs: u32,
+    store_peer: Option<PeerId>,
+) -> TimeControl {
+    if days_per_move > 0 {
+        TimeControl::Correspondence {
+            days_per_move,
+            store_peer,
+        }
+    } else {
+        TimeControl::Live {
+            initial: time::Duration::from_secs(initial_time_secs.into()),
+            increment: time::Duration::from_secs(increment_secs.into()),
+        }
+    }
+}
+
+/// Decodes the store peer field of a challenge, `None` when empty.
+pub(super) fn decode_store_peer(bytes: &[u8]) -> Result<Option<PeerId>, IpchessHandlerError> {
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+
+    PeerId::from_bytes(bytes)
+        .map(Some)
+        .map_err(|_| IpchessHandlerError::InvalidField("store_peer"))
+}
+
+fn decode_match_id(bytes: &[u8]) -> Result<MatchId, IpchessHandlerError> {
+    MatchId::from_bytes(bytes).ok_or(IpchessHandlerError::InvalidField("match_id"))
+}
+
+fn decode_square(index: u32, field: &'static str) -> Result<Square, IpchessHandlerError> {
+    u8::try_from(index)
+        .ok()
+        .and_then(Square::from_index)
+        .ok_or(IpchessHandlerError::InvalidField(field))
+}
+
+fn encode_promotion(promotion: Option<Role>) -> u32 {
+    match promotion {
+        None => 0,
+        Some(Role::Knight) => 1,
+        Some(Role::Bishop) => 2,
+        Some(Role::Rook) => 3,
+        Some(Role::Queen) => 4,
+        // moves are generated by the rules engine, which only promotes to the roles above
+        Some(Role::Pawn) | Some(Role::King) => unreachable!("invalid promotion role"),
     }
+}
 
-    Ok(msg)
+fn decode_promotion(promotion: u32) -> Result<Option<Role>, IpchessHandlerError> {
+    match promotion {
+        0 => Ok(None),
+        1 => Ok(Some(Role::Knight)),
+        2 => Ok(Some(Role::Bishop)),
+        3 => Ok(Some(Role::Rook)),
+        4 => Ok(Some(Role::Queen)),
+        _ => Err(IpchessHandlerError::InvalidField("promotion")),
+    }
 }
 
 async fn send_message(
@@ -397,25 +825,35 @@ async fn send_message(
         Some(ipchessproto::message::Payload::ChallengeDecline(_)) => {
             log::debug!("Sending ChallengeDecline message");
         }
+        Some(ipchessproto::message::Payload::MatchMove(_)) => {
+            log::debug!("Sending MatchMove message");
+        }
+        Some(ipchessproto::message::Payload::MatchDrawClaim(_)) => {
+            log::debug!("Sending MatchDrawClaim message");
+        }
+        Some(ipchessproto::message::Payload::MoveRecordStore(_)) => {
+            log::debug!("Sending MoveRecordStore message");
+        }
+        Some(ipchessproto::message::Payload::MoveRecordFetch(_)) => {
+            log::debug!("Sending MoveRecordFetch message");
+        }
+        Some(ipchessproto::message::Payload::MoveRecordFound(_)) => {
+            log::debug!("Sending MoveRecordFound message");
+        }
+        Some(ipchessproto::message::Payload::MatchChat(_)) => {
+            log::debug!("Sending MatchChat message");
+        }
         None => {
             log::warn!("Sending empty message");
         }
     }
 
-    let msg_len = msg.encoded_len();
-    let mut buf = Vec::with_capacity(msg_len);
-    msg.encode(&mut buf)
-        .map_err(IpchessHandlerError::ProtobufEncode)?;
+    let frame = encode_frame(&msg)?;
 
     stream
-        .write_all(&(msg_len as u16).to_be_bytes())
+        .write_all(&frame)
         .await
-        .map_err(|err| IpchessHandlerError::SubstreamWrite("message length", err))?;
-
-    stream
-        .write_all(&buf)
-        .await
-        .map_err(|err| IpchessHandlerError::SubstreamWrite("message content", err))?;
+        .map_err(|err| IpchessHandlerError::SubstreamWrite("message", err))?;
 
     stream
         .flush()
@@ -424,3 +862,157 @@ async fn send_message(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    use super::{
+        decode_frame, encode_frame, ipchessproto, IpchessHandler, IpchessHandlerError,
+        IpchessHandlerEventIn, IpchessHandlerEventOut, SubstreamState, MAX_FRAME_SIZE_LIMIT,
+    };
+    use crate::{
+        chess::Move,
+        game::MatchId,
+        protocol::{Clock, ManualClock},
+    };
+    use futures::{future, FutureExt};
+    use libp2p::swarm::{KeepAlive, ProtocolsHandler};
+
+    const IDLE_KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+    fn finish_substream(handler: &mut IpchessHandler) {
+        handler
+            .substream_states
+            .push(SubstreamState::PendingSend(future::ok(()).boxed()));
+
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        assert!(matches!(handler.poll(&mut cx), Poll::Pending));
+    }
+
+    /// Returns the frame the handler would write for the message it queued last.
+    fn queued_frame(handler: &mut IpchessHandler) -> Vec<u8> {
+        let msg = match handler.substream_states.pop() {
+            Some(SubstreamState::PendingOpen(msg)) => msg,
+            _ => panic!("no message queued"),
+        };
+
+        encode_frame(&msg).unwrap()
+    }
+
+    #[test]
+    fn messages_too_long_for_a_frame_are_not_encoded() {
+        let chat = |text: String| ipchessproto::Message {
+            payload: Some(ipchessproto::message::Payload::MatchChat(
+                ipchessproto::message::MatchChat {
+                    match_id: vec![3; 32],
+                    text,
+                },
+            )),
+        };
+
+        let fitting = chat("a".repeat(MAX_FRAME_SIZE_LIMIT - 64));
+        let frame = encode_frame(&fitting).unwrap();
+        assert_eq!(
+            u16::from_be_bytes([frame[0], frame[1]]) as usize,
+            frame.len() - 2
+        );
+
+        let too_long = chat("a".repeat(MAX_FRAME_SIZE_LIMIT));
+        assert!(matches!(
+            encode_frame(&too_long),
+            Err(IpchessHandlerError::FrameTooLarge(len)) if len > MAX_FRAME_SIZE_LIMIT
+        ));
+    }
+
+    #[test]
+    fn chat_message_is_decoded_by_the_peer() {
+        let mut handler = IpchessHandler::new(
+            IDLE_KEEP_ALIVE,
+            MAX_FRAME_SIZE_LIMIT,
+            Arc::new(ManualClock::new()),
+        );
+        let match_id = MatchId::from_bytes(&[3; 32]).unwrap();
+
+        handler.inject_event(IpchessHandlerEventIn::MatchChat {
+            match_id,
+            text: "good game".into(),
+        });
+        let frame = queued_frame(&mut handler);
+
+        match decode_frame(&frame, MAX_FRAME_SIZE_LIMIT) {
+            Ok(Some(IpchessHandlerEventOut::MatchChatReceived {
+                match_id: received,
+                text,
+            })) => {
+                assert_eq!(received, match_id);
+                assert_eq!(text, "good game");
+            }
+            res => panic!("unexpected decoded frame {:?}", res),
+        }
+    }
+
+    #[test]
+    fn empty_clock_is_told_apart_from_no_clock() {
+        let mut handler = IpchessHandler::new(
+            IDLE_KEEP_ALIVE,
+            MAX_FRAME_SIZE_LIMIT,
+            Arc::new(ManualClock::new()),
+        );
+        let match_id = MatchId::from_bytes(&[3; 32]).unwrap();
+        let mv: Move = "e2e4".parse().unwrap();
+
+        for &clock in [
+            Some(Duration::from_millis(0)),
+            Some(Duration::from_millis(1500)),
+            None,
+        ]
+        .iter()
+        {
+            handler.inject_event(IpchessHandlerEventIn::MatchMove {
+                match_id,
+                ply: 0,
+                mv,
+                clock,
+            });
+            let frame = queued_frame(&mut handler);
+
+            match decode_frame(&frame, MAX_FRAME_SIZE_LIMIT) {
+                Ok(Some(IpchessHandlerEventOut::MatchMoveReceived {
+                    clock: received, ..
+                })) => assert_eq!(received, clock),
+                res => panic!("unexpected decoded frame {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn idle_keep_alive_is_measured_by_clock() {
+        let clock = ManualClock::new();
+        let mut handler = IpchessHandler::new(
+            IDLE_KEEP_ALIVE,
+            MAX_FRAME_SIZE_LIMIT,
+            Arc::new(clock.clone()),
+        );
+
+        assert_eq!(handler.connection_keep_alive(), KeepAlive::Yes);
+
+        finish_substream(&mut handler);
+        assert_eq!(
+            handler.connection_keep_alive(),
+            KeepAlive::Until(clock.now() + IDLE_KEEP_ALIVE)
+        );
+
+        // a later substream extends the keep-alive from the time it finished
+        clock.advance(Duration::from_secs(10));
+        finish_substream(&mut handler);
+        assert_eq!(
+            handler.connection_keep_alive(),
+            KeepAlive::Until(clock.now() + IDLE_KEEP_ALIVE)
+        );
+    }
+}
@@ -1,6 +1,6 @@
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Message {
-    #[prost(oneof="message::Payload", tags="1, 2, 3, 4, 5")]
+    #[prost(oneof="message::Payload", tags="1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11")]
     pub payload: ::core::option::Option<message::Payload>,
 }
 /// Nested message and enum types in `Message`.
@@ -9,6 +9,29 @@ pub mod message {
     pub struct Challenge {
         #[prost(bytes="vec", tag="1")]
         pub commitment: ::prost::alloc::vec::Vec<u8>,
+        /// Name of the variant to play, like "chess960". Standard chess if empty.
+        #[prost(string, tag="2")]
+        pub variant: ::prost::alloc::string::String,
+        /// Days each player has for a move in a correspondence match, 0 for a live match.
+        #[prost(uint32, tag="3")]
+        pub days_per_move: u32,
+        /// Id of the finished match this challenge offers a rematch of, empty for a new match.
+        #[prost(bytes="vec", tag="4")]
+        pub previous_match_id: ::prost::alloc::vec::Vec<u8>,
+        /// Whether the players of a rematch swap their colors in the previous match, instead of
+        /// drawing colors anew.
+        #[prost(bool, tag="5")]
+        pub swap_colors: bool,
+        /// Seconds each player starts a live match with.
+        #[prost(uint32, tag="6")]
+        pub initial_time_secs: u32,
+        /// Seconds given back to a player after each of its moves in a live match.
+        #[prost(uint32, tag="7")]
+        pub increment_secs: u32,
+        /// Peer id of the node relaying the moves of a correspondence match, empty for moves to
+        /// go through the DHT.
+        #[prost(bytes="vec", tag="8")]
+        pub store_peer: ::prost::alloc::vec::Vec<u8>,
     }
     #[derive(Clone, PartialEq, ::prost::Message)]
     pub struct ChallengeAccept {
@@ -25,6 +48,82 @@ pub mod message {
     }
     #[derive(Clone, PartialEq, ::prost::Message)]
     pub struct ChallengeDecline {
+        /// Variants the declining peer plays, listed when it declined because the challenge's
+        /// variant is not one of them.
+        #[prost(string, repeated, tag="1")]
+        pub supported_variants: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    }
+    /// Milliseconds left on a player's clock, wrapped for a clock showing 0 to be told apart from
+    /// no clock at all.
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct ClockReading {
+        #[prost(uint64, tag="1")]
+        pub ms: u64,
+    }
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct MatchMove {
+        #[prost(bytes="vec", tag="1")]
+        pub match_id: ::prost::alloc::vec::Vec<u8>,
+        /// Number of half moves played before this one.
+        #[prost(uint32, tag="2")]
+        pub ply: u32,
+        /// Square indices, 0 for a1 and 63 for h8.
+        #[prost(uint32, tag="3")]
+        pub from: u32,
+        #[prost(uint32, tag="4")]
+        pub to: u32,
+        /// 0 without promotion, otherwise 1 for a knight, 2 bishop, 3 rook and 4 queen.
+        #[prost(uint32, tag="5")]
+        pub promotion: u32,
+        /// Time left on the mover's clock after the move in a live match, unset in a
+        /// correspondence match.
+        #[prost(message, optional, tag="6")]
+        pub clock: ::core::option::Option<ClockReading>,
+        /// Unix time in seconds the move was played at, set in the move records of
+        /// correspondence matches and 0 in messages.
+        #[prost(uint64, tag="7")]
+        pub played_at_secs: u64,
+    }
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct MatchDrawClaim {
+        #[prost(bytes="vec", tag="1")]
+        pub match_id: ::prost::alloc::vec::Vec<u8>,
+        /// Number of half moves played when the draw is claimed.
+        #[prost(uint32, tag="2")]
+        pub ply: u32,
+    }
+    /// Move record of a correspondence match, sent to the match's store peer to keep.
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct MoveRecordStore {
+        /// Encoded SignedMatchMove.
+        #[prost(bytes="vec", tag="1")]
+        pub record: ::prost::alloc::vec::Vec<u8>,
+    }
+    /// Request to a store peer for the record of the move played at a ply.
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct MoveRecordFetch {
+        #[prost(bytes="vec", tag="1")]
+        pub match_id: ::prost::alloc::vec::Vec<u8>,
+        #[prost(uint32, tag="2")]
+        pub ply: u32,
+        /// Peer id of the player who made the move.
+        #[prost(bytes="vec", tag="3")]
+        pub signer: ::prost::alloc::vec::Vec<u8>,
+    }
+    /// Move record kept by a store peer, sent back to the peer which fetched it.
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct MoveRecordFound {
+        /// Encoded SignedMatchMove.
+        #[prost(bytes="vec", tag="1")]
+        pub record: ::prost::alloc::vec::Vec<u8>,
+    }
+    /// Chat message to the opponent of a match, in progress or finished.
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct MatchChat {
+        #[prost(bytes="vec", tag="1")]
+        pub match_id: ::prost::alloc::vec::Vec<u8>,
+        #[prost(string, tag="2")]
+        pub text: ::prost::alloc::string::String,
     }
     #[derive(Clone, PartialEq, ::prost::Oneof)]
     pub enum Payload {
@@ -38,5 +137,87 @@ pub mod message {
         ChallengeCancel(ChallengeCancel),
         #[prost(message, tag="5")]
         ChallengeDecline(ChallengeDecline),
+        #[prost(message, tag="6")]
+        MatchMove(MatchMove),
+        #[prost(message, tag="7")]
+        MatchDrawClaim(MatchDrawClaim),
+        #[prost(message, tag="8")]
+        MoveRecordStore(MoveRecordStore),
+        #[prost(message, tag="9")]
+        MoveRecordFetch(MoveRecordFetch),
+        #[prost(message, tag="10")]
+        MoveRecordFound(MoveRecordFound),
+        #[prost(message, tag="11")]
+        MatchChat(MatchChat),
     }
 }
+/// Move of a correspondence match stored in the DHT, keyed by match id and ply, or by the match's
+/// store peer, for the opponent to fetch when it was not reachable.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SignedMatchMove {
+    /// Encoded Message.MatchMove.
+    #[prost(bytes="vec", tag="1")]
+    pub match_move: ::prost::alloc::vec::Vec<u8>,
+    /// Protobuf encoded public key of the player who made the move.
+    #[prost(bytes="vec", tag="2")]
+    pub public_key: ::prost::alloc::vec::Vec<u8>,
+    /// Signature of match_move by the player who made the move.
+    #[prost(bytes="vec", tag="3")]
+    pub signature: ::prost::alloc::vec::Vec<u8>,
+}
+/// Record of a finished match kept by one of its players.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MatchTranscript {
+    #[prost(bytes="vec", tag="1")]
+    pub match_id: ::prost::alloc::vec::Vec<u8>,
+    /// Peer ids of the players.
+    #[prost(bytes="vec", tag="2")]
+    pub white: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes="vec", tag="3")]
+    pub black: ::prost::alloc::vec::Vec<u8>,
+    #[prost(string, tag="4")]
+    pub variant: ::prost::alloc::string::String,
+    /// Position the match started from in Forsyth-Edwards Notation.
+    #[prost(string, tag="5")]
+    pub initial_fen: ::prost::alloc::string::String,
+    /// Time control, as sent in the challenge of the match.
+    #[prost(uint32, tag="6")]
+    pub days_per_move: u32,
+    #[prost(uint32, tag="7")]
+    pub initial_time_secs: u32,
+    #[prost(uint32, tag="8")]
+    pub increment_secs: u32,
+    /// Moves played in UCI notation.
+    #[prost(string, repeated, tag="9")]
+    pub moves: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// "white" or "black", empty for a draw.
+    #[prost(string, tag="10")]
+    pub winner: ::prost::alloc::string::String,
+    /// Why the match ended, like "checkmate" or "timeout".
+    #[prost(string, tag="11")]
+    pub reason: ::prost::alloc::string::String,
+    /// Milliseconds left on each player's clock at the end of a live match.
+    #[prost(uint64, tag="12")]
+    pub white_clock_ms: u64,
+    #[prost(uint64, tag="13")]
+    pub black_clock_ms: u64,
+    /// Id of the match this one is a rematch of, empty if it started from a new challenge.
+    #[prost(bytes="vec", tag="14")]
+    pub previous_match_id: ::prost::alloc::vec::Vec<u8>,
+    /// Peer id of the node which relayed the moves of a correspondence match, empty if they went
+    /// through the DHT.
+    #[prost(bytes="vec", tag="15")]
+    pub store_peer: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SignedMatchTranscript {
+    /// Encoded MatchTranscript.
+    #[prost(bytes="vec", tag="1")]
+    pub transcript: ::prost::alloc::vec::Vec<u8>,
+    /// Protobuf encoded public key of the player who recorded the transcript.
+    #[prost(bytes="vec", tag="2")]
+    pub public_key: ::prost::alloc::vec::Vec<u8>,
+    /// Signature of transcript by the player who recorded it.
+    #[prost(bytes="vec", tag="3")]
+    pub signature: ::prost::alloc::vec::Vec<u8>,
+}
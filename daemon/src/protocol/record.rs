@@ -0,0 +1,513 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libp2p::{
+    identity::{error::SigningError, Keypair, PublicKey},
+    kad::record::Key,
+    PeerId,
+};
+use prost::Message;
+use thiserror::Error;
+
+use super::{
+    handler::{
+        decode_match_move, decode_store_peer, decode_time_control, encode_match_move,
+        encode_time_control,
+    },
+    ipchessproto, IpchessHandlerError,
+};
+use crate::{
+    chess::{Color, Move},
+    game::{ClockTimes, DrawReason, Match, MatchId, MatchResult, TimeControl, Variant, WinReason},
+};
+
+#[derive(Error, Debug)]
+pub enum MoveRecordError {
+    #[error("failed signing move record, reason: `{0}`")]
+    Signing(SigningError),
+    #[error("failed decoding move record, reason: `{0}`")]
+    Decode(prost::DecodeError),
+    #[error("invalid move in move record, reason: `{0}`")]
+    InvalidMove(IpchessHandlerError),
+    #[error("move record is not signed by the expected player")]
+    WrongSigner,
+    #[error("invalid move record signature")]
+    InvalidSignature,
+    #[error("move record is for another match or ply")]
+    WrongMove,
+}
+
+#[derive(Error, Debug)]
+pub enum TranscriptError {
+    #[error("failed signing match transcript, reason: `{0}`")]
+    Signing(SigningError),
+    #[error("failed decoding match transcript, reason: `{0}`")]
+    Decode(prost::DecodeError),
+    #[error("invalid field `{0}` in match transcript")]
+    InvalidField(&'static str),
+    #[error("match transcript is not signed by the expected player")]
+    WrongSigner,
+    #[error("invalid match transcript signature")]
+    InvalidSignature,
+    #[error("match transcript is for another match")]
+    WrongMatch,
+}
+
+/// Finished match as recorded by one of its players.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchTranscript {
+    pub match_id: MatchId,
+    pub white: PeerId,
+    pub black: PeerId,
+    pub variant: Variant,
+    /// Position the match started from in Forsyth-Edwards Notation.
+    pub initial_fen: String,
+    pub time_control: TimeControl,
+    pub moves: Vec<Move>,
+    pub result: MatchResult,
+    /// Time left to each player when a live match ended.
+    pub clock: Option<ClockTimes>,
+    /// Match this one is a rematch of.
+    pub previous_match: Option<MatchId>,
+}
+
+impl MatchTranscript {
+    /// Transcript of a match played by `local_peer_id`, with the time its clock showed at the
+    /// end of a live match. `None` while the match is in progress.
+    pub fn of(game: &Match, local_peer_id: PeerId, clock: Option<ClockTimes>) -> Option<Self> {
+        let (white, black) = match game.color() {
+            Color::White => (local_peer_id, game.opponent()),
+            Color::Black => (game.opponent(), local_peer_id),
+        };
+
+        Some(MatchTranscript {
+            match_id: game.id(),
+            white,
+            black,
+            variant: game.variant(),
+            initial_fen: game.initial_position().fen(),
+            time_control: game.time_control(),
+            moves: game.moves().to_vec(),
+            result: game.result()?,
+            clock,
+            previous_match: game.previous_match(),
+        })
+    }
+}
+
+/// DHT key of the transcript of a finished match recorded by `signer`, one of its players.
+pub fn transcript_record_key(match_id: MatchId, signer: &PeerId) -> Key {
+    Key::new(&format!("/ipchess/transcript/{}/{}", match_id, signer).into_bytes())
+}
+
+/// Encodes a match transcript signed by the player who recorded it, to be stored as the value
+/// of its DHT record.
+pub fn sign_match_transcript(
+    keypair: &Keypair,
+    transcript: &MatchTranscript,
+) -> Result<Vec<u8>, TranscriptError> {
+    let (days_per_move, initial_time_secs, increment_secs) =
+        encode_time_control(transcript.time_control);
+    let (winner, reason) = encode_result(transcript.result);
+    let clock_ms = |color| {
+        transcript
+            .clock
+            .map_or(0, |clock: ClockTimes| clock.get(color).as_millis() as u64)
+    };
+
+    let mut encoded = Vec::new();
+    ipchessproto::MatchTranscript {
+        match_id: transcript.match_id.as_bytes().to_vec(),
+        white: transcript.white.to_bytes(),
+        black: transcript.black.to_bytes(),
+        variant: transcript.variant.to_string(),
+        initial_fen: transcript.initial_fen.clone(),
+        days_per_move,
+        initial_time_secs,
+        increment_secs,
+        moves: transcript.moves.iter().map(Move::to_string).collect(),
+        winner: winner.to_string(),
+        reason: reason.to_string(),
+        white_clock_ms: clock_ms(Color::White),
+        black_clock_ms: clock_ms(Color::Black),
+        previous_match_id: transcript
+            .previous_match
+            .map_or_else(Vec::new, |previous| previous.as_bytes().to_vec()),
+        store_peer: transcript
+            .time_control
+            .store_peer()
+            .map_or_else(Vec::new, |store_peer| store_peer.to_bytes()),
+    }
+    .encode(&mut encoded)
+    .expect("Vec<u8> provides capacity as needed");
+
+    let signature = keypair.sign(&encoded).map_err(TranscriptError::Signing)?;
+
+    let mut value = Vec::new();
+    ipchessproto::SignedMatchTranscript {
+        transcript: encoded,
+        public_key: keypair.public().into_protobuf_encoding(),
+        signature,
+    }
+    .encode(&mut value)
+    .expect("Vec<u8> provides capacity as needed");
+
+    Ok(value)
+}
+
+/// Decodes the transcript of a finished match from the value of its DHT record, checking it was
+/// signed by `signer`.
+pub fn verify_match_transcript(
+    value: &[u8],
+    signer: &PeerId,
+    match_id: MatchId,
+) -> Result<MatchTranscript, TranscriptError> {
+    let signed =
+        ipchessproto::SignedMatchTranscript::decode(value).map_err(TranscriptError::Decode)?;
+
+    let public_key = PublicKey::from_protobuf_encoding(&signed.public_key)
+        .map_err(|_| TranscriptError::WrongSigner)?;
+    if PeerId::from(public_key.clone()) != *signer {
+        return Err(TranscriptError::WrongSigner);
+    }
+
+    if !public_key.verify(&signed.transcript, &signed.signature) {
+        return Err(TranscriptError::InvalidSignature);
+    }
+
+    let transcript = ipchessproto::MatchTranscript::decode(signed.transcript.as_slice())
+        .map_err(TranscriptError::Decode)?;
+
+    let record_match_id = MatchId::from_bytes(&transcript.match_id)
+        .ok_or(TranscriptError::InvalidField("match_id"))?;
+    if record_match_id != match_id {
+        return Err(TranscriptError::WrongMatch);
+    }
+
+    let peer_id = |bytes: Vec<u8>, field| {
+        PeerId::from_bytes(&bytes).map_err(|_| TranscriptError::InvalidField(field))
+    };
+    let clock = if transcript.days_per_move == 0 {
+        Some(ClockTimes {
+            white: Duration::from_millis(transcript.white_clock_ms),
+            black: Duration::from_millis(transcript.black_clock_ms),
+        })
+    } else {
+        None
+    };
+    let previous_match = if transcript.previous_match_id.is_empty() {
+        None
+    } else {
+        Some(
+            MatchId::from_bytes(&transcript.previous_match_id)
+                .ok_or(TranscriptError::InvalidField("previous_match_id"))?,
+        )
+    };
+
+    Ok(MatchTranscript {
+        match_id,
+        white: peer_id(transcript.white, "white")?,
+        black: peer_id(transcript.black, "black")?,
+        variant: transcript
+            .variant
+            .parse()
+            .map_err(|_| TranscriptError::InvalidField("variant"))?,
+        initial_fen: transcript.initial_fen,
+        time_control: decode_time_control(
+            transcript.days_per_move,
+            transcript.initial_time_secs,
+            transcript.increment_secs,
+            decode_store_peer(&transcript.store_peer)
+                .map_err(|_| TranscriptError::InvalidField("store_peer"))?,
+        ),
+        moves: transcript
+            .moves
+            .iter()
+            .map(|mv| mv.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| TranscriptError::InvalidField("moves"))?,
+        result: decode_result(&transcript.winner, &transcript.reason)
+            .ok_or(TranscriptError::InvalidField("reason"))?,
+        clock,
+        previous_match,
+    })
+}
+
+/// Names of the reasons a match ended for, as written in transcripts.
+const WIN_REASONS: [(WinReason, &str); 5] = [
+    (WinReason::Checkmate, "checkmate"),
+    (WinReason::Timeout, "timeout"),
+    (WinReason::KingOfTheHill, "king_of_the_hill"),
+    (WinReason::ThreeChecks, "three_checks"),
+    (WinReason::KingExploded, "king_exploded"),
+];
+const DRAW_REASONS: [(DrawReason, &str); 7] = [
+    (DrawReason::Stalemate, "stalemate"),
+    (DrawReason::InsufficientMaterial, "insufficient_material"),
+    (
+        DrawReason::TimeoutVsInsufficientMaterial,
+        "timeout_vs_insufficient_material",
+    ),
+    (DrawReason::ThreefoldRepetition, "threefold_repetition"),
+    (DrawReason::FivefoldRepetition, "fivefold_repetition"),
+    (DrawReason::FiftyMoveRule, "fifty_move_rule"),
+    (DrawReason::SeventyFiveMoveRule, "seventy_five_move_rule"),
+];
+
+/// Encodes a match result into the winner and reason fields of a transcript.
+pub fn encode_result(result: MatchResult) -> (&'static str, &'static str) {
+    match result {
+        MatchResult::Win {
+            winner: Color::White,
+            reason,
+        } => ("white", name(&WIN_REASONS, reason)),
+        MatchResult::Win {
+            winner: Color::Black,
+            reason,
+        } => ("black", name(&WIN_REASONS, reason)),
+        MatchResult::Draw { reason } => ("", name(&DRAW_REASONS, reason)),
+    }
+}
+
+/// Decodes a match result from the winner and reason fields of a transcript, `None` if they do
+/// not name one.
+pub fn decode_result(winner: &str, reason: &str) -> Option<MatchResult> {
+    let winner = match winner {
+        "white" => Color::White,
+        "black" => Color::Black,
+        "" => {
+            return find(&DRAW_REASONS, reason).map(|reason| MatchResult::Draw { reason });
+        }
+        _ => return None,
+    };
+
+    find(&WIN_REASONS, reason).map(|reason| MatchResult::Win { winner, reason })
+}
+
+/// Name of `reason` in a table of reason names, empty if it has none.
+fn name<T: PartialEq>(names: &[(T, &'static str)], reason: T) -> &'static str {
+    names
+        .iter()
+        .find(|(known, _)| *known == reason)
+        .map_or("", |(_, name)| *name)
+}
+
+/// Reason named `name` in a table of reason names.
+fn find<T: Copy>(names: &[(T, &str)], name: &str) -> Option<T> {
+    names
+        .iter()
+        .find(|(_, known)| *known == name)
+        .map(|(reason, _)| *reason)
+}
+
+/// DHT key of the move played at `ply` in a correspondence match.
+pub fn move_record_key(match_id: MatchId, ply: u32) -> Key {
+    Key::new(&format!("/ipchess/move/{}/{}", match_id, ply).into_bytes())
+}
+
+/// Encodes a move of a correspondence match played at `played_at`, signed by the player who
+/// made it, to be stored as the value of the move's DHT record.
+pub fn sign_move_record(
+    keypair: &Keypair,
+    match_id: MatchId,
+    ply: u32,
+    mv: Move,
+    played_at: SystemTime,
+) -> Result<Vec<u8>, MoveRecordError> {
+    let mut msg = encode_match_move(match_id, ply, mv);
+    msg.played_at_secs = played_at
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+
+    let mut match_move = Vec::new();
+    msg.encode(&mut match_move)
+        .expect("Vec<u8> provides capacity as needed");
+
+    let signature = keypair
+        .sign(&match_move)
+        .map_err(MoveRecordError::Signing)?;
+
+    let mut value = Vec::new();
+    ipchessproto::SignedMatchMove {
+        match_move,
+        public_key: keypair.public().into_protobuf_encoding(),
+        signature,
+    }
+    .encode(&mut value)
+    .expect("Vec<u8> provides capacity as needed");
+
+    Ok(value)
+}
+
+/// Move of a correspondence match as signed in its record.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveRecord {
+    /// Player who made the move.
+    pub signer: PeerId,
+    pub match_id: MatchId,
+    pub ply: u32,
+    pub mv: Move,
+    pub played_at: SystemTime,
+}
+
+/// Decodes a move record whatever match it is for, checking it is signed by the public key it
+/// carries.
+pub fn decode_move_record(value: &[u8]) -> Result<MoveRecord, MoveRecordError> {
+    let signed = ipchessproto::SignedMatchMove::decode(value).map_err(MoveRecordError::Decode)?;
+
+    let public_key = PublicKey::from_protobuf_encoding(&signed.public_key)
+        .map_err(|_| MoveRecordError::WrongSigner)?;
+    if !public_key.verify(&signed.match_move, &signed.signature) {
+        return Err(MoveRecordError::InvalidSignature);
+    }
+
+    let match_move = ipchessproto::message::MatchMove::decode(signed.match_move.as_slice())
+        .map_err(MoveRecordError::Decode)?;
+    let (match_id, ply, mv) =
+        decode_match_move(&match_move).map_err(MoveRecordError::InvalidMove)?;
+
+    if match_move.played_at_secs == 0 {
+        return Err(MoveRecordError::InvalidMove(
+            IpchessHandlerError::InvalidField("played_at_secs"),
+        ));
+    }
+
+    Ok(MoveRecord {
+        signer: PeerId::from(public_key),
+        match_id,
+        ply,
+        mv,
+        played_at: UNIX_EPOCH + Duration::from_secs(match_move.played_at_secs),
+    })
+}
+
+/// Decodes the move played at `ply` in a correspondence match and the time it was played at
+/// from the value of its DHT record, checking it was signed by `signer`.
+pub fn verify_move_record(
+    value: &[u8],
+    signer: &PeerId,
+    match_id: MatchId,
+    ply: u32,
+) -> Result<(Move, SystemTime), MoveRecordError> {
+    let record = decode_move_record(value)?;
+
+    if record.signer != *signer {
+        return Err(MoveRecordError::WrongSigner);
+    }
+    if record.match_id != match_id || record.ply != ply {
+        return Err(MoveRecordError::WrongMove);
+    }
+
+    Ok((record.mv, record.played_at))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use libp2p::{identity::Keypair, PeerId};
+    use prost::Message;
+
+    use super::{
+        ipchessproto, sign_match_transcript, sign_move_record, verify_match_transcript,
+        verify_move_record, MatchTranscript, MoveRecordError, TranscriptError,
+    };
+    use crate::{
+        chess::{Color, Move, Position, Square},
+        game::{ClockTimes, Match, MatchId, TimeControl, Variant},
+    };
+
+    fn e2e4() -> Move {
+        Move {
+            from: "e2".parse::<Square>().unwrap(),
+            to: "e4".parse::<Square>().unwrap(),
+            promotion: None,
+        }
+    }
+
+    #[test]
+    fn signed_move_is_verified() {
+        let keypair = Keypair::generate_ed25519();
+        let signer = PeerId::from(keypair.public());
+        let match_id = MatchId::from_bytes(&[3; 32]).unwrap();
+        let played_at = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+
+        let value = sign_move_record(&keypair, match_id, 0, e2e4(), played_at).unwrap();
+        assert_eq!(
+            verify_move_record(&value, &signer, match_id, 0).unwrap(),
+            (e2e4(), played_at)
+        );
+
+        assert!(matches!(
+            verify_move_record(&value, &PeerId::random(), match_id, 0),
+            Err(MoveRecordError::WrongSigner)
+        ));
+        assert!(matches!(
+            verify_move_record(&value, &signer, match_id, 2),
+            Err(MoveRecordError::WrongMove)
+        ));
+    }
+
+    #[test]
+    fn tampered_move_is_rejected() {
+        let keypair = Keypair::generate_ed25519();
+        let signer = PeerId::from(keypair.public());
+        let match_id = MatchId::from_bytes(&[3; 32]).unwrap();
+        let played_at = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+
+        let value = sign_move_record(&keypair, match_id, 0, e2e4(), played_at).unwrap();
+        let mut signed = ipchessproto::SignedMatchMove::decode(value.as_slice()).unwrap();
+        // the last byte of the encoded move is part of its timestamp
+        *signed.match_move.last_mut().unwrap() ^= 1;
+
+        let mut value = Vec::new();
+        signed.encode(&mut value).unwrap();
+
+        assert!(matches!(
+            verify_move_record(&value, &signer, match_id, 0),
+            Err(MoveRecordError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn signed_transcript_is_verified() {
+        let keypair = Keypair::generate_ed25519();
+        let signer = PeerId::from(keypair.public());
+        let opponent = PeerId::random();
+        let match_id = MatchId::from_bytes(&[3; 32]).unwrap();
+
+        let mut game = Match::new(
+            match_id,
+            opponent,
+            Color::Black,
+            Variant::Standard,
+            TimeControl::default(),
+            None,
+            Position::startpos(),
+        );
+        game.play(Color::White, e2e4()).unwrap();
+        assert!(MatchTranscript::of(&game, signer, None).is_none());
+
+        game.time_out(Color::Black).unwrap();
+        let clock = ClockTimes {
+            white: Duration::from_millis(1500),
+            black: Duration::from_millis(0),
+        };
+        let transcript = MatchTranscript::of(&game, signer, Some(clock)).unwrap();
+        assert_eq!((transcript.white, transcript.black), (opponent, signer));
+
+        let value = sign_match_transcript(&keypair, &transcript).unwrap();
+        assert_eq!(
+            verify_match_transcript(&value, &signer, match_id).unwrap(),
+            transcript
+        );
+
+        assert!(matches!(
+            verify_match_transcript(&value, &opponent, match_id),
+            Err(TranscriptError::WrongSigner)
+        ));
+        assert!(matches!(
+            verify_match_transcript(&value, &signer, MatchId::from_bytes(&[4; 32]).unwrap()),
+            Err(TranscriptError::WrongMatch)
+        ));
+    }
+}
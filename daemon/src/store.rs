@@ -0,0 +1,478 @@
+//! Files the daemon keeps in its data directory across restarts: the node's identity, which
+//! its peer id is derived from, its correspondence matches and the matches it finished, which
+//! rematches link back to.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use libp2p::identity::{ed25519, error::DecodingError, Keypair};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    chess::{Move, Position},
+    game::{ClockTimes, DrawReason, Match, MatchResult, TimeControl, Variant},
+    protocol::{decode_result, encode_result},
+    utils::{
+        write_private_file, SerializableColor, SerializableMatchId, SerializablePeerId,
+        SerializableVariant,
+    },
+};
+
+/// Name of the file in the daemon's data directory holding the node's keypair.
+pub const IDENTITY_FILE_NAME: &str = "identity.key";
+/// Name of the directory in the daemon's data directory holding its matches.
+pub const MATCHES_DIR_NAME: &str = "matches";
+
+/// Number of the standard starting position in Chess960.
+const STANDARD_CHESS960_INDEX: u16 = 518;
+
+#[derive(Error, Debug)]
+pub enum StoreError {
+    #[error("failed accessing store, reason: `{0}`")]
+    Io(io::Error),
+    #[error("invalid node identity, reason: `{0}`")]
+    Identity(DecodingError),
+    #[error("failed decoding stored match, reason: `{0}`")]
+    Decode(serde_json::Error),
+    #[error("invalid field `{0}` in stored match")]
+    InvalidField(&'static str),
+}
+
+/// Reads the node's keypair from `path`, generating one and writing it there if there is none
+/// yet, so the node keeps its peer id across restarts.
+pub fn load_or_generate_keypair(path: &Path) -> Result<Keypair, StoreError> {
+    match fs::read(path) {
+        Ok(mut bytes) => ed25519::Keypair::decode(&mut bytes)
+            .map(Keypair::Ed25519)
+            .map_err(StoreError::Identity),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let keypair = ed25519::Keypair::generate();
+            write_private_file(path, &keypair.encode()).map_err(StoreError::Io)?;
+
+            Ok(Keypair::Ed25519(keypair))
+        }
+        Err(err) => Err(StoreError::Io(err)),
+    }
+}
+
+/// Match read back from the store.
+#[derive(Debug)]
+pub struct RestoredMatch {
+    pub game: Match,
+    /// Time by which the player to move must have moved, for correspondence matches in
+    /// progress.
+    pub move_deadline: Option<SystemTime>,
+    /// Time left to each player when a live match ended.
+    pub clock: Option<ClockTimes>,
+}
+
+/// Directory holding a file per match, replaced as the match goes on.
+pub struct MatchStore {
+    dir: PathBuf,
+}
+
+impl MatchStore {
+    /// Opens the store kept in `dir`, creating the directory if needed.
+    pub fn open(dir: PathBuf) -> Result<Self, StoreError> {
+        fs::create_dir_all(&dir).map_err(StoreError::Io)?;
+
+        Ok(Self { dir })
+    }
+
+    /// Writes the current state of a match, along with the time by which the player to move
+    /// must have moved in a correspondence match or the time its clock showed at the end of a
+    /// live match.
+    pub fn save(
+        &self,
+        game: &Match,
+        move_deadline: Option<SystemTime>,
+        clock: Option<ClockTimes>,
+    ) -> Result<(), StoreError> {
+        let stored = StoredMatch::of(game, move_deadline, clock);
+        let contents = serde_json::to_vec_pretty(&stored).map_err(StoreError::Decode)?;
+
+        // written aside and renamed over the previous file, not to leave a truncated one if
+        // the daemon stops while writing
+        let path = self.dir.join(format!("{}.json", game.id()));
+        let partial_path = path.with_extension("json.partial");
+        write_private_file(&partial_path, &contents).map_err(StoreError::Io)?;
+        fs::rename(&partial_path, &path).map_err(StoreError::Io)
+    }
+
+    /// Reads back every stored match. Files which cannot be read are logged and skipped.
+    pub fn load(&self) -> Result<Vec<RestoredMatch>, StoreError> {
+        let mut restored = vec![];
+
+        for entry in fs::read_dir(&self.dir).map_err(StoreError::Io)? {
+            let path = entry.map_err(StoreError::Io)?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                continue;
+            }
+
+            match Self::load_file(&path) {
+                Ok(game) => restored.push(game),
+                Err(err) => log::warn!("Skipping stored match {}: {}", path.display(), err),
+            }
+        }
+
+        Ok(restored)
+    }
+
+    fn load_file(path: &Path) -> Result<RestoredMatch, StoreError> {
+        let contents = fs::read(path).map_err(StoreError::Io)?;
+        let stored: StoredMatch = serde_json::from_slice(&contents).map_err(StoreError::Decode)?;
+
+        stored.restore()
+    }
+}
+
+/// Match as written in its file: how it started and the moves played since, replayed when it
+/// is read back.
+#[derive(Deserialize, Serialize)]
+struct StoredMatch {
+    match_id: SerializableMatchId,
+    opponent: SerializablePeerId,
+    color: SerializableColor,
+    variant: SerializableVariant,
+    time_control: StoredTimeControl,
+    previous_match_id: Option<SerializableMatchId>,
+    /// Position the match started from in Forsyth-Edwards Notation.
+    initial_fen: String,
+    /// Moves played in UCI notation.
+    moves: Vec<String>,
+    result: Option<StoredResult>,
+    /// Unix time in seconds by which the player to move must have moved, for correspondence
+    /// matches in progress.
+    move_deadline_secs: Option<u64>,
+    /// Milliseconds left on each player's clock when a live match ended.
+    clock: Option<StoredClock>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StoredTimeControl {
+    Live {
+        initial_secs: u64,
+        increment_secs: u64,
+    },
+    Correspondence {
+        days_per_move: u32,
+        store_peer: Option<SerializablePeerId>,
+    },
+}
+
+#[derive(Deserialize, Serialize)]
+struct StoredClock {
+    white_ms: u64,
+    black_ms: u64,
+}
+
+/// Result of a finished match, named as in match transcripts.
+#[derive(Deserialize, Serialize)]
+struct StoredResult {
+    /// "white" or "black", empty for a draw.
+    winner: String,
+    reason: String,
+}
+
+impl StoredMatch {
+    fn of(game: &Match, move_deadline: Option<SystemTime>, clock: Option<ClockTimes>) -> Self {
+        let time_control = match game.time_control() {
+            TimeControl::Live { initial, increment } => StoredTimeControl::Live {
+                initial_secs: initial.as_secs(),
+                increment_secs: increment.as_secs(),
+            },
+            TimeControl::Correspondence {
+                days_per_move,
+                store_peer,
+            } => StoredTimeControl::Correspondence {
+                days_per_move,
+                store_peer: store_peer.map(SerializablePeerId),
+            },
+        };
+
+        StoredMatch {
+            match_id: SerializableMatchId(game.id()),
+            opponent: SerializablePeerId(game.opponent()),
+            color: SerializableColor(game.color()),
+            variant: SerializableVariant(game.variant()),
+            time_control,
+            previous_match_id: game.previous_match().map(SerializableMatchId),
+            initial_fen: game.initial_position().fen(),
+            moves: game.moves().iter().map(Move::to_string).collect(),
+            result: game.result().map(|result| {
+                let (winner, reason) = encode_result(result);
+                StoredResult {
+                    winner: winner.to_string(),
+                    reason: reason.to_string(),
+                }
+            }),
+            move_deadline_secs: move_deadline.map(|deadline| {
+                deadline
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()
+            }),
+            clock: clock.map(|clock| StoredClock {
+                white_ms: clock.white.as_millis() as u64,
+                black_ms: clock.black.as_millis() as u64,
+            }),
+        }
+    }
+
+    /// Replays the match from its initial position, ending it as it ended when it was stored.
+    fn restore(self) -> Result<RestoredMatch, StoreError> {
+        let variant = self.variant.0;
+        let mut position = Position::from_fen(&self.initial_fen)
+            .map_err(|_| StoreError::InvalidField("initial_fen"))?;
+        // the FEN of Chess960's standard setup does not tell it is a Chess960 position
+        if variant == Variant::Chess960 && !position.is_chess960() {
+            position = Position::chess960(STANDARD_CHESS960_INDEX)
+                .filter(|standard| standard.fen() == self.initial_fen)
+                .ok_or(StoreError::InvalidField("initial_fen"))?;
+        }
+
+        let time_control = match self.time_control {
+            StoredTimeControl::Live {
+                initial_secs,
+                increment_secs,
+            } => TimeControl::Live {
+                initial: Duration::from_secs(initial_secs),
+                increment: Duration::from_secs(increment_secs),
+            },
+            StoredTimeControl::Correspondence {
+                days_per_move,
+                store_peer,
+            } => TimeControl::Correspondence {
+                days_per_move,
+                store_peer: store_peer.map(|store_peer| store_peer.0),
+            },
+        };
+
+        let mut game = Match::new(
+            self.match_id.0,
+            self.opponent.0,
+            self.color.0,
+            variant,
+            time_control,
+            self.previous_match_id.map(|previous| previous.0),
+            position,
+        );
+
+        for mv in &self.moves {
+            let mv = mv
+                .parse::<Move>()
+                .map_err(|_| StoreError::InvalidField("moves"))?;
+            let turn = game.position().turn();
+            game.play(turn, mv)
+                .map_err(|_| StoreError::InvalidField("moves"))?;
+        }
+
+        if let Some(stored) = &self.result {
+            let result = decode_result(&stored.winner, &stored.reason)
+                .ok_or(StoreError::InvalidField("result"))?;
+
+            // results the moves do not lead to come from a claimed draw or a timeout
+            if game.result().is_none() {
+                let turn = game.position().turn();
+                match result {
+                    MatchResult::Draw { reason }
+                        if reason == DrawReason::ThreefoldRepetition
+                            || reason == DrawReason::FiftyMoveRule =>
+                    {
+                        let _ = game.claim_draw(turn);
+                    }
+                    _ => {
+                        let _ = game.time_out(turn);
+                    }
+                }
+            }
+
+            if game.result() != Some(result) {
+                return Err(StoreError::InvalidField("result"));
+            }
+        }
+
+        Ok(RestoredMatch {
+            game,
+            move_deadline: self
+                .move_deadline_secs
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+            clock: self.clock.map(|clock| ClockTimes {
+                white: Duration::from_millis(clock.white_ms),
+                black: Duration::from_millis(clock.black_ms),
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::PathBuf,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use libp2p::PeerId;
+
+    use super::{load_or_generate_keypair, MatchStore, IDENTITY_FILE_NAME};
+    use crate::{
+        chess::{Color, Position},
+        game::{
+            ClockTimes, DrawReason, Match, MatchId, MatchResult, TimeControl, Variant, WinReason,
+        },
+    };
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("ipchess-store-test-{}", rand::random::<u64>()))
+    }
+
+    fn correspondence_match(variant: Variant, position: Position) -> Match {
+        Match::new(
+            MatchId::from_bytes(&[7; 32]).unwrap(),
+            PeerId::random(),
+            Color::Black,
+            variant,
+            TimeControl::Correspondence {
+                days_per_move: 3,
+                store_peer: Some(PeerId::random()),
+            },
+            Some(MatchId::from_bytes(&[6; 32]).unwrap()),
+            position,
+        )
+    }
+
+    #[test]
+    fn keypair_is_kept_across_loads() {
+        let dir = temp_dir();
+        let path = dir.join(IDENTITY_FILE_NAME);
+
+        let generated = load_or_generate_keypair(&path).unwrap();
+        let loaded = load_or_generate_keypair(&path).unwrap();
+        assert_eq!(
+            PeerId::from(generated.public()),
+            PeerId::from(loaded.public())
+        );
+
+        fs::write(&path, b"not a keypair").unwrap();
+        assert!(load_or_generate_keypair(&path).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn matches_are_replayed_when_loaded() {
+        let dir = temp_dir();
+        let store = MatchStore::open(dir.clone()).unwrap();
+
+        let mut game = correspondence_match(Variant::Standard, Position::startpos());
+        for mv in &["e2e4", "e7e5", "d1h5"] {
+            let turn = game.position().turn();
+            game.play(turn, mv.parse().unwrap()).unwrap();
+        }
+        let deadline = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        store.save(&game, Some(deadline), None).unwrap();
+
+        let restored = store.load().unwrap();
+        assert_eq!(restored.len(), 1);
+        let restored_game = &restored[0].game;
+        assert_eq!(restored_game.id(), game.id());
+        assert_eq!(restored_game.opponent(), game.opponent());
+        assert_eq!(restored_game.color(), Color::Black);
+        assert_eq!(restored_game.time_control(), game.time_control());
+        assert_eq!(restored_game.previous_match(), game.previous_match());
+        assert_eq!(restored_game.moves(), game.moves());
+        assert_eq!(restored_game.position().fen(), game.position().fen());
+        assert_eq!(restored[0].move_deadline, Some(deadline));
+
+        // saving again replaces the match's file, timeouts are replayed
+        let turn = game.position().turn();
+        game.time_out(turn).unwrap();
+        store.save(&game, None, None).unwrap();
+
+        let restored = store.load().unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(
+            restored[0].game.result(),
+            Some(MatchResult::Win {
+                winner: Color::White,
+                reason: WinReason::Timeout,
+            })
+        );
+        assert_eq!(restored[0].move_deadline, None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn finished_live_matches_keep_their_clock() {
+        let dir = temp_dir();
+        let store = MatchStore::open(dir.clone()).unwrap();
+
+        let mut game = Match::new(
+            MatchId::from_bytes(&[8; 32]).unwrap(),
+            PeerId::random(),
+            Color::White,
+            Variant::Standard,
+            TimeControl::default(),
+            None,
+            Position::startpos(),
+        );
+        for _ in 0..2 {
+            for mv in &["g1f3", "g8f6", "f3g1", "f6g8"] {
+                let turn = game.position().turn();
+                game.play(turn, mv.parse().unwrap()).unwrap();
+            }
+        }
+        game.claim_draw(Color::White).unwrap();
+        let clock = ClockTimes {
+            white: Duration::from_millis(61_500),
+            black: Duration::from_secs(3),
+        };
+        store.save(&game, None, Some(clock)).unwrap();
+
+        let restored = store.load().unwrap();
+        assert_eq!(
+            restored[0].game.result(),
+            Some(MatchResult::Draw {
+                reason: DrawReason::ThreefoldRepetition,
+            })
+        );
+        assert_eq!(restored[0].clock, Some(clock));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn chess960_standard_setup_is_restored_as_chess960() {
+        let dir = temp_dir();
+        let store = MatchStore::open(dir.clone()).unwrap();
+
+        let game = correspondence_match(Variant::Chess960, Position::chess960(518).unwrap());
+        store.save(&game, Some(SystemTime::now()), None).unwrap();
+
+        let restored = store.load().unwrap();
+        assert!(restored[0].game.initial_position().is_chess960());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_matches_are_skipped() {
+        let dir = temp_dir();
+        let store = MatchStore::open(dir.clone()).unwrap();
+        fs::write(dir.join("broken.json"), b"{").unwrap();
+
+        let game = correspondence_match(Variant::Standard, Position::startpos());
+        store.save(&game, None, None).unwrap();
+
+        assert_eq!(store.load().unwrap().len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
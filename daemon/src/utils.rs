@@ -1,4 +1,51 @@
-use serde::Serialize;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Metadata, Schema, SchemaObject},
+    JsonSchema,
+};
+use serde::{de, Deserialize, Serialize};
+
+use crate::{
+    chess::Color,
+    game::{MatchId, Variant},
+    protocol::{ChallengeDirection, ChallengeState},
+};
+
+/// Default directory where the daemon keeps its files, e.g. the API cookie file.
+pub fn default_data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("ipchess"))
+}
+
+/// Writes `contents` to a file only readable by the current user, replacing any previous one
+/// and creating its parent directories.
+pub fn write_private_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // file permissions are only applied when the file is created
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    io::Write::write_all(&mut options.open(path)?, contents)
+}
 
 pub struct SerializablePeerId(pub libp2p::PeerId);
 
@@ -10,3 +57,280 @@ impl Serialize for SerializablePeerId {
         serializer.serialize_str(self.0.to_string().as_str())
     }
 }
+
+impl<'de> Deserialize<'de> for SerializablePeerId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+
+        libp2p::PeerId::from_str(&s)
+            .map(SerializablePeerId)
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(&s), &"a peer id"))
+    }
+}
+
+impl JsonSchema for SerializablePeerId {
+    fn schema_name() -> String {
+        "PeerId".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some("Base58 encoded libp2p peer id.".to_string()),
+                ..Default::default()
+            })),
+            instance_type: Some(InstanceType::String.into()),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+pub struct SerializableChallengeDirection(pub ChallengeDirection);
+
+impl SerializableChallengeDirection {
+    const VARIANTS: &'static [&'static str] = &["inbound", "outbound"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self.0 {
+            ChallengeDirection::Inbound => "inbound",
+            ChallengeDirection::Outbound => "outbound",
+        }
+    }
+}
+
+impl Serialize for SerializableChallengeDirection {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SerializableChallengeDirection {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        match String::deserialize(deserializer)?.as_str() {
+            "inbound" => Ok(SerializableChallengeDirection(ChallengeDirection::Inbound)),
+            "outbound" => Ok(SerializableChallengeDirection(ChallengeDirection::Outbound)),
+            other => Err(de::Error::unknown_variant(other, Self::VARIANTS)),
+        }
+    }
+}
+
+impl JsonSchema for SerializableChallengeDirection {
+    fn schema_name() -> String {
+        "ChallengeDirection".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        string_enum_schema(Self::VARIANTS)
+    }
+}
+
+pub struct SerializableChallengeState(pub ChallengeState);
+
+impl SerializableChallengeState {
+    const VARIANTS: &'static [&'static str] = &[
+        "looking_up_peer",
+        "pending_accept",
+        "received",
+        "pending_preimage",
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self.0 {
+            ChallengeState::LookingUpPeer => "looking_up_peer",
+            ChallengeState::PendingAccept => "pending_accept",
+            ChallengeState::Received => "received",
+            ChallengeState::PendingPreimage => "pending_preimage",
+        }
+    }
+}
+
+impl Serialize for SerializableChallengeState {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SerializableChallengeState {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        match String::deserialize(deserializer)?.as_str() {
+            "looking_up_peer" => Ok(SerializableChallengeState(ChallengeState::LookingUpPeer)),
+            "pending_accept" => Ok(SerializableChallengeState(ChallengeState::PendingAccept)),
+            "received" => Ok(SerializableChallengeState(ChallengeState::Received)),
+            "pending_preimage" => Ok(SerializableChallengeState(ChallengeState::PendingPreimage)),
+            other => Err(de::Error::unknown_variant(other, Self::VARIANTS)),
+        }
+    }
+}
+
+impl JsonSchema for SerializableChallengeState {
+    fn schema_name() -> String {
+        "ChallengeState".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        string_enum_schema(Self::VARIANTS)
+    }
+}
+
+pub struct SerializableMatchId(pub MatchId);
+
+impl Serialize for SerializableMatchId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.0.to_string().as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SerializableMatchId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+
+        MatchId::from_str(&s)
+            .map(SerializableMatchId)
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(&s), &"a match id"))
+    }
+}
+
+impl JsonSchema for SerializableMatchId {
+    fn schema_name() -> String {
+        "MatchId".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        described_string_schema("Hex encoded 32 bytes match id.")
+    }
+}
+
+pub struct SerializableColor(pub Color);
+
+impl SerializableColor {
+    const VARIANTS: &'static [&'static str] = &["white", "black"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self.0 {
+            Color::White => "white",
+            Color::Black => "black",
+        }
+    }
+}
+
+impl Serialize for SerializableColor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SerializableColor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        match String::deserialize(deserializer)?.as_str() {
+            "white" => Ok(SerializableColor(Color::White)),
+            "black" => Ok(SerializableColor(Color::Black)),
+            other => Err(de::Error::unknown_variant(other, Self::VARIANTS)),
+        }
+    }
+}
+
+impl JsonSchema for SerializableColor {
+    fn schema_name() -> String {
+        "Color".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        string_enum_schema(Self::VARIANTS)
+    }
+}
+
+pub struct SerializableVariant(pub Variant);
+
+impl SerializableVariant {
+    const VARIANTS: &'static [&'static str] = &[
+        "standard",
+        "chess960",
+        "kingofthehill",
+        "threecheck",
+        "atomic",
+    ];
+}
+
+impl Serialize for SerializableVariant {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SerializableVariant {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        match String::deserialize(deserializer)?.as_str() {
+            "standard" => Ok(SerializableVariant(Variant::Standard)),
+            "chess960" => Ok(SerializableVariant(Variant::Chess960)),
+            "kingofthehill" => Ok(SerializableVariant(Variant::KingOfTheHill)),
+            "threecheck" => Ok(SerializableVariant(Variant::ThreeCheck)),
+            "atomic" => Ok(SerializableVariant(Variant::Atomic)),
+            other => Err(de::Error::unknown_variant(other, Self::VARIANTS)),
+        }
+    }
+}
+
+impl JsonSchema for SerializableVariant {
+    fn schema_name() -> String {
+        "Variant".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        string_enum_schema(Self::VARIANTS)
+    }
+}
+
+fn described_string_schema(description: &str) -> Schema {
+    SchemaObject {
+        metadata: Some(Box::new(Metadata {
+            description: Some(description.to_string()),
+            ..Default::default()
+        })),
+        instance_type: Some(InstanceType::String.into()),
+        ..Default::default()
+    }
+    .into()
+}
+
+fn string_enum_schema(values: &[&str]) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        enum_values: Some(values.iter().map(|&value| value.into()).collect()),
+        ..Default::default()
+    }
+    .into()
+}
@@ -0,0 +1,214 @@
+//! Challenge negotiation between nodes, driven through the API.
+
+mod common;
+
+use ipchess::{
+    api::{error_code, ClientError, ServerEventNotification},
+    game::{TimeControl, Variant},
+    utils::{SerializablePeerId, SerializableVariant},
+};
+
+use common::{describe, TestNetwork};
+
+#[tokio::test]
+async fn accepted_challenge_reveals_commitment_to_both_peers() {
+    let mut network = TestNetwork::new(3).await;
+    // neither node is the bootstrap node, so the challenged peer is found through the DHT
+    let (challenger, challenged) = network.pair(1, 2);
+
+    challenger
+        .client
+        .challenge_peer(
+            challenged.peer_id,
+            Variant::Standard,
+            TimeControl::default(),
+        )
+        .await
+        .unwrap();
+
+    match challenged.next_event().await {
+        ServerEventNotification::PeerChallenge {
+            peer_id: SerializablePeerId(peer_id),
+            variant: SerializableVariant(Variant::Standard),
+            days_per_move: None,
+            time_control: Some(_),
+            store_peer: None,
+        } if peer_id == challenger.peer_id => {}
+        event => panic!("unexpected event {}", describe(&event)),
+    }
+
+    let challenges = challenged.client.list_challenges().await.unwrap();
+    assert_eq!(challenges.len(), 1);
+    assert_eq!(challenges[0].peer_id.0, challenger.peer_id);
+    assert_eq!(challenges[0].direction.as_str(), "inbound");
+    assert_eq!(challenges[0].state.as_str(), "received");
+    assert_eq!(challenges[0].variant.0, Variant::Standard);
+
+    challenged
+        .client
+        .accept_peer_challenge(challenger.peer_id)
+        .await
+        .unwrap();
+
+    // the challenger reveals its commitment's preimage as soon as it sees the acceptance, the
+    // challenged peer only considers the challenge accepted after checking it
+    match challenger.next_event().await {
+        ServerEventNotification::ChallengeAccepted {
+            peer_id: SerializablePeerId(peer_id),
+        } if peer_id == challenged.peer_id => {}
+        event => panic!("unexpected event {}", describe(&event)),
+    }
+
+    match challenged.next_event().await {
+        ServerEventNotification::ChallengeAccepted {
+            peer_id: SerializablePeerId(peer_id),
+        } if peer_id == challenger.peer_id => {}
+        event => panic!("unexpected event {}", describe(&event)),
+    }
+
+    assert!(challenger
+        .client
+        .list_challenges()
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(challenged
+        .client
+        .list_challenges()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn canceled_challenge_is_dropped_by_both_peers() {
+    let mut network = TestNetwork::new(2).await;
+    let (challenger, challenged) = network.pair(1, 0);
+
+    challenger
+        .client
+        .challenge_peer(
+            challenged.peer_id,
+            Variant::Standard,
+            TimeControl::default(),
+        )
+        .await
+        .unwrap();
+
+    match challenged.next_event().await {
+        ServerEventNotification::PeerChallenge { .. } => {}
+        event => panic!("unexpected event {}", describe(&event)),
+    }
+
+    challenger
+        .client
+        .cancel_challenge(challenged.peer_id)
+        .await
+        .unwrap();
+
+    match challenged.next_event().await {
+        ServerEventNotification::ChallengeCanceled {
+            peer_id: SerializablePeerId(peer_id),
+        } if peer_id == challenger.peer_id => {}
+        event => panic!("unexpected event {}", describe(&event)),
+    }
+
+    assert!(challenger
+        .client
+        .list_challenges()
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(challenged
+        .client
+        .list_challenges()
+        .await
+        .unwrap()
+        .is_empty());
+
+    match challenged
+        .client
+        .accept_peer_challenge(challenger.peer_id)
+        .await
+    {
+        Err(ClientError::Call { code, .. }) => assert_eq!(code, error_code::NO_SUCH_CHALLENGE),
+        res => panic!("accepting a canceled challenge returned {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn declined_challenge_is_reported_to_challenger() {
+    let mut network = TestNetwork::new(2).await;
+    let (challenger, challenged) = network.pair(1, 0);
+
+    challenger
+        .client
+        .challenge_peer(
+            challenged.peer_id,
+            Variant::Standard,
+            TimeControl::default(),
+        )
+        .await
+        .unwrap();
+
+    match challenged.next_event().await {
+        ServerEventNotification::PeerChallenge { .. } => {}
+        event => panic!("unexpected event {}", describe(&event)),
+    }
+
+    challenged
+        .client
+        .decline_peer_challenge(challenger.peer_id)
+        .await
+        .unwrap();
+
+    match challenger.next_event().await {
+        ServerEventNotification::ChallengeDeclined {
+            peer_id: SerializablePeerId(peer_id),
+            supported_variants: None,
+        } if peer_id == challenged.peer_id => {}
+        event => panic!("unexpected event {}", describe(&event)),
+    }
+
+    assert!(challenger
+        .client
+        .list_challenges()
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(challenged
+        .client
+        .list_challenges()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn duplicate_challenge_is_rejected() {
+    let mut network = TestNetwork::new(2).await;
+    let (challenger, challenged) = network.pair(1, 0);
+
+    challenger
+        .client
+        .challenge_peer(
+            challenged.peer_id,
+            Variant::Standard,
+            TimeControl::default(),
+        )
+        .await
+        .unwrap();
+
+    match challenger
+        .client
+        .challenge_peer(
+            challenged.peer_id,
+            Variant::Standard,
+            TimeControl::default(),
+        )
+        .await
+    {
+        Err(ClientError::Call { code, .. }) => assert_eq!(code, error_code::DUPLICATE_CHALLENGE),
+        res => panic!("second challenge returned {:?}", res),
+    }
+}
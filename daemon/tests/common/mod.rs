@@ -0,0 +1,187 @@
+//! In-memory network of ipchess nodes for integration tests.
+//!
+//! Nodes talk over libp2p's memory transport with identities derived from their index, and are
+//! driven through the same JSON-RPC API the daemon's clients use. The first node of a network is
+//! the DHT bootstrap node for all the others.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use futures::StreamExt;
+use ipchess::{
+    api::{self, Client, EventStream, ServerAddr, ServerEventNotification},
+    Node,
+};
+use libp2p::{
+    core::{
+        muxing::StreamMuxerBox,
+        transport::{Boxed, MemoryTransport},
+        upgrade,
+    },
+    identity::{self, Keypair},
+    mplex,
+    multiaddr::Protocol,
+    noise, Multiaddr, PeerId, Transport,
+};
+use tokio::sync::oneshot;
+
+/// Time allowed for an expected event to arrive before failing the test.
+const EVENT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Interval at which nodes are polled while waiting for them to join the network.
+const CONNECTED_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Memory transport ports are shared by every test in the process.
+static NEXT_MEMORY_PORT: AtomicU64 = AtomicU64::new(1);
+
+pub struct TestNode {
+    pub peer_id: PeerId,
+    pub client: Client,
+    events: EventStream,
+    // dropping the sender stops the node
+    _shutdown_tx: oneshot::Sender<()>,
+}
+
+impl TestNode {
+    async fn start(index: u8, bootstrap_peers: Vec<(PeerId, Multiaddr)>) -> (Self, Multiaddr) {
+        let keypair = identity(index);
+        let peer_id = PeerId::from(keypair.public());
+
+        let listen_addr = Multiaddr::from(Protocol::Memory(
+            NEXT_MEMORY_PORT.fetch_add(1, Ordering::Relaxed),
+        ));
+
+        let auth = api::ServerAuth::generate(vec![]);
+        let token = auth.token().to_string();
+
+        let node = Node::builder(api::ServerTransport::WebSocket { port: 0, auth })
+            .transport(memory_transport(&keypair))
+            .keypair(keypair)
+            .listen_on(listen_addr.clone())
+            .bootstrap_peers(bootstrap_peers)
+            .build()
+            .await
+            .expect("failed starting node");
+
+        let api_port = match node.api_addr() {
+            ServerAddr::WebSocket(addr) => addr.port(),
+            #[cfg(unix)]
+            ServerAddr::Unix(_) => unreachable!("node was started with a WebSocket API"),
+        };
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        tokio::spawn(node.run(async move {
+            let _ = shutdown_rx.await;
+        }));
+
+        let client = Client::connect_with_token(api_port, &token)
+            .await
+            .expect("failed connecting to node API");
+        let events = client
+            .subscribe_events(None)
+            .await
+            .expect("failed subscribing to node events");
+
+        let node = Self {
+            peer_id,
+            client,
+            events,
+            _shutdown_tx: shutdown_tx,
+        };
+
+        (node, listen_addr)
+    }
+
+    /// Waits for the node's next event notification.
+    pub async fn next_event(&mut self) -> ServerEventNotification {
+        let event = tokio::time::timeout(EVENT_TIMEOUT, self.events.next())
+            .await
+            .expect("timed out waiting for node event")
+            .expect("node closed the events subscription")
+            .expect("failed receiving node event");
+
+        event.notification
+    }
+}
+
+pub struct TestNetwork {
+    pub nodes: Vec<TestNode>,
+}
+
+impl TestNetwork {
+    /// Starts `size` nodes and waits until they have all joined the network.
+    pub async fn new(size: u8) -> Self {
+        assert!(size >= 2, "a network needs at least two nodes");
+
+        let (bootstrap_node, bootstrap_addr) = TestNode::start(0, vec![]).await;
+        let bootstrap_peers = vec![(bootstrap_node.peer_id, bootstrap_addr)];
+
+        let mut nodes = vec![bootstrap_node];
+        for index in 1..size {
+            let (node, _) = TestNode::start(index, bootstrap_peers.clone()).await;
+            nodes.push(node);
+        }
+
+        for node in nodes.iter() {
+            wait_connected(node).await;
+        }
+
+        Self { nodes }
+    }
+
+    /// Returns mutable references to two distinct nodes.
+    pub fn pair(&mut self, a: usize, b: usize) -> (&mut TestNode, &mut TestNode) {
+        assert!(a != b, "a node cannot be paired with itself");
+
+        if a < b {
+            let (left, right) = self.nodes.split_at_mut(b);
+            (&mut left[a], &mut right[0])
+        } else {
+            let (left, right) = self.nodes.split_at_mut(a);
+            (&mut right[0], &mut left[b])
+        }
+    }
+}
+
+/// Describes an event in assertion messages.
+pub fn describe(event: &ServerEventNotification) -> String {
+    serde_json::to_string(event).expect("failed serializing event")
+}
+
+/// Deterministic ed25519 identity of the node at `index`.
+fn identity(index: u8) -> Keypair {
+    let secret = identity::ed25519::SecretKey::from_bytes([index + 1; 32])
+        .expect("32 bytes are a valid ed25519 secret key");
+
+    Keypair::Ed25519(secret.into())
+}
+
+fn memory_transport(keypair: &Keypair) -> Boxed<(PeerId, StreamMuxerBox)> {
+    let noise_keys = noise::Keypair::<noise::X25519Spec>::new()
+        .into_authentic(keypair)
+        .expect("failed signing noise static key");
+
+    MemoryTransport::default()
+        .upgrade(upgrade::Version::V1)
+        .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
+        .multiplex(mplex::MplexConfig::new())
+        .boxed()
+}
+
+async fn wait_connected(node: &TestNode) {
+    let connected = async {
+        while !node
+            .client
+            .is_connected()
+            .await
+            .expect("failed querying node")
+        {
+            tokio::time::sleep(CONNECTED_POLL_INTERVAL).await;
+        }
+    };
+
+    tokio::time::timeout(EVENT_TIMEOUT, connected)
+        .await
+        .expect("timed out waiting for node to join the network");
+}
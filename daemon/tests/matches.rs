@@ -0,0 +1,469 @@
+//! Matches played between nodes, driven through the API.
+
+mod common;
+
+use std::time::Duration;
+
+use ipchess::{
+    api::{error_code, ClientError, MatchEndReason, ServerEventNotification, TimeControlInfo},
+    chess::Color,
+    game::{MatchId, TimeControl, Variant},
+    utils::{SerializableColor, SerializableMatchId, SerializableVariant},
+};
+
+use common::{describe, TestNetwork, TestNode};
+
+/// Waits for a node's notification that a match started, returning its id and the node's color.
+async fn match_started(node: &mut TestNode) -> (MatchId, Color) {
+    loop {
+        match node.next_event().await {
+            ServerEventNotification::ChallengeAccepted { .. } => {}
+            ServerEventNotification::MatchStarted {
+                match_id: SerializableMatchId(match_id),
+                color: SerializableColor(color),
+                ..
+            } => return (match_id, color),
+            event => panic!("unexpected event {}", describe(&event)),
+        }
+    }
+}
+
+/// Has `challenger` challenge `challenged` to a match of `variant` played under `time_control`,
+/// and starts it once accepted, returning the match id and the challenger's color.
+async fn start_match(
+    challenger: &mut TestNode,
+    challenged: &mut TestNode,
+    variant: Variant,
+    time_control: TimeControl,
+) -> (MatchId, Color) {
+    challenger
+        .client
+        .challenge_peer(challenged.peer_id, variant, time_control)
+        .await
+        .unwrap();
+
+    match challenged.next_event().await {
+        ServerEventNotification::PeerChallenge {
+            variant: SerializableVariant(requested),
+            days_per_move: requested_days,
+            ..
+        } if requested == variant && requested_days == time_control.days_per_move() => {}
+        event => panic!("unexpected event {}", describe(&event)),
+    }
+
+    challenged
+        .client
+        .accept_peer_challenge(challenger.peer_id)
+        .await
+        .unwrap();
+
+    let (challenger_match_id, challenger_color) = match_started(challenger).await;
+    let (challenged_match_id, challenged_color) = match_started(challenged).await;
+
+    assert_eq!(challenger_match_id, challenged_match_id);
+    assert_eq!(challenger_color, !challenged_color);
+
+    (challenger_match_id, challenger_color)
+}
+
+/// Waits for a node's notification of a move, given in SAN, returning the draw the player to
+/// move may claim.
+async fn move_played(node: &mut TestNode, match_id: MatchId, san: &str) -> Option<MatchEndReason> {
+    match node.next_event().await {
+        ServerEventNotification::MovePlayed {
+            match_id: SerializableMatchId(id),
+            mv: played,
+            claimable_draw,
+            ..
+        } if id == match_id && played.san == san => claimable_draw,
+        event => panic!("unexpected event {}", describe(&event)),
+    }
+}
+
+/// Plays a move given in SAN and waits for both nodes to report it, returning the draw the
+/// opponent may then claim.
+async fn play(
+    mover: &mut TestNode,
+    opponent: &mut TestNode,
+    match_id: MatchId,
+    san: &str,
+) -> Option<MatchEndReason> {
+    mover.client.make_move(match_id, san).await.unwrap();
+
+    move_played(mover, match_id, san).await;
+    move_played(opponent, match_id, san).await
+}
+
+#[tokio::test]
+async fn threefold_repetition_is_claimed_by_player_to_move() {
+    let mut network = TestNetwork::new(2).await;
+    let (challenger, challenged) = network.pair(1, 0);
+
+    let (match_id, challenger_color) = start_match(
+        challenger,
+        challenged,
+        Variant::Standard,
+        TimeControl::default(),
+    )
+    .await;
+    let (white, black) = match challenger_color {
+        Color::White => (challenger, challenged),
+        Color::Black => (challenged, challenger),
+    };
+
+    match black.client.make_move(match_id, "e5").await {
+        Err(ClientError::Call { code, .. }) => assert_eq!(code, error_code::NOT_YOUR_TURN),
+        res => panic!("moving out of turn returned {:?}", res),
+    }
+
+    let mut claimable_draw = None;
+    for _ in 0..2 {
+        play(white, black, match_id, "Nf3").await;
+        play(black, white, match_id, "Nf6").await;
+        play(white, black, match_id, "Ng1").await;
+        claimable_draw = play(black, white, match_id, "Ng8").await;
+    }
+    assert_eq!(claimable_draw, Some(MatchEndReason::ThreefoldRepetition));
+
+    match black.client.claim_draw(match_id).await {
+        Err(ClientError::Call { code, .. }) => assert_eq!(code, error_code::NOT_YOUR_TURN),
+        res => panic!("claiming a draw out of turn returned {:?}", res),
+    }
+
+    let result = white.client.claim_draw(match_id).await.unwrap();
+    assert!(result.winner.is_none());
+    assert_eq!(result.reason, MatchEndReason::ThreefoldRepetition);
+
+    for node in [white, black].iter_mut() {
+        match node.next_event().await {
+            ServerEventNotification::MatchEnded {
+                match_id: SerializableMatchId(id),
+                result,
+            } if id == match_id => {
+                assert_eq!(result.reason, MatchEndReason::ThreefoldRepetition)
+            }
+            event => panic!("unexpected event {}", describe(&event)),
+        }
+
+        let matches = node.client.list_matches().await.unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(
+            matches[0].result.as_ref().map(|result| result.reason),
+            Some(MatchEndReason::ThreefoldRepetition)
+        );
+    }
+}
+
+#[tokio::test]
+async fn moves_are_reported_in_uci_and_san() {
+    let mut network = TestNetwork::new(2).await;
+    let (challenger, challenged) = network.pair(1, 0);
+
+    let (match_id, challenger_color) = start_match(
+        challenger,
+        challenged,
+        Variant::Standard,
+        TimeControl::default(),
+    )
+    .await;
+    let (white, black) = match challenger_color {
+        Color::White => (challenger, challenged),
+        Color::Black => (challenged, challenger),
+    };
+
+    white.client.make_move(match_id, "e2e4").await.unwrap();
+    for node in [&mut *white, &mut *black].iter_mut() {
+        match node.next_event().await {
+            ServerEventNotification::MovePlayed { mv, .. } => {
+                assert_eq!(mv.uci, "e2e4");
+                assert_eq!(mv.san, "e4");
+            }
+            event => panic!("unexpected event {}", describe(&event)),
+        }
+    }
+
+    for notation in ["e4", "Nf3", "e7e4", "nonsense"].iter() {
+        match black.client.make_move(match_id, notation).await {
+            Err(ClientError::Call { code, .. }) => assert_eq!(code, error_code::ILLEGAL_MOVE),
+            res => panic!("playing {} returned {:?}", notation, res),
+        }
+    }
+
+    play(black, white, match_id, "e5").await;
+    play(white, black, match_id, "Nf3").await;
+
+    let matches = white.client.list_matches().await.unwrap();
+    let moves: Vec<_> = matches[0]
+        .moves
+        .iter()
+        .map(|mv| (mv.uci.as_str(), mv.san.as_str()))
+        .collect();
+    assert_eq!(moves, [("e2e4", "e4"), ("e7e5", "e5"), ("g1f3", "Nf3")]);
+}
+
+#[tokio::test]
+async fn chess960_match_starts_from_the_same_shuffled_position() {
+    let mut network = TestNetwork::new(2).await;
+    let (challenger, challenged) = network.pair(1, 0);
+
+    let (match_id, _) = start_match(
+        challenger,
+        challenged,
+        Variant::Chess960,
+        TimeControl::default(),
+    )
+    .await;
+
+    let challenger_match = challenger.client.list_matches().await.unwrap().remove(0);
+    let challenged_match = challenged.client.list_matches().await.unwrap().remove(0);
+
+    assert_eq!(challenger_match.match_id.0, match_id);
+    assert_eq!(challenger_match.variant.0, Variant::Chess960);
+    assert_eq!(challenged_match.variant.0, Variant::Chess960);
+    assert_eq!(challenger_match.fen, challenged_match.fen);
+
+    // castling rights name the rooks' files
+    let castling = challenger_match.fen.split(' ').nth(2).unwrap();
+    assert!(castling.chars().all(|c| matches!(c, 'A'..='H' | 'a'..='h')));
+}
+
+#[tokio::test]
+async fn king_of_the_hill_match_ends_when_a_king_reaches_the_center() {
+    let mut network = TestNetwork::new(2).await;
+    let (challenger, challenged) = network.pair(1, 0);
+
+    let (match_id, challenger_color) = start_match(
+        challenger,
+        challenged,
+        Variant::KingOfTheHill,
+        TimeControl::default(),
+    )
+    .await;
+    let (white, black) = match challenger_color {
+        Color::White => (challenger, challenged),
+        Color::Black => (challenged, challenger),
+    };
+
+    for moves in [["e3", "e6"], ["Ke2", "Ke7"], ["Kd3", "Kd6"]].iter() {
+        play(white, black, match_id, moves[0]).await;
+        play(black, white, match_id, moves[1]).await;
+    }
+    play(white, black, match_id, "Kd4").await;
+
+    for node in [white, black].iter_mut() {
+        match node.next_event().await {
+            ServerEventNotification::MatchEnded {
+                match_id: SerializableMatchId(id),
+                result,
+            } if id == match_id => {
+                assert_eq!(result.winner.map(|winner| winner.0), Some(Color::White));
+                assert_eq!(result.reason, MatchEndReason::KingOfTheHill);
+            }
+            event => panic!("unexpected event {}", describe(&event)),
+        }
+    }
+}
+
+#[tokio::test]
+async fn correspondence_match_reports_move_deadline() {
+    let mut network = TestNetwork::new(2).await;
+    let (challenger, challenged) = network.pair(1, 0);
+    let three_days_ms = 3 * 24 * 60 * 60 * 1000;
+
+    match challenger
+        .client
+        .challenge_peer(
+            challenged.peer_id,
+            Variant::Standard,
+            TimeControl::Correspondence {
+                days_per_move: 0,
+                store_peer: None,
+            },
+        )
+        .await
+    {
+        Err(ClientError::Call { code, .. }) => {
+            assert_eq!(code, error_code::INVALID_DAYS_PER_MOVE)
+        }
+        res => panic!("challenging with no days per move returned {:?}", res),
+    }
+
+    let (match_id, challenger_color) = start_match(
+        challenger,
+        challenged,
+        Variant::Standard,
+        TimeControl::Correspondence {
+            days_per_move: 3,
+            store_peer: None,
+        },
+    )
+    .await;
+    let (white, black) = match challenger_color {
+        Color::White => (challenger, challenged),
+        Color::Black => (challenged, challenger),
+    };
+
+    play(white, black, match_id, "e4").await;
+
+    for node in [white, black].iter() {
+        let matches = node.client.list_matches().await.unwrap();
+        assert_eq!(matches[0].days_per_move, Some(3));
+
+        let remaining = matches[0].remaining_move_time_ms.unwrap();
+        assert!(remaining > 0 && remaining <= three_days_ms);
+        assert!(matches[0].clock.is_none());
+    }
+}
+
+#[tokio::test]
+async fn live_match_reports_clock() {
+    let mut network = TestNetwork::new(2).await;
+    let (challenger, challenged) = network.pair(1, 0);
+    let time_control = TimeControl::Live {
+        initial: Duration::from_secs(60),
+        increment: Duration::from_secs(2),
+    };
+
+    let (match_id, challenger_color) =
+        start_match(challenger, challenged, Variant::Standard, time_control).await;
+    let (white, black) = match challenger_color {
+        Color::White => (challenger, challenged),
+        Color::Black => (challenged, challenger),
+    };
+
+    play(white, black, match_id, "e4").await;
+
+    for node in [white, black].iter() {
+        let matches = node.client.list_matches().await.unwrap();
+        assert_eq!(
+            matches[0].time_control,
+            Some(TimeControlInfo {
+                initial_secs: 60,
+                increment_secs: 2,
+            })
+        );
+        assert!(matches[0].remaining_move_time_ms.is_none());
+
+        // white got the increment back after spending less than it on its move
+        let clock = matches[0].clock.unwrap();
+        assert!(clock.white_ms > 55_000 && clock.white_ms <= 62_000);
+        assert!(clock.black_ms <= 60_000);
+    }
+}
+
+#[tokio::test]
+async fn rematch_swaps_colors_and_links_previous_match() {
+    let mut network = TestNetwork::new(2).await;
+    let (challenger, challenged) = network.pair(1, 0);
+
+    let (match_id, challenger_color) = start_match(
+        challenger,
+        challenged,
+        Variant::Standard,
+        TimeControl::default(),
+    )
+    .await;
+    let (white, black) = match challenger_color {
+        Color::White => (challenger, challenged),
+        Color::Black => (challenged, challenger),
+    };
+
+    match black.client.offer_rematch(match_id, true).await {
+        Err(ClientError::Call { code, .. }) => assert_eq!(code, error_code::MATCH_IN_PROGRESS),
+        res => panic!(
+            "offering a rematch of a match in progress returned {:?}",
+            res
+        ),
+    }
+
+    play(white, black, match_id, "f3").await;
+    play(black, white, match_id, "e5").await;
+    play(white, black, match_id, "g4").await;
+    play(black, white, match_id, "Qh4#").await;
+
+    for node in [&mut *white, &mut *black].iter_mut() {
+        match node.next_event().await {
+            ServerEventNotification::MatchEnded {
+                match_id: SerializableMatchId(id),
+                ..
+            } if id == match_id => {}
+            event => panic!("unexpected event {}", describe(&event)),
+        }
+    }
+
+    black.client.offer_rematch(match_id, true).await.unwrap();
+
+    match white.next_event().await {
+        ServerEventNotification::RematchOffered {
+            match_id: SerializableMatchId(id),
+            swap_colors: true,
+            ..
+        } if id == match_id => {}
+        event => panic!("unexpected event {}", describe(&event)),
+    }
+
+    white.client.accept_rematch(match_id).await.unwrap();
+
+    let (black_rematch_id, black_color) = match_started(black).await;
+    let (white_rematch_id, white_color) = match_started(white).await;
+
+    assert_eq!(black_rematch_id, white_rematch_id);
+    assert_ne!(black_rematch_id, match_id);
+    assert_eq!(black_color, Color::White);
+    assert_eq!(white_color, Color::Black);
+
+    let matches = white.client.list_matches().await.unwrap();
+    let rematch = matches
+        .iter()
+        .find(|info| info.match_id.0 == black_rematch_id)
+        .unwrap();
+    assert_eq!(
+        rematch.previous_match_id.as_ref().map(|id| id.0),
+        Some(match_id)
+    );
+}
+
+#[tokio::test]
+async fn chat_messages_reach_the_opponent() {
+    let mut network = TestNetwork::new(2).await;
+    let (challenger, challenged) = network.pair(1, 0);
+
+    let (match_id, challenger_color) = start_match(
+        challenger,
+        challenged,
+        Variant::Standard,
+        TimeControl::default(),
+    )
+    .await;
+
+    let too_long = "a".repeat(501);
+    for text in ["", "   ", too_long.as_str()].iter() {
+        match challenger.client.send_chat_message(match_id, text).await {
+            Err(ClientError::Call { code, .. }) => {
+                assert_eq!(code, error_code::INVALID_CHAT_MESSAGE)
+            }
+            res => panic!("sending {:?} returned {:?}", text, res),
+        }
+    }
+
+    challenger
+        .client
+        .send_chat_message(match_id, "good luck")
+        .await
+        .unwrap();
+
+    // the sender sees its own message too, for both chat logs to read the same
+    for node in [&mut *challenger, &mut *challenged].iter_mut() {
+        match node.next_event().await {
+            ServerEventNotification::ChatMessage {
+                match_id: SerializableMatchId(id),
+                color: SerializableColor(color),
+                text,
+            } if id == match_id => {
+                assert_eq!(color, challenger_color);
+                assert_eq!(text, "good luck");
+            }
+            event => panic!("unexpected event {}", describe(&event)),
+        }
+    }
+}
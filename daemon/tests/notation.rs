@@ -0,0 +1,196 @@
+//! Moves written in Standard Algebraic Notation and in the long algebraic notation of UCI, and
+//! games exported as PGN.
+
+use ipchess::chess::{
+    Move, ParseMoveError, Pgn, PgnResult, Position, Role, SanError, Square, STARTING_FEN,
+};
+
+fn position(fen: &str) -> Position {
+    Position::from_fen(fen).unwrap()
+}
+
+/// Plays moves given in UCI notation from the starting position.
+fn after(moves: &[&str]) -> Position {
+    let mut position = Position::startpos();
+    for uci in moves {
+        position.play(uci.parse().unwrap()).unwrap();
+    }
+    position
+}
+
+fn san(position: &Position, uci: &str) -> String {
+    position.san(uci.parse().unwrap())
+}
+
+#[test]
+fn uci_round_trip() {
+    let mv = "e7e8q".parse::<Move>().unwrap();
+    assert_eq!(
+        mv,
+        Move {
+            from: "e7".parse::<Square>().unwrap(),
+            to: "e8".parse::<Square>().unwrap(),
+            promotion: Some(Role::Queen),
+        }
+    );
+    assert_eq!(mv.to_string(), "e7e8q");
+    assert_eq!("g1f3".parse::<Move>().unwrap().to_string(), "g1f3");
+
+    for invalid in &["", "e2", "e2e9", "e2e4Q", "e7e8k", "e2e4qq", "é2e4"] {
+        assert_eq!(invalid.parse::<Move>(), Err(ParseMoveError), "{}", invalid);
+    }
+}
+
+#[test]
+fn san_of_pawn_and_piece_moves() {
+    let start = Position::startpos();
+    assert_eq!(san(&start, "e2e4"), "e4");
+    assert_eq!(san(&start, "g1f3"), "Nf3");
+
+    assert_eq!(san(&after(&["e2e4", "d7d5"]), "e4d5"), "exd5");
+    assert_eq!(
+        san(&position("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1"), "e5d6"),
+        "exd6"
+    );
+
+    let promotion = position("8/4P1k1/8/8/8/8/8/4K3 w - - 0 1");
+    assert_eq!(san(&promotion, "e7e8q"), "e8=Q");
+    assert_eq!(san(&promotion, "e7e8n"), "e8=N+");
+}
+
+#[test]
+fn san_of_castling() {
+    let position = position("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+    assert_eq!(san(&position, "e1g1"), "O-O");
+    assert_eq!(san(&position, "e1c1"), "O-O-O");
+}
+
+#[test]
+fn san_of_chess960_castling() {
+    // the king moves onto its own rook
+    let position = position("1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/1R2K1R1 w GBgb - 0 1");
+    assert_eq!(san(&position, "e1g1"), "O-O");
+    assert_eq!(san(&position, "e1b1"), "O-O-O");
+    assert_eq!(position.parse_san("O-O"), Ok("e1g1".parse().unwrap()));
+    assert_eq!(position.parse_san("O-O-O"), Ok("e1b1".parse().unwrap()));
+
+    let mut castled = position.clone();
+    castled.play("e1b1".parse().unwrap()).unwrap();
+    assert_eq!(
+        castled.fen(),
+        "1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/2KR2R1 b gb - 1 1"
+    );
+}
+
+#[test]
+fn san_disambiguates_by_file_rank_or_square() {
+    assert_eq!(
+        san(&position("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1"), "b1d2"),
+        "Nbd2"
+    );
+    assert_eq!(
+        san(&position("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1"), "a1a3"),
+        "R1a3"
+    );
+    assert_eq!(
+        san(&position("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1"), "a1b2"),
+        "Qa1b2"
+    );
+}
+
+#[test]
+fn san_marks_check_and_checkmate() {
+    assert_eq!(san(&after(&["e2e4", "d7d5"]), "f1b5"), "Bb5+");
+
+    let scholars_mate = after(&["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6"]);
+    assert_eq!(san(&scholars_mate, "h5f7"), "Qxf7#");
+}
+
+#[test]
+fn san_round_trip() {
+    for fen in &[
+        STARTING_FEN,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1",
+    ] {
+        let position = position(fen);
+        for mv in position.legal_moves() {
+            let san = position.san(mv);
+            assert_eq!(position.parse_san(&san), Ok(mv), "{} in {}", san, fen);
+        }
+    }
+}
+
+#[test]
+fn parse_san_accepts_common_variants() {
+    let promotion = position("8/4P1k1/8/8/8/8/8/4K3 w - - 0 1");
+    assert_eq!(promotion.parse_san("e8Q"), promotion.parse_san("e8=Q"));
+
+    let castling = position("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+    assert_eq!(castling.parse_san("0-0"), Ok("e1g1".parse().unwrap()));
+
+    let start = Position::startpos();
+    assert_eq!(start.parse_san("Nf3!?"), Ok("g1f3".parse().unwrap()));
+    assert_eq!(start.parse_san("Ng1f3"), Ok("g1f3".parse().unwrap()));
+}
+
+#[test]
+fn parse_san_rejects_bad_moves() {
+    let start = Position::startpos();
+    assert_eq!(start.parse_san("e5"), Err(SanError::Illegal));
+    assert_eq!(start.parse_san("O-O"), Err(SanError::Illegal));
+    assert_eq!(start.parse_san("Z9"), Err(SanError::Invalid));
+    assert_eq!(start.parse_san(""), Err(SanError::Invalid));
+
+    let knights = position("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1");
+    assert_eq!(knights.parse_san("Nd2"), Err(SanError::Ambiguous));
+}
+
+#[test]
+fn parse_move_accepts_uci_or_san() {
+    let start = Position::startpos();
+    let e4 = "e2e4".parse::<Move>().unwrap();
+
+    assert_eq!(start.parse_move("e2e4"), Ok(e4));
+    assert_eq!(start.parse_move("e4"), Ok(e4));
+    assert_eq!(start.parse_move("e2e5"), Err(SanError::Illegal));
+}
+
+#[test]
+fn pgn_export_round_trip() {
+    let pgn = Pgn {
+        tags: vec![
+            ("Event".to_string(), "ipchess \"casual\" match".to_string()),
+            ("White".to_string(), "a".to_string()),
+            ("Black".to_string(), "b".to_string()),
+        ],
+        moves: ["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7#"]
+            .iter()
+            .map(|san| san.to_string())
+            .collect(),
+        result: Some(PgnResult::WhiteWins),
+    };
+
+    let exported = pgn.to_string();
+    assert_eq!(
+        exported,
+        "[Event \"ipchess \\\"casual\\\" match\"]\n[White \"a\"]\n[Black \"b\"]\n\n\
+         1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0\n"
+    );
+    assert_eq!(Pgn::parse(&exported), Ok(pgn));
+}
+
+#[test]
+fn pgn_export_wraps_long_movetext() {
+    let pgn = Pgn {
+        moves: vec!["Nf3".to_string(); 60],
+        ..Pgn::default()
+    };
+
+    let exported = pgn.to_string();
+    assert!(exported.lines().count() > 1);
+    assert!(exported.lines().all(|line| line.len() <= 79));
+    assert!(exported.ends_with(" *\n"));
+    assert_eq!(Pgn::parse(&exported).unwrap().moves, pgn.moves);
+}
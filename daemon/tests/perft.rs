@@ -0,0 +1,180 @@
+//! Move generation checked against the well-known perft counts.
+//!
+//! Counts are from the positions collected on the Chess Programming Wiki, Chess960 ones included,
+//! and from Martin Sedlak's suite of edge cases.
+
+use ipchess::chess::{perft, Position, STARTING_FEN};
+
+/// Checks the node counts of a position at depths 1, 2, ... up to the number of counts given.
+fn assert_perft(fen: &str, counts: &[u64]) {
+    let position = Position::from_fen(fen).unwrap();
+
+    for (depth, &count) in (1..).zip(counts) {
+        assert_eq!(
+            perft(&position, depth),
+            count,
+            "perft({}) of {}",
+            depth,
+            fen
+        );
+    }
+}
+
+fn perft_of(fen: &str, depth: u32) -> u64 {
+    perft(&Position::from_fen(fen).unwrap(), depth)
+}
+
+#[test]
+fn start_position() {
+    assert_perft(STARTING_FEN, &[20, 400, 8_902, 197_281, 4_865_609]);
+}
+
+#[test]
+fn kiwipete() {
+    assert_perft(
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        &[48, 2_039, 97_862, 4_085_603],
+    );
+}
+
+#[test]
+fn position_3() {
+    assert_perft(
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        &[14, 191, 2_812, 43_238, 674_624],
+    );
+}
+
+#[test]
+fn position_4() {
+    assert_perft(
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        &[6, 264, 9_467, 422_333],
+    );
+}
+
+#[test]
+fn position_4_mirrored() {
+    assert_perft(
+        "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+        &[6, 264, 9_467, 422_333],
+    );
+}
+
+#[test]
+fn position_5() {
+    assert_perft(
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        &[44, 1_486, 62_379, 2_103_487],
+    );
+}
+
+#[test]
+fn position_6() {
+    assert_perft(
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        &[46, 2_079, 89_890, 3_894_594],
+    );
+}
+
+#[test]
+fn illegal_en_passant_captures() {
+    // the capture would expose the king along the rank or a diagonal
+    assert_eq!(perft_of("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", 6), 1_134_888);
+    assert_eq!(perft_of("8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1", 6), 1_015_133);
+}
+
+#[test]
+fn en_passant_capture_checks_opponent() {
+    assert_eq!(
+        perft_of("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", 6),
+        1_440_467
+    );
+}
+
+#[test]
+fn castling() {
+    // short and long castling giving check
+    assert_eq!(perft_of("5k2/8/8/8/8/8/8/4K2R w K - 0 1", 6), 661_072);
+    assert_eq!(perft_of("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", 6), 803_711);
+    // castling rights lost when rooks move or are captured
+    assert_eq!(
+        perft_of("r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1", 4),
+        1_274_206
+    );
+    // castling prevented by attacked squares
+    assert_eq!(
+        perft_of("r3k2r/8/3Q4/8/8/5q2/8/R3K2R b KQkq - 0 1", 4),
+        1_720_476
+    );
+}
+
+#[test]
+fn promotions() {
+    // promoting out of check and giving check
+    assert_eq!(perft_of("2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1", 6), 3_821_001);
+    assert_eq!(perft_of("4k3/1P6/8/8/8/8/K7/8 w - - 0 1", 6), 217_342);
+    assert_eq!(perft_of("8/P1k5/K7/8/8/8/8/8 w - - 0 1", 6), 92_683);
+}
+
+#[test]
+fn discovered_check() {
+    assert_eq!(
+        perft_of("8/8/1P2K3/8/2n5/1q6/8/5k2 b - - 0 1", 5),
+        1_004_658
+    );
+}
+
+#[test]
+fn stalemate_and_checkmate() {
+    assert_eq!(perft_of("K1k5/8/P7/8/8/8/8/8 w - - 0 1", 6), 2_217);
+    assert_eq!(perft_of("8/k1P5/8/1K6/8/8/8/8 w - - 0 1", 7), 567_584);
+    assert_eq!(perft_of("8/8/2k5/5q2/5n2/8/5K2/8 b - - 0 1", 4), 23_527);
+}
+
+#[test]
+fn chess960() {
+    assert_perft(
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        &[21, 528, 12_189, 326_672],
+    );
+    assert_perft(
+        "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+        &[21, 807, 18_002, 667_366],
+    );
+    assert_perft(
+        "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+        &[20, 479, 10_471, 273_318],
+    );
+}
+
+#[test]
+fn chess960_standard_setup() {
+    // castling onto the rooks rather than two squares does not change the number of moves
+    let position = Position::chess960(518).unwrap();
+    assert_eq!(
+        position.fen(),
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"
+    );
+    assert_eq!(perft(&position, 4), 197_281);
+
+    assert_perft(
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w HAha - 0 1",
+        &[48, 2_039, 97_862, 4_085_603],
+    );
+}
+
+#[test]
+fn chess960_numbering() {
+    let fen = |index| Position::chess960(index).map(|position| position.fen());
+
+    assert_eq!(
+        fen(0).unwrap(),
+        "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1"
+    );
+    assert_eq!(
+        fen(959).unwrap(),
+        "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w CAca - 0 1"
+    );
+    assert_eq!(fen(960), None);
+}